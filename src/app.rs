@@ -1,29 +1,56 @@
+use std::fmt;
 use std::io::stdout;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use std::{collections::HashMap, sync::Arc, sync::Condvar, sync::Mutex};
+use std::{collections::HashMap, collections::HashSet, sync::Arc, sync::Condvar, sync::Mutex};
 use std::{fs, thread};
 
+pub mod actions;
+pub mod chat_export;
+pub mod chat_search;
+pub mod command_line;
 pub mod events;
+pub mod groups;
 pub mod inputs;
+pub mod mentions;
+pub mod mouse;
+pub mod outbox;
+pub mod profiles;
+pub mod rpc;
+pub mod search;
+pub mod unread;
 pub mod vim_input;
 
 pub use crate::app;
+use crate::app::chat_export::ChatExport;
+use crate::app::chat_search::ChatSearch;
+use crate::app::command_line::CommandLine;
 use crate::app::events::{AppEvent, AppInput};
+use crate::app::groups::GroupJob;
+use crate::app::mouse::Mouse;
+use crate::app::outbox::{OutboxEntry, OutboxJob};
+use crate::app::profiles::ProfileSwitcher;
+use crate::app::rpc::{RpcClient, RpcServer};
+use crate::app::search::MessageSearch;
+use crate::app::unread::ReadReceipts;
+use crate::backend::{Backend, FfiBackend, log_backend};
+use crate::clipboard::{self, Clipboard};
+use crate::config::{Config, ConfigError, default_config_path};
 use crate::db;
 use crate::key_handler::KeybindHandler;
 use crate::ui;
+use crate::util::stable_hash;
 use crate::vim;
 // use crate::key_handler;
 
-use arboard::Clipboard;
 use db::DatabaseHandler;
-use directories::ProjectDirs;
-use log::{debug, error, info, trace};
+use db::migrations::MigrationError;
+use directories::{ProjectDirs, UserDirs};
+use log::{debug, error, info, trace, warn};
 use notify_rust::Notification;
 use ratatui::crossterm::ExecutableCommand;
-use ratatui::crossterm::event;
+use ratatui::crossterm::event::{self, DisableMouseCapture, EnableMouseCapture};
 use ratatui::crossterm::terminal::{
     EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
 };
@@ -34,7 +61,6 @@ use ratatui_image::protocol::StatefulProtocol;
 use ratatui_image::{Resize, ResizeEncodeRender};
 use ratatui_textarea::TextArea;
 use ui::message_list::MessageListState;
-use ui::message_list::{IMAGE_HEIGHT, IMAGE_WIDTH};
 use vim::Vim;
 use whatsrust as wr;
 
@@ -48,17 +74,80 @@ enum InputReaderState {
     Stopped,
 }
 
+/// Why the app could not start.
+#[derive(Debug)]
+pub enum StartError {
+    Database(MigrationError),
+    WhatsApp(wr::Error),
+}
+
+impl fmt::Display for StartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartError::Database(err) => write!(f, "{err}"),
+            StartError::WhatsApp(err) => write!(f, "failed to connect to WhatsApp: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for StartError {}
+
+impl From<MigrationError> for StartError {
+    fn from(err: MigrationError) -> Self {
+        StartError::Database(err)
+    }
+}
+
+impl From<wr::Error> for StartError {
+    fn from(err: wr::Error) -> Self {
+        StartError::WhatsApp(err)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SelectedWidget {
     ChatList,
     Input,
     MessageList,
     MessageView,
+    ReactionPicker,
+    MessageInfo,
+    Search,
+    Export,
+    Profiles,
+    ConfigErrors,
+    CommandLine,
 }
 
+/// How many messages of a chat are read from the database at a time.
+pub const HISTORY_PAGE_SIZE: usize = 50;
+
 #[derive(Clone, Debug)]
 pub struct Chat {
     pub jid: wr::JID,
     pub last_message_time: Option<i64>,
+    /// How many messages were received since the chat was last read.
+    pub unread: usize,
+}
+
+/// When one participant received, read and played one of our messages.
+#[derive(Clone, Debug)]
+pub struct ParticipantReceipt {
+    pub participant: wr::JID,
+    pub delivered_at: Option<i64>,
+    pub read_at: Option<i64>,
+    pub played_at: Option<i64>,
+}
+
+/// How much of the history of a chat was read from the database.
+#[derive(Default)]
+struct History {
+    /// The oldest message read, the next page is read from before it.
+    oldest: Option<(i64, wr::MessageId)>,
+    /// Nothing older is left to read.
+    complete: bool,
+    /// A page was requested and is yet to be read.
+    requested: bool,
 }
 
 #[derive(Debug)]
@@ -76,9 +165,12 @@ pub enum Metadata {
 }
 
 pub struct App<'a> {
+    pub backend: Arc<dyn Backend>,
     pub db_handler: DatabaseHandler,
     pub media_path: PathBuf,
     pub whatsmeow_db: PathBuf,
+    /// Where scripts connect to for the JSON-RPC API.
+    pub rpc_socket: PathBuf,
 
     pub messages: HashMap<wr::MessageId, wr::Message>,
     pub chats: HashMap<wr::JID, Chat>,
@@ -86,9 +178,27 @@ pub struct App<'a> {
     // Maps JID to display name
     pub contacts: HashMap<wr::JID, Arc<str>>,
 
-    pub clipboard: Clipboard,
+    pub clipboard: Box<dyn Clipboard>,
 
     pub chat_messages: HashMap<wr::JID, Vec<wr::MessageId>>,
+    // How far back each chat was read, messages are read a page at a time as needed
+    history: HashMap<wr::JID, History>,
+
+    // Reactions to each message, at most one per sender
+    pub reactions: HashMap<wr::MessageId, Vec<wr::Reaction>>,
+
+    // Receipts for our own messages, one per participant
+    pub receipts: HashMap<wr::MessageId, Vec<ParticipantReceipt>>,
+
+    // Metadata of the groups shown in the participants panel, fetched on first use
+    pub group_infos: HashMap<wr::JID, wr::GroupInfo>,
+    // Groups whose metadata was asked for and has not arrived yet
+    group_infos_loading: HashSet<wr::JID>,
+
+    // Messages not yet accepted by the server, by local id
+    pub outbox: HashMap<wr::MessageId, OutboxEntry>,
+    // Delay before the first retry of a failed send, doubled on each attempt
+    pub retry_delay: Duration,
 
     pub sorted_chats: Vec<wr::JID>,
     pub chat_list_state: ListState,
@@ -96,8 +206,18 @@ pub struct App<'a> {
     pub history_sync_percent: Option<u8>,
 
     pub quoting_message: Option<wr::Message>,
+    pub editing_message: Option<wr::Message>,
     pub attached_file: Option<(Arc<str>, wr::FileKind)>,
     pub message_list_state: MessageListState,
+    /// The first message that was unread when its chat was opened, the new messages
+    /// divider is drawn above it.
+    pub new_messages: Option<wr::MessageId>,
+    pub chat_search: ChatSearch,
+    pub reaction_picker_index: usize,
+    pub chat_export: ChatExport,
+    pub profile_switcher: ProfileSwitcher,
+    pub command_line: CommandLine,
+    pub mouse: Mouse,
     pub metadata: HashMap<wr::MessageId, Metadata>,
     pub image_cache: HashMap<Arc<str>, StatefulProtocol>,
     pub default_protocol_type: ProtocolType,
@@ -106,33 +226,63 @@ pub struct App<'a> {
     pub selected_widget: SelectedWidget,
 
     pub kh: KeybindHandler,
+    pub config: Config,
+    /// What was wrong in the config, shown until dismissed.
+    pub config_errors: Vec<ConfigError>,
 
     pub show_logs: bool,
+    pub show_participants: bool,
 
     pub vim: Vim,
     pub input_widget: TextArea<'a>,
     pub input_border: Block<'a>,
     pub visual_line_anchor: Option<usize>,
+    /// Mentions completed in the input, by the name they were completed with.
+    pub mentions: Vec<(Arc<str>, wr::JID)>,
+    /// The candidate selected in the mention popup.
+    pub mention_index: usize,
 
     pub contact_search_active: bool,
     pub contact_search: TextInput,
     pub filtered_chats: Vec<wr::JID>,
 
+    pub search: MessageSearch,
+
     pub should_quit: bool,
+    /// The data directory of the profile to open once the app quits, if it quits to
+    /// switch profiles.
+    pub switch_to: Option<PathBuf>,
 
     pub tx: mpsc::Sender<AppInput>,
     pub rx: mpsc::Receiver<AppInput>,
+    download_tx: Option<mpsc::Sender<(wr::MessageId, wr::FileId)>>,
+    outbox_tx: Option<mpsc::Sender<OutboxJob>>,
+    group_tx: Option<mpsc::Sender<GroupJob>>,
+    read_tx: Option<mpsc::Sender<ReadReceipts>>,
+    rpc_server: Option<RpcServer>,
+    // Connections that asked for incoming messages and events
+    rpc_subscribers: Vec<RpcClient>,
     input_reader_control: Arc<(Mutex<InputReaderState>, Condvar)>,
 }
 
 impl Default for App<'_> {
     fn default() -> Self {
-        let mut input_widget = TextArea::default();
-        // input_widget.set_cursor_line_style(vim::Mode::Nor::default());
-        input_widget.set_cursor_style(vim::Mode::Insert.cursor_style());
-        // input_widget.set_block(vim::Mode::Normal.block());
-        input_widget.set_placeholder_text("Type a message...");
+        Self::open(&default_data_dir())
+    }
+}
+
+/// Where the databases and media are kept unless told otherwise.
+pub fn default_data_dir() -> PathBuf {
+    ProjectDirs::from("com", "nullptr", "wstui")
+        .unwrap()
+        .data_dir()
+        .to_path_buf()
+}
 
+impl App<'_> {
+    /// Creates an app on WhatsApp, the system clipboard and the terminal, that keeps its
+    /// databases and media under `data_dir`.
+    pub fn open(data_dir: &Path) -> Self {
         let picker = Picker::from_query_stdio().unwrap_or_else(|err| {
             // Fallback for non-interactive environments (e.g. CI, piped stdio).
             log::warn!(
@@ -140,122 +290,220 @@ impl Default for App<'_> {
             );
             Picker::halfblocks()
         });
+
+        let mut app = Self::new(
+            data_dir,
+            Arc::new(FfiBackend),
+            clipboard::system_or_memory(),
+            picker,
+        );
+        let (config, errors) = Config::load(&default_config_path());
+        app.set_config(config, errors);
+        app
+    }
+
+    /// Applies `config`, and shows what was wrong in it if anything was.
+    pub fn set_config(&mut self, config: Config, errors: Vec<ConfigError>) {
+        self.kh.keymap = config.keys.clone();
+        self.kh.timeout = config.key_timeout;
+        self.set_vim_mode(config.vim_mode);
+        self.config = config;
+        for err in &errors {
+            error!("Config: {err}");
+        }
+        if !errors.is_empty() {
+            self.selected_widget = SelectedWidget::ConfigErrors;
+        }
+        self.config_errors = errors;
+    }
+
+    /// Creates an app that keeps its databases and media under `data_dir`.
+    /// Nothing is loaded or connected until `start` is called.
+    pub fn new(
+        data_dir: &Path,
+        backend: Arc<dyn Backend>,
+        clipboard: Box<dyn Clipboard>,
+        picker: Picker,
+    ) -> Self {
+        let mut input_widget = TextArea::default();
+        // input_widget.set_cursor_line_style(vim::Mode::Nor::default());
+        input_widget.set_cursor_style(vim::Mode::Insert.cursor_style());
+        // input_widget.set_block(vim::Mode::Normal.block());
+        input_widget.set_placeholder_text("Type a message...");
+
         let default_protocol_type = picker.protocol_type();
 
-        let project_dirs = ProjectDirs::from("com", "nullptr", "wstui").unwrap();
-        let data_dir = project_dirs.data_dir();
         fs::create_dir_all(data_dir).unwrap();
 
         let (tx, rx) = mpsc::channel::<AppInput>();
 
         Self {
+            backend,
             db_handler: DatabaseHandler::new(&data_dir.join("whatsapp.db")),
             media_path: data_dir.join("media"),
             whatsmeow_db: data_dir.join("whatsmeow.db"),
+            rpc_socket: data_dir.join(rpc::SOCKET_NAME),
 
-            clipboard: Clipboard::new().unwrap(),
+            clipboard,
 
             messages: HashMap::new(),
             chats: HashMap::new(),
             contacts: HashMap::new(),
             chat_messages: HashMap::new(),
+            history: HashMap::new(),
+            reactions: HashMap::new(),
+            receipts: HashMap::new(),
+            group_infos: HashMap::new(),
+            group_infos_loading: HashSet::new(),
+            outbox: HashMap::new(),
+            retry_delay: Duration::from_secs(2),
 
             sorted_chats: Vec::new(),
             chat_list_state: ListState::default(),
 
             message_list_state: MessageListState::default(),
+            new_messages: None,
+            chat_search: ChatSearch::default(),
+            reaction_picker_index: 0,
+            chat_export: ChatExport::new(
+                UserDirs::new()
+                    .and_then(|dirs| dirs.download_dir().map(Path::to_path_buf))
+                    .unwrap_or_else(|| data_dir.join("exports")),
+            ),
+            profile_switcher: ProfileSwitcher::for_data_dir(data_dir),
+            command_line: CommandLine::default(),
+            mouse: Mouse::default(),
             metadata: HashMap::new(),
             history_sync_percent: None,
             image_cache: HashMap::new(),
             default_protocol_type,
             quoting_message: None,
+            editing_message: None,
             attached_file: None,
             picker: Arc::new(Mutex::new(picker)),
             selected_widget: SelectedWidget::ChatList,
 
             kh: KeybindHandler::default(),
+            config: Config::default(),
+            config_errors: Vec::new(),
 
             contact_search_active: false,
             contact_search: TextInput::new(),
             filtered_chats: Vec::new(),
+            search: MessageSearch::default(),
 
             show_logs: false,
+            show_participants: false,
             vim: Vim::new(vim::Mode::Insert),
             input_border: vim::Mode::Insert.block(),
             input_widget,
             visual_line_anchor: None,
+            mentions: Vec::new(),
+            mention_index: 0,
             should_quit: false,
+            switch_to: None,
             tx,
             rx,
+            download_tx: None,
+            outbox_tx: None,
+            group_tx: None,
+            read_tx: None,
+            rpc_server: None,
+            rpc_subscribers: Vec::new(),
             input_reader_control: Arc::new((Mutex::new(InputReaderState::Running), Condvar::new())),
         }
     }
-}
 
-impl App<'_> {
-    pub fn run(&mut self, phone: Option<String>) {
-        self.db_handler.init();
+    /// Loads the local database, registers the backend handlers and connects.
+    /// Blocks until the backend is connected.
+    pub fn start(&mut self, phone: Option<String>) -> Result<(), StartError> {
+        self.db_handler.init()?;
         self.load_data_from_db();
         self.sort_chats();
 
-        wr::new_client(self.whatsmeow_db.to_str().unwrap());
-
+        // The handlers are registered before the client is created, which already logs:
+        // the ones left by the app of a previous profile send to a channel that is gone.
+        // Sends are allowed to fail for the same reason once this app is dropped.
         {
             let tx = self.tx.clone();
-            wr::set_log_handler(move |msg, level| {
-                let level = match level {
-                    0 => log::Level::Error,
-                    1 => log::Level::Warn,
-                    2 => log::Level::Info,
-                    3 => log::Level::Debug,
-                    _ => log::Level::Trace,
-                };
-                log::log!(level, "{msg}");
-                tx.send(AppInput::Draw).unwrap();
-            });
+            self.backend.set_log_handler(Box::new(move |msg, level| {
+                log_backend(&msg, level);
+                let _ = tx.send(AppInput::Draw);
+            }));
         }
         {
             let tx = self.tx.clone();
-            wr::set_event_handler(move |event| {
-                tx.send(AppInput::WhatsApp(event)).unwrap();
-            })
+            self.backend.set_event_handler(Box::new(move |event| {
+                let _ = tx.send(AppInput::WhatsApp(event));
+            }));
         }
         {
             let tx = self.tx.clone();
-            wr::set_message_handler(move |message, is_sync| {
-                tx.send(AppInput::Message { message, is_sync }).unwrap();
-            });
+            self.backend
+                .set_message_handler(Box::new(move |message, is_sync| {
+                    let _ = tx.send(AppInput::Message { message, is_sync });
+                }));
         }
 
+        self.backend.new_client(&self.whatsmeow_db)?;
+
         // Single dedicated thread for all CGo downloads. Calling Go from many Rust-spawned
         // threads can crash even with a mutex; one long-lived worker avoids that.
         let (download_tx, download_rx) = mpsc::channel::<(wr::MessageId, wr::FileId)>();
         let media_path = self.media_path.to_owned();
         let app_tx = self.tx.clone();
+        let backend = Arc::clone(&self.backend);
         thread::spawn(move || {
             for (message_id, file_id) in download_rx {
-                let result = wr::download_file(&file_id, &media_path);
-                let state = if result.is_err() {
-                    FileMeta::DownloadFailed
-                } else {
-                    FileMeta::Downloaded
+                let state = match backend.download_file(&file_id, &media_path) {
+                    Ok(()) => FileMeta::Downloaded,
+                    Err(err) => {
+                        error!("Failed to download {message_id}: {err}");
+                        FileMeta::DownloadFailed
+                    }
                 };
                 app_tx
                     .send(AppInput::App(AppEvent::SetFileState(message_id, state)))
                     .unwrap();
             }
         });
+        self.download_tx = Some(download_tx);
+        self.group_tx = Some(groups::spawn_worker(
+            Arc::clone(&self.backend),
+            self.tx.clone(),
+        ));
+        self.read_tx = Some(unread::spawn_worker(Arc::clone(&self.backend)));
+
+        self.outbox_tx = Some(outbox::spawn_worker(
+            Arc::clone(&self.backend),
+            self.tx.clone(),
+            self.retry_delay,
+        ));
+        self.load_outbox();
+        self.start_rpc_server();
 
         info!("Connecting to WhatsApp Web");
         // thread::spawn(|| {
-        wr::connect(move |data| {
+        let backend = Arc::clone(&self.backend);
+        self.backend.connect(Box::new(move |data| {
             qr2term::print_qr(data).unwrap();
             if let Some(phone) = phone.as_ref() {
-                let code = wr::pair_phone(phone);
-                println!("Pairing code: {}", code);
+                match backend.pair_phone(phone) {
+                    Ok(code) => println!("Pairing code: {}", code),
+                    Err(err) => eprintln!("Failed to get a pairing code: {err}"),
+                }
             }
-        });
+        }))?;
         // });
+        Ok(())
+    }
+
+    pub fn run(&mut self, phone: Option<String>) {
+        if let Err(e) = self.start(phone) {
+            error!("Failed to start: {e}");
+            eprintln!("Failed to start: {e}");
+            return;
+        }
         info!("Connected, initializing terminal UI");
 
         let mut terminal = match ratatui::try_init() {
@@ -266,6 +514,9 @@ impl App<'_> {
                 return;
             }
         };
+        if let Err(e) = stdout().execute(EnableMouseCapture) {
+            error!("Failed to enable mouse capture: {e}");
+        }
 
         {
             let tx = self.tx.clone();
@@ -319,206 +570,452 @@ impl App<'_> {
             let msg = self.rx.recv();
             // info!("Received message: {:?}", &msg);
             let should_draw = match msg {
-                Ok(AppInput::App(event)) => match event {
-                    AppEvent::EditWithExternalEditor => {
-                        self.suspend_input_reader();
-                        stdout().execute(LeaveAlternateScreen).unwrap();
-                        disable_raw_mode().unwrap();
-                        let edit_result = edit::edit(self.input_widget.lines().join("\n"));
-                        stdout().execute(EnterAlternateScreen).unwrap();
-                        enable_raw_mode().unwrap();
-                        terminal.clear().unwrap();
-                        self.resume_input_reader();
-
-                        if let Ok(text) = edit_result {
-                            self.input_widget.select_all();
-                            self.input_widget.delete_next_char();
-                            self.input_widget.insert_str(&text);
-                        } else {
-                            error!("Failed to launch external editor");
-                        }
-                        true
+                Ok(AppInput::App(AppEvent::EditWithExternalEditor)) => {
+                    self.suspend_input_reader();
+                    stdout().execute(DisableMouseCapture).unwrap();
+                    stdout().execute(LeaveAlternateScreen).unwrap();
+                    disable_raw_mode().unwrap();
+                    let edit_result = edit::edit(self.input_widget.lines().join("\n"));
+                    stdout().execute(EnterAlternateScreen).unwrap();
+                    stdout().execute(EnableMouseCapture).unwrap();
+                    enable_raw_mode().unwrap();
+                    terminal.clear().unwrap();
+                    self.resume_input_reader();
+
+                    if let Ok(text) = edit_result {
+                        self.input_widget.select_all();
+                        self.input_widget.delete_next_char();
+                        self.input_widget.insert_str(&text);
+                    } else {
+                        error!("Failed to launch external editor");
                     }
-                    AppEvent::SetFilePreview(message_id, file_path, img) => {
-                        self.image_cache.insert(file_path.clone(), img);
-                        self.metadata
-                            .insert(message_id.clone(), Metadata::File(FileMeta::Loaded));
+                    true
+                }
+                Ok(input) => self.update(input),
+                Err(_) => {
+                    error!("Failed to receive input from channel");
+                    true
+                }
+            };
+
+            if should_draw {
+                terminal.draw(|frame| ui::draw(frame, self)).unwrap();
+            }
 
-                        trace!("Set file preview for message: {:?}", message_id);
+            if self.should_quit {
+                break;
+            }
+        }
 
-                        true
-                    }
-                    AppEvent::LoadFilePreview(message_id) => {
-                        if !matches!(
-                            self.metadata.get(&message_id),
-                            Some(Metadata::File(FileMeta::Loading))
-                        ) {
-                            self.metadata
-                                .insert(message_id.clone(), Metadata::File(FileMeta::Loading));
-
-                            let tx = self.tx.clone();
-                            let media_path = self.media_path.to_owned();
-                            let picker = Arc::clone(&self.picker);
-
-                            let file = match &self.messages.get(&message_id).unwrap().message {
-                                wr::MessageContent::File(f) => Some(f.clone()),
-                                _ => None,
-                            };
-                            if let Some(file) = file {
-                                thread::spawn(move || {
-                                    let binding = file.path.to_string();
-                                    let path = std::path::Path::new(&binding);
-                                    let image_res = image::ImageReader::open(media_path.join(path))
-                                        .unwrap()
-                                        .decode();
-
-                                    if let Ok(image_src) = image_res {
-                                        let mut img =
-                                            picker.lock().unwrap().new_resize_protocol(image_src);
-                                        img.resize_encode(
-                                            &Resize::Scale(None),
-                                            Rect {
-                                                x: 0,
-                                                y: 0,
-                                                width: IMAGE_WIDTH as u16,
-                                                height: IMAGE_HEIGHT as u16,
-                                            },
-                                        );
-
-                                        tx.send(AppInput::App(AppEvent::SetFilePreview(
-                                            message_id.clone(),
-                                            file.path.clone(),
-                                            img,
-                                        )))
-                                        .unwrap();
-                                    } else {
-                                        tx.send(AppInput::App(AppEvent::SetFileState(
-                                            message_id.clone(),
-                                            FileMeta::LoadFailed,
-                                        )))
-                                        .unwrap();
-                                    }
-                                });
-                            } else {
-                                error!("Expected a file message for preview");
-                            }
-                        }
-                        false // We will redraw after the preview is loaded
+        self.stop_input_reader();
+        // Removes the socket, so scripts see right away that wstui is gone.
+        self.rpc_server = None;
+        let _ = stdout().execute(DisableMouseCapture);
+        ratatui::restore();
+        if let Err(e) = self.backend.disconnect() {
+            error!("Failed to disconnect: {e}");
+        }
+    }
+
+    /// Applies a single input to the app state. Returns whether the UI should be redrawn.
+    pub fn update(&mut self, input: AppInput) -> bool {
+        if let AppInput::WhatsApp(event) = &input {
+            self.publish_event(event);
+        }
+        match input {
+            AppInput::App(event) => match event {
+                // Needs to suspend the terminal, so it is handled by `run`.
+                AppEvent::EditWithExternalEditor => false,
+                AppEvent::KeyTimeout(id) => {
+                    if let Some(action) = self.kh.timed_out(id) {
+                        self.run_action(action);
                     }
-                    AppEvent::SetFileState(message_id, state) => {
+                    true
+                }
+                AppEvent::SetFilePreview(message_id, file_path, img) => {
+                    self.image_cache.insert(file_path.clone(), img);
+                    self.metadata
+                        .insert(message_id.clone(), Metadata::File(FileMeta::Loaded));
+
+                    trace!("Set file preview for message: {:?}", message_id);
+
+                    true
+                }
+                AppEvent::LoadFilePreview(message_id) => {
+                    if !matches!(
+                        self.metadata.get(&message_id),
+                        Some(Metadata::File(FileMeta::Loading))
+                    ) {
                         self.metadata
-                            .insert(message_id.clone(), Metadata::File(state));
+                            .insert(message_id.clone(), Metadata::File(FileMeta::Loading));
+
+                        let tx = self.tx.clone();
+                        let media_path = self.media_path.to_owned();
+                        let picker = Arc::clone(&self.picker);
+                        let (width, height) = (
+                            self.config.layout.image_width,
+                            self.config.layout.image_height,
+                        );
 
-                        true
-                    }
-                    AppEvent::DownloadFile(message_id, file_id) => {
-                        if matches!(
-                            self.metadata.get(&message_id),
-                            Some(Metadata::File(FileMeta::Downloading))
-                        ) {
-                            false
+                        let file = match &self.messages.get(&message_id).unwrap().message {
+                            wr::MessageContent::File(f) => Some(f.clone()),
+                            _ => None,
+                        };
+                        if let Some(file) = file {
+                            thread::spawn(move || {
+                                let binding = file.path.to_string();
+                                let path = std::path::Path::new(&binding);
+                                let image_res = image::ImageReader::open(media_path.join(path))
+                                    .unwrap()
+                                    .decode();
+
+                                if let Ok(image_src) = image_res {
+                                    let mut img =
+                                        picker.lock().unwrap().new_resize_protocol(image_src);
+                                    img.resize_encode(
+                                        &Resize::Scale(None),
+                                        Rect {
+                                            x: 0,
+                                            y: 0,
+                                            width,
+                                            height,
+                                        },
+                                    );
+
+                                    tx.send(AppInput::App(AppEvent::SetFilePreview(
+                                        message_id.clone(),
+                                        file.path.clone(),
+                                        img,
+                                    )))
+                                    .unwrap();
+                                } else {
+                                    tx.send(AppInput::App(AppEvent::SetFileState(
+                                        message_id.clone(),
+                                        FileMeta::LoadFailed,
+                                    )))
+                                    .unwrap();
+                                }
+                            });
                         } else {
-                            self.metadata
-                                .insert(message_id.clone(), Metadata::File(FileMeta::Downloading));
-                            download_tx.send((message_id, file_id)).unwrap();
-                            false
+                            error!("Expected a file message for preview");
                         }
                     }
-                    AppEvent::DownloadFileDone(message_id, state) => {
+                    false // We will redraw after the preview is loaded
+                }
+                AppEvent::SetFileState(message_id, state) => {
+                    self.metadata
+                        .insert(message_id.clone(), Metadata::File(state));
+
+                    true
+                }
+                AppEvent::DownloadFile(message_id, file_id) => {
+                    if matches!(
+                        self.metadata.get(&message_id),
+                        Some(Metadata::File(FileMeta::Downloading))
+                    ) {
+                        false
+                    } else if self.is_file_present(&message_id) {
+                        // Files downloaded before, or imported, need nothing from the servers.
                         self.metadata
-                            .insert(message_id.clone(), Metadata::File(state));
+                            .insert(message_id, Metadata::File(FileMeta::Downloaded));
                         true
+                    } else {
+                        self.metadata
+                            .insert(message_id.clone(), Metadata::File(FileMeta::Downloading));
+                        if let Some(download_tx) = &self.download_tx {
+                            download_tx.send((message_id, file_id)).unwrap();
+                        }
+                        false
                     }
-                },
-                Ok(AppInput::WhatsApp(event)) => match event {
-                    wr::Event::AppStateSyncComplete => {
-                        self.get_contacts();
-                        self.sort_chats();
+                }
+                AppEvent::DownloadFileDone(message_id, state) => {
+                    self.metadata
+                        .insert(message_id.clone(), Metadata::File(state));
+                    true
+                }
+                AppEvent::LoadOlderMessages(chat) => {
+                    self.load_older_messages(&chat);
+                    true
+                }
+                AppEvent::MessageSent(id, message_id) => {
+                    self.on_message_sent(&id, &message_id);
+                    true
+                }
+                AppEvent::SendFailed {
+                    id,
+                    attempts,
+                    error,
+                    retrying,
+                } => {
+                    self.on_send_failed(&id, attempts, &error, retrying);
+                    true
+                }
+                AppEvent::GroupInfoLoaded(jid, info) => {
+                    self.on_group_info_loaded(jid, info);
+                    true
+                }
+                AppEvent::UpdateParticipantsFailed(group, action, error) => {
+                    self.on_update_participants_failed(&group, action, &error);
+                    true
+                }
+            },
+            AppInput::WhatsApp(event) => match event {
+                wr::Event::AppStateSyncComplete => {
+                    self.get_contacts();
+                    self.sort_chats();
 
-                        true
-                    }
-                    wr::Event::SyncProgress(percent) => {
-                        self.history_sync_percent = Some(percent);
-                        true
-                    }
-                    wr::Event::Receipt {
-                        kind,
-                        chat,
-                        message_ids,
-                    } => {
-                        debug!(
-                            "Received receipt: {:?} for chat: {:?} with messages: {:?}",
-                            kind, chat, message_ids
-                        );
-                        for msg_id in message_ids {
-                            if let Some(message) = self.messages.get_mut(&msg_id) {
-                                message.info.read_by += 1;
-                                self.db_handler.add_message(message);
-                            }
-                        }
-                        true
+                    true
+                }
+                wr::Event::SyncProgress(percent) => {
+                    self.history_sync_percent = Some(percent);
+                    true
+                }
+                wr::Event::Receipt {
+                    kind,
+                    chat,
+                    sender,
+                    message_ids,
+                    timestamp,
+                } => {
+                    debug!(
+                        "Received receipt: {:?} for chat: {:?} from {:?} with messages: {:?}",
+                        kind, chat, sender, message_ids
+                    );
+                    for msg_id in message_ids {
+                        self.add_receipt(kind, &sender, &msg_id, timestamp);
                     }
-                },
-                Ok(AppInput::Message {
-                    message: msg,
-                    is_sync,
-                }) => {
-                    if !is_sync {
-                        self.handle_notification(&msg);
+                    // We read the chat on another device.
+                    if kind == wr::ReceiptKind::ReadSelf {
+                        self.set_unread(&chat, 0);
                     }
-
-                    self.db_handler.add_message(&msg);
-                    self.add_message(msg);
+                    true
+                }
+                wr::Event::Reaction(reaction) => {
+                    self.db_handler.add_reaction(&reaction);
+                    self.add_reaction(reaction);
+                    true
+                }
+                wr::Event::MessageEdit {
+                    chat,
+                    sender,
+                    message_id,
+                    text,
+                    ..
+                } => {
+                    self.edit_message(&chat, &sender, &message_id, text);
+                    true
+                }
+                wr::Event::MessageRevoke {
+                    chat, message_id, ..
+                } => {
+                    self.revoke_message(&chat, &message_id);
+                    true
+                }
+                wr::Event::GroupChange {
+                    chat,
+                    sender,
+                    change,
+                    timestamp,
+                } => {
+                    self.apply_group_change(&chat, &change);
+
+                    let self_jid = self.backend.self_jid();
+                    let message =
+                        group_change_message(chat, sender, change, timestamp, self_jid.as_ref());
+                    self.db_handler.add_message(&message);
+                    self.add_message(message);
 
                     let chat_jid = self.get_selected_chat();
-
                     self.sort_chats();
-
                     self.select_chat(chat_jid);
-                    !is_sync
-                }
-                Ok(AppInput::Terminal(event)) => {
-                    self.on_terminal_event(event);
                     true
                 }
-                Ok(AppInput::Draw) => true,
-                Err(_) => {
-                    error!("Failed to receive input from channel");
+                wr::Event::ChatRead { chat, read, .. } => {
+                    self.on_chat_read(&chat, read);
                     true
                 }
-            };
+            },
+            AppInput::Message {
+                message: msg,
+                is_sync,
+            } => {
+                // A re-delivered original must not undo an edit or revoke we already applied.
+                let known = self.load_message(&msg.info.id);
+                if known
+                    && self
+                        .messages
+                        .get(&msg.info.id)
+                        .is_some_and(|m| m.info.edit_state != wr::EditState::Original)
+                {
+                    return false;
+                }
 
-            if should_draw {
-                terminal.draw(|frame| ui::draw(frame, self)).unwrap();
-            }
+                if !is_sync {
+                    self.handle_notification(&msg);
+                }
+                if let Some(quote_id) = &msg.info.quote_id {
+                    self.load_message(quote_id);
+                }
 
-            if self.should_quit {
-                break;
+                self.db_handler.add_message(&msg);
+                if !is_sync {
+                    self.publish_message(&msg);
+                }
+                let count_unread = !is_sync && !known;
+                self.add_message(msg.clone());
+                if count_unread {
+                    self.count_new_message(&msg);
+                }
+
+                let chat_jid = self.get_selected_chat();
+
+                self.sort_chats();
+
+                self.select_chat(chat_jid);
+                !is_sync
+            }
+            AppInput::Terminal(event) => {
+                self.on_terminal_event(event);
+                true
+            }
+            AppInput::Rpc { client, request } => {
+                self.on_rpc_request(client, request);
+                true
             }
+            AppInput::Draw => true,
         }
-
-        self.stop_input_reader();
-        ratatui::restore();
-        wr::disconnect();
     }
 
     fn load_data_from_db(&mut self) {
         info!("Reading database");
-        for chat in self.db_handler.get_chats() {
+        // Messages are read later, a page at a time, once their chat is shown.
+        for chat in self.db_handler.get_chat_summaries() {
             self.chats.insert(chat.jid.clone(), chat);
         }
         for (jid, name) in self.db_handler.get_contacts() {
             self.contacts.insert(jid, name);
         }
+        info!("Finished reading database with {} chats", self.chats.len());
+    }
 
-        for message in self.db_handler.get_messages() {
-            self.add_message(message);
+    /// Whether `chat` may have messages in the database that were not read yet.
+    pub fn has_older_messages(&self, chat: &wr::JID) -> bool {
+        !self
+            .history
+            .get(chat)
+            .is_some_and(|history| history.complete)
+    }
+
+    /// Asks for the page of messages of `chat` before the oldest one read, once.
+    pub fn request_older_messages(&mut self, chat: &wr::JID) {
+        let history = self.history.entry(chat.clone()).or_default();
+        if history.complete || history.requested {
+            return;
         }
-        info!(
-            "Finished reading database with {} chats and {} messages",
-            self.chats.len(),
-            self.messages.len()
-        );
+        history.requested = true;
+        self.tx
+            .send(AppInput::App(AppEvent::LoadOlderMessages(chat.clone())))
+            .unwrap();
+    }
+
+    /// Reads the page of messages of `chat` before the oldest one read so far.
+    pub fn load_older_messages(&mut self, chat: &wr::JID) {
+        let history = self.history.entry(chat.clone()).or_default();
+        history.requested = false;
+        if history.complete {
+            return;
+        }
+        let page =
+            self.db_handler
+                .get_messages_before(chat, history.oldest.as_ref(), HISTORY_PAGE_SIZE);
+        history.complete = page.len() < HISTORY_PAGE_SIZE;
+        if let Some(oldest) = page.first() {
+            history.oldest = Some((oldest.info.timestamp, oldest.info.id.clone()));
+        }
+        debug!("Read {} older messages of {chat:?}", page.len());
+
+        let chat_messages = self.chat_messages.entry(chat.clone()).or_default();
+        let known = chat_messages.iter().cloned().collect::<HashSet<_>>();
+        let mut new = Vec::new();
+        for message in page {
+            let id = message.info.id.clone();
+            if !known.contains(&id) {
+                chat_messages.push(id.clone());
+            }
+            // What is in memory may be newer than what was saved so far.
+            if !self.messages.contains_key(&id) {
+                self.messages.insert(id.clone(), message);
+                new.push(id);
+            }
+        }
+        for id in new {
+            self.load_message_extras(&id);
+            // Quotes can reach further back than the page.
+            if let Some(quote_id) = self.messages[&id].info.quote_id.clone() {
+                self.load_message(&quote_id);
+            }
+        }
+        self.sort_chat_messages(chat.clone());
+    }
+
+    /// Makes sure the message with `id` is in memory, reading it from the database if
+    /// its page was not read yet. Returns whether it is known at all.
+    fn load_message(&mut self, id: &wr::MessageId) -> bool {
+        if self.messages.contains_key(id) {
+            return true;
+        }
+        let Some(message) = self.db_handler.get_message(id) else {
+            return false;
+        };
+        // It joins the list of its chat when its page is read.
+        self.messages.insert(id.clone(), message);
+        self.load_message_extras(id);
+        true
+    }
+
+    fn load_message_extras(&mut self, id: &wr::MessageId) {
+        for reaction in self.db_handler.get_reactions(id) {
+            self.add_reaction(reaction);
+        }
+        let receipts = self.db_handler.get_receipts(id);
+        if !receipts.is_empty() {
+            self.receipts.insert(id.clone(), receipts);
+        }
+    }
+
+    /// `saved` with the messages of `chat` from `since` up to `until` that may still be
+    /// on their way to the database, oldest first.
+    fn with_unsaved_messages(
+        &self,
+        chat: &wr::JID,
+        saved: Vec<wr::Message>,
+        since: Option<i64>,
+        until: Option<i64>,
+    ) -> Vec<wr::Message> {
+        let mut messages = saved
+            .into_iter()
+            .map(|message| (message.info.id.clone(), message))
+            .collect::<HashMap<_, _>>();
+        for id in self.chat_messages.get(chat).into_iter().flatten() {
+            if let Some(message) = self.messages.get(id)
+                && since.is_none_or(|since| message.info.timestamp >= since)
+                && until.is_none_or(|until| message.info.timestamp < until)
+            {
+                messages.insert(id.clone(), message.clone());
+            }
+        }
+        let mut messages = messages.into_values().collect::<Vec<_>>();
+        messages
+            .sort_by(|a, b| (a.info.timestamp, &a.info.id).cmp(&(b.info.timestamp, &b.info.id)));
+        messages
+    }
+
+    /// Whether the file of a message is already in the media directory.
+    fn is_file_present(&self, id: &wr::MessageId) -> bool {
+        self.messages.get(id).is_some_and(|message| {
+            matches!(&message.message, wr::MessageContent::File(file)
+                if self.media_path.join(file.path.as_ref()).exists())
+        })
     }
 
     /// Display name for a JID (chat or sender). Falls back to the JID string if not in contacts.
@@ -530,11 +1027,24 @@ impl App<'_> {
     }
 
     fn handle_notification(&self, message: &wr::Message) {
-        if message.info.is_from_me {
+        let options = &self.config.notifications;
+        if message.info.is_from_me || !options.enabled {
+            return;
+        }
+        if !options.groups && is_group(&message.info.chat) && !self.mentions_me(&message.info) {
             return;
         }
 
-        let chat_settings = wr::get_chat_settings(&message.info.chat);
+        let chat_settings = self
+            .backend
+            .get_chat_settings(&message.info.chat)
+            .unwrap_or_else(|err| {
+                warn!(
+                    "Failed to get chat settings for {:?}: {err}",
+                    message.info.chat
+                );
+                wr::ChatSettings::default()
+            });
         info!(
             "Chat settings for {:?}: {:?}",
             message.info.chat, chat_settings
@@ -544,17 +1054,22 @@ impl App<'_> {
                 .duration_since(UNIX_EPOCH)
                 .map(|duration| duration.as_secs() as i64)
                 .unwrap_or_default();
-            if chat_settings.muted_until > now {
+            // Being mentioned gets through even when the chat is muted.
+            if chat_settings.muted_until > now && !self.mentions_me(&message.info) {
                 return;
             }
         }
 
         let summary = self.contact_name(&message.info.sender);
         let body = match &message.message {
-            wr::MessageContent::Text(text) => text.to_string(),
+            _ if !options.show_text => "New message".to_string(),
+            wr::MessageContent::Text(text) => self.resolve_mentions(text, &message.info.mentions),
+            wr::MessageContent::GroupChange(change) => {
+                self.describe_group_change(&message.info, change)
+            }
             wr::MessageContent::File(file) => {
                 if let Some(caption) = &file.caption {
-                    caption.to_string()
+                    self.resolve_mentions(caption, &message.info.mentions)
                 } else {
                     match file.kind {
                         wr::FileKind::Image => "Sent an image".to_string(),
@@ -607,6 +1122,110 @@ impl App<'_> {
         state_changed.notify_all();
     }
 
+    /// One line describing a group change, like "Alice added Bob".
+    pub fn describe_group_change(
+        &self,
+        info: &wr::MessageInfo,
+        change: &wr::GroupChange,
+    ) -> String {
+        describe_group_change(info, change, self.backend.self_jid().as_ref(), &|jid| {
+            self.contact_name(jid)
+        })
+    }
+
+    /// Fetches the metadata of the selected group for the participants panel, unless it
+    /// is already known.
+    pub fn load_group_info(&mut self) {
+        if let Some(jid) = self.get_selected_chat()
+            && self.show_participants
+        {
+            self.fetch_group_info(&jid);
+        }
+    }
+
+    /// Asks the group info worker for the metadata of a group, unless it is already
+    /// known or on its way.
+    fn fetch_group_info(&mut self, jid: &wr::JID) {
+        if !is_group(jid)
+            || self.group_infos.contains_key(jid)
+            || self.group_infos_loading.contains(jid)
+        {
+            return;
+        }
+
+        if let Some(tx) = &self.group_tx {
+            tx.send(GroupJob::Info(jid.clone())).unwrap();
+            self.group_infos_loading.insert(jid.clone());
+        }
+    }
+
+    fn on_group_info_loaded(&mut self, jid: wr::JID, info: Result<wr::GroupInfo, wr::Error>) {
+        self.group_infos_loading.remove(&jid);
+        match info {
+            Ok(info) => {
+                self.group_infos.insert(jid.clone(), info);
+                // Receipts that came in before the participants were known.
+                let own_messages: Vec<wr::MessageId> = self
+                    .messages
+                    .values()
+                    .filter(|m| m.info.chat == jid && m.info.is_from_me)
+                    .map(|m| m.info.id.clone())
+                    .collect();
+                for message_id in own_messages {
+                    self.update_delivery(&message_id);
+                }
+            }
+            Err(err) => error!("Failed to get group info for {}: {err}", jid.0),
+        }
+    }
+
+    /// The participants of a group other than us, none while its metadata is unknown.
+    fn group_recipients(&self, group: &wr::JID) -> Vec<wr::JID> {
+        let self_jid = self.backend.self_jid();
+        self.group_infos
+            .get(group)
+            .map(|info| group_recipients(info, self_jid.as_ref()))
+            .unwrap_or_default()
+    }
+
+    /// Keeps the name and the cached metadata of a group up to date with a change.
+    fn apply_group_change(&mut self, chat: &wr::JID, change: &wr::GroupChange) {
+        if change.kind == wr::GroupChangeKind::Subject {
+            self.contacts.insert(chat.clone(), change.text.clone());
+            self.db_handler.add_contact(chat, &change.text);
+        }
+
+        let Some(info) = self.group_infos.get_mut(chat) else {
+            return;
+        };
+        match change.kind {
+            wr::GroupChangeKind::Join => {
+                for jid in &change.participants {
+                    if !info.participants.iter().any(|p| p.jid == *jid) {
+                        info.participants.push(wr::GroupParticipant {
+                            jid: jid.clone(),
+                            is_admin: false,
+                            is_super_admin: false,
+                        });
+                    }
+                }
+            }
+            wr::GroupChangeKind::Leave => {
+                info.participants
+                    .retain(|p| !change.participants.contains(&p.jid));
+            }
+            wr::GroupChangeKind::Promote | wr::GroupChangeKind::Demote => {
+                for participant in &mut info.participants {
+                    if change.participants.contains(&participant.jid) {
+                        participant.is_admin = change.kind == wr::GroupChangeKind::Promote;
+                    }
+                }
+            }
+            wr::GroupChangeKind::Subject => info.name = change.text.clone(),
+            wr::GroupChangeKind::Description => info.description = change.text.clone(),
+        }
+    }
+
     pub fn get_selected_chat(&self) -> Option<wr::JID> {
         self.chat_list_state.selected().map(|index| {
             if self.contact_search.input.is_empty() {
@@ -660,6 +1279,7 @@ impl App<'_> {
             Chat {
                 jid: chat_jid.clone(),
                 last_message_time: Some(message.info.timestamp),
+                unread: 0,
             },
             |chat| {
                 if Some(message.info.timestamp) > chat.last_message_time {
@@ -686,6 +1306,104 @@ impl App<'_> {
         }
     }
 
+    /// Replaces the text (or caption) of a message with the one from an edit.
+    fn edit_message(
+        &mut self,
+        chat: &wr::JID,
+        sender: &wr::JID,
+        message_id: &wr::MessageId,
+        text: Arc<str>,
+    ) {
+        self.load_message(message_id);
+        let Some(message) = self.messages.get_mut(message_id) else {
+            debug!("Received an edit for unknown message {message_id}");
+            return;
+        };
+        if apply_edit(message, chat, sender, text) {
+            self.db_handler.add_message(message);
+        }
+    }
+
+    /// Drops the content of a message deleted for everyone, keeping a tombstone in its place.
+    fn revoke_message(&mut self, chat: &wr::JID, message_id: &wr::MessageId) {
+        self.load_message(message_id);
+        let Some(message) = self.messages.get_mut(message_id) else {
+            debug!("Received a revoke for unknown message {message_id}");
+            return;
+        };
+        if apply_revoke(message, chat) {
+            self.db_handler.add_message(message);
+            self.metadata.remove(message_id);
+        }
+    }
+
+    /// Records `reaction`, replacing any earlier reaction from the same sender.
+    fn add_reaction(&mut self, reaction: wr::Reaction) {
+        let reactions = self
+            .reactions
+            .entry(reaction.message_id.clone())
+            .or_default();
+
+        if let Some(pos) = reactions.iter().position(|r| r.sender == reaction.sender) {
+            if reactions[pos].timestamp > reaction.timestamp {
+                return;
+            }
+            reactions.remove(pos);
+        }
+        if !reaction.emoji.is_empty() {
+            reactions.push(reaction);
+        }
+    }
+
+    /// Updates the delivery state of one of our messages from a receipt sent by `participant`.
+    fn add_receipt(
+        &mut self,
+        kind: wr::ReceiptKind,
+        participant: &wr::JID,
+        message_id: &wr::MessageId,
+        timestamp: i64,
+    ) {
+        self.load_message(message_id);
+        let Some(chat) = self.messages.get(message_id).map(|m| m.info.chat.clone()) else {
+            return;
+        };
+        self.fetch_group_info(&chat);
+        let recipients = is_group(&chat).then(|| self.group_recipients(&chat));
+
+        let message = self.messages.get_mut(message_id).unwrap();
+        let receipts = self.receipts.entry(message_id.clone()).or_default();
+        let delivery = message.info.delivery;
+        let receipt = apply_receipt(
+            message,
+            receipts,
+            kind,
+            participant,
+            timestamp,
+            recipients.as_deref(),
+        );
+        if let Some(receipt) = receipt {
+            self.db_handler.add_receipt(message_id, &receipt);
+        }
+        if message.info.delivery != delivery {
+            self.db_handler.add_message(message);
+        }
+    }
+
+    /// Moves the delivery state of one of our messages forward to what its receipts show.
+    fn update_delivery(&mut self, message_id: &wr::MessageId) {
+        let (Some(message), Some(receipts)) =
+            (self.messages.get(message_id), self.receipts.get(message_id))
+        else {
+            return;
+        };
+        let recipients =
+            is_group(&message.info.chat).then(|| self.group_recipients(&message.info.chat));
+        let message = self.messages.get_mut(message_id).unwrap();
+        if advance_delivery(message, receipts, recipients.as_deref()) {
+            self.db_handler.add_message(message);
+        }
+    }
+
     fn add_or_update_chat<F: FnOnce(&mut Chat)>(&mut self, chat: Chat, callback: F) {
         if let Some(existing_chat) = self.chats.get_mut(&chat.jid) {
             callback(existing_chat);
@@ -697,7 +1415,14 @@ impl App<'_> {
     }
 
     fn get_contacts(&mut self) {
-        for (jid, name) in wr::get_contacts() {
+        let contacts = match self.backend.get_contacts() {
+            Ok(contacts) => contacts,
+            Err(err) => {
+                error!("Failed to get contacts: {err}");
+                return;
+            }
+        };
+        for (jid, name) in contacts {
             self.contacts.insert(jid.clone(), name.clone());
             self.db_handler.add_contact(&jid, name.as_ref());
         }
@@ -725,3 +1450,271 @@ impl App<'_> {
         }
     }
 }
+
+pub(crate) fn is_group(jid: &wr::JID) -> bool {
+    jid.0.ends_with("@g.us")
+}
+
+/// One line describing a group change, like "Alice added Bob", with the names
+/// `contact_name` gives. `self_jid` is called "you".
+pub fn describe_group_change(
+    info: &wr::MessageInfo,
+    change: &wr::GroupChange,
+    self_jid: Option<&wr::JID>,
+    contact_name: &dyn Fn(&wr::JID) -> Arc<str>,
+) -> String {
+    let name = |jid: &wr::JID| {
+        if Some(jid) == self_jid {
+            "you".into()
+        } else {
+            contact_name(jid)
+        }
+    };
+    // Changes not made by a participant are stored with the group as the sender.
+    let actor: Option<Arc<str>> = if info.is_from_me {
+        Some("You".into())
+    } else if info.sender != info.chat {
+        Some(contact_name(&info.sender))
+    } else {
+        None
+    };
+    let names = change
+        .participants
+        .iter()
+        .map(name)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let by_themselves = change.participants == [info.sender.clone()];
+
+    match (change.kind, actor) {
+        (wr::GroupChangeKind::Join, Some(actor)) if !by_themselves => {
+            format!("{actor} added {names}")
+        }
+        (wr::GroupChangeKind::Join, _) => format!("{names} joined"),
+        (wr::GroupChangeKind::Leave, Some(actor)) if !by_themselves => {
+            format!("{actor} removed {names}")
+        }
+        (wr::GroupChangeKind::Leave, _) => format!("{names} left"),
+        (wr::GroupChangeKind::Promote, Some(actor)) => format!("{actor} made {names} admin"),
+        (wr::GroupChangeKind::Promote, None) => format!("{names} became admin"),
+        (wr::GroupChangeKind::Demote, Some(actor)) => {
+            format!("{actor} dismissed {names} as admin")
+        }
+        (wr::GroupChangeKind::Demote, None) => format!("{names} is no longer admin"),
+        (wr::GroupChangeKind::Subject, Some(actor)) => {
+            format!("{actor} changed the subject to \"{}\"", change.text)
+        }
+        (wr::GroupChangeKind::Subject, None) => {
+            format!("The subject was changed to \"{}\"", change.text)
+        }
+        (wr::GroupChangeKind::Description, Some(actor)) => {
+            format!("{actor} changed the group description")
+        }
+        (wr::GroupChangeKind::Description, None) => "The group description was changed".to_string(),
+    }
+}
+
+/// The user part of a JID, ignoring the device and server.
+pub(crate) fn jid_user(jid: &wr::JID) -> &str {
+    jid.0.split(['@', ':']).next().unwrap_or_default()
+}
+
+/// The participants of a group other than us, who our messages are sent to.
+pub(crate) fn group_recipients(info: &wr::GroupInfo, self_jid: Option<&wr::JID>) -> Vec<wr::JID> {
+    info.participants
+        .iter()
+        .map(|p| &p.jid)
+        .filter(|jid| self_jid.is_none_or(|me| jid_user(me) != jid_user(jid)))
+        .cloned()
+        .collect()
+}
+
+/// The message a group change is shown as in the chat. Several changes of a kind can
+/// come in the same second, so what changed is in the id too.
+pub(crate) fn group_change_message(
+    chat: wr::JID,
+    sender: Option<wr::JID>,
+    change: wr::GroupChange,
+    timestamp: i64,
+    self_jid: Option<&wr::JID>,
+) -> wr::Message {
+    let what = change
+        .participants
+        .iter()
+        .map(|jid| &*jid.0)
+        .chain([&*change.text])
+        .collect::<Vec<_>>();
+    let id = format!(
+        "{}-{}-{}-{:016x}",
+        chat.0,
+        timestamp,
+        change.kind as u8,
+        stable_hash(&what)
+    );
+    wr::Message {
+        info: wr::MessageInfo {
+            id: id.into(),
+            is_from_me: sender.is_some() && sender.as_ref() == self_jid,
+            sender: sender.unwrap_or_else(|| chat.clone()),
+            chat,
+            timestamp,
+            quote_id: None,
+            delivery: wr::DeliveryState::default(),
+            edit_state: wr::EditState::Original,
+            mentions: Vec::new(),
+        },
+        message: wr::MessageContent::GroupChange(change),
+    }
+}
+
+/// Replaces the text (or caption) of `message` with the one from an edit `sender` made
+/// in `chat`. Returns whether the edit was valid and applied.
+pub(crate) fn apply_edit(
+    message: &mut wr::Message,
+    chat: &wr::JID,
+    sender: &wr::JID,
+    text: Arc<str>,
+) -> bool {
+    let id = &message.info.id;
+    if message.info.chat != *chat
+        || jid_user(&message.info.sender) != jid_user(sender)
+        || message.info.edit_state == wr::EditState::Revoked
+    {
+        debug!("Ignoring invalid edit for message {id}");
+        return false;
+    }
+
+    match &mut message.message {
+        wr::MessageContent::Text(body) => *body = text,
+        wr::MessageContent::File(file) => {
+            file.caption = if text.is_empty() { None } else { Some(text) }
+        }
+        wr::MessageContent::GroupChange(_) => {
+            debug!("Ignoring edit of group change {id}");
+            return false;
+        }
+    }
+    message.info.edit_state = wr::EditState::Edited;
+    true
+}
+
+/// Drops the content of `message`, deleted for everyone in `chat`, keeping a tombstone
+/// in its place. Returns whether the revoke was valid and applied.
+pub(crate) fn apply_revoke(message: &mut wr::Message, chat: &wr::JID) -> bool {
+    if message.info.chat != *chat {
+        debug!("Ignoring invalid revoke for message {}", message.info.id);
+        return false;
+    }
+    message.message = wr::MessageContent::Text("".into());
+    message.info.edit_state = wr::EditState::Revoked;
+    true
+}
+
+/// Applies a receipt `participant` sent for `message` to it and to its `receipts`, with
+/// `recipients` as for `delivery_from_receipts`. Only receipts for our own messages
+/// count: the participant's receipt is returned to be saved, and the delivery state of
+/// the message moves forward, or to failed.
+pub(crate) fn apply_receipt(
+    message: &mut wr::Message,
+    receipts: &mut Vec<ParticipantReceipt>,
+    kind: wr::ReceiptKind,
+    participant: &wr::JID,
+    timestamp: i64,
+    recipients: Option<&[wr::JID]>,
+) -> Option<ParticipantReceipt> {
+    // Receipts for incoming messages only tell us what we read elsewhere.
+    if !message.info.is_from_me {
+        return None;
+    }
+    match kind {
+        wr::ReceiptKind::ReadSelf | wr::ReceiptKind::PlayedSelf => None,
+        wr::ReceiptKind::ServerError => {
+            message.info.delivery = wr::DeliveryState::Failed;
+            None
+        }
+        wr::ReceiptKind::Delivered | wr::ReceiptKind::Read | wr::ReceiptKind::Played => {
+            let receipt = record_receipt(receipts, kind, participant, timestamp).clone();
+            advance_delivery(message, receipts, recipients);
+            Some(receipt)
+        }
+    }
+}
+
+/// Moves the delivery state of one of our messages forward to what its receipts show.
+/// Returns whether it moved.
+pub(crate) fn advance_delivery(
+    message: &mut wr::Message,
+    receipts: &[ParticipantReceipt],
+    recipients: Option<&[wr::JID]>,
+) -> bool {
+    let delivery = delivery_from_receipts(receipts, recipients);
+    if delivery > message.info.delivery {
+        message.info.delivery = delivery;
+        true
+    } else {
+        false
+    }
+}
+
+/// Merges a delivery, read or played receipt from `participant` into the receipts of
+/// a message, and returns the participant's receipt.
+fn record_receipt<'a>(
+    receipts: &'a mut Vec<ParticipantReceipt>,
+    kind: wr::ReceiptKind,
+    participant: &wr::JID,
+    timestamp: i64,
+) -> &'a ParticipantReceipt {
+    let index = match receipts.iter().position(|r| r.participant == *participant) {
+        Some(index) => index,
+        None => {
+            receipts.push(ParticipantReceipt {
+                participant: participant.clone(),
+                delivered_at: None,
+                read_at: None,
+                played_at: None,
+            });
+            receipts.len() - 1
+        }
+    };
+    let receipt = &mut receipts[index];
+
+    // Reading implies delivery and playing implies reading.
+    receipt.delivered_at.get_or_insert(timestamp);
+    if matches!(kind, wr::ReceiptKind::Read | wr::ReceiptKind::Played) {
+        receipt.read_at.get_or_insert(timestamp);
+    }
+    if kind == wr::ReceiptKind::Played {
+        receipt.played_at.get_or_insert(timestamp);
+    }
+    receipt
+}
+
+/// A message counts as read (or played) once everyone it was sent to did so. In a
+/// private chat that is just the other person; in a group it is every other
+/// participant in `recipients`, and with none known it stays delivered.
+fn delivery_from_receipts(
+    receipts: &[ParticipantReceipt],
+    recipients: Option<&[wr::JID]>,
+) -> wr::DeliveryState {
+    let everyone = |done: fn(&ParticipantReceipt) -> bool| match recipients {
+        None => receipts.iter().all(done),
+        Some(recipients) => {
+            !recipients.is_empty()
+                && recipients.iter().all(|jid| {
+                    receipts
+                        .iter()
+                        .any(|r| jid_user(&r.participant) == jid_user(jid) && done(r))
+                })
+        }
+    };
+
+    if receipts.is_empty() {
+        wr::DeliveryState::Sent
+    } else if everyone(|r| r.played_at.is_some()) {
+        wr::DeliveryState::Played
+    } else if everyone(|r| r.read_at.is_some()) {
+        wr::DeliveryState::Read
+    } else {
+        wr::DeliveryState::Delivered
+    }
+}
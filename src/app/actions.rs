@@ -0,0 +1,515 @@
+//! What each `Action` does. Keys and the command line both run actions through
+//! `App::perform`, so an operation behaves the same however it is reached.
+
+use std::time::Duration;
+
+use log::{debug, error};
+use rfd::FileDialog;
+use whatsrust as wr;
+
+use crate::app::chat_export::ExportField;
+use crate::app::events::{AppEvent, AppInput};
+use crate::app::{App, SelectedWidget};
+use crate::key_handler::{Action, key_notation};
+use crate::vim;
+
+/// The actions whose keys work wherever the focus is.
+pub const GLOBAL_ACTIONS: &[Action] = &[
+    Action::Quit,
+    Action::ToggleLogs,
+    Action::Search,
+    Action::SwitchProfile,
+    Action::ToggleParticipants,
+];
+
+/// The actions moving the focus away from each panel. They work even while something is
+/// typed into the panel.
+pub const CHAT_LIST_FOCUS: &[Action] = &[Action::FocusRight];
+pub const MESSAGE_LIST_FOCUS: &[Action] = &[Action::FocusDown, Action::FocusLeft];
+pub const INPUT_FOCUS: &[Action] = &[Action::FocusUp, Action::FocusLeft];
+
+pub const CHAT_LIST_ACTIONS: &[Action] = &[
+    Action::CommandLine,
+    Action::NextChat,
+    Action::PreviousChat,
+    Action::OpenChat,
+    Action::FilterChats,
+    Action::ExportChat,
+    Action::Mute,
+    Action::Unmute,
+];
+
+/// The popups listing profiles and export formats move through them with the keys
+/// that move through the chats.
+pub const PROFILE_SWITCHER_ACTIONS: &[Action] =
+    &[Action::NextChat, Action::PreviousChat, Action::NewProfile];
+pub const CHAT_EXPORT_ACTIONS: &[Action] = &[Action::NextChat, Action::PreviousChat];
+
+/// What is typed goes into the command line, so its actions need keys of their own.
+pub const COMMAND_LINE_ACTIONS: &[Action] = &[Action::NextCandidate, Action::PreviousCandidate];
+
+/// Resending and discarding come first: they share their keys with deleting, which
+/// they take over for messages still in the outbox.
+pub const MESSAGE_LIST_ACTIONS: &[Action] = &[
+    Action::CommandLine,
+    Action::Resend,
+    Action::Discard,
+    Action::ScrollUp,
+    Action::ScrollDown,
+    Action::NextMessage,
+    Action::PreviousMessage,
+    Action::FirstMessage,
+    Action::LastMessage,
+    Action::SearchInChat,
+    Action::NextMatch,
+    Action::PreviousMatch,
+    Action::ClearSelection,
+    Action::OpenMessage,
+    Action::Reply,
+    Action::EditMessage,
+    Action::DeleteMessage,
+    Action::React,
+    Action::ViewMessage,
+    Action::MessageInfo,
+    Action::CopyMessage,
+    Action::GoToQuote,
+];
+
+/// The actions of the input in every vim mode.
+pub const INPUT_ACTIONS: &[Action] = &[Action::Send, Action::ExternalEditor];
+
+/// The actions of the input in normal mode, where keys are not typed into it.
+pub const INPUT_NORMAL_ACTIONS: &[Action] = &[
+    Action::CommandLine,
+    Action::CancelReply,
+    Action::CancelEdit,
+    Action::RemoveAttachment,
+    Action::AttachImage,
+    Action::AttachDocument,
+    Action::PasteClipboard,
+];
+
+/// The actions whose keys are active at the same time, for each place the focus can be.
+/// Keys bound to two actions of one of them only ever run one.
+pub fn key_contexts() -> Vec<Vec<Action>> {
+    let message_list = |without: &[Action]| {
+        let actions = MESSAGE_LIST_ACTIONS
+            .iter()
+            .filter(|action| !without.contains(action))
+            .copied()
+            .collect::<Vec<_>>();
+        [GLOBAL_ACTIONS, MESSAGE_LIST_FOCUS, &actions].concat()
+    };
+    vec![
+        [GLOBAL_ACTIONS, CHAT_LIST_FOCUS, CHAT_LIST_ACTIONS].concat(),
+        message_list(&[Action::Resend, Action::Discard]),
+        // In the outbox resending and discarding take over the keys of deleting.
+        message_list(&[Action::DeleteMessage]),
+        [
+            GLOBAL_ACTIONS,
+            INPUT_FOCUS,
+            INPUT_ACTIONS,
+            INPUT_NORMAL_ACTIONS,
+        ]
+        .concat(),
+        [GLOBAL_ACTIONS, PROFILE_SWITCHER_ACTIONS].concat(),
+        [GLOBAL_ACTIONS, CHAT_EXPORT_ACTIONS].concat(),
+        [GLOBAL_ACTIONS, COMMAND_LINE_ACTIONS].concat(),
+    ]
+}
+
+impl App<'_> {
+    /// Runs the first of `actions` whose keys were pressed. Whether one was.
+    pub(super) fn run_pressed(&mut self, actions: &[Action]) -> bool {
+        match self.kh.pressed_action(actions) {
+            Some(action) => {
+                self.run_action(action);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs `action` for a key.
+    pub(super) fn run_action(&mut self, action: Action) {
+        // A key that does not apply where it is pressed does nothing.
+        if let Err(err) = self.perform(action) {
+            debug!("{}: {err}", action.name());
+        }
+    }
+
+    /// The actions of the message list. Resending and discarding only apply to messages
+    /// still in the outbox.
+    pub(super) fn message_list_actions(&self) -> Vec<Action> {
+        let in_outbox = self
+            .message_list_state
+            .get_selected_message()
+            .is_some_and(|msg_id| self.outbox.contains_key(&msg_id));
+        MESSAGE_LIST_ACTIONS
+            .iter()
+            .copied()
+            .filter(|action| in_outbox || !matches!(action, Action::Resend | Action::Discard))
+            .collect()
+    }
+
+    /// The actions whose keys work where the focus is now, in the order they are tried.
+    pub fn active_actions(&self) -> Vec<Action> {
+        let mut actions = GLOBAL_ACTIONS.to_vec();
+        match self.selected_widget {
+            SelectedWidget::ChatList => {
+                actions.extend(CHAT_LIST_FOCUS);
+                if !self.contact_search_active {
+                    actions.extend(CHAT_LIST_ACTIONS);
+                }
+            }
+            SelectedWidget::MessageList if !self.chat_search.active => {
+                actions.extend(MESSAGE_LIST_FOCUS);
+                actions.extend(self.message_list_actions());
+            }
+            SelectedWidget::Input => {
+                actions.extend(INPUT_FOCUS);
+                actions.extend(INPUT_ACTIONS);
+                if self.vim.mode == vim::Mode::Normal {
+                    actions.extend(INPUT_NORMAL_ACTIONS);
+                }
+            }
+            SelectedWidget::Profiles if self.profile_switcher.new_name.is_none() => {
+                actions.extend(PROFILE_SWITCHER_ACTIONS);
+            }
+            SelectedWidget::Export if self.chat_export.focus == ExportField::Format => {
+                actions.extend(CHAT_EXPORT_ACTIONS);
+            }
+            SelectedWidget::CommandLine => actions.extend(COMMAND_LINE_ACTIONS),
+            _ => {}
+        }
+        actions
+    }
+
+    /// What the keys pressed so far can go on to, as the rest of the keys and what they
+    /// do.
+    pub fn key_continuations(&self) -> Vec<(String, &'static str)> {
+        let pressed = &self.kh.key_buffer;
+        if pressed.is_empty() {
+            return Vec::new();
+        }
+        let mut continuations = Vec::new();
+        for action in self.active_actions() {
+            for keys in self.kh.keymap.keys(action) {
+                if keys.len() > pressed.len() && keys.starts_with(pressed) {
+                    let continuation = (key_notation(&keys[pressed.len()..]), action.description());
+                    if !continuations.contains(&continuation) {
+                        continuations.push(continuation);
+                    }
+                }
+            }
+        }
+        continuations
+    }
+
+    /// Runs `action`, or says why it cannot run now.
+    pub fn perform(&mut self, action: Action) -> Result<(), String> {
+        match action {
+            Action::Quit => {
+                self.db_handler.stop();
+                self.should_quit = true;
+            }
+            Action::ToggleLogs => self.show_logs = !self.show_logs,
+            Action::ToggleParticipants => {
+                self.show_participants = !self.show_participants;
+                self.load_group_info();
+            }
+            Action::Search => self.open_search(),
+            Action::SwitchProfile => self.open_profile_switcher(),
+            Action::CommandLine => self.open_command_line(),
+            Action::FocusLeft => self.selected_widget = SelectedWidget::ChatList,
+            Action::FocusRight => {
+                self.selected_widget = SelectedWidget::MessageList;
+                self.input_widget.select_all();
+            }
+            Action::FocusUp => self.selected_widget = SelectedWidget::MessageList,
+            Action::FocusDown => self.selected_widget = SelectedWidget::Input,
+
+            Action::NextChat => self.move_selection(true),
+            Action::PreviousChat => self.move_selection(false),
+            Action::OpenChat => {
+                self.require_chat()?;
+                self.message_list_state.reset();
+                self.selected_widget = SelectedWidget::Input;
+            }
+            Action::FilterChats => {
+                self.contact_search_active = true;
+                self.selected_widget = SelectedWidget::ChatList;
+            }
+            Action::ExportChat => {
+                self.require_chat()?;
+                self.open_chat_export();
+            }
+            Action::Mute => self.mute_selected_chat(None)?,
+            Action::Unmute => {
+                let chat = self.require_chat()?;
+                self.backend
+                    .unmute_chat(&chat)
+                    .map_err(|err| format!("Failed to unmute {}: {err}", chat.0))?;
+            }
+            Action::NewProfile => self.start_new_profile(),
+            Action::NextCandidate => self.move_candidate_selection(true)?,
+            Action::PreviousCandidate => self.move_candidate_selection(false)?,
+
+            Action::ScrollUp => {
+                self.message_list_state.offset = self.message_list_state.offset.saturating_sub(1);
+            }
+            Action::ScrollDown => {
+                self.message_list_state.offset = self.message_list_state.offset.saturating_add(1);
+            }
+            Action::PreviousMessage => self.message_list_state.select_next(),
+            Action::NextMessage => self.message_list_state.select_previous(),
+            Action::LastMessage => self.message_list_state.select_first(),
+            Action::FirstMessage => self.message_list_state.select_last(),
+            Action::SearchInChat => {
+                self.require_chat()?;
+                self.open_chat_search();
+                self.selected_widget = SelectedWidget::MessageList;
+            }
+            Action::NextMatch => self.select_next_match(true),
+            Action::PreviousMatch => self.select_next_match(false),
+            Action::ClearSelection => {
+                self.message_list_state.reset();
+                self.chat_search.clear();
+            }
+            Action::OpenMessage => {
+                let msg = self.require_message()?;
+                if let wr::MessageContent::File(content) = &msg.message {
+                    let path = self.media_path.join(content.path.as_ref());
+                    if let Err(e) = open::that(&path) {
+                        error!("Failed to open file {}: {:?}", content.path, e);
+                    }
+                }
+            }
+            Action::Reply => {
+                self.quoting_message = Some(self.require_message()?);
+                self.selected_widget = SelectedWidget::Input;
+            }
+            Action::EditMessage => {
+                let msg = self.require_message()?;
+                let wr::MessageContent::Text(text) = &msg.message else {
+                    return Err("Only text can be edited".to_string());
+                };
+                self.require_own_sent(&msg)?;
+                self.input_widget.select_all();
+                self.input_widget.delete_next_char();
+                self.input_widget.insert_str(text);
+                self.quoting_message = None;
+                self.attached_file = None;
+                self.editing_message = Some(msg.clone());
+                self.selected_widget = SelectedWidget::Input;
+            }
+            Action::DeleteMessage => {
+                let msg = self.require_message()?;
+                // A message still in the outbox only exists here, deleting it discards it.
+                if self.outbox.contains_key(&msg.info.id) {
+                    self.discard(&msg.info.id);
+                    return Ok(());
+                }
+                self.require_own_sent(&msg)?;
+                if let Err(err) = self.backend.revoke_message(&msg) {
+                    error!("Failed to delete {}: {err}", msg.info.id);
+                }
+            }
+            Action::Resend => {
+                let id = self.require_outbox_message()?;
+                self.resend(&id);
+            }
+            Action::Discard => {
+                let id = self.require_outbox_message()?;
+                self.discard(&id);
+            }
+            Action::React => {
+                self.require_message()?;
+                self.reaction_picker_index = 0;
+                self.selected_widget = SelectedWidget::ReactionPicker;
+            }
+            Action::ViewMessage => {
+                self.require_message()?;
+                self.selected_widget = SelectedWidget::MessageView;
+            }
+            Action::MessageInfo => {
+                self.require_message()?;
+                self.selected_widget = SelectedWidget::MessageInfo;
+            }
+            Action::CopyMessage => {
+                let msg = self.require_message()?;
+                let text = match &msg.message {
+                    wr::MessageContent::Text(text) => text.to_string(),
+                    wr::MessageContent::File(content) => {
+                        let path = self.media_path.join(content.path.as_ref());
+                        path.to_string_lossy().into_owned()
+                    }
+                    wr::MessageContent::GroupChange(_) => return Ok(()),
+                };
+                if let Err(e) = self.clipboard.set_text(text) {
+                    error!("Failed to copy to clipboard: {:?}", e);
+                }
+            }
+            Action::GoToQuote => {
+                let msg = self.require_message()?;
+                let quote_id = msg
+                    .info
+                    .quote_id
+                    .ok_or("The message does not quote another")?;
+                self.jump_to_message(&msg.info.chat, &quote_id);
+            }
+
+            Action::Send => self.send_input(),
+            Action::ExternalEditor => self
+                .tx
+                .send(AppInput::App(AppEvent::EditWithExternalEditor))
+                .unwrap(),
+            Action::CancelReply => self.quoting_message = None,
+            Action::CancelEdit => self.editing_message = None,
+            Action::RemoveAttachment => self.attached_file = None,
+            Action::AttachImage => {
+                if let Some(path) = FileDialog::new().pick_file() {
+                    self.attached_file = Some((path.to_str().unwrap().into(), wr::FileKind::Image));
+                }
+            }
+            Action::AttachDocument => {
+                if let Some(path) = FileDialog::new().pick_file() {
+                    self.attached_file =
+                        Some((path.to_str().unwrap().into(), wr::FileKind::Document));
+                }
+            }
+            Action::PasteClipboard => {
+                if let Ok(text) = self.clipboard.get_text() {
+                    self.input_widget.insert_str(&text);
+                } else {
+                    error!("Failed to get text from clipboard");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Mutes the selected chat for `duration`, or for good.
+    pub(super) fn mute_selected_chat(&mut self, duration: Option<Duration>) -> Result<(), String> {
+        let chat = self.require_chat()?;
+        self.backend
+            .mute_chat(&chat, duration)
+            .map_err(|err| format!("Failed to mute {}: {err}", chat.0))
+    }
+
+    /// Sends what is in the input to the selected chat, or saves the edit being made.
+    fn send_input(&mut self) {
+        if let Some(msg) = self.editing_message.take() {
+            let text = self.input_widget.lines().join("\n");
+            match self.backend.edit_message(&msg, &text) {
+                Ok(()) => {
+                    self.input_widget.select_all();
+                    self.input_widget.delete_next_char();
+                }
+                Err(err) => {
+                    // Keep the new text around so the edit can be retried.
+                    error!("Failed to edit {}: {err}", msg.info.id);
+                    self.editing_message = Some(msg);
+                }
+            }
+        } else if let Some(c) = self.get_selected_chat() {
+            let (text, mentions) = self.take_mentions(&self.input_widget.lines().join("\n"));
+            let msg = if let Some((path, typ)) = &self.attached_file {
+                wr::MessageContent::File(wr::FileContent {
+                    kind: typ.clone(),
+                    path: path.clone(),
+                    file_id: "".into(),
+                    caption: Some(text.into()),
+                })
+            } else {
+                wr::MessageContent::Text(text.into())
+            };
+
+            self.queue_message(c, msg, mentions);
+
+            self.input_widget.select_all();
+            self.input_widget.delete_next_char();
+            self.quoting_message = None;
+            self.attached_file = None;
+        }
+    }
+
+    /// Moves through the list of the popup in focus, or through the chats.
+    fn move_selection(&mut self, next: bool) {
+        let by = if next { 1 } else { -1 };
+        match self.selected_widget {
+            SelectedWidget::Profiles => self.profile_switcher.move_selection(by),
+            SelectedWidget::Export => self.chat_export.move_format(by),
+            _ => self.move_chat_selection(next),
+        }
+    }
+
+    fn move_chat_selection(&mut self, next: bool) {
+        if next {
+            self.chat_list_state.select_next();
+        } else {
+            self.chat_list_state.select_previous();
+        }
+
+        // Bound the selected index to the number of chats
+        let len = self.sorted_chats.len();
+        if len == 0 {
+            self.chat_list_state.select(None);
+            return;
+        } else if let Some(selected) = self.chat_list_state.selected()
+            && selected >= len
+        {
+            self.chat_list_state.select(Some(len.saturating_sub(1)));
+        }
+
+        self.open_selected_chat();
+    }
+
+    /// Shows the messages of the chat just selected in the chat list.
+    pub(super) fn open_selected_chat(&mut self) {
+        self.sort_chat_messages(self.get_selected_chat().unwrap());
+        self.message_list_state.reset();
+        self.load_group_info();
+        self.new_messages = None;
+        self.read_selected_chat();
+    }
+
+    pub(super) fn require_chat(&self) -> Result<wr::JID, String> {
+        self.get_selected_chat()
+            .ok_or_else(|| "No chat is selected".to_string())
+    }
+
+    /// The selected message. Group changes are not real messages, there is nothing to do
+    /// with them.
+    fn require_message(&self) -> Result<wr::Message, String> {
+        self.message_list_state
+            .get_selected_message()
+            .and_then(|id| self.messages.get(&id))
+            .filter(|msg| !matches!(msg.message, wr::MessageContent::GroupChange(_)))
+            .cloned()
+            .ok_or_else(|| "No message is selected".to_string())
+    }
+
+    /// Messages still in the outbox only exist locally, they can be resent or discarded
+    /// once they failed but not edited or deleted for everyone.
+    fn require_own_sent(&self, msg: &wr::Message) -> Result<(), String> {
+        if !msg.info.is_from_me {
+            Err("The message was not sent by you".to_string())
+        } else if self.outbox.contains_key(&msg.info.id) {
+            Err("The message was not sent yet".to_string())
+        } else if msg.info.edit_state == wr::EditState::Revoked {
+            Err("The message was deleted".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn require_outbox_message(&self) -> Result<wr::MessageId, String> {
+        let msg = self.require_message()?;
+        if self.outbox.contains_key(&msg.info.id) {
+            Ok(msg.info.id)
+        } else {
+            Err("The message is not waiting to be sent".to_string())
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use ratatui::crossterm::event::KeyCode;
+use strum::IntoEnumIterator;
+
+use crate::app::actions::CHAT_EXPORT_ACTIONS;
+use crate::app::{App, SelectedWidget};
+use crate::export::{ExportFormat, ExportOptions, export_messages};
+use crate::key_handler::Key;
+use crate::ui::text_input::TextInput;
+
+/// The part of the export popup that takes the keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportField {
+    Format,
+    Since,
+    Until,
+}
+
+/// The export of the selected chat to a file.
+pub struct ChatExport {
+    pub format: ExportFormat,
+    pub focus: ExportField,
+    /// The first and last day to export, both optional.
+    pub since: TextInput,
+    pub until: TextInput,
+    /// Where the files are written.
+    pub dir: PathBuf,
+    /// What came of the last export: where it went, or why it failed.
+    pub status: Option<Result<String, String>>,
+}
+
+impl ChatExport {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            format: ExportFormat::Markdown,
+            focus: ExportField::Format,
+            since: TextInput::new(),
+            until: TextInput::new(),
+            dir,
+            status: None,
+        }
+    }
+
+    fn focused_input(&mut self) -> Option<&mut TextInput> {
+        match self.focus {
+            ExportField::Format => None,
+            ExportField::Since => Some(&mut self.since),
+            ExportField::Until => Some(&mut self.until),
+        }
+    }
+
+    pub(super) fn move_format(&mut self, by: isize) {
+        let formats = ExportFormat::iter().collect::<Vec<_>>();
+        let index = formats.iter().position(|f| *f == self.format).unwrap_or(0);
+        let index = index.saturating_add_signed(by).min(formats.len() - 1);
+        self.format = formats[index];
+    }
+}
+
+impl App<'_> {
+    pub fn open_chat_export(&mut self) {
+        if self.get_selected_chat().is_some() {
+            self.chat_export.focus = ExportField::Format;
+            self.chat_export.status = None;
+            self.selected_widget = SelectedWidget::Export;
+        }
+    }
+
+    pub(super) fn chat_export_on_event(&mut self, key: &Key) {
+        if self.kh.kp(&[Key::k(KeyCode::Esc)]) {
+            self.selected_widget = SelectedWidget::ChatList;
+        } else if self.kh.kp(&[Key::k(KeyCode::Enter)]) {
+            self.chat_export.status = Some(self.export_selected_chat());
+        } else if self.kh.kp(&[Key::k(KeyCode::Tab)]) {
+            self.chat_export.focus = match self.chat_export.focus {
+                ExportField::Format => ExportField::Since,
+                ExportField::Since => ExportField::Until,
+                ExportField::Until => ExportField::Format,
+            };
+        } else if let Some(input) = self.chat_export.focused_input() {
+            match key.code {
+                KeyCode::Char(c) => input.enter_char(c),
+                KeyCode::Backspace => input.delete_char(),
+                KeyCode::Left => input.move_cursor_left(),
+                KeyCode::Right => input.move_cursor_right(),
+                _ => {}
+            }
+        } else if self.kh.kp(&[Key::k(KeyCode::Down)]) {
+            self.chat_export.move_format(1);
+        } else if self.kh.kp(&[Key::k(KeyCode::Up)]) {
+            self.chat_export.move_format(-1);
+        } else if !self.run_pressed(CHAT_EXPORT_ACTIONS)
+            && let KeyCode::Char(c) = key.code
+            && let Some(index) = c.to_digit(10)
+            && let Some(format) = ExportFormat::iter().nth((index as usize).wrapping_sub(1))
+        {
+            self.chat_export.format = format;
+        }
+    }
+
+    /// Writes the selected chat to a new file in the export directory, and says where
+    /// it went.
+    fn export_selected_chat(&mut self) -> Result<String, String> {
+        let chat = self.get_selected_chat().ok_or("No chat is selected")?;
+        let dir = &self.chat_export.dir;
+        let options = ExportOptions {
+            chat: chat.clone(),
+            format: self.chat_export.format,
+            since: parse_day(&self.chat_export.since.input)?,
+            until: parse_day(&self.chat_export.until.input)?,
+            out_dir: Some(dir.clone()),
+        };
+
+        std::fs::create_dir_all(dir)
+            .map_err(|err| format!("Failed to create {}: {err}", dir.display()))?;
+        let name = file_name(&self.contact_name(&chat));
+        let extension = options.format.extension();
+        let mut path = dir.join(format!("{name}.{extension}"));
+        for i in 1.. {
+            if !path.exists() {
+                break;
+            }
+            path = dir.join(format!("{name} ({i}).{extension}"));
+        }
+
+        let (since, until) = options.time_range();
+        let saved = self.db_handler.get_messages_between(&chat, since, until);
+        let messages = self.with_unsaved_messages(&chat, saved, since, until);
+        File::create(&path)
+            .map(BufWriter::new)
+            .and_then(|mut out| {
+                export_messages(
+                    &self.db_handler,
+                    &self.media_path,
+                    self.backend.self_jid().as_ref(),
+                    &options,
+                    &messages,
+                    &mut out,
+                )?;
+                out.flush()
+            })
+            .map_err(|err| format!("Failed to write {}: {err}", path.display()))?;
+        Ok(format!(
+            "Exported {} messages to {}",
+            messages.len(),
+            path.display()
+        ))
+    }
+}
+
+/// The day typed in a date field, `None` if it was left empty.
+fn parse_day(input: &str) -> Result<Option<NaiveDate>, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(None);
+    }
+    NaiveDate::parse_from_str(input, "%Y-%m-%d")
+        .map(Some)
+        .map_err(|_| format!("\"{input}\" is not a day like 2024-01-31"))
+}
+
+/// `name` with the characters file systems do not take replaced.
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
@@ -0,0 +1,212 @@
+use ratatui::crossterm::event::KeyCode;
+use regex::{Regex, RegexBuilder};
+use whatsrust as wr;
+
+use crate::app::App;
+use crate::key_handler::Key;
+use crate::ui::text_input::TextInput;
+
+/// The `/` search within the messages of the selected chat.
+pub struct ChatSearch {
+    /// Whether the prompt is open and takes the keys.
+    pub active: bool,
+    pub input: TextInput,
+    /// Whether the pattern is a regular expression rather than plain text.
+    pub regex_mode: bool,
+    /// The pattern to match, `None` when it is empty or not a valid regex.
+    pub regex: Option<Regex>,
+    /// The message that was selected when the prompt was opened.
+    origin: Option<wr::MessageId>,
+}
+
+impl Default for ChatSearch {
+    fn default() -> Self {
+        Self {
+            active: false,
+            input: TextInput::new(),
+            regex_mode: false,
+            regex: None,
+            origin: None,
+        }
+    }
+}
+
+impl ChatSearch {
+    /// Forgets the pattern, so nothing is highlighted anymore.
+    pub fn clear(&mut self) {
+        self.active = false;
+        self.input.clean();
+        self.regex = None;
+    }
+
+    /// Compiles the pattern. Like vim's smartcase, it ignores case unless it has
+    /// uppercase letters.
+    fn compile(&mut self) {
+        let pattern = &self.input.input;
+        self.regex = if pattern.is_empty() {
+            None
+        } else {
+            let source = if self.regex_mode {
+                pattern.clone()
+            } else {
+                regex::escape(pattern)
+            };
+            RegexBuilder::new(&source)
+                .case_insensitive(!pattern.chars().any(char::is_uppercase))
+                .build()
+                .ok()
+        };
+    }
+}
+
+impl App<'_> {
+    pub fn open_chat_search(&mut self) {
+        self.chat_search.clear();
+        self.chat_search.active = true;
+        self.chat_search.origin = self.message_list_state.get_selected_message();
+    }
+
+    pub(super) fn chat_search_on_event(&mut self, key: &Key) {
+        if self.kh.kp(&[Key::k(KeyCode::Esc)]) {
+            self.chat_search.clear();
+            self.select_search_origin();
+        } else if self.kh.kp(&[Key::k(KeyCode::Enter)]) {
+            self.chat_search.active = false;
+        } else if self.kh.kp(&[Key::ctrl('r')]) {
+            self.chat_search.regex_mode = !self.chat_search.regex_mode;
+            self.update_chat_search();
+        } else {
+            match key.code {
+                // Deleting past the start closes the prompt, as in vim.
+                KeyCode::Backspace if self.chat_search.input.input.is_empty() => {
+                    self.chat_search.clear();
+                    self.select_search_origin();
+                }
+                KeyCode::Char(c) => {
+                    self.chat_search.input.enter_char(c);
+                    self.update_chat_search();
+                }
+                KeyCode::Backspace => {
+                    self.chat_search.input.delete_char();
+                    self.update_chat_search();
+                }
+                KeyCode::Left => self.chat_search.input.move_cursor_left(),
+                KeyCode::Right => self.chat_search.input.move_cursor_right(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether `message` is shown with a match of the pattern, mentions resolved.
+    pub fn matches_chat_search(&self, message: &wr::Message) -> bool {
+        let Some(regex) = &self.chat_search.regex else {
+            return false;
+        };
+        let text = match &message.message {
+            wr::MessageContent::Text(_) if message.info.edit_state == wr::EditState::Revoked => {
+                return false;
+            }
+            wr::MessageContent::Text(text) => text,
+            wr::MessageContent::File(file) => match &file.caption {
+                Some(caption) => caption,
+                None => return false,
+            },
+            wr::MessageContent::GroupChange(_) => return false,
+        };
+        regex.is_match(&self.resolve_mentions(text, &message.info.mentions))
+    }
+
+    /// Selects the next match, older than the selected message when `older` holds and
+    /// newer otherwise. Going back reads older pages of the chat until there is one,
+    /// and both directions wrap around once there is nothing left.
+    pub fn select_next_match(&mut self, older: bool) {
+        let Some(chat) = self.get_selected_chat() else {
+            return;
+        };
+        if self.chat_search.regex.is_none() {
+            return;
+        }
+
+        loop {
+            let matches = self.chat_search_matches(&chat);
+            let selected = self
+                .message_list_state
+                .get_selected_message()
+                .and_then(|id| self.newest_first(&chat).position(|m| *m == id));
+            let next = if older {
+                let after = selected.map_or(0, |index| index + 1);
+                matches.iter().find(|(index, _)| *index >= after)
+            } else {
+                let before = selected.unwrap_or(usize::MAX);
+                matches.iter().rev().find(|(index, _)| *index < before)
+            };
+            if let Some((_, id)) = next {
+                self.message_list_state.set_selected_message(id.clone());
+                return;
+            }
+            if older && self.has_older_messages(&chat) {
+                self.load_older_messages(&chat);
+                continue;
+            }
+
+            let wrapped = if older {
+                matches.first()
+            } else {
+                matches.last()
+            };
+            if let Some((_, id)) = wrapped {
+                self.message_list_state.set_selected_message(id.clone());
+            }
+            return;
+        }
+    }
+
+    /// Moves the selection to the first match from where the prompt was opened, as the
+    /// pattern is typed. Only the messages already read are searched.
+    fn update_chat_search(&mut self) {
+        self.chat_search.compile();
+        let Some(chat) = self.get_selected_chat() else {
+            return;
+        };
+        let matches = self.chat_search_matches(&chat);
+        let origin = self
+            .chat_search
+            .origin
+            .as_ref()
+            .and_then(|origin| self.newest_first(&chat).position(|id| id == origin))
+            .unwrap_or(0);
+        match matches
+            .iter()
+            .find(|(index, _)| *index >= origin)
+            .or(matches.first())
+        {
+            Some((_, id)) => self.message_list_state.set_selected_message(id.clone()),
+            None => self.select_search_origin(),
+        }
+    }
+
+    fn select_search_origin(&mut self) {
+        match self.chat_search.origin.clone() {
+            Some(origin) => self.message_list_state.set_selected_message(origin),
+            None => self.message_list_state.reset(),
+        }
+    }
+
+    /// The messages of `chat` read so far, in the order of the message list.
+    fn newest_first(&self, chat: &wr::JID) -> impl Iterator<Item = &wr::MessageId> {
+        self.chat_messages.get(chat).into_iter().flatten().rev()
+    }
+
+    /// The matching messages of `chat` with their index in the message list.
+    fn chat_search_matches(&self, chat: &wr::JID) -> Vec<(usize, wr::MessageId)> {
+        self.newest_first(chat)
+            .enumerate()
+            .filter(|(_, id)| {
+                self.messages
+                    .get(*id)
+                    .is_some_and(|message| self.matches_chat_search(message))
+            })
+            .map(|(index, id)| (index, id.clone()))
+            .collect()
+    }
+}
@@ -0,0 +1,375 @@
+//! The command line opened with `:`. It runs any action by name, and a few commands
+//! that take an argument, like `:mute 8h`, `:goto Alice` or `:promote Bob`. Names are
+//! completed fuzzily: `:tgl` finds `toggle_logs`.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use directories::UserDirs;
+use ratatui::crossterm::event::KeyCode;
+use strum::IntoEnumIterator;
+use whatsrust as wr;
+
+use crate::app::actions::COMMAND_LINE_ACTIONS;
+use crate::app::groups::action_name;
+use crate::app::{App, SelectedWidget};
+use crate::cli::{file_message, parse_duration};
+use crate::db::match_chats;
+use crate::key_handler::{Action, Key};
+use crate::ui::text_input::TextInput;
+
+/// What can be run from the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Run(Action),
+    Attach,
+    GoTo,
+    /// Change a participant of the selected group.
+    Participant(wr::ParticipantAction),
+}
+
+impl Command {
+    /// Every command, the actions in the order they are declared.
+    pub fn all() -> impl Iterator<Item = Command> {
+        Action::iter()
+            .filter(|action| *action != Action::CommandLine)
+            .map(Command::Run)
+            .chain([Command::Attach, Command::GoTo])
+            .chain(wr::ParticipantAction::iter().map(Command::Participant))
+    }
+
+    pub fn from_name(name: &str) -> Option<Command> {
+        Self::all().find(|command| command.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Run(action) => action.name(),
+            Command::Attach => "attach",
+            Command::GoTo => "goto",
+            Command::Participant(action) => action_name(action),
+        }
+    }
+
+    /// The argument the command takes, in brackets if it can go without.
+    pub fn usage(self) -> &'static str {
+        match self {
+            Command::Run(Action::Send) => "[text]",
+            Command::Run(Action::Mute) => "[duration]",
+            Command::Run(_) => "",
+            Command::Attach => "<path>",
+            Command::GoTo => "<chat>",
+            Command::Participant(_) => "<participant>",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Command::Run(action) => action.description(),
+            Command::Attach => "Attach a file to the message in the input",
+            Command::GoTo => "Open a chat by name or phone number",
+            Command::Participant(wr::ParticipantAction::Add) => {
+                "Add someone to the group, by name or phone number"
+            }
+            Command::Participant(wr::ParticipantAction::Remove) => "Remove someone from the group",
+            Command::Participant(wr::ParticipantAction::Promote) => "Make a participant an admin",
+            Command::Participant(wr::ParticipantAction::Demote) => {
+                "Make an admin a regular participant"
+            }
+        }
+    }
+}
+
+/// A completion of the word under the cursor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    /// What the word is replaced with.
+    pub text: String,
+    /// Shown after it, dimmed: the usage of a command.
+    pub usage: &'static str,
+    pub description: String,
+}
+
+pub struct CommandLine {
+    pub input: TextInput,
+    pub candidates: Vec<Candidate>,
+    pub selected: usize,
+    /// Why the last command failed. The command line stays open to fix it.
+    pub error: Option<String>,
+    /// Where the focus goes back to once the command line closes.
+    previous: SelectedWidget,
+}
+
+impl Default for CommandLine {
+    fn default() -> Self {
+        Self {
+            input: TextInput::new(),
+            candidates: Vec::new(),
+            selected: 0,
+            error: None,
+            previous: SelectedWidget::ChatList,
+        }
+    }
+}
+
+/// The command and its argument, trimmed.
+fn split_command(line: &str) -> (&str, &str) {
+    let line = line.trim_start();
+    match line.split_once(' ') {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (line.trim_end(), ""),
+    }
+}
+
+/// How well `pattern` matches `text`, ignoring case, or `None` if it does not: each of
+/// its characters must appear in `text` in order. Lower is better, so matches at the
+/// start and with the characters close together come first.
+pub fn fuzzy_score(pattern: &str, text: &str) -> Option<(usize, usize)> {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    let Some(first) = pattern.first() else {
+        return Some((0, 0));
+    };
+    text.iter()
+        .enumerate()
+        .filter(|(_, c)| *c == first)
+        .filter_map(|(start, _)| {
+            let mut end = start;
+            for c in &pattern[1..] {
+                end += 1 + text[end + 1..].iter().position(|t| t == c)?;
+            }
+            Some((start, end - start))
+        })
+        .min_by_key(|(start, span)| (*span, *start))
+}
+
+fn by_score<T>(mut scored: Vec<((usize, usize), T)>) -> Vec<T> {
+    scored.sort_by_key(|(score, _)| *score);
+    scored.into_iter().map(|(_, item)| item).collect()
+}
+
+/// `~/` at the start of `path` stands for the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), UserDirs::new()) {
+        (Some(rest), Some(dirs)) => dirs.home_dir().join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+impl App<'_> {
+    pub fn open_command_line(&mut self) {
+        if self.selected_widget != SelectedWidget::CommandLine {
+            self.command_line.previous = self.selected_widget;
+        }
+        self.command_line.input.clean();
+        self.command_line.error = None;
+        self.update_candidates();
+        self.selected_widget = SelectedWidget::CommandLine;
+    }
+
+    fn close_command_line(&mut self) {
+        self.selected_widget = self.command_line.previous;
+    }
+
+    /// Selects the next or previous completion of what is typed.
+    pub(super) fn move_candidate_selection(&mut self, next: bool) -> Result<(), String> {
+        if self.selected_widget != SelectedWidget::CommandLine {
+            return Err("The command line is not open".to_string());
+        }
+        let line = &mut self.command_line;
+        line.selected = if next {
+            (line.selected + 1).min(line.candidates.len().saturating_sub(1))
+        } else {
+            line.selected.saturating_sub(1)
+        };
+        Ok(())
+    }
+
+    pub(super) fn command_line_on_event(&mut self, key: &Key) {
+        if self.kh.kp(&[Key::k(KeyCode::Esc)]) {
+            self.close_command_line();
+        } else if self.kh.kp(&[Key::k(KeyCode::Enter)]) {
+            self.complete();
+            let text = self.command_line.input.input.clone();
+            // Commands run where the focus was before, and may move it, like `:reply`.
+            self.close_command_line();
+            if let Err(err) = self.run_command(&text) {
+                self.command_line.error = Some(err);
+                self.selected_widget = SelectedWidget::CommandLine;
+            }
+        } else if self.kh.kp(&[Key::k(KeyCode::Tab)]) {
+            self.complete();
+        } else if self.kh.kp(&[Key::k(KeyCode::Down)]) {
+            let _ = self.move_candidate_selection(true);
+        } else if self.kh.kp(&[Key::k(KeyCode::Up)]) {
+            let _ = self.move_candidate_selection(false);
+        } else if !self.run_pressed(COMMAND_LINE_ACTIONS) {
+            let line = &mut self.command_line;
+            match key.code {
+                KeyCode::Backspace if line.input.input.is_empty() => self.close_command_line(),
+                KeyCode::Char(c) => {
+                    line.input.enter_char(c);
+                    self.update_candidates();
+                }
+                KeyCode::Backspace => {
+                    line.input.delete_char();
+                    self.update_candidates();
+                }
+                KeyCode::Left => line.input.move_cursor_left(),
+                KeyCode::Right => line.input.move_cursor_right(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Replaces the word being typed with the selected candidate, unless it names a
+    /// command already. A command that takes an argument is followed by a space.
+    fn complete(&mut self) {
+        let line = &self.command_line;
+        let Some(candidate) = line.candidates.get(line.selected).cloned() else {
+            return;
+        };
+        let (name, argument) = split_command(&line.input.input);
+        let text = if line.input.input.trim_start().contains(' ') {
+            if argument == candidate.text {
+                return;
+            }
+            format!("{name} {}", candidate.text)
+        } else if Command::from_name(name).is_some() {
+            return;
+        } else if candidate.usage.is_empty() {
+            candidate.text
+        } else {
+            format!("{} ", candidate.text)
+        };
+        self.command_line.input.clean();
+        for c in text.chars() {
+            self.command_line.input.enter_char(c);
+        }
+        self.update_candidates();
+    }
+
+    /// The commands matching the name being typed, or the chats matching the argument of
+    /// `:goto`, or the people a participant command can be applied to.
+    fn update_candidates(&mut self) {
+        let input = &self.command_line.input.input;
+        let (name, argument) = split_command(input);
+        self.command_line.candidates = if !input.trim_start().contains(' ') {
+            by_score(
+                Command::all()
+                    .filter_map(|command| {
+                        let score = fuzzy_score(name, command.name())?;
+                        let candidate = Candidate {
+                            text: command.name().to_string(),
+                            usage: command.usage(),
+                            description: command.description().to_string(),
+                        };
+                        Some((score, candidate))
+                    })
+                    .collect(),
+            )
+        } else if Command::from_name(name) == Some(Command::GoTo) {
+            by_score(
+                self.sorted_chats
+                    .iter()
+                    .filter_map(|jid| {
+                        let chat = self.contact_name(jid).to_string();
+                        let score = fuzzy_score(argument, &chat)?;
+                        let candidate = Candidate {
+                            text: chat,
+                            usage: "",
+                            description: jid.0.to_string(),
+                        };
+                        Some((score, candidate))
+                    })
+                    .collect(),
+            )
+        } else if let Some(Command::Participant(action)) = Command::from_name(name) {
+            by_score(
+                self.participant_candidates(action)
+                    .into_iter()
+                    .filter_map(|jid| {
+                        let name = self.contact_name(&jid).to_string();
+                        let score = fuzzy_score(argument, &name)?;
+                        let candidate = Candidate {
+                            text: name,
+                            usage: "",
+                            description: jid.0.to_string(),
+                        };
+                        Some((score, candidate))
+                    })
+                    .collect(),
+            )
+        } else {
+            Vec::new()
+        };
+        self.command_line.selected = 0;
+        self.command_line.error = None;
+    }
+
+    /// Runs a line of the command line.
+    fn run_command(&mut self, line: &str) -> Result<(), String> {
+        let (name, argument) = split_command(line);
+        if name.is_empty() {
+            return Ok(());
+        }
+        let command =
+            Command::from_name(name).ok_or_else(|| format!("Unknown command \"{name}\""))?;
+        if command.usage().starts_with('<') && argument.is_empty() {
+            return Err(format!("Usage: {name} {}", command.usage()));
+        }
+
+        match command {
+            Command::Run(Action::Send) if !argument.is_empty() => {
+                let chat = self.require_chat()?;
+                self.queue_message(chat, wr::MessageContent::Text(argument.into()), Vec::new());
+                Ok(())
+            }
+            Command::Run(Action::Mute) if !argument.is_empty() => {
+                let seconds = parse_duration(argument)
+                    .filter(|seconds| *seconds > 0)
+                    .ok_or_else(|| {
+                        format!("\"{argument}\" is not a duration like 30m, 8h or 2w")
+                    })?;
+                self.mute_selected_chat(Some(Duration::from_secs(seconds as u64)))
+            }
+            Command::Run(action) if argument.is_empty() => self.perform(action),
+            Command::Run(_) => Err(format!("{name} takes no argument")),
+            Command::Attach => self.attach_file(&expand_home(argument)),
+            Command::GoTo => self.go_to_chat(argument),
+            Command::Participant(action) => self.update_participant(action, argument),
+        }
+    }
+
+    fn attach_file(&mut self, path: &Path) -> Result<(), String> {
+        let content = file_message(path, "").map_err(|err| format!("{}: {err}", path.display()))?;
+        if let wr::MessageContent::File(file) = content {
+            self.attached_file = Some((file.path, file.kind));
+            self.selected_widget = SelectedWidget::Input;
+        }
+        Ok(())
+    }
+
+    fn go_to_chat(&mut self, query: &str) -> Result<(), String> {
+        let chats = self.chats.keys().cloned().collect::<Vec<_>>();
+        let mut chats = match_chats(query, &chats, &self.contacts);
+        if chats.len() > 1 {
+            let names = chats
+                .iter()
+                .map(|jid| self.contact_name(jid).to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(format!("\"{query}\" matches more than one chat: {names}"));
+        }
+        let chat = chats
+            .pop()
+            .ok_or_else(|| format!("No chat matches \"{query}\""))?;
+
+        self.contact_search_active = false;
+        self.contact_search.clean();
+        self.select_chat(Some(chat));
+        self.open_selected_chat();
+        self.selected_widget = SelectedWidget::Input;
+        Ok(())
+    }
+}
@@ -7,6 +7,7 @@ use ratatui_image::protocol::StatefulProtocol;
 
 use whatsrust as wr;
 
+use crate::app::rpc::{RpcClient, RpcRequest};
 use crate::app::{App, FileMeta};
 
 
@@ -14,9 +15,25 @@ pub enum AppEvent {
     DownloadFile(wr::MessageId, wr::FileId),
     DownloadFileDone(wr::MessageId, FileMeta),
     LoadFilePreview(wr::MessageId),
+    /// Read the page of messages of a chat before the oldest one read so far.
+    LoadOlderMessages(wr::JID),
     SetFilePreview(wr::MessageId, Arc<str>, StatefulProtocol),
     SetFileState(wr::MessageId, FileMeta),
     EditWithExternalEditor,
+    /// No key followed the pending keys with this id in time.
+    KeyTimeout(u64),
+    /// An outbox message (local id) was accepted by the server under a new id.
+    MessageSent(wr::MessageId, wr::MessageId),
+    SendFailed {
+        id: wr::MessageId,
+        attempts: u32,
+        error: wr::Error,
+        retrying: bool,
+    },
+    /// The metadata of a group arrived from the group info worker.
+    GroupInfoLoaded(wr::JID, Result<wr::GroupInfo, wr::Error>),
+    /// Changing participants of a group failed.
+    UpdateParticipantsFailed(wr::JID, wr::ParticipantAction, wr::Error),
 }
 
 #[derive(Debug)]
@@ -29,6 +46,11 @@ pub enum AppInput {
     },
     WhatsApp(wr::Event),
     Terminal(Event),
+    /// A request that came in on the control socket.
+    Rpc {
+        client: RpcClient,
+        request: RpcRequest,
+    },
 }
 
 
@@ -49,6 +71,9 @@ impl fmt::Debug for AppEvent {
             AppEvent::LoadFilePreview(message_id) => {
                 f.debug_tuple("LoadFilePreview").field(message_id).finish()
             }
+            AppEvent::LoadOlderMessages(chat) => {
+                f.debug_tuple("LoadOlderMessages").field(chat).finish()
+            }
             AppEvent::SetFilePreview(message_id, path, _) => f
                 .debug_tuple("SetFilePreview")
                 .field(message_id)
@@ -60,6 +85,35 @@ impl fmt::Debug for AppEvent {
                 .field(state)
                 .finish(),
             AppEvent::EditWithExternalEditor => f.debug_tuple("EditWithExternalEditor").finish(),
+            AppEvent::KeyTimeout(id) => f.debug_tuple("KeyTimeout").field(id).finish(),
+            AppEvent::MessageSent(id, message_id) => f
+                .debug_tuple("MessageSent")
+                .field(id)
+                .field(message_id)
+                .finish(),
+            AppEvent::SendFailed {
+                id,
+                attempts,
+                error,
+                retrying,
+            } => f
+                .debug_struct("SendFailed")
+                .field("id", id)
+                .field("attempts", attempts)
+                .field("error", error)
+                .field("retrying", retrying)
+                .finish(),
+            AppEvent::GroupInfoLoaded(jid, info) => f
+                .debug_tuple("GroupInfoLoaded")
+                .field(jid)
+                .field(info)
+                .finish(),
+            AppEvent::UpdateParticipantsFailed(group, action, error) => f
+                .debug_tuple("UpdateParticipantsFailed")
+                .field(group)
+                .field(action)
+                .field(error)
+                .finish(),
         }
     }
 }
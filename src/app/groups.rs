@@ -0,0 +1,186 @@
+use std::sync::{Arc, mpsc};
+use std::thread;
+
+use log::error;
+use whatsrust as wr;
+
+use crate::app::events::{AppEvent, AppInput};
+use crate::app::{App, SelectedWidget, is_group, jid_user};
+use crate::backend::Backend;
+use crate::db::match_chats;
+
+/// A round trip to the server about a group, made off the UI thread.
+pub enum GroupJob {
+    /// Fetch the metadata of a group, delivered as `AppEvent::GroupInfoLoaded`.
+    Info(wr::JID),
+    /// Change participants of a group. The change itself comes back as an event, only
+    /// failures are reported with `AppEvent::UpdateParticipantsFailed`.
+    UpdateParticipants {
+        group: wr::JID,
+        participants: Vec<wr::JID>,
+        action: wr::ParticipantAction,
+    },
+}
+
+/// Runs group jobs one at a time on a single thread, like the downloads.
+pub fn spawn_worker(
+    backend: Arc<dyn Backend>,
+    app_tx: mpsc::Sender<AppInput>,
+) -> mpsc::Sender<GroupJob> {
+    let (group_tx, group_rx) = mpsc::channel::<GroupJob>();
+    thread::spawn(move || {
+        for job in group_rx {
+            let event = match job {
+                GroupJob::Info(jid) => {
+                    let info = backend.get_group_info(&jid);
+                    AppEvent::GroupInfoLoaded(jid, info)
+                }
+                GroupJob::UpdateParticipants {
+                    group,
+                    participants,
+                    action,
+                } => match backend.update_group_participants(&group, &participants, action) {
+                    Ok(()) => continue,
+                    Err(err) => AppEvent::UpdateParticipantsFailed(group, action, err),
+                },
+            };
+            if app_tx.send(AppInput::App(event)).is_err() {
+                break;
+            }
+        }
+    });
+    group_tx
+}
+
+/// The command that runs `action`, and what it is shown as in the command line.
+pub fn action_name(action: wr::ParticipantAction) -> &'static str {
+    match action {
+        wr::ParticipantAction::Add => "add",
+        wr::ParticipantAction::Remove => "remove",
+        wr::ParticipantAction::Promote => "promote",
+        wr::ParticipantAction::Demote => "demote",
+    }
+}
+
+impl App<'_> {
+    /// Who `action` can be applied to in the selected group: the contacts that are not
+    /// in it yet to add, the other participants for the rest.
+    pub(super) fn participant_candidates(&self, action: wr::ParticipantAction) -> Vec<wr::JID> {
+        let Some(info) = self
+            .get_selected_chat()
+            .and_then(|chat| self.group_infos.get(&chat))
+        else {
+            return Vec::new();
+        };
+        let self_jid = self.backend.self_jid();
+        let is_self = |jid: &wr::JID| {
+            self_jid
+                .as_ref()
+                .is_some_and(|me| jid_user(me) == jid_user(jid))
+        };
+        let is_participant = |jid: &wr::JID| {
+            info.participants
+                .iter()
+                .any(|p| jid_user(&p.jid) == jid_user(jid))
+        };
+
+        let mut candidates: Vec<wr::JID> = match action {
+            wr::ParticipantAction::Add => self
+                .contacts
+                .keys()
+                .filter(|jid| jid.0.ends_with("@s.whatsapp.net") && !is_participant(jid))
+                .cloned()
+                .collect(),
+            wr::ParticipantAction::Remove => {
+                info.participants.iter().map(|p| p.jid.clone()).collect()
+            }
+            wr::ParticipantAction::Promote => info
+                .participants
+                .iter()
+                .filter(|p| !p.is_admin)
+                .map(|p| p.jid.clone())
+                .collect(),
+            wr::ParticipantAction::Demote => info
+                .participants
+                .iter()
+                .filter(|p| p.is_admin && !p.is_super_admin)
+                .map(|p| p.jid.clone())
+                .collect(),
+        };
+        candidates.retain(|jid| !is_self(jid));
+        candidates.sort_by_key(|jid| self.contact_name(jid));
+        candidates
+    }
+
+    /// Applies `action` to the participant `query` names in the selected group: a
+    /// name, a JID or a phone number, which is enough to add someone not in the
+    /// contacts.
+    pub(super) fn update_participant(
+        &mut self,
+        action: wr::ParticipantAction,
+        query: &str,
+    ) -> Result<(), String> {
+        let group = self.require_chat()?;
+        if !is_group(&group) {
+            return Err(format!("{} is not a group", self.contact_name(&group)));
+        }
+        if !self.group_infos.contains_key(&group) {
+            self.fetch_group_info(&group);
+            return Err("The participants of the group are still loading".to_string());
+        }
+
+        let candidates = self.participant_candidates(action);
+        let mut matches = match_chats(query, &candidates, &self.contacts);
+        let number = query.trim_start_matches('+').replace([' ', '-'], "");
+        if matches.is_empty()
+            && action == wr::ParticipantAction::Add
+            && !number.is_empty()
+            && number.chars().all(|c| c.is_ascii_digit())
+        {
+            matches.push(format!("{number}@s.whatsapp.net").into());
+        }
+        if matches.len() > 1 {
+            let names = matches
+                .iter()
+                .map(|jid| self.contact_name(jid).to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(format!("\"{query}\" matches more than one person: {names}"));
+        }
+        let participant = matches
+            .pop()
+            .ok_or_else(|| format!("No one to {} matches \"{query}\"", action_name(action)))?;
+
+        if let Some(tx) = &self.group_tx {
+            tx.send(GroupJob::UpdateParticipants {
+                group,
+                participants: vec![participant],
+                action,
+            })
+            .unwrap();
+        }
+        Ok(())
+    }
+
+    /// Shows why a change to the participants failed in the command line it was made
+    /// from.
+    pub(super) fn on_update_participants_failed(
+        &mut self,
+        group: &wr::JID,
+        action: wr::ParticipantAction,
+        err: &wr::Error,
+    ) {
+        error!(
+            "Failed to {} participants of {}: {err}",
+            action_name(action),
+            group.0
+        );
+        self.open_command_line();
+        self.command_line.error = Some(format!(
+            "Failed to {} in {}: {err}",
+            action_name(action),
+            self.contact_name(group)
+        ));
+        self.selected_widget = SelectedWidget::CommandLine;
+    }
+}
@@ -1,8 +1,15 @@
+use std::thread;
+
 use log::error;
 use ratatui::crossterm::event::{Event, KeyCode, KeyEventKind};
 
+use crate::app::actions::{
+    CHAT_LIST_ACTIONS, CHAT_LIST_FOCUS, GLOBAL_ACTIONS, INPUT_FOCUS, MESSAGE_LIST_FOCUS,
+};
+use crate::app::events::{AppEvent, AppInput};
 use crate::app::{App, SelectedWidget};
 use crate::key_handler::Key;
+use crate::ui::reaction_picker::REACTIONS;
 use whatsrust as wr;
 
 impl App<'_> {
@@ -10,23 +17,32 @@ impl App<'_> {
         if let Event::Key(key_event) = event
             && key_event.kind == KeyEventKind::Press
         {
+            if let Some(action) = self.kh.interrupt_pending(&key_event) {
+                self.run_action(action);
+            }
+
             let key = self.kh.pressed_start(&key_event);
 
             self.handle_input(key);
 
             self.kh.pressed_end();
+
+            if let Some(id) = self.kh.pending_id() {
+                let tx = self.tx.clone();
+                let timeout = self.kh.timeout;
+                thread::spawn(move || {
+                    thread::sleep(timeout);
+                    let _ = tx.send(AppInput::App(AppEvent::KeyTimeout(id)));
+                });
+            }
+        } else if let Event::Mouse(mouse_event) = event {
+            self.on_mouse_event(mouse_event);
         }
+        self.read_selected_chat_if_in_it();
     }
 
     fn handle_input(&mut self, key: Key) {
-        if self.kh.kp(&[Key::ctrl('q')]) {
-            self.db_handler.stop();
-            self.should_quit = true;
-            return;
-        }
-
-        if self.kh.kp(&[Key::ctrl_shift('l')]) {
-            self.show_logs = !self.show_logs;
+        if self.run_pressed(GLOBAL_ACTIONS) {
             return;
         }
 
@@ -61,40 +77,45 @@ impl App<'_> {
         //     return;
         // }
 
+        if matches!(self.selected_widget, SelectedWidget::MessageList) && self.chat_search.active {
+            self.chat_search_on_event(&key);
+            return;
+        }
+
         match self.selected_widget {
             SelectedWidget::ChatList => {
-                if self.kh.kp(&[Key::ctrl('l')]) {
-                    self.selected_widget = SelectedWidget::MessageList;
-                    self.input_widget.select_all();
+                if self.run_pressed(CHAT_LIST_FOCUS) {
                     return;
                 }
             }
             SelectedWidget::Input => {
-                if self.kh.kp(&[Key::ctrl('k')]) {
-                    self.selected_widget = SelectedWidget::MessageList;
-                    return;
-                }
-                if self.kh.kp(&[Key::ctrl('h')]) {
-                    self.selected_widget = SelectedWidget::ChatList;
+                if self.run_pressed(INPUT_FOCUS) {
                     return;
                 }
             }
             SelectedWidget::MessageList => {
-                if self.kh.kp(&[Key::ctrl('j')]) {
-                    self.selected_widget = SelectedWidget::Input;
-                    return;
-                }
-                if self.kh.kp(&[Key::ctrl('h')]) {
-                    self.selected_widget = SelectedWidget::ChatList;
+                if self.run_pressed(MESSAGE_LIST_FOCUS) {
                     return;
                 }
             }
-            SelectedWidget::MessageView => {
+            SelectedWidget::MessageView
+            | SelectedWidget::ReactionPicker
+            | SelectedWidget::MessageInfo => {
                 if self.kh.kp(&[Key::k(KeyCode::Esc)]) {
                     self.selected_widget = SelectedWidget::MessageList;
                     return;
                 }
             }
+            SelectedWidget::ConfigErrors => {
+                if self.kh.kp(&[Key::k(KeyCode::Esc)]) || self.kh.kp(&[Key::k(KeyCode::Enter)]) {
+                    self.selected_widget = SelectedWidget::ChatList;
+                }
+                return;
+            }
+            SelectedWidget::Search
+            | SelectedWidget::Export
+            | SelectedWidget::Profiles
+            | SelectedWidget::CommandLine => {}
         }
 
         match self.selected_widget {
@@ -107,7 +128,23 @@ impl App<'_> {
             SelectedWidget::Input => {
                 self.input_on_event(&key);
             }
-            SelectedWidget::MessageView => {}
+            SelectedWidget::MessageView | SelectedWidget::MessageInfo => {}
+            SelectedWidget::ReactionPicker => {
+                self.reaction_picker_on_event(&key);
+            }
+            SelectedWidget::Search => {
+                self.search_on_event(&key);
+            }
+            SelectedWidget::Export => {
+                self.chat_export_on_event(&key);
+            }
+            SelectedWidget::Profiles => {
+                self.profile_switcher_on_event(&key);
+            }
+            SelectedWidget::CommandLine => {
+                self.command_line_on_event(&key);
+            }
+            SelectedWidget::ConfigErrors => {}
         }
     }
 
@@ -119,43 +156,13 @@ impl App<'_> {
             self.contact_search.clean();
 
             self.select_chat(chat_jid);
+            self.load_group_info();
 
             return;
         }
 
         if !self.contact_search_active {
-            let mut moved = false;
-            if self.kh.kp(&[Key::c('j')]) {
-                self.chat_list_state.select_next();
-                moved = true;
-            } else if self.kh.kp(&[Key::c('k')]) {
-                self.chat_list_state.select_previous();
-                moved = true;
-            }
-            if moved {
-                // Bound the selected index to the number of chats
-                let len = self.sorted_chats.len();
-                if len == 0 {
-                    self.chat_list_state.select(None);
-                    return;
-                } else if let Some(selected) = self.chat_list_state.selected() {
-                    if selected >= len {
-                        self.chat_list_state.select(Some(len.saturating_sub(1)));
-                    }
-                }
-
-                self.sort_chat_messages(self.get_selected_chat().unwrap());
-                self.message_list_state.reset();
-            }
-
-            if self.kh.kp(&[Key::k(KeyCode::Enter)]) {
-                if self.chat_list_state.selected().is_some() {
-                    self.message_list_state.reset();
-                    self.selected_widget = SelectedWidget::Input;
-                }
-            } else if self.kh.kp(&[Key::c('/')]) {
-                self.contact_search_active = true;
-            }
+            self.run_pressed(CHAT_LIST_ACTIONS);
         } else {
             match key.code {
                 // KeyCode::Enter => self.contact_search_active.submit_message(),
@@ -178,71 +185,40 @@ impl App<'_> {
     }
 
     fn message_list_on_event(&mut self) {
-        if self.kh.kp(&[Key::ctrl('e')]) {
-            self.message_list_state.offset = self.message_list_state.offset.saturating_sub(1);
-        } else if self.kh.kp(&[Key::ctrl('y')]) {
-            self.message_list_state.offset = self.message_list_state.offset.saturating_add(1);
-        } else if self.kh.kp(&[Key::c('k')]) {
-            self.message_list_state.select_next();
-        } else if self.kh.kp(&[Key::c('j')]) {
-            self.message_list_state.select_previous();
-        } else if self.kh.kp(&[Key::c('G')]) {
-            self.message_list_state.select_first();
-        } else if self.kh.kp(&[Key::c('g'), Key::c('g')]) {
-            self.message_list_state.select_last();
-        } else if self.kh.kp(&[Key::k(KeyCode::Esc)]) {
-            self.message_list_state.reset();
-        }
+        let actions = self.message_list_actions();
+        self.run_pressed(&actions);
+    }
 
-        if let Some(msg_id) = self.message_list_state.get_selected_message()
-            && let Some(msg) = self.messages.get(&msg_id).cloned()
+    fn reaction_picker_on_event(&mut self, key: &Key) {
+        let Some(msg) = self
+            .message_list_state
+            .get_selected_message()
+            .and_then(|msg_id| self.messages.get(&msg_id).cloned())
+        else {
+            self.selected_widget = SelectedWidget::MessageList;
+            return;
+        };
+
+        if self.kh.kp(&[Key::c('l')]) {
+            self.reaction_picker_index = (self.reaction_picker_index + 1).min(REACTIONS.len() - 1);
+        } else if self.kh.kp(&[Key::c('h')]) {
+            self.reaction_picker_index = self.reaction_picker_index.saturating_sub(1);
+        } else if self.kh.kp(&[Key::k(KeyCode::Enter)]) {
+            self.send_reaction(&msg, REACTIONS[self.reaction_picker_index]);
+        } else if self.kh.kp(&[Key::c('x')]) {
+            self.send_reaction(&msg, "");
+        } else if let KeyCode::Char(c) = key.code
+            && let Some(index) = c.to_digit(10)
+            && (1..=REACTIONS.len()).contains(&(index as usize))
         {
-            if self.kh.kp(&[Key::c('o')]) {
-                match &msg.message {
-                    wr::MessageContent::Text(_text) => {
-                        // let mut file = tempfile::tempfile().unwrap();
-                        //
-                        // file.write_all(text.as_bytes()).unwrap();
-                        //
-                        // open::that(file.).unwrap();
-                    }
-                    wr::MessageContent::File(content) => {
-                        match open::that(self.media_path.join(content.path.as_ref())) {
-                            Ok(_) => {}
-                            Err(e) => {
-                                error!("Failed to open file {}: {:?}", content.path, e);
-                            }
-                        }
-                    }
-                }
-            } else if self.kh.kp(&[Key::c('r')]) {
-                self.quoting_message = Some(msg.clone());
-                self.selected_widget = SelectedWidget::Input;
-            } else if self.kh.kp(&[Key::k(KeyCode::Enter)]) {
-                self.selected_widget = SelectedWidget::MessageView;
-            } else if self.kh.kp(&[Key::c('y')]) {
-                match &msg.message {
-                    wr::MessageContent::Text(text) => {
-                        if let Err(e) = self.clipboard.set_text(text.to_string()) {
-                            error!("Failed to copy text to clipboard: {:?}", e);
-                        }
-                    }
-                    wr::MessageContent::File(content) => {
-                        let path = self.media_path.join(content.path.as_ref());
-                        if let Err(e) = self.clipboard.set_text(path.to_string_lossy().into_owned())
-                        {
-                            error!("Failed to copy file to clipboard: {:?}", e);
-                        }
-                    }
-                }
-            }
+            self.send_reaction(&msg, REACTIONS[index as usize - 1]);
+        }
+    }
 
-            if let Some(ref quote_id) = msg.info.quote_id {
-                if self.kh.kp(&[Key::c('g'), Key::c('q')]) {
-                    self.message_list_state
-                        .set_selected_message(quote_id.clone());
-                }
-            }
+    fn send_reaction(&mut self, msg: &wr::Message, emoji: &str) {
+        if let Err(err) = self.backend.send_reaction(msg, emoji) {
+            error!("Failed to react to {}: {err}", msg.info.id);
         }
+        self.selected_widget = SelectedWidget::MessageList;
     }
 }
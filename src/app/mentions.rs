@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use whatsrust as wr;
+
+use crate::app::{App, is_group, jid_user};
+
+/// How many people the mention popup lists at most.
+pub const MAX_MENTION_CANDIDATES: usize = 5;
+
+impl App<'_> {
+    /// What was typed after the `@` the cursor is in, if the word before the cursor
+    /// starts with one.
+    pub fn mention_query(&self) -> Option<String> {
+        let (row, col) = self.input_widget.cursor();
+        let line = self.input_widget.lines().get(row)?;
+        let before = line.chars().take(col).collect::<String>();
+        let word = before.rsplit(char::is_whitespace).next()?;
+        let query = word.strip_prefix('@')?;
+        (!query.contains('@')).then(|| query.to_string())
+    }
+
+    /// The people that can be mentioned in the selected chat and match the query,
+    /// the participants of the group first and then the rest of the contacts. Until
+    /// the participants arrive, only the contacts are offered.
+    pub fn mention_candidates(&self) -> Vec<(wr::JID, Arc<str>)> {
+        let (Some(query), Some(chat)) = (self.mention_query(), self.get_selected_chat()) else {
+            return Vec::new();
+        };
+        let query = query.to_lowercase();
+        let self_user = self
+            .backend
+            .self_jid()
+            .map(|jid| jid_user(&jid).to_string());
+
+        let mut participants = self
+            .group_infos
+            .get(&chat)
+            .map(|info| info.participants.iter().map(|p| p.jid.clone()).collect())
+            .unwrap_or_else(|| {
+                if is_group(&chat) {
+                    Vec::new()
+                } else {
+                    vec![chat.clone()]
+                }
+            });
+        participants.sort_by_cached_key(|jid| self.mention_name(jid).to_lowercase());
+        let mut contacts = self
+            .contacts
+            .keys()
+            .filter(|jid| jid.0.ends_with("@s.whatsapp.net"))
+            .cloned()
+            .collect::<Vec<_>>();
+        contacts.sort_by_cached_key(|jid| self.mention_name(jid).to_lowercase());
+
+        let mut candidates: Vec<(wr::JID, Arc<str>)> = Vec::new();
+        for jid in participants.into_iter().chain(contacts) {
+            if is_group(&jid)
+                || self_user.as_deref() == Some(jid_user(&jid))
+                || candidates
+                    .iter()
+                    .any(|(known, _)| jid_user(known) == jid_user(&jid))
+            {
+                continue;
+            }
+            let name = self.mention_name(&jid);
+            let lowercase = name.to_lowercase();
+            if lowercase.starts_with(&query)
+                || lowercase
+                    .split_whitespace()
+                    .any(|word| word.starts_with(&query))
+                || jid_user(&jid).starts_with(&query)
+            {
+                candidates.push((jid, name));
+                if candidates.len() == MAX_MENTION_CANDIDATES {
+                    break;
+                }
+            }
+        }
+        candidates
+    }
+
+    /// Replaces the query before the cursor with the selected candidate.
+    pub fn complete_mention(&mut self) {
+        let candidates = self.mention_candidates();
+        let (Some(query), Some((jid, name))) = (
+            self.mention_query(),
+            candidates
+                .get(self.mention_index)
+                .or(candidates.first())
+                .cloned(),
+        ) else {
+            return;
+        };
+
+        for _ in 0..=query.chars().count() {
+            self.input_widget.delete_char();
+        }
+        self.input_widget.insert_str(format!("@{name} "));
+        if !self.mentions.iter().any(|(_, known)| *known == jid) {
+            self.mentions.push((name, jid));
+        }
+        self.mention_index = 0;
+    }
+
+    /// Asks for the participants of the selected group once the user starts typing a
+    /// mention, so they can be offered first. They are fetched in the background, the
+    /// popup picks them up when they arrive.
+    pub(super) fn load_mention_participants(&mut self) {
+        if let Some(jid) = self.get_selected_chat()
+            && is_group(&jid)
+            && self.mention_query().is_some()
+        {
+            self.fetch_group_info(&jid);
+        }
+    }
+
+    /// Rewrites the mentions completed in `text` the way WhatsApp expects them, as `@`
+    /// and the number, and returns who is mentioned. Forgets the completed mentions.
+    pub(super) fn take_mentions(&mut self, text: &str) -> (String, Vec<wr::JID>) {
+        let mut completed = std::mem::take(&mut self.mentions);
+        // Longer names first, so "@Ann Marie" is not taken for a mention of "@Ann".
+        completed.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+
+        let mut text = text.to_string();
+        let mut mentions = Vec::new();
+        for (name, jid) in completed {
+            if let Some(replaced) =
+                replace_tag(&text, &format!("@{name}"), &format!("@{}", jid_user(&jid)))
+            {
+                text = replaced;
+                mentions.push(jid);
+            }
+        }
+        (text, mentions)
+    }
+
+    /// `text` with every mention written as `@` and the name of the person.
+    pub fn resolve_mentions(&self, text: &str, mentions: &[wr::JID]) -> String {
+        resolve_mentions(text, mentions, &self.contacts)
+    }
+
+    /// How each of `mentions` reads once resolved, `@` included.
+    pub fn mention_tags(&self, mentions: &[wr::JID]) -> Vec<String> {
+        mentions
+            .iter()
+            .map(|jid| format!("@{}", self.mention_name(jid)))
+            .collect()
+    }
+
+    pub fn mentions_me(&self, info: &wr::MessageInfo) -> bool {
+        self.backend.self_jid().is_some_and(|self_jid| {
+            info.mentions
+                .iter()
+                .any(|jid| jid_user(jid) == jid_user(&self_jid))
+        })
+    }
+
+    fn mention_name(&self, jid: &wr::JID) -> Arc<str> {
+        mention_name(&self.contacts, jid)
+    }
+}
+
+/// `text` with every mention written as `@` and the name `contacts` has for the person.
+pub fn resolve_mentions(
+    text: &str,
+    mentions: &[wr::JID],
+    contacts: &HashMap<wr::JID, Arc<str>>,
+) -> String {
+    let mut text = text.to_string();
+    for jid in mentions {
+        let tag = format!("@{}", jid_user(jid));
+        if let Some(replaced) =
+            replace_tag(&text, &tag, &format!("@{}", mention_name(contacts, jid)))
+        {
+            text = replaced;
+        }
+    }
+    text
+}
+
+/// The contact name, or the number for people who are not in the contacts.
+fn mention_name(contacts: &HashMap<wr::JID, Arc<str>>, jid: &wr::JID) -> Arc<str> {
+    contacts
+        .get(jid)
+        .cloned()
+        .unwrap_or_else(|| jid_user(jid).into())
+}
+
+/// Replaces every `tag` in `text` that is not followed by more of a word, so `@12`
+/// is not found in `@123`. `None` if there is none.
+fn replace_tag(text: &str, tag: &str, with: &str) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = text;
+    let mut found = false;
+    while let Some(start) = rest.find(tag) {
+        let end = start + tag.len();
+        let whole = !rest[end..].starts_with(|c: char| c.is_alphanumeric());
+        result.push_str(&rest[..start]);
+        result.push_str(if whole { with } else { tag });
+        found |= whole;
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+    found.then_some(result)
+}
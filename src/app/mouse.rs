@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::{Position, Rect};
+use whatsrust as wr;
+
+use crate::app::{App, SelectedWidget};
+use crate::key_handler::Action;
+
+/// Two clicks on the same message closer than this are a double click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Lines scrolled by a turn of the mouse wheel.
+const WHEEL_LINES: usize = 3;
+
+/// Where the panels and messages were drawn last, to find what a click lands on.
+#[derive(Debug, Default)]
+pub struct Mouse {
+    /// The rows of the chat list, inside its border.
+    pub chat_list: Rect,
+    pub message_list: Rect,
+    pub input: Rect,
+    /// The visible part of each message shown, with its index in the message list.
+    pub messages: Vec<(usize, wr::MessageId, Rect)>,
+    /// When and on which message the last click was.
+    last_click: Option<(Instant, wr::MessageId)>,
+}
+
+impl Mouse {
+    /// Forgets the areas, before they are drawn again.
+    pub fn clear_areas(&mut self) {
+        self.chat_list = Rect::default();
+        self.message_list = Rect::default();
+        self.input = Rect::default();
+        self.messages.clear();
+    }
+
+    fn message_at(&self, position: Position) -> Option<(usize, wr::MessageId)> {
+        self.messages
+            .iter()
+            .find(|(_, _, area)| area.contains(position))
+            .map(|(index, id, _)| (*index, id.clone()))
+    }
+}
+
+impl App<'_> {
+    pub(super) fn on_mouse_event(&mut self, event: MouseEvent) {
+        // Popups and the message view take the whole screen, or cover what is below.
+        if !matches!(
+            self.selected_widget,
+            SelectedWidget::ChatList | SelectedWidget::MessageList | SelectedWidget::Input
+        ) {
+            return;
+        }
+
+        let position = Position::new(event.column, event.row);
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => self.click(position),
+            MouseEventKind::ScrollUp if self.mouse.message_list.contains(position) => {
+                self.message_list_state.offset =
+                    self.message_list_state.offset.saturating_add(WHEEL_LINES);
+            }
+            MouseEventKind::ScrollDown if self.mouse.message_list.contains(position) => {
+                self.message_list_state.offset =
+                    self.message_list_state.offset.saturating_sub(WHEEL_LINES);
+            }
+            _ => {}
+        }
+    }
+
+    fn click(&mut self, position: Position) {
+        if self.mouse.chat_list.contains(position) {
+            self.selected_widget = SelectedWidget::ChatList;
+            self.contact_search_active = false;
+            let shown = if self.contact_search.input.is_empty() {
+                self.sorted_chats.len()
+            } else {
+                self.filtered_chats.len()
+            };
+            let index =
+                self.chat_list_state.offset() + (position.y - self.mouse.chat_list.y) as usize;
+            if index < shown && self.chat_list_state.selected() != Some(index) {
+                self.chat_list_state.select(Some(index));
+                self.open_selected_chat();
+            }
+        } else if self.mouse.message_list.contains(position) {
+            self.selected_widget = SelectedWidget::MessageList;
+            let Some((index, id)) = self.mouse.message_at(position) else {
+                return;
+            };
+            let now = Instant::now();
+            let double =
+                self.mouse.last_click.as_ref().is_some_and(|(at, last)| {
+                    *last == id && now.duration_since(*at) < DOUBLE_CLICK
+                });
+            // Selected in place, without scrolling it into view like the keys do.
+            self.message_list_state.set_selected_message(id.clone());
+            self.message_list_state.selected = Some(index);
+            if double {
+                self.mouse.last_click = None;
+                self.run_action(Action::ViewMessage);
+            } else {
+                self.mouse.last_click = Some((now, id));
+            }
+        } else if self.mouse.input.contains(position) {
+            self.selected_widget = SelectedWidget::Input;
+        }
+    }
+}
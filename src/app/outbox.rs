@@ -0,0 +1,226 @@
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{error, info, warn};
+use whatsrust as wr;
+
+use crate::app::events::{AppEvent, AppInput};
+use crate::app::{App, FileMeta, Metadata};
+use crate::backend::Backend;
+
+/// How many times a message is tried before it is marked as failed.
+pub const SEND_ATTEMPTS: u32 = 5;
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// A message waiting to be sent. It is shown in its chat under a local id until
+/// the server accepts it and echoes it back with its real id.
+#[derive(Clone, Debug)]
+pub struct OutboxEntry {
+    pub id: wr::MessageId,
+    pub chat: wr::JID,
+    pub content: wr::MessageContent,
+    pub quote_id: Option<wr::MessageId>,
+    pub mentions: Vec<wr::JID>,
+    pub timestamp: i64,
+    pub attempts: u32,
+    /// Set once all attempts failed. Failed messages wait for the user to resend
+    /// or discard them.
+    pub failed: bool,
+}
+
+pub struct OutboxJob {
+    pub entry: OutboxEntry,
+    pub quote: Option<wr::Message>,
+}
+
+/// Sends queued messages one at a time, so they arrive in the order they were written.
+/// A failing message is retried with exponential backoff, starting at `retry_delay`.
+pub fn spawn_worker(
+    backend: Arc<dyn Backend>,
+    app_tx: mpsc::Sender<AppInput>,
+    retry_delay: Duration,
+) -> mpsc::Sender<OutboxJob> {
+    let (outbox_tx, outbox_rx) = mpsc::channel::<OutboxJob>();
+    thread::spawn(move || {
+        for job in outbox_rx {
+            let mut attempts = 0;
+            loop {
+                attempts += 1;
+                let result = backend.send_message(
+                    &job.entry.chat,
+                    &job.entry.content,
+                    job.quote.as_ref(),
+                    &job.entry.mentions,
+                );
+                let event = match result {
+                    Ok(message_id) => AppEvent::MessageSent(job.entry.id.clone(), message_id),
+                    Err(err) => AppEvent::SendFailed {
+                        id: job.entry.id.clone(),
+                        attempts,
+                        error: err,
+                        retrying: attempts < SEND_ATTEMPTS,
+                    },
+                };
+                let done = matches!(event, AppEvent::MessageSent(..)) || attempts >= SEND_ATTEMPTS;
+                if app_tx.send(AppInput::App(event)).is_err() || done {
+                    break;
+                }
+                thread::sleep((retry_delay * 2u32.pow(attempts - 1)).min(MAX_RETRY_DELAY));
+            }
+        }
+    });
+    outbox_tx
+}
+
+impl App<'_> {
+    /// Puts a message in the outbox and shows it as pending until it is sent. Returns
+    /// the local id it is shown under.
+    pub fn queue_message(
+        &mut self,
+        chat: wr::JID,
+        content: wr::MessageContent,
+        mentions: Vec<wr::JID>,
+    ) -> wr::MessageId {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let entry = OutboxEntry {
+            id: format!("OUTBOX-{:X}", nanos.as_nanos()).into(),
+            chat,
+            content,
+            quote_id: self.quoting_message.as_ref().map(|m| m.info.id.clone()),
+            mentions,
+            timestamp: nanos.as_secs() as i64,
+            attempts: 0,
+            failed: false,
+        };
+
+        self.db_handler.save_outbox_entry(&entry);
+        self.show_outbox_entry(&entry);
+        self.outbox.insert(entry.id.clone(), entry.clone());
+        let id = entry.id.clone();
+        self.send_outbox_entry(entry);
+        id
+    }
+
+    /// Re-queues the messages that were still in the outbox when the app was closed.
+    pub(super) fn load_outbox(&mut self) {
+        for entry in self.db_handler.get_outbox() {
+            self.show_outbox_entry(&entry);
+            self.outbox.insert(entry.id.clone(), entry.clone());
+            if !entry.failed {
+                self.send_outbox_entry(entry);
+            }
+        }
+    }
+
+    pub fn resend(&mut self, id: &wr::MessageId) {
+        let Some(entry) = self.outbox.get_mut(id).filter(|entry| entry.failed) else {
+            return;
+        };
+        entry.failed = false;
+        entry.attempts = 0;
+        let entry = entry.clone();
+        self.db_handler.save_outbox_entry(&entry);
+        if let Some(message) = self.messages.get_mut(id) {
+            message.info.delivery = wr::DeliveryState::Pending;
+        }
+        self.send_outbox_entry(entry);
+    }
+
+    /// Drops a failed message from the outbox without sending it.
+    pub fn discard(&mut self, id: &wr::MessageId) {
+        if self.outbox.get(id).is_some_and(|entry| entry.failed) {
+            self.remove_outbox_entry(id);
+        }
+    }
+
+    pub(super) fn on_message_sent(&mut self, id: &wr::MessageId, message_id: &wr::MessageId) {
+        info!("Outbox message {id} sent as {message_id}");
+        self.remove_outbox_entry(id);
+    }
+
+    pub(super) fn on_send_failed(
+        &mut self,
+        id: &wr::MessageId,
+        attempts: u32,
+        error: &wr::Error,
+        retrying: bool,
+    ) {
+        let Some(entry) = self.outbox.get_mut(id) else {
+            return;
+        };
+        if retrying {
+            warn!("Sending {id} failed (attempt {attempts}), retrying: {error}");
+        } else {
+            error!("Sending {id} failed after {attempts} attempts: {error}");
+        }
+
+        entry.attempts = attempts;
+        entry.failed = !retrying;
+        self.db_handler.save_outbox_entry(entry);
+        if !retrying && let Some(message) = self.messages.get_mut(id) {
+            message.info.delivery = wr::DeliveryState::Failed;
+        }
+    }
+
+    /// Queues an entry for sending, with the message it quotes read from the database
+    /// if its page is not, as for entries resent at startup.
+    fn send_outbox_entry(&mut self, entry: OutboxEntry) {
+        let quote = entry
+            .quote_id
+            .as_ref()
+            .filter(|quote_id| self.load_message(quote_id))
+            .and_then(|quote_id| self.messages.get(quote_id))
+            .cloned();
+        if let Some(outbox_tx) = &self.outbox_tx {
+            outbox_tx.send(OutboxJob { entry, quote }).unwrap();
+        }
+    }
+
+    /// Adds the placeholder message for an outbox entry. It lives only in memory,
+    /// the outbox table is what gets persisted.
+    fn show_outbox_entry(&mut self, entry: &OutboxEntry) {
+        let Some(sender) = self.backend.self_jid() else {
+            error!("Cannot show outgoing message {} before pairing", entry.id);
+            return;
+        };
+
+        if let wr::MessageContent::File(_) = entry.content {
+            // The file is still on disk where the user picked it, nothing to download.
+            self.metadata
+                .insert(entry.id.clone(), Metadata::File(FileMeta::Downloaded));
+        }
+        self.add_message(wr::Message {
+            info: wr::MessageInfo {
+                id: entry.id.clone(),
+                chat: entry.chat.clone(),
+                sender,
+                timestamp: entry.timestamp,
+                is_from_me: true,
+                quote_id: entry.quote_id.clone(),
+                delivery: if entry.failed {
+                    wr::DeliveryState::Failed
+                } else {
+                    wr::DeliveryState::Pending
+                },
+                edit_state: wr::EditState::Original,
+                mentions: entry.mentions.clone(),
+            },
+            message: entry.content.clone(),
+        });
+    }
+
+    fn remove_outbox_entry(&mut self, id: &wr::MessageId) {
+        let Some(entry) = self.outbox.remove(id) else {
+            return;
+        };
+        self.db_handler.remove_outbox_entry(id);
+        self.messages.remove(id);
+        self.metadata.remove(id);
+        if let Some(chat_messages) = self.chat_messages.get_mut(&entry.chat) {
+            chat_messages.retain(|message_id| message_id != id);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Profiles keep separate accounts apart. Each one has its own databases, media and
+//! socket, in `profiles/<name>` under the data directory. The default profile is the
+//! data directory itself, as it was before there were profiles.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ratatui::crossterm::event::KeyCode;
+
+use crate::app::actions::PROFILE_SWITCHER_ACTIONS;
+use crate::app::{App, SelectedWidget};
+use crate::key_handler::Key;
+use crate::ui::text_input::TextInput;
+
+/// The directory under the default profile that holds the others.
+const PROFILES_DIR: &str = "profiles";
+
+/// The data directory of `profile`, or of the default profile for `None`.
+pub fn profile_dir(base: &Path, profile: Option<&str>) -> PathBuf {
+    match profile {
+        Some(name) => base.join(PROFILES_DIR).join(name),
+        None => base.to_path_buf(),
+    }
+}
+
+/// The names of the profiles other than the default one, sorted.
+pub fn list_profiles(base: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(base.join(PROFILES_DIR)) else {
+        return Vec::new();
+    };
+    let mut names = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+/// Checks the name of a profile. It names a directory, so it cannot be empty, hold a
+/// path separator or start with a dot.
+pub fn parse_profile_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        Err("the name of a profile cannot be empty".to_string())
+    } else if name.starts_with('.') || name.contains(['/', '\\']) {
+        Err(format!("\"{name}\" is not a plain directory name"))
+    } else {
+        Ok(name.to_string())
+    }
+}
+
+/// The popup to switch to another profile, or to create one.
+pub struct ProfileSwitcher {
+    /// The data directory of the default profile.
+    pub base: PathBuf,
+    /// The profile the app runs on, `None` for the default one.
+    pub current: Option<String>,
+    /// Every profile, the default one first. Read again each time the popup opens.
+    pub profiles: Vec<Option<String>>,
+    pub selected: usize,
+    /// The name of the profile being created, while it is typed.
+    pub new_name: Option<TextInput>,
+    /// Why the last switch failed.
+    pub error: Option<String>,
+}
+
+impl ProfileSwitcher {
+    /// The switcher of an app keeping its data in `data_dir`, which is the directory of
+    /// a named profile if it is in a `profiles` directory.
+    pub fn for_data_dir(data_dir: &Path) -> Self {
+        let named = data_dir
+            .parent()
+            .filter(|parent| parent.file_name().is_some_and(|name| name == PROFILES_DIR))
+            .and_then(|parent| Some((parent.parent()?, data_dir.file_name()?.to_str()?)));
+        let (base, current) = match named {
+            Some((base, name)) => (base.to_path_buf(), Some(name.to_string())),
+            None => (data_dir.to_path_buf(), None),
+        };
+        Self {
+            base,
+            current,
+            profiles: Vec::new(),
+            selected: 0,
+            new_name: None,
+            error: None,
+        }
+    }
+
+    pub(super) fn move_selection(&mut self, by: isize) {
+        self.selected = self
+            .selected
+            .saturating_add_signed(by)
+            .min(self.profiles.len().saturating_sub(1));
+    }
+
+    fn reload(&mut self) {
+        self.profiles = std::iter::once(None)
+            .chain(list_profiles(&self.base).into_iter().map(Some))
+            .collect();
+        self.selected = self
+            .profiles
+            .iter()
+            .position(|profile| *profile == self.current)
+            .unwrap_or(0);
+    }
+}
+
+impl App<'_> {
+    pub fn open_profile_switcher(&mut self) {
+        self.profile_switcher.reload();
+        self.profile_switcher.new_name = None;
+        self.profile_switcher.error = None;
+        self.selected_widget = SelectedWidget::Profiles;
+    }
+
+    /// Starts typing the name of a new profile, in the switcher.
+    pub fn start_new_profile(&mut self) {
+        if self.selected_widget != SelectedWidget::Profiles {
+            self.open_profile_switcher();
+        }
+        self.profile_switcher.new_name = Some(TextInput::new());
+        self.profile_switcher.error = None;
+    }
+
+    pub(super) fn profile_switcher_on_event(&mut self, key: &Key) {
+        let switcher = &mut self.profile_switcher;
+        if let Some(input) = &mut switcher.new_name {
+            if self.kh.kp(&[Key::k(KeyCode::Esc)]) {
+                switcher.new_name = None;
+            } else if self.kh.kp(&[Key::k(KeyCode::Enter)]) {
+                match parse_profile_name(&input.input) {
+                    Ok(name) => self.switch_profile(Some(name)),
+                    Err(err) => switcher.error = Some(err),
+                }
+            } else {
+                match key.code {
+                    KeyCode::Char(c) => input.enter_char(c),
+                    KeyCode::Backspace => input.delete_char(),
+                    KeyCode::Left => input.move_cursor_left(),
+                    KeyCode::Right => input.move_cursor_right(),
+                    _ => {}
+                }
+            }
+        } else if self.kh.kp(&[Key::k(KeyCode::Esc)]) {
+            self.selected_widget = SelectedWidget::ChatList;
+        } else if self.kh.kp(&[Key::k(KeyCode::Down)]) {
+            switcher.move_selection(1);
+        } else if self.kh.kp(&[Key::k(KeyCode::Up)]) {
+            switcher.move_selection(-1);
+        } else if self.kh.kp(&[Key::k(KeyCode::Enter)]) {
+            let profile = switcher.profiles[switcher.selected].clone();
+            self.switch_profile(profile);
+        } else {
+            self.run_pressed(PROFILE_SWITCHER_ACTIONS);
+        }
+    }
+
+    /// Quits, to be opened again on `profile` by whoever runs the app. Nothing happens
+    /// if it is the profile the app runs on already.
+    fn switch_profile(&mut self, profile: Option<String>) {
+        let switcher = &mut self.profile_switcher;
+        if profile == switcher.current {
+            self.selected_widget = SelectedWidget::ChatList;
+            return;
+        }
+        let dir = profile_dir(&switcher.base, profile.as_deref());
+        if let Err(err) = fs::create_dir_all(&dir) {
+            switcher.error = Some(format!("Failed to create {}: {err}", dir.display()));
+            return;
+        }
+        self.switch_to = Some(dir);
+        self.db_handler.stop();
+        self.should_quit = true;
+    }
+}
@@ -0,0 +1,528 @@
+//! A JSON-RPC 2.0 API on a Unix socket in the data directory, so scripts and editor
+//! plugins can use the session of a running wstui. Requests, responses and
+//! notifications are one JSON object per line.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, mpsc};
+use std::{fs, thread};
+
+use log::{error, info};
+use serde_json::{Value, json};
+use whatsrust as wr;
+
+use crate::app::App;
+use crate::app::events::AppInput;
+use crate::cli::{file_message, parse_time};
+use crate::db::match_chats;
+use crate::export::{chat_json, message_json};
+use crate::import::parse_address;
+
+/// The name of the socket in the data directory.
+pub const SOCKET_NAME: &str = "wstui.sock";
+
+/// A request read from a connection. Requests without an id are notifications and
+/// get no response.
+#[derive(Debug)]
+pub struct RpcRequest {
+    pub id: Option<Value>,
+    pub method: String,
+    pub params: Value,
+}
+
+#[derive(Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn parse_error(message: impl Into<String>) -> Self {
+        Self {
+            code: -32700,
+            message: message.into(),
+        }
+    }
+
+    fn invalid_request(message: impl Into<String>) -> Self {
+        Self {
+            code: -32600,
+            message: message.into(),
+        }
+    }
+
+    fn method_not_found(method: &str) -> Self {
+        Self {
+            code: -32601,
+            message: format!("there is no method \"{method}\""),
+        }
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self {
+            code: -32602,
+            message: message.into(),
+        }
+    }
+
+    /// The request was understood but could not be carried out.
+    fn failed(message: impl Into<String>) -> Self {
+        Self {
+            code: -32000,
+            message: message.into(),
+        }
+    }
+}
+
+/// One connection to the socket, to answer its requests and send it notifications.
+#[derive(Clone, Debug)]
+pub struct RpcClient {
+    id: u64,
+    tx: mpsc::Sender<String>,
+}
+
+impl RpcClient {
+    pub fn respond(&self, id: Option<Value>, result: Result<Value, RpcError>) {
+        let Some(id) = id else {
+            return;
+        };
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": err.code, "message": err.message },
+            }),
+        };
+        let _ = self.tx.send(response.to_string());
+    }
+
+    /// Returns false once the connection is closed.
+    pub fn notify(&self, method: &str, params: Value) -> bool {
+        let notification = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        self.tx.send(notification.to_string()).is_ok()
+    }
+}
+
+/// Listens on the socket until dropped, when the socket is removed.
+pub struct RpcServer {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+}
+
+impl RpcServer {
+    /// Listens on `path` and passes every request to the app through `app_tx`. Fails if
+    /// another wstui already listens there; a socket left behind by one that crashed is
+    /// replaced.
+    pub fn listen(path: &Path, app_tx: mpsc::Sender<AppInput>) -> io::Result<Self> {
+        if path.exists() {
+            if is_listening(path) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("another wstui is listening on {}", path.display()),
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+        let listener = bind_private(path)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let next_id = AtomicU64::new(0);
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    match stream {
+                        Ok(stream) => {
+                            let id = next_id.fetch_add(1, Ordering::Relaxed);
+                            serve(id, stream, app_tx.clone());
+                        }
+                        Err(err) => error!("Failed to accept a control connection: {err}"),
+                    }
+                }
+            });
+        }
+        info!("Listening for JSON-RPC requests on {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            stop,
+        })
+    }
+}
+
+/// Whether a running wstui listens on the socket at `path`, rather than it being left
+/// behind by one that crashed.
+pub fn is_listening(path: &Path) -> bool {
+    UnixStream::connect(path).is_ok()
+}
+
+/// Binds a socket at `path` that only we can connect to, since whoever can connect can
+/// read and send messages as us. The socket is bound in a directory only we can enter
+/// and moved to `path` once its own permissions are restricted, so it is never
+/// reachable with the permissions the umask gives it.
+fn bind_private(path: &Path) -> io::Result<UnixListener> {
+    let mut dir_name = path.file_name().unwrap_or_default().to_os_string();
+    dir_name.push(".tmp");
+    let dir = path.with_file_name(dir_name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::DirBuilder::new().mode(0o700).create(&dir)?;
+
+    let bound = dir.join("socket");
+    let listener = UnixListener::bind(&bound).and_then(|listener| {
+        fs::set_permissions(&bound, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&bound, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_dir_all(&dir);
+    listener
+}
+
+impl Drop for RpcServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wakes up the listening thread so it sees it has to stop.
+        let _ = UnixStream::connect(&self.path);
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Reads the requests of a connection on one thread and writes what is sent to the
+/// client on another, so notifications never wait for a request.
+fn serve(id: u64, stream: UnixStream, app_tx: mpsc::Sender<AppInput>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(err) => {
+            error!("Failed to set up a control connection: {err}");
+            return;
+        }
+    };
+    let (tx, rx) = mpsc::channel::<String>();
+    let client = RpcClient { id, tx };
+
+    thread::spawn(move || {
+        for line in rx {
+            if writeln!(writer, "{line}").is_err() {
+                break;
+            }
+        }
+    });
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_request(&line) {
+                Ok(request) => {
+                    let input = AppInput::Rpc {
+                        client: client.clone(),
+                        request,
+                    };
+                    if app_tx.send(input).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => client.respond(Some(Value::Null), Err(err)),
+            }
+        }
+    });
+}
+
+fn parse_request(line: &str) -> Result<RpcRequest, RpcError> {
+    let value = serde_json::from_str::<Value>(line)
+        .map_err(|err| RpcError::parse_error(format!("invalid JSON: {err}")))?;
+    let Value::Object(mut object) = value else {
+        return Err(RpcError::invalid_request("a request must be an object"));
+    };
+    if object
+        .get("jsonrpc")
+        .is_some_and(|version| version != "2.0")
+    {
+        return Err(RpcError::invalid_request("only JSON-RPC 2.0 is supported"));
+    }
+    let Some(Value::String(method)) = object.remove("method") else {
+        return Err(RpcError::invalid_request("the method is missing"));
+    };
+    Ok(RpcRequest {
+        id: object.remove("id"),
+        method,
+        params: object.remove("params").unwrap_or(Value::Null),
+    })
+}
+
+/// The object an event is sent as to subscribers, and printed as by `wstui tail`.
+pub(crate) fn event_json(event: &wr::Event) -> Value {
+    match event {
+        wr::Event::SyncProgress(percent) => json!({ "type": "sync_progress", "percent": percent }),
+        wr::Event::AppStateSyncComplete => json!({ "type": "app_state_sync_complete" }),
+        wr::Event::Receipt {
+            kind,
+            chat,
+            sender,
+            message_ids,
+            timestamp,
+        } => json!({
+            "type": "receipt",
+            "kind": match kind {
+                wr::ReceiptKind::Delivered => "delivered",
+                wr::ReceiptKind::Read => "read",
+                wr::ReceiptKind::ReadSelf => "read_self",
+                wr::ReceiptKind::Played => "played",
+                wr::ReceiptKind::PlayedSelf => "played_self",
+                wr::ReceiptKind::ServerError => "server_error",
+            },
+            "chat": chat.0.as_ref(),
+            "sender": sender.0.as_ref(),
+            "message_ids": message_ids.iter().map(|id| id.as_ref()).collect::<Vec<_>>(),
+            "timestamp": timestamp,
+        }),
+        wr::Event::Reaction(reaction) => json!({
+            "type": "reaction",
+            "chat": reaction.chat.0.as_ref(),
+            "sender": reaction.sender.0.as_ref(),
+            "message_id": reaction.message_id.as_ref(),
+            "emoji": reaction.emoji.as_ref(),
+            "from_me": reaction.is_from_me,
+            "timestamp": reaction.timestamp,
+        }),
+        wr::Event::MessageEdit {
+            chat,
+            sender,
+            message_id,
+            text,
+            timestamp,
+        } => json!({
+            "type": "edit",
+            "chat": chat.0.as_ref(),
+            "sender": sender.0.as_ref(),
+            "message_id": message_id.as_ref(),
+            "text": text.as_ref(),
+            "timestamp": timestamp,
+        }),
+        wr::Event::MessageRevoke {
+            chat,
+            sender,
+            message_id,
+            timestamp,
+        } => json!({
+            "type": "revoke",
+            "chat": chat.0.as_ref(),
+            "sender": sender.0.as_ref(),
+            "message_id": message_id.as_ref(),
+            "timestamp": timestamp,
+        }),
+        wr::Event::GroupChange {
+            chat,
+            sender,
+            change,
+            timestamp,
+        } => json!({
+            "type": "group_change",
+            "chat": chat.0.as_ref(),
+            "sender": sender.as_ref().map(|sender| sender.0.as_ref()),
+            "kind": match change.kind {
+                wr::GroupChangeKind::Join => "join",
+                wr::GroupChangeKind::Leave => "leave",
+                wr::GroupChangeKind::Promote => "promote",
+                wr::GroupChangeKind::Demote => "demote",
+                wr::GroupChangeKind::Subject => "subject",
+                wr::GroupChangeKind::Description => "description",
+            },
+            "participants": change.participants.iter().map(|jid| jid.0.as_ref()).collect::<Vec<_>>(),
+            "text": change.text.as_ref(),
+            "timestamp": timestamp,
+        }),
+        wr::Event::ChatRead {
+            chat,
+            read,
+            timestamp,
+        } => json!({
+            "type": "chat_read",
+            "chat": chat.0.as_ref(),
+            "read": read,
+            "timestamp": timestamp,
+        }),
+    }
+}
+
+fn str_param<'a>(params: &'a Value, name: &str) -> Result<Option<&'a str>, RpcError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(_) => Err(RpcError::invalid_params(format!("{name} must be a string"))),
+    }
+}
+
+fn required_str_param<'a>(params: &'a Value, name: &str) -> Result<&'a str, RpcError> {
+    str_param(params, name)?.ok_or_else(|| RpcError::invalid_params(format!("{name} is missing")))
+}
+
+/// A time given as a Unix timestamp, or as a string like `wstui messages --since`
+/// takes.
+fn time_param(params: &Value, name: &str) -> Result<Option<i64>, RpcError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => parse_time(value)
+            .map(Some)
+            .map_err(RpcError::invalid_params),
+        Some(value) => value.as_i64().map(Some).ok_or_else(|| {
+            RpcError::invalid_params(format!("{name} must be a timestamp or a string"))
+        }),
+    }
+}
+
+impl App<'_> {
+    /// Listens on the socket of the data directory, unless another wstui does.
+    pub(super) fn start_rpc_server(&mut self) {
+        match RpcServer::listen(&self.rpc_socket, self.tx.clone()) {
+            Ok(server) => self.rpc_server = Some(server),
+            Err(err) => error!("Not listening for JSON-RPC requests: {err}"),
+        }
+    }
+
+    pub(super) fn on_rpc_request(&mut self, client: RpcClient, request: RpcRequest) {
+        let params = &request.params;
+        let result = match request.method.as_str() {
+            "send" => self.rpc_send(params),
+            "chats" => Ok(self.rpc_chats()),
+            "messages" => self.rpc_messages(params),
+            "mark_read" => self
+                .rpc_chat(params)
+                .map(|chat| json!({ "messages": self.mark_chat_read(&chat) })),
+            "subscribe" => {
+                self.rpc_subscribers.retain(|c| c.id != client.id);
+                self.rpc_subscribers.push(client.clone());
+                Ok(json!(true))
+            }
+            "unsubscribe" => {
+                self.rpc_subscribers.retain(|c| c.id != client.id);
+                Ok(json!(true))
+            }
+            method => Err(RpcError::method_not_found(method)),
+        };
+        client.respond(request.id, result);
+    }
+
+    /// Tells the subscribers about a message that was just received or sent.
+    pub(super) fn publish_message(&mut self, message: &wr::Message) {
+        if self.rpc_subscribers.is_empty() {
+            return;
+        }
+        let params = message_json(
+            &self.db_handler,
+            &self.media_path,
+            self.backend.self_jid().as_ref(),
+            &self.contacts,
+            message,
+        );
+        self.rpc_subscribers
+            .retain(|client| client.notify("message", params.clone()));
+    }
+
+    pub(super) fn publish_event(&mut self, event: &wr::Event) {
+        if self.rpc_subscribers.is_empty() {
+            return;
+        }
+        let params = event_json(event);
+        self.rpc_subscribers
+            .retain(|client| client.notify("event", params.clone()));
+    }
+
+    /// The chat in the `chat` parameter: its JID, a phone number or a contact name.
+    fn rpc_chat(&self, params: &Value) -> Result<wr::JID, RpcError> {
+        let query = required_str_param(params, "chat")?;
+        let chats = self.chats.keys().cloned().collect::<Vec<_>>();
+        let mut chats = match_chats(query, &chats, &self.contacts);
+        match chats.len() {
+            0 => parse_address(query)
+                .ok_or_else(|| RpcError::failed(format!("no chat matches \"{query}\""))),
+            1 => Ok(chats.remove(0)),
+            _ => {
+                let candidates = chats
+                    .iter()
+                    .map(|jid| jid.0.as_ref())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                Err(RpcError::failed(format!(
+                    "\"{query}\" matches more than one chat: {candidates}"
+                )))
+            }
+        }
+    }
+
+    /// Puts a message in the outbox, like sending it from the input does. The result is
+    /// the id it is shown under until the server accepts it.
+    fn rpc_send(&mut self, params: &Value) -> Result<Value, RpcError> {
+        let chat = self.rpc_chat(params)?;
+        let text = str_param(params, "text")?.unwrap_or_default();
+        let content = match str_param(params, "file")? {
+            Some(path) => file_message(Path::new(path), text)
+                .map_err(|err| RpcError::failed(format!("{path}: {err}")))?,
+            None if text.is_empty() => {
+                return Err(RpcError::invalid_params("there is no text or file to send"));
+            }
+            None => wr::MessageContent::Text(text.into()),
+        };
+
+        let quoting = self.quoting_message.take();
+        let id = self.queue_message(chat, content, Vec::new());
+        self.quoting_message = quoting;
+        Ok(json!({ "id": id.as_ref() }))
+    }
+
+    fn rpc_chats(&self) -> Value {
+        let chats = self
+            .sorted_chats
+            .iter()
+            .filter_map(|jid| self.chats.get(jid))
+            .map(|chat| chat_json(&self.contacts, chat))
+            .collect();
+        Value::Array(chats)
+    }
+
+    /// The messages of a chat from `since` up to `until`, the `limit` latest if given.
+    fn rpc_messages(&self, params: &Value) -> Result<Value, RpcError> {
+        let chat = self.rpc_chat(params)?;
+        let since = time_param(params, "since")?;
+        let until = time_param(params, "until")?;
+        let limit = match params.get("limit") {
+            None | Some(Value::Null) => None,
+            Some(limit) => Some(
+                limit
+                    .as_u64()
+                    .ok_or_else(|| RpcError::invalid_params("limit must be a positive number"))?
+                    as usize,
+            ),
+        };
+
+        let saved = self.db_handler.get_messages_between(&chat, since, until);
+        let mut messages = self.with_unsaved_messages(&chat, saved, since, until);
+        if let Some(limit) = limit {
+            messages.drain(..messages.len().saturating_sub(limit));
+        }
+        let self_jid = self.backend.self_jid();
+        let messages = messages
+            .iter()
+            .map(|message| {
+                message_json(
+                    &self.db_handler,
+                    &self.media_path,
+                    self_jid.as_ref(),
+                    &self.contacts,
+                    message,
+                )
+            })
+            .collect();
+        Ok(Value::Array(messages))
+    }
+}
@@ -0,0 +1,102 @@
+use ratatui::crossterm::event::KeyCode;
+use ratatui::widgets::ListState;
+use whatsrust as wr;
+
+use crate::app::{App, SelectedWidget};
+use crate::db::SearchHit;
+use crate::key_handler::Key;
+use crate::ui::text_input::TextInput;
+
+/// How many hits the search lists at most.
+pub const MAX_SEARCH_HITS: usize = 100;
+
+/// The search across the messages of every chat.
+pub struct MessageSearch {
+    pub input: TextInput,
+    pub hits: Vec<SearchHit>,
+    pub list_state: ListState,
+}
+
+impl Default for MessageSearch {
+    fn default() -> Self {
+        Self {
+            input: TextInput::new(),
+            hits: Vec::new(),
+            list_state: ListState::default(),
+        }
+    }
+}
+
+impl App<'_> {
+    /// Opens the search, with the last query and its hits still there.
+    pub fn open_search(&mut self) {
+        self.update_search_hits();
+        self.selected_widget = SelectedWidget::Search;
+    }
+
+    pub(super) fn search_on_event(&mut self, key: &Key) {
+        if self.kh.kp(&[Key::k(KeyCode::Esc)]) {
+            self.selected_widget = SelectedWidget::ChatList;
+        } else if self.kh.kp(&[Key::k(KeyCode::Down)]) {
+            self.search.list_state.select_next();
+        } else if self.kh.kp(&[Key::k(KeyCode::Up)]) {
+            self.search.list_state.select_previous();
+        } else if self.kh.kp(&[Key::k(KeyCode::Enter)]) {
+            if let Some(hit) = self
+                .search
+                .list_state
+                .selected()
+                .and_then(|index| self.search.hits.get(index))
+                .cloned()
+            {
+                self.jump_to_message(&hit.message.info.chat, &hit.message.info.id);
+            }
+        } else {
+            match key.code {
+                KeyCode::Char(c) => {
+                    self.search.input.enter_char(c);
+                    self.update_search_hits();
+                }
+                KeyCode::Backspace => {
+                    self.search.input.delete_char();
+                    self.update_search_hits();
+                }
+                KeyCode::Left => self.search.input.move_cursor_left(),
+                KeyCode::Right => self.search.input.move_cursor_right(),
+                _ => {}
+            }
+        }
+    }
+
+    fn update_search_hits(&mut self) {
+        self.search.hits = self
+            .db_handler
+            .search_messages(&self.search.input.input, MAX_SEARCH_HITS);
+        self.search
+            .list_state
+            .select((!self.search.hits.is_empty()).then_some(0));
+    }
+
+    /// Opens `chat` and selects the message with `id`, reading older pages of the chat
+    /// until it is among them.
+    pub fn jump_to_message(&mut self, chat: &wr::JID, id: &wr::MessageId) {
+        if self.get_selected_chat().as_ref() != Some(chat) {
+            self.contact_search_active = false;
+            self.contact_search.clean();
+            self.select_chat(Some(chat.clone()));
+            self.load_group_info();
+        }
+        while !self
+            .chat_messages
+            .get(chat)
+            .is_some_and(|messages| messages.contains(id))
+            && self.has_older_messages(chat)
+        {
+            self.load_older_messages(chat);
+        }
+
+        self.message_list_state.reset();
+        self.message_list_state.set_selected_message(id.clone());
+        self.selected_widget = SelectedWidget::MessageList;
+    }
+}
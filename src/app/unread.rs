@@ -0,0 +1,168 @@
+//! How many messages of each chat were not read yet. Opening a chat reads them, sending
+//! read receipts, and reading a chat on another device reads it here too.
+
+use std::collections::HashMap;
+use std::sync::{Arc, mpsc};
+use std::thread;
+
+use log::error;
+use whatsrust as wr;
+
+use crate::app::{App, SelectedWidget};
+use crate::backend::Backend;
+
+/// How many of the latest messages of a chat are looked at to find the unread ones.
+const MARK_READ_LIMIT: usize = 100;
+
+/// Read receipts for messages one person sent in a chat.
+pub struct ReadReceipts {
+    pub chat: wr::JID,
+    pub sender: wr::JID,
+    pub ids: Vec<wr::MessageId>,
+}
+
+/// Sends read receipts one batch at a time on a single thread, so reading a chat never
+/// waits for the server. The messages are read here either way, failures are only
+/// logged.
+pub fn spawn_worker(backend: Arc<dyn Backend>) -> mpsc::Sender<ReadReceipts> {
+    let (read_tx, read_rx) = mpsc::channel::<ReadReceipts>();
+    thread::spawn(move || {
+        for receipts in read_rx {
+            if let Err(err) = backend.mark_read(&receipts.chat, &receipts.sender, &receipts.ids) {
+                error!("Failed to mark {:?} as read: {err}", receipts.ids);
+            }
+        }
+    });
+    read_tx
+}
+
+/// How many messages of a chat with `unread` ones are unread once it was read, or
+/// marked as unread, on another device. Marking it unread leaves at least one.
+pub(crate) fn unread_after_read_elsewhere(unread: usize, read: bool) -> usize {
+    if read { 0 } else { unread.max(1) }
+}
+
+impl App<'_> {
+    /// Sets how many messages of `chat` were not read yet, and saves it.
+    pub fn set_unread(&mut self, chat: &wr::JID, count: usize) {
+        if let Some(entry) = self.chats.get_mut(chat)
+            && entry.unread != count
+        {
+            entry.unread = count;
+            self.db_handler.set_unread(chat, count);
+        }
+    }
+
+    pub(super) fn unread(&self, chat: &wr::JID) -> usize {
+        self.chats.get(chat).map_or(0, |chat| chat.unread)
+    }
+
+    /// Whether the user is in the selected chat, rather than choosing one or in a popup.
+    fn in_selected_chat(&self) -> bool {
+        matches!(
+            self.selected_widget,
+            SelectedWidget::MessageList | SelectedWidget::Input
+        )
+    }
+
+    /// Counts a message that just came in as unread, unless the user is in its chat and
+    /// reads it right away. Writing in a chat, from any device, reads it.
+    pub(super) fn count_new_message(&mut self, message: &wr::Message) {
+        let chat = &message.info.chat;
+        if message.info.is_from_me {
+            self.set_unread(chat, 0);
+        } else if self.in_selected_chat() && self.get_selected_chat().as_ref() == Some(chat) {
+            self.send_read_receipts(chat, std::slice::from_ref(message));
+        } else {
+            self.set_unread(chat, self.unread(chat) + 1);
+        }
+    }
+
+    /// Reads the unread messages of the selected chat, remembering the first of them to
+    /// draw the new messages divider above it.
+    pub(super) fn read_selected_chat(&mut self) {
+        let Some(chat) = self.get_selected_chat() else {
+            return;
+        };
+        if self.unread(&chat) == 0 {
+            return;
+        }
+        self.new_messages = self
+            .unread_messages(&chat)
+            .first()
+            .map(|message| message.info.id.clone());
+        self.mark_chat_read(&chat);
+    }
+
+    /// Sends read receipts for `messages` of `chat` from the receipts worker, a batch per
+    /// sender.
+    pub(super) fn send_read_receipts(&self, chat: &wr::JID, messages: &[wr::Message]) {
+        let Some(tx) = &self.read_tx else {
+            return;
+        };
+        let mut by_sender = HashMap::<wr::JID, Vec<wr::MessageId>>::new();
+        for message in messages {
+            by_sender
+                .entry(message.info.sender.clone())
+                .or_default()
+                .push(message.info.id.clone());
+        }
+        for (sender, ids) in by_sender {
+            tx.send(ReadReceipts {
+                chat: chat.clone(),
+                sender,
+                ids,
+            })
+            .unwrap();
+        }
+    }
+
+    /// Going into the selected chat reads it, like opening it does.
+    pub(super) fn read_selected_chat_if_in_it(&mut self) {
+        if self.in_selected_chat() {
+            self.read_selected_chat();
+        }
+    }
+
+    /// `chat` was read, or marked as unread, on another device.
+    pub(super) fn on_chat_read(&mut self, chat: &wr::JID, read: bool) {
+        let count = unread_after_read_elsewhere(self.unread(chat), read);
+        self.set_unread(chat, count);
+    }
+
+    /// The messages received in `chat` since we last wrote in it, among the latest
+    /// ones, oldest first.
+    fn received_since_last_sent(&self, chat: &wr::JID) -> Vec<wr::Message> {
+        let saved = self
+            .db_handler
+            .get_messages_before(chat, None, MARK_READ_LIMIT);
+        let messages = self.with_unsaved_messages(chat, saved, None, None);
+
+        let mut received = messages
+            .into_iter()
+            .rev()
+            .take_while(|message| !message.info.is_from_me)
+            // Group changes are not messages anyone sent.
+            .filter(|message| !matches!(message.message, wr::MessageContent::GroupChange(_)))
+            .collect::<Vec<_>>();
+        received.reverse();
+        received
+    }
+
+    /// The unread messages of `chat`, the latest of those received since we last wrote
+    /// in it, oldest first.
+    fn unread_messages(&self, chat: &wr::JID) -> Vec<wr::Message> {
+        let mut received = self.received_since_last_sent(chat);
+        let read = received.len().saturating_sub(self.unread(chat));
+        received.split_off(read)
+    }
+
+    /// Sends read receipts for the unread messages of `chat`, and returns how many
+    /// there were. The chat has no unread messages after.
+    pub fn mark_chat_read(&mut self, chat: &wr::JID) -> usize {
+        let unread = self.unread_messages(chat);
+        self.send_read_receipts(chat, &unread);
+        self.set_unread(chat, 0);
+        unread.len()
+    }
+}
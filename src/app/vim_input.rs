@@ -1,11 +1,9 @@
-use log::{error, info};
+use log::info;
 use ratatui::crossterm::event::KeyCode;
 use ratatui_textarea::CursorMove;
-use rfd::FileDialog;
-use whatsrust as wr;
 
 use crate::app::App;
-use crate::app::events::{AppEvent, AppInput};
+use crate::app::actions::{INPUT_ACTIONS, INPUT_NORMAL_ACTIONS};
 use crate::key_handler::Key;
 use crate::vim;
 use strum::{EnumIter, IntoEnumIterator};
@@ -36,32 +34,8 @@ impl App<'_> {
     }
 
     pub fn input_on_event(&mut self, key: &Key) {
-        if self.kh.kp(&[Key::ctrl('x')]) {
-            if let Some(c) = self.get_selected_chat() {
-                let text = self.input_widget.lines().join("\n");
-                let msg = if let Some((path, typ)) = &self.attached_file {
-                    wr::MessageContent::File(wr::FileContent {
-                        kind: typ.clone(),
-                        path: path.clone(),
-                        file_id: "".into(),
-                        caption: Some(text.into()),
-                    })
-                } else {
-                    wr::MessageContent::Text(text.into())
-                };
-
-                wr::send_message(&c, &msg, self.quoting_message.as_ref());
-
-                self.input_widget.select_all();
-                self.input_widget.delete_next_char();
-                self.quoting_message = None;
-                self.attached_file = None;
-            }
+        if self.run_pressed(INPUT_ACTIONS) {
             return;
-        } else if self.kh.kp(&[Key::ctrl('e')]) {
-            self.tx
-                .send(AppInput::App(AppEvent::EditWithExternalEditor))
-                .unwrap();
         }
 
         if self.vim.mode == vim::Mode::Normal {
@@ -76,6 +50,10 @@ impl App<'_> {
     }
 
     fn input_normal_on_event(&mut self) {
+        if self.run_pressed(INPUT_NORMAL_ACTIONS) {
+            return;
+        }
+
         if self.kh.kp(&[Key::c('i')]) {
             self.input_widget.cancel_selection();
             self.set_vim_mode(vim::Mode::Insert);
@@ -123,24 +101,6 @@ impl App<'_> {
             self.input_widget.undo();
         } else if self.kh.kp(&[Key::ctrl('r')]) {
             self.input_widget.redo();
-        } else if self.kh.kp(&[Key::c(' '), Key::c('r')]) {
-            self.quoting_message = None;
-        } else if self.kh.kp(&[Key::c(' '), Key::c('a'), Key::c('r')]) {
-            self.attached_file = None;
-        } else if self.kh.kp(&[Key::c(' '), Key::c('a'), Key::c('i')]) {
-            if let Some(path) = FileDialog::new().pick_file() {
-                self.attached_file = Some((path.to_str().unwrap().into(), wr::FileKind::Image));
-            }
-        } else if self.kh.kp(&[Key::c(' '), Key::c('a'), Key::c('d')]) {
-            if let Some(path) = FileDialog::new().pick_file() {
-                self.attached_file = Some((path.to_str().unwrap().into(), wr::FileKind::Document));
-            }
-        } else if self.kh.kp(&[Key::c(' '), Key::c('p')]) {
-            if let Ok(text) = self.clipboard.get_text() {
-                self.input_widget.insert_str(&text);
-            } else {
-                error!("Failed to get text from clipboard");
-            }
         }
 
         if self.kh.kp(&[Key::c('y'), Key::c('y')]) {
@@ -221,7 +181,25 @@ impl App<'_> {
             self.set_vim_mode(vim::Mode::Normal);
         }
 
+        let candidates = self.mention_candidates().len();
+        if candidates > 0 && self.vim.mode == vim::Mode::Insert {
+            if self.kh.kp(&[Key::k(KeyCode::Tab)]) {
+                self.complete_mention();
+                return;
+            }
+            if self.kh.kp(&[Key::k(KeyCode::Down)]) {
+                self.mention_index = (self.mention_index + 1) % candidates;
+                return;
+            }
+            if self.kh.kp(&[Key::k(KeyCode::Up)]) {
+                self.mention_index = (self.mention_index + candidates - 1) % candidates;
+                return;
+            }
+        }
+
         self.input_widget.input(key.clone());
+        self.mention_index = 0;
+        self.load_mention_participants();
     }
 
     fn input_visual_on_event(&mut self) {
@@ -0,0 +1,190 @@
+pub mod fake;
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use whatsrust as wr;
+
+pub use fake::FakeBackend;
+
+pub type LogHandler = Box<dyn FnMut(String, u8) + Send>;
+pub type EventHandler = Box<dyn FnMut(wr::Event) + Send>;
+pub type MessageHandler = Box<dyn FnMut(wr::Message, bool) + Send>;
+pub type QrHandler = Box<dyn FnMut(String) + Send>;
+
+/// Logs a message the backend logged, at its level: 0 for errors up to 4 for traces.
+pub fn log_backend(msg: &str, level: u8) {
+    let level = match level {
+        0 => log::Level::Error,
+        1 => log::Level::Warn,
+        2 => log::Level::Info,
+        3 => log::Level::Debug,
+        _ => log::Level::Trace,
+    };
+    log::log!(level, "{msg}");
+}
+
+/// Everything the app needs from a WhatsApp session.
+///
+/// `FfiBackend` forwards to the Go bridge in `whatsrust`, `FakeBackend` runs
+/// entirely in-process so the app can be driven without a phone or network.
+pub trait Backend: Send + Sync {
+    fn new_client(&self, db_path: &Path) -> Result<(), wr::Error>;
+
+    /// Blocks until the session is connected. `on_qr` is called with every QR code
+    /// that should be shown to the user while pairing.
+    fn connect(&self, on_qr: QrHandler) -> Result<(), wr::Error>;
+    fn disconnect(&self) -> Result<(), wr::Error>;
+    fn pair_phone(&self, phone: &str) -> Result<String, wr::Error>;
+    /// Our own JID, `None` until the device is paired.
+    fn self_jid(&self) -> Option<wr::JID>;
+
+    fn set_log_handler(&self, handler: LogHandler);
+    fn set_event_handler(&self, handler: EventHandler);
+    fn set_message_handler(&self, handler: MessageHandler);
+
+    /// Sends a message and returns the id the server gave it. The sent message is
+    /// also echoed through the message handler. Each of `mentions` must appear in
+    /// the text as `@` followed by the user part of its JID.
+    fn send_message(
+        &self,
+        jid: &wr::JID,
+        content: &wr::MessageContent,
+        quoted_message: Option<&wr::Message>,
+        mentions: &[wr::JID],
+    ) -> Result<wr::MessageId, wr::Error>;
+    /// Reacts to `message`. An empty `emoji` removes our reaction.
+    fn send_reaction(&self, message: &wr::Message, emoji: &str) -> Result<(), wr::Error>;
+    /// Replaces the text of one of our own messages.
+    fn edit_message(&self, message: &wr::Message, text: &str) -> Result<(), wr::Error>;
+    /// Deletes `message` for everyone.
+    fn revoke_message(&self, message: &wr::Message) -> Result<(), wr::Error>;
+    /// Sends read receipts for `message_ids`, all sent by `sender` in `chat`.
+    fn mark_read(
+        &self,
+        chat: &wr::JID,
+        sender: &wr::JID,
+        message_ids: &[wr::MessageId],
+    ) -> Result<(), wr::Error>;
+    fn get_contacts(&self) -> Result<Vec<(wr::JID, Arc<str>)>, wr::Error>;
+    fn get_chat_settings(&self, jid: &wr::JID) -> Result<wr::ChatSettings, wr::Error>;
+    /// Mutes `chat` for `duration`, or until it is unmuted when it is `None`.
+    fn mute_chat(&self, chat: &wr::JID, duration: Option<Duration>) -> Result<(), wr::Error>;
+    fn unmute_chat(&self, chat: &wr::JID) -> Result<(), wr::Error>;
+    /// The subject, description and participants of a group.
+    fn get_group_info(&self, jid: &wr::JID) -> Result<wr::GroupInfo, wr::Error>;
+    /// Adds, removes, promotes or demotes `participants` of `group`, as one of its
+    /// admins. The change comes back through the event handler.
+    fn update_group_participants(
+        &self,
+        group: &wr::JID,
+        participants: &[wr::JID],
+        action: wr::ParticipantAction,
+    ) -> Result<(), wr::Error>;
+    fn download_file(&self, file_id: &wr::FileId, base_path: &Path) -> Result<(), wr::Error>;
+}
+
+/// The real backend, backed by whatsmeow through the `whatsrust` FFI.
+pub struct FfiBackend;
+
+impl Backend for FfiBackend {
+    fn new_client(&self, db_path: &Path) -> Result<(), wr::Error> {
+        let db_path = db_path.to_str().ok_or_else(|| {
+            wr::Error::InvalidArgument(format!("{} is not valid UTF-8", db_path.display()).into())
+        })?;
+        wr::new_client(db_path)
+    }
+
+    fn connect(&self, on_qr: QrHandler) -> Result<(), wr::Error> {
+        wr::connect(on_qr)
+    }
+
+    fn disconnect(&self) -> Result<(), wr::Error> {
+        wr::disconnect()
+    }
+
+    fn pair_phone(&self, phone: &str) -> Result<String, wr::Error> {
+        wr::pair_phone(phone)
+    }
+
+    fn self_jid(&self) -> Option<wr::JID> {
+        wr::get_self_jid().ok()
+    }
+
+    fn set_log_handler(&self, handler: LogHandler) {
+        wr::set_log_handler(handler);
+    }
+
+    fn set_event_handler(&self, handler: EventHandler) {
+        wr::set_event_handler(handler);
+    }
+
+    fn set_message_handler(&self, handler: MessageHandler) {
+        wr::set_message_handler(handler);
+    }
+
+    fn send_message(
+        &self,
+        jid: &wr::JID,
+        content: &wr::MessageContent,
+        quoted_message: Option<&wr::Message>,
+        mentions: &[wr::JID],
+    ) -> Result<wr::MessageId, wr::Error> {
+        wr::send_message(jid, content, quoted_message, mentions)
+    }
+
+    fn send_reaction(&self, message: &wr::Message, emoji: &str) -> Result<(), wr::Error> {
+        wr::send_reaction(message, emoji)
+    }
+
+    fn edit_message(&self, message: &wr::Message, text: &str) -> Result<(), wr::Error> {
+        wr::edit_message(message, text)
+    }
+
+    fn revoke_message(&self, message: &wr::Message) -> Result<(), wr::Error> {
+        wr::revoke_message(message)
+    }
+
+    fn mark_read(
+        &self,
+        chat: &wr::JID,
+        sender: &wr::JID,
+        message_ids: &[wr::MessageId],
+    ) -> Result<(), wr::Error> {
+        wr::mark_read(chat, sender, message_ids)
+    }
+
+    fn get_contacts(&self) -> Result<Vec<(wr::JID, Arc<str>)>, wr::Error> {
+        wr::get_contacts()
+    }
+
+    fn get_chat_settings(&self, jid: &wr::JID) -> Result<wr::ChatSettings, wr::Error> {
+        wr::get_chat_settings(jid)
+    }
+
+    fn mute_chat(&self, chat: &wr::JID, duration: Option<Duration>) -> Result<(), wr::Error> {
+        wr::mute_chat(chat, duration)
+    }
+
+    fn unmute_chat(&self, chat: &wr::JID) -> Result<(), wr::Error> {
+        wr::unmute_chat(chat)
+    }
+
+    fn get_group_info(&self, jid: &wr::JID) -> Result<wr::GroupInfo, wr::Error> {
+        wr::get_group_info(jid)
+    }
+
+    fn update_group_participants(
+        &self,
+        group: &wr::JID,
+        participants: &[wr::JID],
+        action: wr::ParticipantAction,
+    ) -> Result<(), wr::Error> {
+        wr::update_group_participants(group, participants, action)
+    }
+
+    fn download_file(&self, file_id: &wr::FileId, base_path: &Path) -> Result<(), wr::Error> {
+        wr::download_file(file_id, base_path)
+    }
+}
@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use whatsrust as wr;
+
+use crate::backend::{Backend, EventHandler, LogHandler, MessageHandler, QrHandler};
+
+#[derive(Clone, Debug)]
+pub struct SentMessage {
+    pub jid: wr::JID,
+    pub content: wr::MessageContent,
+    pub quote_id: Option<wr::MessageId>,
+    pub mentions: Vec<wr::JID>,
+}
+
+#[derive(Default)]
+struct State {
+    connected: bool,
+    qr_codes: Vec<String>,
+    contacts: Vec<(wr::JID, Arc<str>)>,
+    chat_settings: HashMap<wr::JID, wr::ChatSettings>,
+    groups: HashMap<wr::JID, wr::GroupInfo>,
+    downloads: HashMap<wr::FileId, (PathBuf, Vec<u8>)>,
+    sent: Vec<SentMessage>,
+    sent_reactions: Vec<wr::Reaction>,
+    edits: Vec<(wr::MessageId, Arc<str>)>,
+    revokes: Vec<wr::MessageId>,
+    read_receipts: Vec<(wr::JID, wr::JID, Vec<wr::MessageId>)>,
+    failing_sends: usize,
+    new_client_log: Option<String>,
+    next_id: u64,
+}
+
+/// In-process stand-in for WhatsApp.
+///
+/// Tests script what the "server" does (incoming messages, events, contacts,
+/// downloadable files) and inspect what the app sent. Sent messages are echoed
+/// back through the message handler, the same way the Go bridge does.
+pub struct FakeBackend {
+    pub self_jid: wr::JID,
+    state: Mutex<State>,
+    log_handler: Mutex<Option<LogHandler>>,
+    event_handler: Mutex<Option<EventHandler>>,
+    message_handler: Mutex<Option<MessageHandler>>,
+}
+
+impl Default for FakeBackend {
+    fn default() -> Self {
+        Self::new("0@s.whatsapp.net".to_string().into())
+    }
+}
+
+impl FakeBackend {
+    pub fn new(self_jid: wr::JID) -> Self {
+        Self {
+            self_jid,
+            state: Mutex::new(State::default()),
+            log_handler: Mutex::new(None),
+            event_handler: Mutex::new(None),
+            message_handler: Mutex::new(None),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state.lock().unwrap().connected
+    }
+
+    /// QR codes handed to the `connect` callback, in order.
+    pub fn set_qr_codes(&self, codes: Vec<String>) {
+        self.state.lock().unwrap().qr_codes = codes;
+    }
+
+    pub fn set_contacts(&self, contacts: Vec<(wr::JID, Arc<str>)>) {
+        self.state.lock().unwrap().contacts = contacts;
+    }
+
+    pub fn set_chat_settings(&self, jid: wr::JID, settings: wr::ChatSettings) {
+        self.state
+            .lock()
+            .unwrap()
+            .chat_settings
+            .insert(jid, settings);
+    }
+
+    pub fn set_group_info(&self, info: wr::GroupInfo) {
+        self.state
+            .lock()
+            .unwrap()
+            .groups
+            .insert(info.jid.clone(), info);
+    }
+
+    /// Makes `file_id` downloadable. The file is written to `path` relative to the
+    /// media directory, like the Go bridge does with the target path in the file id.
+    pub fn add_download(&self, file_id: wr::FileId, path: PathBuf, data: Vec<u8>) {
+        self.state
+            .lock()
+            .unwrap()
+            .downloads
+            .insert(file_id, (path, data));
+    }
+
+    /// Logs `msg` whenever a client is created, like the Go bridge does on startup.
+    pub fn log_on_new_client(&self, msg: &str) {
+        self.state.lock().unwrap().new_client_log = Some(msg.to_string());
+    }
+
+    /// Makes the next `count` sends fail. Sends also fail while disconnected.
+    pub fn fail_next_sends(&self, count: usize) {
+        self.state.lock().unwrap().failing_sends = count;
+    }
+
+    pub fn sent_messages(&self) -> Vec<SentMessage> {
+        self.state.lock().unwrap().sent.clone()
+    }
+
+    pub fn sent_reactions(&self) -> Vec<wr::Reaction> {
+        self.state.lock().unwrap().sent_reactions.clone()
+    }
+
+    /// Edits sent by the app, as (message id, new text).
+    pub fn edits(&self) -> Vec<(wr::MessageId, Arc<str>)> {
+        self.state.lock().unwrap().edits.clone()
+    }
+
+    /// Ids of the messages the app deleted for everyone.
+    pub fn revokes(&self) -> Vec<wr::MessageId> {
+        self.state.lock().unwrap().revokes.clone()
+    }
+
+    /// Read receipts sent by the app, as (chat, sender, message ids).
+    pub fn read_receipts(&self) -> Vec<(wr::JID, wr::JID, Vec<wr::MessageId>)> {
+        self.state.lock().unwrap().read_receipts.clone()
+    }
+
+    pub fn receive_message(&self, message: wr::Message) {
+        self.deliver(message, false);
+    }
+
+    /// Delivers a message as if it came from a history sync.
+    pub fn sync_message(&self, message: wr::Message) {
+        self.deliver(message, true);
+    }
+
+    pub fn emit_event(&self, event: wr::Event) {
+        if let Some(handler) = self.event_handler.lock().unwrap().as_mut() {
+            handler(event);
+        }
+    }
+
+    pub fn log(&self, msg: &str, level: u8) {
+        if let Some(handler) = self.log_handler.lock().unwrap().as_mut() {
+            handler(msg.to_string(), level);
+        }
+    }
+
+    fn deliver(&self, message: wr::Message, is_sync: bool) {
+        if let Some(handler) = self.message_handler.lock().unwrap().as_mut() {
+            handler(message, is_sync);
+        }
+    }
+}
+
+impl Backend for FakeBackend {
+    fn new_client(&self, _db_path: &Path) -> Result<(), wr::Error> {
+        let log = self.state.lock().unwrap().new_client_log.clone();
+        if let Some(msg) = log {
+            self.log(&msg, 2);
+        }
+        Ok(())
+    }
+
+    fn connect(&self, mut on_qr: QrHandler) -> Result<(), wr::Error> {
+        let codes = std::mem::take(&mut self.state.lock().unwrap().qr_codes);
+        for code in codes {
+            on_qr(code);
+        }
+        self.state.lock().unwrap().connected = true;
+        Ok(())
+    }
+
+    fn disconnect(&self) -> Result<(), wr::Error> {
+        self.state.lock().unwrap().connected = false;
+        Ok(())
+    }
+
+    fn pair_phone(&self, phone: &str) -> Result<String, wr::Error> {
+        Ok(format!("FAKE-{phone}"))
+    }
+
+    fn self_jid(&self) -> Option<wr::JID> {
+        Some(self.self_jid.clone())
+    }
+
+    fn set_log_handler(&self, handler: LogHandler) {
+        *self.log_handler.lock().unwrap() = Some(handler);
+    }
+
+    fn set_event_handler(&self, handler: EventHandler) {
+        *self.event_handler.lock().unwrap() = Some(handler);
+    }
+
+    fn set_message_handler(&self, handler: MessageHandler) {
+        *self.message_handler.lock().unwrap() = Some(handler);
+    }
+
+    fn send_message(
+        &self,
+        jid: &wr::JID,
+        content: &wr::MessageContent,
+        quoted_message: Option<&wr::Message>,
+        mentions: &[wr::JID],
+    ) -> Result<wr::MessageId, wr::Error> {
+        let quote_id = quoted_message.map(|m| m.info.id.clone());
+        let id: wr::MessageId = {
+            let mut state = self.state.lock().unwrap();
+            if !state.connected {
+                return Err(wr::Error::NotConnected);
+            }
+            if state.failing_sends > 0 {
+                state.failing_sends -= 1;
+                return Err(wr::Error::Timeout);
+            }
+            state.sent.push(SentMessage {
+                jid: jid.clone(),
+                content: content.clone(),
+                quote_id: quote_id.clone(),
+                mentions: mentions.to_vec(),
+            });
+            state.next_id += 1;
+            format!("FAKE{:016X}", state.next_id).into()
+        };
+
+        let timestamp = now();
+
+        self.deliver(
+            wr::Message {
+                info: wr::MessageInfo {
+                    id: id.clone(),
+                    chat: jid.clone(),
+                    sender: self.self_jid.clone(),
+                    timestamp,
+                    is_from_me: true,
+                    quote_id,
+                    delivery: wr::DeliveryState::Sent,
+                    edit_state: wr::EditState::Original,
+                    mentions: mentions.to_vec(),
+                },
+                message: content.clone(),
+            },
+            false,
+        );
+        Ok(id)
+    }
+
+    fn send_reaction(&self, message: &wr::Message, emoji: &str) -> Result<(), wr::Error> {
+        let reaction = wr::Reaction {
+            chat: message.info.chat.clone(),
+            sender: self.self_jid.clone(),
+            message_id: message.info.id.clone(),
+            emoji: emoji.into(),
+            timestamp: now(),
+            is_from_me: true,
+        };
+        self.state
+            .lock()
+            .unwrap()
+            .sent_reactions
+            .push(reaction.clone());
+        self.emit_event(wr::Event::Reaction(reaction));
+        Ok(())
+    }
+
+    fn edit_message(&self, message: &wr::Message, text: &str) -> Result<(), wr::Error> {
+        self.state
+            .lock()
+            .unwrap()
+            .edits
+            .push((message.info.id.clone(), text.into()));
+        self.emit_event(wr::Event::MessageEdit {
+            chat: message.info.chat.clone(),
+            sender: self.self_jid.clone(),
+            message_id: message.info.id.clone(),
+            text: text.into(),
+            timestamp: now(),
+        });
+        Ok(())
+    }
+
+    fn revoke_message(&self, message: &wr::Message) -> Result<(), wr::Error> {
+        self.state
+            .lock()
+            .unwrap()
+            .revokes
+            .push(message.info.id.clone());
+        self.emit_event(wr::Event::MessageRevoke {
+            chat: message.info.chat.clone(),
+            sender: self.self_jid.clone(),
+            message_id: message.info.id.clone(),
+            timestamp: now(),
+        });
+        Ok(())
+    }
+
+    fn mark_read(
+        &self,
+        chat: &wr::JID,
+        sender: &wr::JID,
+        message_ids: &[wr::MessageId],
+    ) -> Result<(), wr::Error> {
+        self.state.lock().unwrap().read_receipts.push((
+            chat.clone(),
+            sender.clone(),
+            message_ids.to_vec(),
+        ));
+        Ok(())
+    }
+
+    fn get_contacts(&self) -> Result<Vec<(wr::JID, Arc<str>)>, wr::Error> {
+        Ok(self.state.lock().unwrap().contacts.clone())
+    }
+
+    fn get_chat_settings(&self, jid: &wr::JID) -> Result<wr::ChatSettings, wr::Error> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .chat_settings
+            .get(jid)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn mute_chat(&self, chat: &wr::JID, duration: Option<Duration>) -> Result<(), wr::Error> {
+        let muted_until = match duration {
+            Some(duration) => now() + duration.as_secs().max(1) as i64,
+            None => i64::MAX,
+        };
+        let mut state = self.state.lock().unwrap();
+        let settings = state.chat_settings.entry(chat.clone()).or_default();
+        settings.found = true;
+        settings.muted_until = muted_until;
+        Ok(())
+    }
+
+    fn unmute_chat(&self, chat: &wr::JID) -> Result<(), wr::Error> {
+        let mut state = self.state.lock().unwrap();
+        let settings = state.chat_settings.entry(chat.clone()).or_default();
+        settings.found = true;
+        settings.muted_until = 0;
+        Ok(())
+    }
+
+    fn get_group_info(&self, jid: &wr::JID) -> Result<wr::GroupInfo, wr::Error> {
+        self.state
+            .lock()
+            .unwrap()
+            .groups
+            .get(jid)
+            .cloned()
+            .ok_or_else(|| wr::Error::Other(format!("{} is not a group", jid.0).into()))
+    }
+
+    fn update_group_participants(
+        &self,
+        group: &wr::JID,
+        participants: &[wr::JID],
+        action: wr::ParticipantAction,
+    ) -> Result<(), wr::Error> {
+        let kind = {
+            let mut state = self.state.lock().unwrap();
+            if !state.connected {
+                return Err(wr::Error::NotConnected);
+            }
+            let info = state
+                .groups
+                .get_mut(group)
+                .ok_or_else(|| wr::Error::Other(format!("{} is not a group", group.0).into()))?;
+            let is_admin = info
+                .participants
+                .iter()
+                .any(|p| p.jid == self.self_jid && p.is_admin);
+            if !is_admin {
+                return Err(wr::Error::Other("forbidden: not a group admin".into()));
+            }
+
+            match action {
+                wr::ParticipantAction::Add => {
+                    info.participants
+                        .extend(participants.iter().map(|jid| wr::GroupParticipant {
+                            jid: jid.clone(),
+                            is_admin: false,
+                            is_super_admin: false,
+                        }));
+                    wr::GroupChangeKind::Join
+                }
+                wr::ParticipantAction::Remove => {
+                    info.participants.retain(|p| !participants.contains(&p.jid));
+                    wr::GroupChangeKind::Leave
+                }
+                wr::ParticipantAction::Promote | wr::ParticipantAction::Demote => {
+                    let promote = action == wr::ParticipantAction::Promote;
+                    for participant in &mut info.participants {
+                        if participants.contains(&participant.jid) {
+                            participant.is_admin = promote;
+                        }
+                    }
+                    if promote {
+                        wr::GroupChangeKind::Promote
+                    } else {
+                        wr::GroupChangeKind::Demote
+                    }
+                }
+            }
+        };
+
+        // The server tells every participant, us included, about the change.
+        self.emit_event(wr::Event::GroupChange {
+            chat: group.clone(),
+            sender: Some(self.self_jid.clone()),
+            change: wr::GroupChange {
+                kind,
+                participants: participants.to_vec(),
+                text: "".into(),
+            },
+            timestamp: now(),
+        });
+        Ok(())
+    }
+
+    fn download_file(&self, file_id: &wr::FileId, base_path: &Path) -> Result<(), wr::Error> {
+        let (path, data) = self
+            .state
+            .lock()
+            .unwrap()
+            .downloads
+            .get(file_id)
+            .cloned()
+            .ok_or(wr::Error::MediaExpired)?;
+
+        let target = base_path.join(path);
+        let io_error = |err: std::io::Error| wr::Error::DownloadFailed(err.to_string().into());
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        fs::write(target, data).map_err(io_error)
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
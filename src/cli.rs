@@ -0,0 +1,475 @@
+//! The commands that use the data and the WhatsApp session of wstui without opening the
+//! interface, for scripts and other tools.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant};
+
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+use log::{error, info};
+use serde_json::json;
+use whatsrust as wr;
+
+use crate::app::rpc::{self, event_json};
+use crate::app::unread::unread_after_read_elsewhere;
+use crate::app::{
+    Chat, ParticipantReceipt, apply_edit, apply_receipt, apply_revoke, group_change_message,
+    group_recipients, is_group,
+};
+use crate::backend::{Backend, log_backend};
+use crate::db::DatabaseHandler;
+use crate::export::{ExportFormat, chat_json, write_messages};
+use crate::import::file_kind;
+
+/// How long `send` waits for the server to echo a sent message, so it can be saved.
+const ECHO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Writes every chat as a JSON object per line, the most recent first.
+pub fn write_chats(db: &DatabaseHandler, out: &mut dyn Write) -> io::Result<usize> {
+    let contacts = db
+        .get_contacts()
+        .into_iter()
+        .collect::<std::collections::HashMap<_, _>>();
+    let mut chats = db.get_chat_summaries();
+    chats.sort_by_key(|chat| std::cmp::Reverse(chat.last_message_time));
+    for chat in &chats {
+        writeln!(out, "{}", chat_json(&contacts, chat))?;
+    }
+    Ok(chats.len())
+}
+
+/// Parses a point in time given on the command line: a day (`2024-01-31`), a day and a
+/// time (`2024-01-31 18:30`), both in local time, or how long ago (`90s`, `15m`, `2h`,
+/// `7d`, `2w`).
+pub fn parse_time(input: &str) -> Result<i64, String> {
+    let input = input.trim();
+    if let Some(ago) = parse_duration(input) {
+        return Ok(Local::now().timestamp() - ago);
+    }
+    let time = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(input, "%Y-%m-%d")
+            .ok()
+            .and_then(|day| day.and_hms_opt(0, 0, 0))
+    })
+    .ok_or_else(|| format!("\"{input}\" is not a time like 2024-01-31, 2024-01-31 18:30 or 2h"))?;
+    Ok(Local
+        .from_local_datetime(&time)
+        .earliest()
+        .map_or_else(|| time.and_utc().timestamp(), |time| time.timestamp()))
+}
+
+/// The number of seconds in `90s`, `15m`, `2h`, `7d` or `2w`.
+pub fn parse_duration(input: &str) -> Option<i64> {
+    let unit = match input.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let count = input[..input.len() - 1].parse::<i64>().ok()?;
+    count.checked_mul(unit)
+}
+
+/// Writes the messages of `chat` sent from `since` (included) to `until` (excluded).
+/// With a `limit`, only that many of the most recent ones are written.
+pub fn write_chat_messages(
+    db: &DatabaseHandler,
+    media_path: &Path,
+    chat: &wr::JID,
+    range: (Option<i64>, Option<i64>),
+    limit: Option<usize>,
+    format: ExportFormat,
+    out: &mut dyn Write,
+) -> io::Result<usize> {
+    let mut messages = db.get_messages_between(chat, range.0, range.1);
+    if let Some(limit) = limit {
+        messages.drain(..messages.len().saturating_sub(limit));
+    }
+    write_messages(db, media_path, None, chat, format, &messages, out)?;
+    Ok(messages.len())
+}
+
+/// A message that sends the file at `path`, with `caption` under it unless it is empty.
+/// The kind of file is told by its extension.
+pub fn file_message(path: &Path, caption: &str) -> io::Result<wr::MessageContent> {
+    let path = path.canonicalize()?;
+    let kind = match file_kind(&path.to_string_lossy()) {
+        // Stickers need to be made for it, any other picture goes as an image.
+        wr::FileKind::Sticker => wr::FileKind::Image,
+        kind => kind,
+    };
+    Ok(wr::MessageContent::File(wr::FileContent {
+        kind,
+        path: path.to_string_lossy().into(),
+        file_id: "".into(),
+        caption: (!caption.is_empty()).then(|| caption.into()),
+    }))
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Backend(wr::Error),
+    /// wstui was never paired with a phone on this device.
+    NotPaired,
+    /// The interface is open on the socket at this path, with the only connection
+    /// WhatsApp allows.
+    ClientRunning(PathBuf),
+    File(PathBuf, io::Error),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Backend(err) => write!(f, "{err}"),
+            SessionError::NotPaired => {
+                write!(
+                    f,
+                    "wstui is not paired with a phone yet, run it once to pair"
+                )
+            }
+            SessionError::ClientRunning(socket) => write!(
+                f,
+                "wstui is open on this profile, close it or use its socket at {}",
+                socket.display()
+            ),
+            SessionError::File(path, err) => write!(f, "{}: {err}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<wr::Error> for SessionError {
+    fn from(err: wr::Error) -> Self {
+        SessionError::Backend(err)
+    }
+}
+
+/// What the session receives from WhatsApp, in the order it came.
+enum Received {
+    Message(wr::Message, bool),
+    Event(wr::Event),
+}
+
+/// The WhatsApp session of wstui, connected without the interface. Every message it
+/// receives, and every edit, deletion, reaction and receipt, is saved to the database,
+/// so the interface shows it later.
+pub struct Session {
+    backend: Arc<dyn Backend>,
+    received: mpsc::Receiver<Received>,
+}
+
+impl Session {
+    /// Connects with the session kept in `data_dir`. Fails instead of showing a QR code
+    /// if the device was never paired, and instead of taking over the connection of the
+    /// interface if it is open.
+    pub fn open(backend: Arc<dyn Backend>, data_dir: &Path) -> Result<Self, SessionError> {
+        let socket = data_dir.join(rpc::SOCKET_NAME);
+        if rpc::is_listening(&socket) {
+            return Err(SessionError::ClientRunning(socket));
+        }
+
+        backend.set_log_handler(Box::new(|msg, level| log_backend(&msg, level)));
+        let (tx, received) = mpsc::channel();
+        let event_tx = tx.clone();
+        backend.set_event_handler(Box::new(move |event| {
+            let _ = event_tx.send(Received::Event(event));
+        }));
+        backend.set_message_handler(Box::new(move |message, is_sync| {
+            let _ = tx.send(Received::Message(message, is_sync));
+        }));
+
+        backend.new_client(&data_dir.join("whatsmeow.db"))?;
+        if backend.self_jid().is_none() {
+            return Err(SessionError::NotPaired);
+        }
+        info!("Connecting to WhatsApp Web");
+        backend.connect(Box::new(|_| {}))?;
+        Ok(Self { backend, received })
+    }
+
+    pub fn self_jid(&self) -> Option<wr::JID> {
+        self.backend.self_jid()
+    }
+
+    /// Sends `text` to `chat`, as the caption of `file` if there is one, and saves the
+    /// sent message once the server echoes it.
+    pub fn send(
+        &self,
+        db: &DatabaseHandler,
+        chat: &wr::JID,
+        text: &str,
+        file: Option<&Path>,
+    ) -> Result<wr::MessageId, SessionError> {
+        let content = match file {
+            Some(path) => file_message(path, text)
+                .map_err(|err| SessionError::File(path.to_path_buf(), err))?,
+            None => wr::MessageContent::Text(text.into()),
+        };
+        let id = self.backend.send_message(chat, &content, None, &[])?;
+
+        let mut saver = Saver::new(db, &*self.backend);
+        let deadline = Instant::now() + ECHO_TIMEOUT;
+        while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+            let Ok(received) = self.received.recv_timeout(timeout) else {
+                break;
+            };
+            if let Some((message, _)) = saver.save(received)
+                && message.info.id == id
+            {
+                break;
+            }
+        }
+        Ok(id)
+    }
+
+    /// Writes every message received from now on as a JSON object per line, and every
+    /// edit, deletion, reaction, receipt and chat read elsewhere as an `{"event": ...}`
+    /// line, until `stop` is set or `out` is closed. Messages from history syncs are
+    /// saved but not written.
+    pub fn tail(
+        &self,
+        db: &DatabaseHandler,
+        media_path: &Path,
+        stop: &AtomicBool,
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
+        let self_jid = self.self_jid();
+        let mut saver = Saver::new(db, &*self.backend);
+        while !stop.load(Ordering::Relaxed) {
+            let received = match self.received.recv_timeout(Duration::from_millis(100)) {
+                Ok(received) => received,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            if let Received::Event(event) = &received
+                && matches!(
+                    event,
+                    wr::Event::Receipt { .. }
+                        | wr::Event::Reaction(_)
+                        | wr::Event::MessageEdit { .. }
+                        | wr::Event::MessageRevoke { .. }
+                        | wr::Event::ChatRead { .. }
+                )
+            {
+                writeln!(out, "{}", json!({ "event": event_json(event) }))?;
+                out.flush()?;
+            }
+            let Some((message, false)) = saver.save(received) else {
+                continue;
+            };
+            write_messages(
+                db,
+                media_path,
+                self_jid.as_ref(),
+                &message.info.chat,
+                ExportFormat::Jsonl,
+                std::slice::from_ref(&message),
+                out,
+            )?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn close(self) -> Result<(), SessionError> {
+        self.backend.disconnect()?;
+        Ok(())
+    }
+}
+
+/// Saves what the session receives like the interface would. The database writes in
+/// the background, so the messages and receipts saved are kept at hand for the events
+/// that follow them.
+struct Saver<'a> {
+    db: &'a DatabaseHandler,
+    backend: &'a dyn Backend,
+    messages: HashMap<wr::MessageId, wr::Message>,
+    receipts: HashMap<wr::MessageId, Vec<ParticipantReceipt>>,
+    group_infos: HashMap<wr::JID, wr::GroupInfo>,
+}
+
+impl<'a> Saver<'a> {
+    fn new(db: &'a DatabaseHandler, backend: &'a dyn Backend) -> Self {
+        Self {
+            db,
+            backend,
+            messages: HashMap::new(),
+            receipts: HashMap::new(),
+            group_infos: HashMap::new(),
+        }
+    }
+
+    /// Saves a message or applies an event. Returns the message saved, group changes
+    /// included, and whether it came from a history sync.
+    fn save(&mut self, received: Received) -> Option<(wr::Message, bool)> {
+        match received {
+            Received::Message(message, is_sync) => {
+                self.save_message(message).map(|message| (message, is_sync))
+            }
+            Received::Event(event) => self.apply(event).map(|message| (message, false)),
+        }
+    }
+
+    /// Saves a received message and its chat, unless it is a re-delivered original of
+    /// a message that was edited or deleted since.
+    fn save_message(&mut self, message: wr::Message) -> Option<wr::Message> {
+        if self
+            .message(&message.info.id)
+            .is_some_and(|saved| saved.info.edit_state != wr::EditState::Original)
+        {
+            return None;
+        }
+        self.db.add_message(&message);
+        self.db.add_chat(&Chat {
+            jid: message.info.chat.clone(),
+            last_message_time: Some(message.info.timestamp),
+            unread: 0,
+        });
+        self.messages
+            .insert(message.info.id.clone(), message.clone());
+        Some(message)
+    }
+
+    /// Applies an event to what is saved. Group changes are saved as messages, which
+    /// are returned.
+    fn apply(&mut self, event: wr::Event) -> Option<wr::Message> {
+        match event {
+            wr::Event::Receipt {
+                kind,
+                chat,
+                sender,
+                message_ids,
+                timestamp,
+            } => {
+                for id in &message_ids {
+                    self.add_receipt(kind, &sender, id, timestamp);
+                }
+                // We read the chat on another device.
+                if kind == wr::ReceiptKind::ReadSelf {
+                    self.db.set_unread(&chat, 0);
+                }
+            }
+            wr::Event::Reaction(reaction) => self.db.add_reaction(&reaction),
+            wr::Event::MessageEdit {
+                chat,
+                sender,
+                message_id,
+                text,
+                ..
+            } => {
+                let db = self.db;
+                if let Some(message) = self.message(&message_id)
+                    && apply_edit(message, &chat, &sender, text)
+                {
+                    db.add_message(message);
+                }
+            }
+            wr::Event::MessageRevoke {
+                chat, message_id, ..
+            } => {
+                let db = self.db;
+                if let Some(message) = self.message(&message_id)
+                    && apply_revoke(message, &chat)
+                {
+                    db.add_message(message);
+                }
+            }
+            wr::Event::GroupChange {
+                chat,
+                sender,
+                change,
+                timestamp,
+            } => {
+                self.group_infos.remove(&chat);
+                let self_jid = self.backend.self_jid();
+                let message =
+                    group_change_message(chat, sender, change, timestamp, self_jid.as_ref());
+                return self.save_message(message);
+            }
+            wr::Event::ChatRead { chat, read, .. } => {
+                let count = unread_after_read_elsewhere(self.db.get_unread(&chat), read);
+                self.db.set_unread(&chat, count);
+            }
+            wr::Event::SyncProgress(_) | wr::Event::AppStateSyncComplete => {}
+        }
+        None
+    }
+
+    /// A message saved before or during this session.
+    fn message(&mut self, id: &wr::MessageId) -> Option<&mut wr::Message> {
+        if !self.messages.contains_key(id) {
+            let message = self.db.get_message(id)?;
+            self.messages.insert(id.clone(), message);
+        }
+        self.messages.get_mut(id)
+    }
+
+    /// Saves a receipt for one of our messages and moves its delivery state forward.
+    fn add_receipt(
+        &mut self,
+        kind: wr::ReceiptKind,
+        participant: &wr::JID,
+        message_id: &wr::MessageId,
+        timestamp: i64,
+    ) {
+        let Some(chat) = self.message(message_id).map(|m| m.info.chat.clone()) else {
+            return;
+        };
+        let recipients = is_group(&chat).then(|| self.group_recipients(&chat));
+
+        let db = self.db;
+        let message = self.messages.get_mut(message_id).unwrap();
+        let receipts = self
+            .receipts
+            .entry(message_id.clone())
+            .or_insert_with(|| db.get_receipts(message_id));
+        let delivery = message.info.delivery;
+        let receipt = apply_receipt(
+            message,
+            receipts,
+            kind,
+            participant,
+            timestamp,
+            recipients.as_deref(),
+        );
+        if let Some(receipt) = receipt {
+            db.add_receipt(message_id, &receipt);
+        }
+        if message.info.delivery != delivery {
+            db.add_message(message);
+        }
+    }
+
+    /// Who our messages in `group` are sent to. The group is asked to the server once,
+    /// and has no one to wait for if that fails.
+    fn group_recipients(&mut self, group: &wr::JID) -> Vec<wr::JID> {
+        if !self.group_infos.contains_key(group) {
+            match self.backend.get_group_info(group) {
+                Ok(info) => {
+                    self.group_infos.insert(group.clone(), info);
+                }
+                Err(err) => error!("Failed to get group info for {}: {err}", group.0),
+            }
+        }
+        let self_jid = self.backend.self_jid();
+        self.group_infos
+            .get(group)
+            .map(|info| group_recipients(info, self_jid.as_ref()))
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,55 @@
+use log::warn;
+
+pub type ClipboardError = arboard::Error;
+
+pub trait Clipboard {
+    fn get_text(&mut self) -> Result<String, ClipboardError>;
+    fn set_text(&mut self, text: String) -> Result<(), ClipboardError>;
+}
+
+/// The desktop clipboard (X11 / Wayland / macOS / Windows).
+pub struct SystemClipboard(arboard::Clipboard);
+
+impl SystemClipboard {
+    pub fn new() -> Result<Self, ClipboardError> {
+        arboard::Clipboard::new().map(Self)
+    }
+}
+
+impl Clipboard for SystemClipboard {
+    fn get_text(&mut self) -> Result<String, ClipboardError> {
+        self.0.get_text()
+    }
+
+    fn set_text(&mut self, text: String) -> Result<(), ClipboardError> {
+        self.0.set_text(text)
+    }
+}
+
+/// A clipboard private to the process, used when there is no desktop clipboard.
+#[derive(Debug, Default)]
+pub struct MemoryClipboard {
+    pub text: Option<String>,
+}
+
+impl Clipboard for MemoryClipboard {
+    fn get_text(&mut self) -> Result<String, ClipboardError> {
+        self.text.clone().ok_or(ClipboardError::ContentNotAvailable)
+    }
+
+    fn set_text(&mut self, text: String) -> Result<(), ClipboardError> {
+        self.text = Some(text);
+        Ok(())
+    }
+}
+
+/// The system clipboard if one is reachable, otherwise an in-memory one.
+pub fn system_or_memory() -> Box<dyn Clipboard> {
+    match SystemClipboard::new() {
+        Ok(clipboard) => Box::new(clipboard),
+        Err(err) => {
+            warn!("System clipboard unavailable, falling back to an in-memory one: {err}");
+            Box::new(MemoryClipboard::default())
+        }
+    }
+}
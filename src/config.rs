@@ -0,0 +1,323 @@
+//! The settings read from `config.toml` in the config directory: keybindings and how
+//! long key sequences wait, colors, sizes, notifications and the starting mode of the
+//! input. Every setting is optional, and one that is wrong is reported and left at its
+//! default, so a mistake never keeps wstui from starting.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use directories::ProjectDirs;
+use ratatui::style::Color;
+use toml::{Table, Value};
+
+use crate::app::actions::key_contexts;
+use crate::key_handler::{Action, DEFAULT_KEY_TIMEOUT, Key, Keymap, key_notation, parse_keys};
+use crate::vim;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub keys: Keymap,
+    pub theme: Theme,
+    pub layout: LayoutConfig,
+    pub notifications: NotificationConfig,
+    /// The mode the input starts in.
+    pub vim_mode: vim::Mode,
+    /// How long keys bound to an action wait for more when they also start a longer
+    /// binding, like `g` bound next to `gg`.
+    pub key_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            keys: Keymap::default(),
+            theme: Theme::default(),
+            layout: LayoutConfig::default(),
+            notifications: NotificationConfig::default(),
+            vim_mode: vim::Mode::Insert,
+            key_timeout: DEFAULT_KEY_TIMEOUT,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// The border of the focused panel and of popups.
+    pub focused_border: Color,
+    pub border: Color,
+    /// The selected chat in the chat list.
+    pub selected_chat: Color,
+    /// The selected entry of lists and popups, and the selected message.
+    pub selection_fg: Color,
+    pub selection_bg: Color,
+    /// The background of the matches of a search.
+    pub search_match: Color,
+    /// The unread counts in the chat list and the new messages divider.
+    pub unread: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            focused_border: Color::Green,
+            border: Color::White,
+            selected_chat: Color::Green,
+            selection_fg: Color::Black,
+            selection_bg: Color::Gray,
+            search_match: Color::Yellow,
+            unread: Color::Cyan,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LayoutConfig {
+    /// The least number of columns the chat list takes.
+    pub chat_list_width: u16,
+    /// The size of the pictures shown in the message list, in rows and columns.
+    pub image_height: u16,
+    pub image_width: u16,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            chat_list_width: 30,
+            image_height: 12,
+            image_width: 36,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NotificationConfig {
+    pub enabled: bool,
+    /// Whether the text of the message is shown, or only who sent it.
+    pub show_text: bool,
+    /// Whether group messages notify. Mentions of you always do.
+    pub groups: bool,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            show_text: true,
+            groups: true,
+        }
+    }
+}
+
+/// A setting that could not be used, by where it is in the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// The dotted path of the setting, empty when the whole file is at fault.
+    pub key: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.key.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.key, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Where the config is read from.
+pub fn default_config_path() -> PathBuf {
+    ProjectDirs::from("com", "nullptr", "wstui")
+        .unwrap()
+        .config_dir()
+        .join("config.toml")
+}
+
+impl Config {
+    /// Reads the config at `path`. Without a file there every setting is at its default.
+    pub fn load(path: &Path) -> (Self, Vec<ConfigError>) {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (Self::default(), Vec::new()),
+            Err(err) => {
+                let error = ConfigError {
+                    key: String::new(),
+                    message: format!("failed to read {}: {err}", path.display()),
+                };
+                (Self::default(), vec![error])
+            }
+        }
+    }
+
+    /// The config written in `text`, and what was wrong in it.
+    pub fn parse(text: &str) -> (Self, Vec<ConfigError>) {
+        let mut config = Self::default();
+        let mut errors = Vec::new();
+        let table = match text.parse::<Table>() {
+            Ok(table) => table,
+            Err(err) => {
+                errors.push(ConfigError {
+                    key: String::new(),
+                    message: err.to_string().trim_end().to_string(),
+                });
+                return (config, errors);
+            }
+        };
+
+        let mut image_width_set = false;
+        let mut bound = Vec::new();
+        for (section, value) in &table {
+            let Some(entries) = value.as_table() else {
+                errors.push(error(section, "expected a table, like [section]"));
+                continue;
+            };
+            for (name, value) in entries {
+                let key = format!("{section}.{name}");
+                let result = match (section.as_str(), name.as_str()) {
+                    ("keys", action) => match Action::from_str(action) {
+                        Ok(action) => key_bindings(value).map(|keys| {
+                            config.keys.bind(action, keys);
+                            bound.push(action);
+                        }),
+                        Err(_) => Err("unknown action".to_string()),
+                    },
+                    ("theme", name) => {
+                        let theme = &mut config.theme;
+                        let color = match name {
+                            "focused_border" => &mut theme.focused_border,
+                            "border" => &mut theme.border,
+                            "selected_chat" => &mut theme.selected_chat,
+                            "selection_fg" => &mut theme.selection_fg,
+                            "selection_bg" => &mut theme.selection_bg,
+                            "search_match" => &mut theme.search_match,
+                            "unread" => &mut theme.unread,
+                            _ => {
+                                errors.push(error(&key, "unknown setting"));
+                                continue;
+                            }
+                        };
+                        color_value(value).map(|value| *color = value)
+                    }
+                    ("layout", "chat_list_width") => size_value(value, 10..=200)
+                        .map(|value| config.layout.chat_list_width = value),
+                    ("layout", "image_height") => {
+                        size_value(value, 1..=100).map(|value| config.layout.image_height = value)
+                    }
+                    ("layout", "image_width") => size_value(value, 1..=300).map(|value| {
+                        config.layout.image_width = value;
+                        image_width_set = true;
+                    }),
+                    ("notifications", "enabled") => {
+                        bool_value(value).map(|value| config.notifications.enabled = value)
+                    }
+                    ("notifications", "show_text") => {
+                        bool_value(value).map(|value| config.notifications.show_text = value)
+                    }
+                    ("notifications", "groups") => {
+                        bool_value(value).map(|value| config.notifications.groups = value)
+                    }
+                    ("input", "vim_mode") => {
+                        vim_mode_value(value).map(|mode| config.vim_mode = mode)
+                    }
+                    ("input", "timeout") => size_value(value, 0..=10_000)
+                        .map(|ms| config.key_timeout = Duration::from_millis(ms.into())),
+                    ("layout" | "notifications" | "input", _) => Err("unknown setting".to_string()),
+                    _ => {
+                        errors.push(error(section, "unknown section"));
+                        break;
+                    }
+                };
+                if let Err(message) = result {
+                    errors.push(error(&key, &message));
+                }
+            }
+        }
+        // Keys bound to two actions that are active together would only run one of them.
+        // The configured action does not get them, checked once every action is bound so
+        // that keys can be swapped.
+        for action in bound {
+            for context in key_contexts().iter().filter(|c| c.contains(&action)) {
+                for (keys, other) in config.keys.conflicts(action, context) {
+                    config.keys.unbind(action, &keys);
+                    let message = format!(
+                        "\"{}\" is also bound to {}",
+                        key_notation(&keys),
+                        other.name()
+                    );
+                    errors.push(error(&format!("keys.{}", action.name()), &message));
+                }
+            }
+        }
+
+        // Pictures are about three times as wide in columns as they are high in rows.
+        if !image_width_set {
+            config.layout.image_width = config.layout.image_height * 3;
+        }
+        (config, errors)
+    }
+}
+
+fn error(key: &str, message: &str) -> ConfigError {
+    ConfigError {
+        key: key.to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// The key sequences of an action: one like `"gg"`, or a list of them. An empty list
+/// leaves the action unbound.
+fn key_bindings(value: &Value) -> Result<Vec<Vec<Key>>, String> {
+    match value {
+        Value::String(notation) => Ok(vec![parse_keys(notation)?]),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::String(notation) => parse_keys(notation),
+                _ => Err("expected keys like \"gg\" or \"<C-q>\"".to_string()),
+            })
+            .collect(),
+        _ => Err("expected keys like \"gg\" or \"<C-q>\", or a list of them".to_string()),
+    }
+}
+
+/// A color by name (`green`, `lightblue`), index (`42`) or value (`#ff8800`).
+fn color_value(value: &Value) -> Result<Color, String> {
+    let name = value
+        .as_str()
+        .ok_or("expected a color like \"green\" or \"#ff8800\"")?;
+    Color::from_str(name).map_err(|_| format!("\"{name}\" is not a color"))
+}
+
+fn size_value(value: &Value, range: std::ops::RangeInclusive<u16>) -> Result<u16, String> {
+    value
+        .as_integer()
+        .and_then(|size| u16::try_from(size).ok())
+        .filter(|size| range.contains(size))
+        .ok_or_else(|| {
+            format!(
+                "expected a number from {} to {}",
+                range.start(),
+                range.end()
+            )
+        })
+}
+
+fn vim_mode_value(value: &Value) -> Result<vim::Mode, String> {
+    match value.as_str() {
+        Some("insert") => Ok(vim::Mode::Insert),
+        Some("normal") => Ok(vim::Mode::Normal),
+        _ => Err("expected \"insert\" or \"normal\"".to_string()),
+    }
+}
+
+fn bool_value(value: &Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| "expected true or false".to_string())
+}
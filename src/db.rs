@@ -1,19 +1,40 @@
+pub mod migrations;
+
 use std::{
-    path::Path,
+    collections::HashMap,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
 use log::debug;
-use rusqlite::Connection;
-use strum::IntoEnumIterator;
+use rusqlite::{Connection, OptionalExtension};
+use strum::FromRepr;
 use whatsrust as wr;
 
-use crate::app::Chat;
+use crate::app::outbox::OutboxEntry;
+use crate::app::{Chat, ParticipantReceipt, jid_user};
+use migrations::MigrationError;
+
+/// Marks where a match starts in the snippet of a `SearchHit`.
+pub const MATCH_START: char = '\u{1}';
+/// Marks where a match ends in the snippet of a `SearchHit`.
+pub const MATCH_END: char = '\u{2}';
+
+/// A message found by `DatabaseHandler::search_messages`.
+#[derive(Clone, Debug)]
+pub struct SearchHit {
+    pub message: wr::Message,
+    /// The part of the text around the matches.
+    pub snippet: String,
+}
 
 pub struct DatabaseHandler {
     db: Connection,
+    db_path: PathBuf,
     new_messages_queue: Arc<Mutex<Vec<wr::Message>>>,
     new_chats_queue: Arc<Mutex<Vec<Chat>>>,
+    new_reactions_queue: Arc<Mutex<Vec<wr::Reaction>>>,
+    new_receipts_queue: Arc<Mutex<Vec<(wr::MessageId, ParticipantReceipt)>>>,
     should_stop: Arc<Mutex<bool>>,
     thread: Option<std::thread::JoinHandle<()>>,
 }
@@ -24,14 +45,19 @@ impl DatabaseHandler {
 
         let new_messages_queue = Arc::new(Mutex::new(Vec::<wr::Message>::new()));
         let new_chats_queue = Arc::new(Mutex::new(Vec::<Chat>::new()));
+        let new_reactions_queue = Arc::new(Mutex::new(Vec::<wr::Reaction>::new()));
+        let new_receipts_queue =
+            Arc::new(Mutex::new(Vec::<(wr::MessageId, ParticipantReceipt)>::new()));
         let should_stop = Arc::new(Mutex::new(false));
 
         let new_messages_queue_clone = Arc::clone(&new_messages_queue);
         let new_chats_queue_clone = Arc::clone(&new_chats_queue);
+        let new_reactions_queue_clone = Arc::clone(&new_reactions_queue);
+        let new_receipts_queue_clone = Arc::clone(&new_receipts_queue);
         let should_stop_clone = Arc::clone(&should_stop);
-        let db_path = db_path.to_owned();
+        let writer_db_path = db_path.to_owned();
         let thread = std::thread::spawn(move || {
-            let mut db = Connection::open(db_path).unwrap();
+            let mut db = Connection::open(writer_db_path).unwrap();
             loop {
                 std::thread::sleep(std::time::Duration::from_secs(1));
                 let new_chats = {
@@ -46,8 +72,10 @@ impl DatabaseHandler {
                     debug!("Saving {} new chats to the database", new_chats.len());
                     let tx = db.transaction().unwrap();
                     {
+                        // The unread count is written by `set_unread` as it changes, and
+                        // must not be reset by a chat saved from an older copy.
                         let mut statement = tx
-                            .prepare("INSERT OR REPLACE INTO chats (jid) VALUES (?)")
+                            .prepare("INSERT OR IGNORE INTO chats (jid) VALUES (?)")
                             .unwrap();
                         for chat in new_chats {
                             statement.execute(rusqlite::params![&*chat.jid.0]).unwrap();
@@ -56,64 +84,187 @@ impl DatabaseHandler {
                     tx.commit().unwrap();
                 }
 
-                let messages = {
-                    let mut queue = new_messages_queue_clone.lock().unwrap();
-                    let mut messages = Vec::new();
-                    while let Some(message) = queue.pop() {
-                        messages.push(message);
-                    }
-                    messages
-                };
+                // Edits rewrite earlier rows, so messages must be saved in arrival order too.
+                let messages = std::mem::take(&mut *new_messages_queue_clone.lock().unwrap());
                 if !messages.is_empty() {
                     debug!("Saving {} new messages to the database", messages.len());
                     let tx = db.transaction().unwrap();
 
                     {
-                        let mut text_stmt = tx
-                            .prepare("INSERT OR REPLACE INTO text_messages (id, chat_jid, sender_jid, timestamp, quote_id, is_from_me, read, message) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+                        let mut message_stmt = tx
+                            .prepare("INSERT OR REPLACE INTO messages (id, chat_jid, sender_jid, timestamp, quote_id, is_from_me, kind, text, delivery, edit_state, mentions) VALUES (:id, :chat_jid, :sender_jid, :timestamp, :quote_id, :is_from_me, :kind, :text, :delivery, :edit_state, :mentions)")
+                            .unwrap();
+                        let mut media_stmt = tx
+                            .prepare("INSERT OR REPLACE INTO message_media (message_id, kind, path, file_id) VALUES (:message_id, :kind, :path, :file_id)")
+                            .unwrap();
+                        let mut group_change_stmt = tx
+                            .prepare("INSERT OR REPLACE INTO group_changes (message_id, kind, participants) VALUES (:message_id, :kind, :participants)")
+                            .unwrap();
+                        // A revoked file message is stored as an empty text message.
+                        let mut delete_media_stmt = tx
+                            .prepare("DELETE FROM message_media WHERE message_id = :message_id")
+                            .unwrap();
+                        let mut saved_search_text_stmt = tx
+                            .prepare(&format!(
+                                "SELECT text FROM messages WHERE id = :id AND {SEARCHABLE}"
+                            ))
                             .unwrap();
-                        let mut file_stmt = tx
-                            .prepare("INSERT OR REPLACE INTO file_messages (id, chat_jid, sender_jid, timestamp, quote_id, is_from_me, read, kind, path, file_id, caption) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+                        let mut insert_search_stmt = tx
+                            .prepare("INSERT INTO message_search (message_id, text) VALUES (:message_id, :text)")
+                            .unwrap();
+                        // Only edits and revokes change the text of a saved message, so the
+                        // scan this takes is rare.
+                        let mut delete_search_stmt = tx
+                            .prepare("DELETE FROM message_search WHERE message_id = :message_id")
                             .unwrap();
                         for msg in &messages {
-                            match &msg.message {
-                                wr::MessageContent::Text(text) => {
-                                    text_stmt
-                                        .execute(rusqlite::params![
-                                            msg.info.id,
-                                            msg.info.chat.0,
-                                            msg.info.sender.0,
-                                            msg.info.timestamp,
-                                            msg.info.quote_id,
-                                            msg.info.is_from_me,
-                                            msg.info.read_by,
-                                            text,
-                                        ])
+                            let saved_search_text: Option<String> = saved_search_text_stmt
+                                .query_row(rusqlite::named_params! { ":id": msg.info.id }, |row| {
+                                    row.get::<_, Option<String>>("text")
+                                })
+                                .optional()
+                                .unwrap()
+                                .flatten()
+                                .filter(|text| !text.is_empty());
+                            let search_text = search_text(msg);
+                            if saved_search_text.as_deref() != search_text {
+                                if saved_search_text.is_some() {
+                                    delete_search_stmt
+                                        .execute(rusqlite::named_params! {
+                                            ":message_id": msg.info.id,
+                                        })
                                         .unwrap();
                                 }
+                                if let Some(text) = search_text {
+                                    insert_search_stmt
+                                        .execute(rusqlite::named_params! {
+                                            ":message_id": msg.info.id,
+                                            ":text": text,
+                                        })
+                                        .unwrap();
+                                }
+                            }
+
+                            let text = match &msg.message {
+                                wr::MessageContent::Text(text) => Some(text.clone()),
+                                wr::MessageContent::File(file) => file.caption.clone(),
+                                wr::MessageContent::GroupChange(change) => {
+                                    Some(change.text.clone())
+                                }
+                            };
+                            message_stmt
+                                .execute(rusqlite::named_params! {
+                                    ":id": msg.info.id,
+                                    ":chat_jid": msg.info.chat.0,
+                                    ":sender_jid": msg.info.sender.0,
+                                    ":timestamp": msg.info.timestamp,
+                                    ":quote_id": msg.info.quote_id,
+                                    ":is_from_me": msg.info.is_from_me,
+                                    ":kind": ContentKind::of(&msg.message) as u8,
+                                    ":text": text,
+                                    ":delivery": msg.info.delivery as u8,
+                                    ":edit_state": msg.info.edit_state as u8,
+                                    ":mentions": (!msg.info.mentions.is_empty())
+                                        .then(|| join_jids(&msg.info.mentions)),
+                                })
+                                .unwrap();
+                            match &msg.message {
+                                wr::MessageContent::Text(_) => {
+                                    if msg.info.edit_state == wr::EditState::Revoked {
+                                        delete_media_stmt
+                                            .execute(rusqlite::named_params! {
+                                                ":message_id": msg.info.id,
+                                            })
+                                            .unwrap();
+                                    }
+                                }
                                 wr::MessageContent::File(file) => {
-                                    file_stmt
-                                        .execute(rusqlite::params![
-                                            msg.info.id,
-                                            msg.info.chat.0,
-                                            msg.info.sender.0,
-                                            msg.info.timestamp,
-                                            msg.info.quote_id,
-                                            msg.info.is_from_me,
-                                            msg.info.read_by,
-                                            file.kind.clone() as u8,
-                                            file.path,
-                                            file.file_id,
-                                            file.caption,
-                                        ])
+                                    media_stmt
+                                        .execute(rusqlite::named_params! {
+                                            ":message_id": msg.info.id,
+                                            ":kind": file.kind.clone() as u8,
+                                            ":path": file.path,
+                                            ":file_id": file.file_id,
+                                        })
                                         .unwrap();
                                 }
+                                wr::MessageContent::GroupChange(change) => {
+                                    group_change_stmt
+                                        .execute(rusqlite::named_params! {
+                                            ":message_id": msg.info.id,
+                                            ":kind": change.kind as u8,
+                                            ":participants": join_jids(&change.participants),
+                                        })
+                                        .unwrap();
+                                }
+                            }
+                        }
+                    }
+                    tx.commit().unwrap();
+                }
+
+                // Reactions replace each other, so they must be applied in arrival order.
+                let reactions = std::mem::take(&mut *new_reactions_queue_clone.lock().unwrap());
+                if !reactions.is_empty() {
+                    debug!("Saving {} new reactions to the database", reactions.len());
+                    let tx = db.transaction().unwrap();
+                    {
+                        let mut insert_stmt = tx
+                            .prepare("INSERT OR REPLACE INTO reactions (message_id, chat_jid, sender_jid, emoji, timestamp, is_from_me) VALUES (?, ?, ?, ?, ?, ?)")
+                            .unwrap();
+                        let mut delete_stmt = tx
+                            .prepare(
+                                "DELETE FROM reactions WHERE message_id = ? AND sender_jid = ?",
+                            )
+                            .unwrap();
+                        for reaction in &reactions {
+                            if reaction.emoji.is_empty() {
+                                delete_stmt
+                                    .execute(rusqlite::params![
+                                        reaction.message_id,
+                                        reaction.sender.0,
+                                    ])
+                                    .unwrap();
+                            } else {
+                                insert_stmt
+                                    .execute(rusqlite::params![
+                                        reaction.message_id,
+                                        reaction.chat.0,
+                                        reaction.sender.0,
+                                        reaction.emoji,
+                                        reaction.timestamp,
+                                        reaction.is_from_me,
+                                    ])
+                                    .unwrap();
                             }
                         }
                     }
                     tx.commit().unwrap();
                 }
 
+                // Receipts carry the merged state of each participant, so the last one wins.
+                let receipts = std::mem::take(&mut *new_receipts_queue_clone.lock().unwrap());
+                if !receipts.is_empty() {
+                    debug!("Saving {} new receipts to the database", receipts.len());
+                    let tx = db.transaction().unwrap();
+                    {
+                        let mut stmt = tx
+                            .prepare("INSERT OR REPLACE INTO receipts (message_id, participant_jid, delivered_at, read_at, played_at) VALUES (?, ?, ?, ?, ?)")
+                            .unwrap();
+                        for (message_id, receipt) in &receipts {
+                            stmt.execute(rusqlite::params![
+                                message_id,
+                                receipt.participant.0,
+                                receipt.delivered_at,
+                                receipt.read_at,
+                                receipt.played_at,
+                            ])
+                            .unwrap();
+                        }
+                    }
+                    tx.commit().unwrap();
+                }
+
                 let should_stop = should_stop_clone.lock().unwrap();
                 if *should_stop {
                     break;
@@ -124,8 +275,11 @@ impl DatabaseHandler {
 
         Self {
             db,
+            db_path: db_path.to_owned(),
             new_messages_queue,
             new_chats_queue,
+            new_reactions_queue,
+            new_receipts_queue,
             should_stop,
             thread: Some(thread),
         }
@@ -150,14 +304,58 @@ impl DatabaseHandler {
         queue.push(chat.clone());
     }
 
-    pub fn get_chats(&self) -> Vec<Chat> {
-        let mut query = self.db.prepare("SELECT jid FROM chats").unwrap();
+    pub fn add_reaction(&self, reaction: &wr::Reaction) {
+        let mut queue = self.new_reactions_queue.lock().unwrap();
+        queue.push(reaction.clone());
+    }
+
+    pub fn add_receipt(&self, message_id: &wr::MessageId, receipt: &ParticipantReceipt) {
+        let mut queue = self.new_receipts_queue.lock().unwrap();
+        queue.push((message_id.clone(), receipt.clone()));
+    }
+
+    /// Saves how many messages of `chat` were not read yet.
+    pub fn set_unread(&self, chat: &wr::JID, count: usize) {
+        self.db
+            .execute(
+                "INSERT INTO chats (jid, unread) VALUES (?1, ?2)
+                ON CONFLICT (jid) DO UPDATE SET unread = ?2",
+                rusqlite::params![chat.0, count as i64],
+            )
+            .unwrap();
+    }
+
+    /// How many messages of `chat` were not read yet, none for a chat not saved.
+    pub fn get_unread(&self, chat: &wr::JID) -> usize {
+        self.db
+            .query_row(
+                "SELECT unread FROM chats WHERE jid = ?1",
+                rusqlite::params![chat.0],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()
+            .unwrap()
+            .map_or(0, |count| count as usize)
+    }
+
+    /// Every chat with the time of its latest message, without reading the messages.
+    pub fn get_chat_summaries(&self) -> Vec<Chat> {
+        let mut query = self
+            .db
+            .prepare(
+                "SELECT c.jid, c.unread,
+                    (SELECT MAX(m.timestamp) FROM messages m WHERE m.chat_jid = c.jid)
+                        AS last_message_time
+                FROM chats c",
+            )
+            .unwrap();
         query
             .query_map([], |row| {
-                let jid: String = row.get(0).unwrap();
+                let jid: String = row.get("jid")?;
                 Ok(Chat {
                     jid: jid.into(),
-                    last_message_time: None,
+                    last_message_time: row.get("last_message_time")?,
+                    unread: row.get::<_, i64>("unread")? as usize,
                 })
             })
             .unwrap()
@@ -165,88 +363,274 @@ impl DatabaseHandler {
             .unwrap()
     }
 
+    /// Every message, oldest first.
     pub fn get_messages(&self) -> Vec<wr::Message> {
-        let mut messages = Vec::new();
-        for kind in wr::MessageContent::iter() {
-            let msgs = match kind {
-                wr::MessageContent::Text(_) => {
-                    let mut query = self.db.prepare("SELECT * FROM text_messages").unwrap();
-                    query
-                        .query_map([], |row| {
-                            let id: String = row.get(0).unwrap();
-                            let chat_jid: String = row.get(1).unwrap();
-                            let sender_jid: String = row.get(2).unwrap();
-                            let timestamp: i64 = row.get(3).unwrap();
-                            let quote_id: Option<String> = row.get(4).unwrap_or(None);
-                            let is_from_me: bool = row.get(5).unwrap();
-                            let read_by: u16 = row.get(6).unwrap();
-
-                            let message: String = row.get(7).unwrap();
-
-                            Ok(wr::Message {
-                                info: wr::MessageInfo {
-                                    id: id.into(),
-                                    chat: chat_jid.into(),
-                                    sender: sender_jid.into(),
-                                    timestamp,
-                                    quote_id: quote_id.map(|q| q.into()),
-                                    is_from_me,
-                                    read_by,
-                                },
-                                message: wr::MessageContent::Text(message.into()),
-                            })
-                        })
-                        .unwrap()
-                        .collect::<Vec<Result<_, _>>>()
-                }
-                wr::MessageContent::File(_) => {
-                    let mut query = self.db.prepare("SELECT * FROM file_messages").unwrap();
-                    query
-                        .query_map([], |row| {
-                            let id: String = row.get(0).unwrap();
-                            let chat_jid: String = row.get(1).unwrap();
-                            let sender_jid: String = row.get(2).unwrap();
-                            let timestamp: i64 = row.get(3).unwrap();
-                            let quote_id: Option<String> = row.get(4).unwrap_or(None);
-                            let is_from_me: bool = row.get(5).unwrap();
-                            let read_by: u16 = row.get(6).unwrap();
-
-                            let kind: u8 = row.get(7).unwrap();
-                            let path: String = row.get(8).unwrap();
-                            let file_id: String = row.get(9).unwrap();
-                            let caption: Option<String> = row.get(10).unwrap_or(None);
-
-                            Ok(wr::Message {
-                                info: wr::MessageInfo {
-                                    id: id.into(),
-                                    chat: chat_jid.into(),
-                                    sender: sender_jid.into(),
-                                    timestamp,
-                                    quote_id: quote_id.map(|q| q.into()),
-                                    is_from_me,
-                                    read_by,
-                                },
-                                message: wr::MessageContent::File(wr::FileContent {
-                                    kind: wr::FileKind::from_repr(kind).unwrap(),
-                                    path: path.into(),
-                                    file_id: file_id.into(),
-                                    caption: caption.map(|c| c.into()),
-                                }),
-                            })
-                        })
-                        .unwrap()
-                        .collect::<Vec<Result<_, _>>>()
-                }
-            };
-
-            for msg in msgs {
-                messages.push(msg.unwrap());
-            }
-        }
+        let mut stmt = self
+            .db
+            .prepare(&format!(
+                "SELECT {MESSAGE_COLUMNS} FROM messages m {MESSAGE_JOINS} ORDER BY m.timestamp"
+            ))
+            .unwrap();
+        stmt.query_map([], message_from_row)
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+    }
 
+    /// The `limit` latest messages of `chat` from before `before`, oldest first. `before`
+    /// is a timestamp and the id that orders messages sent in the same second, usually
+    /// those of the oldest message read so far. `None` reads the latest messages.
+    pub fn get_messages_before(
+        &self,
+        chat: &wr::JID,
+        before: Option<&(i64, wr::MessageId)>,
+        limit: usize,
+    ) -> Vec<wr::Message> {
+        let (timestamp, id) = match before {
+            Some((timestamp, id)) => (*timestamp, id.as_ref()),
+            None => (i64::MAX, ""),
+        };
+        let mut stmt = self
+            .db
+            .prepare_cached(&format!(
+                "SELECT {MESSAGE_COLUMNS} FROM messages m {MESSAGE_JOINS}
+                WHERE m.chat_jid = :chat_jid AND (m.timestamp, m.id) < (:timestamp, :id)
+                ORDER BY m.timestamp DESC, m.id DESC
+                LIMIT :limit"
+            ))
+            .unwrap();
+        let mut messages = stmt
+            .query_map(
+                rusqlite::named_params! {
+                    ":chat_jid": chat.0,
+                    ":timestamp": timestamp,
+                    ":id": id,
+                    ":limit": limit as i64,
+                },
+                message_from_row,
+            )
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>();
+        messages.reverse();
         messages
     }
 
+    /// Every message of `chat` sent from `since` up to, not including, `until`, oldest
+    /// first. `None` leaves that end of the range open.
+    pub fn get_messages_between(
+        &self,
+        chat: &wr::JID,
+        since: Option<i64>,
+        until: Option<i64>,
+    ) -> Vec<wr::Message> {
+        let mut stmt = self
+            .db
+            .prepare_cached(&format!(
+                "SELECT {MESSAGE_COLUMNS} FROM messages m {MESSAGE_JOINS}
+                WHERE m.chat_jid = :chat_jid AND m.timestamp >= :since AND m.timestamp < :until
+                ORDER BY m.timestamp, m.id"
+            ))
+            .unwrap();
+        stmt.query_map(
+            rusqlite::named_params! {
+                ":chat_jid": chat.0,
+                ":since": since.unwrap_or(i64::MIN),
+                ":until": until.unwrap_or(i64::MAX),
+            },
+            message_from_row,
+        )
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect()
+    }
+
+    /// The chats `query` may refer to, as told by `match_chats`.
+    pub fn find_chats(&self, query: &str) -> Vec<wr::JID> {
+        let chats = self
+            .get_chat_summaries()
+            .into_iter()
+            .map(|chat| chat.jid)
+            .collect::<Vec<_>>();
+        let contacts = self.get_contacts().into_iter().collect::<HashMap<_, _>>();
+        match_chats(query, &chats, &contacts)
+    }
+
+    pub fn get_message(&self, id: &wr::MessageId) -> Option<wr::Message> {
+        let mut stmt = self
+            .db
+            .prepare_cached(&format!(
+                "SELECT {MESSAGE_COLUMNS} FROM messages m {MESSAGE_JOINS} WHERE m.id = :id"
+            ))
+            .unwrap();
+        stmt.query_row(rusqlite::named_params! { ":id": id }, message_from_row)
+            .optional()
+            .unwrap()
+    }
+
+    pub fn get_receipts(&self, message_id: &wr::MessageId) -> Vec<ParticipantReceipt> {
+        let mut stmt = self
+            .db
+            .prepare_cached("SELECT participant_jid, delivered_at, read_at, played_at FROM receipts WHERE message_id = :message_id")
+            .unwrap();
+        stmt.query_map(
+            rusqlite::named_params! { ":message_id": message_id },
+            |row| {
+                let participant_jid: String = row.get("participant_jid")?;
+                Ok(ParticipantReceipt {
+                    participant: participant_jid.into(),
+                    delivered_at: row.get("delivered_at")?,
+                    read_at: row.get("read_at")?,
+                    played_at: row.get("played_at")?,
+                })
+            },
+        )
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect()
+    }
+
+    pub fn get_reactions(&self, message_id: &wr::MessageId) -> Vec<wr::Reaction> {
+        let mut stmt = self
+            .db
+            .prepare_cached("SELECT message_id, chat_jid, sender_jid, emoji, timestamp, is_from_me FROM reactions WHERE message_id = :message_id ORDER BY timestamp")
+            .unwrap();
+        stmt.query_map(
+            rusqlite::named_params! { ":message_id": message_id },
+            |row| {
+                let message_id: String = row.get("message_id")?;
+                let chat_jid: String = row.get("chat_jid")?;
+                let sender_jid: String = row.get("sender_jid")?;
+                let emoji: String = row.get("emoji")?;
+                Ok(wr::Reaction {
+                    chat: chat_jid.into(),
+                    sender: sender_jid.into(),
+                    message_id: message_id.into(),
+                    emoji: emoji.into(),
+                    timestamp: row.get("timestamp")?,
+                    is_from_me: row.get("is_from_me")?,
+                })
+            },
+        )
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect()
+    }
+
+    /// The latest `limit` messages with every word of `query` in their text or caption,
+    /// newest first. Matches in each snippet are between `MATCH_START` and `MATCH_END`.
+    pub fn search_messages(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let Some(query) = search_query(query) else {
+            return Vec::new();
+        };
+        let mut stmt = self
+            .db
+            .prepare_cached(&format!(
+                "SELECT {MESSAGE_COLUMNS},
+                    snippet(message_search, 1, :start, :end, '…', 12) AS snippet
+                FROM message_search
+                JOIN messages m ON m.id = message_search.message_id
+                {MESSAGE_JOINS}
+                WHERE message_search MATCH :query
+                ORDER BY m.timestamp DESC
+                LIMIT :limit"
+            ))
+            .unwrap();
+        stmt.query_map(
+            rusqlite::named_params! {
+                ":start": MATCH_START.to_string(),
+                ":end": MATCH_END.to_string(),
+                ":query": query,
+                ":limit": limit as i64,
+            },
+            |row| {
+                Ok(SearchHit {
+                    message: message_from_row(row)?,
+                    snippet: row.get("snippet")?,
+                })
+            },
+        )
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect()
+    }
+
+    /// Outbox changes are written right away, a queued message must survive a crash.
+    pub fn save_outbox_entry(&self, entry: &OutboxEntry) {
+        let (text, file_kind, file_path) = match &entry.content {
+            wr::MessageContent::Text(text) => (Some(text.clone()), None, None),
+            wr::MessageContent::File(file) => (
+                file.caption.clone(),
+                Some(file.kind.clone() as u8),
+                Some(file.path.clone()),
+            ),
+            wr::MessageContent::GroupChange(_) => unreachable!("group changes are never sent"),
+        };
+        self.db
+            .execute(
+                "INSERT OR REPLACE INTO outbox (id, chat_jid, timestamp, quote_id, text, file_kind, file_path, attempts, failed, mentions) VALUES (:id, :chat_jid, :timestamp, :quote_id, :text, :file_kind, :file_path, :attempts, :failed, :mentions)",
+                rusqlite::named_params! {
+                    ":id": entry.id,
+                    ":chat_jid": entry.chat.0,
+                    ":timestamp": entry.timestamp,
+                    ":quote_id": entry.quote_id,
+                    ":text": text,
+                    ":file_kind": file_kind,
+                    ":file_path": file_path,
+                    ":attempts": entry.attempts,
+                    ":failed": entry.failed,
+                    ":mentions": (!entry.mentions.is_empty()).then(|| join_jids(&entry.mentions)),
+                },
+            )
+            .unwrap();
+    }
+
+    pub fn remove_outbox_entry(&self, id: &wr::MessageId) {
+        self.db
+            .execute("DELETE FROM outbox WHERE id = ?", rusqlite::params![id])
+            .unwrap();
+    }
+
+    pub fn get_outbox(&self) -> Vec<OutboxEntry> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT id, chat_jid, timestamp, quote_id, text, file_kind, file_path, attempts, failed, mentions FROM outbox ORDER BY timestamp")
+            .unwrap();
+        stmt.query_map([], |row| {
+            let id: String = row.get("id")?;
+            let chat_jid: String = row.get("chat_jid")?;
+            let quote_id: Option<String> = row.get("quote_id")?;
+            let text: Option<String> = row.get("text")?;
+            let file_kind: Option<u8> = row.get("file_kind")?;
+            let file_path: Option<String> = row.get("file_path")?;
+            let mentions: Option<String> = row.get("mentions")?;
+
+            let content = match (file_kind, file_path) {
+                (Some(kind), Some(path)) => wr::MessageContent::File(wr::FileContent {
+                    kind: wr::FileKind::from_repr(kind).unwrap_or_default(),
+                    path: path.into(),
+                    file_id: "".into(),
+                    caption: text.map(|t| t.into()),
+                }),
+                _ => wr::MessageContent::Text(text.unwrap_or_default().into()),
+            };
+
+            Ok(OutboxEntry {
+                id: id.into(),
+                chat: chat_jid.into(),
+                content,
+                quote_id: quote_id.map(|q| q.into()),
+                mentions: split_jids(&mentions.unwrap_or_default()),
+                timestamp: row.get("timestamp")?,
+                attempts: row.get("attempts")?,
+                failed: row.get("failed")?,
+            })
+        })
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect()
+    }
+
     pub fn add_contact(&self, jid: &wr::JID, name: &str) {
         self.db
             .execute(
@@ -260,76 +644,185 @@ impl DatabaseHandler {
         let mut stmt = self.db.prepare("SELECT jid, name FROM contacts").unwrap();
         let rows = stmt
             .query_map([], |row| {
-                let jid: String = row.get(0).unwrap();
-                let name: String = row.get(1).unwrap();
+                let jid: String = row.get("jid")?;
+                let name: String = row.get("name")?;
                 Ok((jid.into(), Arc::from(name)))
             })
             .unwrap();
         rows.map(|r| r.unwrap()).collect()
     }
 
-    pub fn init(&self) {
-        self.db
-            .execute(
-                "CREATE TABLE IF NOT EXISTS chats (
-                    jid TEXT PRIMARY KEY
-                )",
-                [],
-            )
-            .unwrap();
+    /// Creates the schema, or migrates it to the latest version. Fails on a database
+    /// written by a newer wstui.
+    pub fn init(&mut self) -> Result<(), MigrationError> {
+        migrations::migrate(&mut self.db, &self.db_path)
+    }
+}
 
-        self.db
-            .execute(
-                "CREATE TABLE IF NOT EXISTS contacts (
-                    jid TEXT PRIMARY KEY,
-                    name TEXT NOT NULL
-                )",
-                [],
-            )
-            .unwrap();
+/// The chats `query` may refer to: the chat with that JID or phone number, or else the
+/// chats named that in `contacts`, or else those whose name contains it, ignoring case.
+pub fn match_chats(
+    query: &str,
+    chats: &[wr::JID],
+    contacts: &HashMap<wr::JID, Arc<str>>,
+) -> Vec<wr::JID> {
+    let number = query.trim_start_matches('+').replace([' ', '-'], "");
+    let by_address = chats
+        .iter()
+        .filter(|jid| {
+            *jid.0 == *query
+                || (!number.is_empty()
+                    && number.chars().all(|c| c.is_ascii_digit())
+                    && jid_user(jid) == number)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    if !by_address.is_empty() {
+        return by_address;
+    }
 
-        for kind in wr::MessageContent::iter() {
-            match kind {
-                wr::MessageContent::Text(_) => {
-                    self.db
-                        .execute(
-                            "CREATE TABLE IF NOT EXISTS text_messages (
-                                id TEXT PRIMARY KEY,
-                                chat_jid TEXT,
-                                sender_jid TEXT,
-                                timestamp INTEGER,
-                                quote_id TEXT,
-                                is_from_me INTEGER,
-                                read INTEGER,
-
-                                message TEXT
-                            )",
-                            [],
-                        )
-                        .unwrap();
-                }
-                wr::MessageContent::File(_) => {
-                    self.db
-                        .execute(
-                            "CREATE TABLE IF NOT EXISTS file_messages (
-                                id TEXT PRIMARY KEY,
-                                chat_jid TEXT,
-                                sender_jid TEXT,
-                                timestamp INTEGER,
-                                quote_id TEXT,
-                                is_from_me INTEGER,
-                                read INTEGER,
-
-                                kind INTEGER,
-                                path TEXT,
-                                file_id TEXT,
-                                caption TEXT
-                            )",
-                            [],
-                        )
-                        .unwrap();
-                }
-            }
+    let query = query.to_lowercase();
+    let named = |matches: &dyn Fn(&str) -> bool| {
+        chats
+            .iter()
+            .filter(|jid| {
+                contacts
+                    .get(*jid)
+                    .is_some_and(|name| matches(&name.to_lowercase()))
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let exact = named(&|name| name == query);
+    if exact.is_empty() {
+        named(&|name| name.contains(&query))
+    } else {
+        exact
+    }
+}
+
+/// What the `kind` column of `messages` holds for each kind of content.
+#[derive(Clone, Copy, FromRepr)]
+#[repr(u8)]
+enum ContentKind {
+    Text = 0,
+    File = 1,
+    GroupChange = 2,
+}
+
+impl ContentKind {
+    fn of(content: &wr::MessageContent) -> Self {
+        match content {
+            wr::MessageContent::Text(_) => ContentKind::Text,
+            wr::MessageContent::File(_) => ContentKind::File,
+            wr::MessageContent::GroupChange(_) => ContentKind::GroupChange,
         }
     }
 }
+
+/// The columns `message_from_row` reads, from `messages m` and `MESSAGE_JOINS`.
+const MESSAGE_COLUMNS: &str = "m.id, m.chat_jid, m.sender_jid, m.timestamp, m.quote_id,
+    m.is_from_me, m.kind, m.text, m.delivery, m.edit_state, m.mentions,
+    media.kind AS media_kind, media.path, media.file_id,
+    g.kind AS change_kind, g.participants";
+
+const MESSAGE_JOINS: &str = "LEFT JOIN message_media media ON media.message_id = m.id
+    LEFT JOIN group_changes g ON g.message_id = m.id";
+
+/// Which rows of `messages` have their text in `message_search`: text messages and
+/// files, but not group changes or revoked messages.
+const SEARCHABLE: &str = "kind IN (0, 1) AND edit_state != 2";
+
+/// The text of a message that is searched: the text or the caption.
+fn search_text(message: &wr::Message) -> Option<&str> {
+    let text = match &message.message {
+        wr::MessageContent::Text(_) if message.info.edit_state == wr::EditState::Revoked => None,
+        wr::MessageContent::Text(text) => Some(text.as_ref()),
+        wr::MessageContent::File(file) => file.caption.as_deref(),
+        wr::MessageContent::GroupChange(_) => None,
+    };
+    text.filter(|text| !text.is_empty())
+}
+
+/// Turns what the user typed into an FTS5 query that matches messages with every word,
+/// the last one possibly unfinished. Quoting each word keeps FTS5 operators and
+/// punctuation from being taken as syntax.
+fn search_query(query: &str) -> Option<String> {
+    let words = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        return None;
+    }
+    Some(format!("{}*", words.join(" ")))
+}
+
+fn message_from_row(row: &rusqlite::Row) -> rusqlite::Result<wr::Message> {
+    let id: String = row.get("id")?;
+    let chat_jid: String = row.get("chat_jid")?;
+    let sender_jid: String = row.get("sender_jid")?;
+    let quote_id: Option<String> = row.get("quote_id")?;
+    let kind: u8 = row.get("kind")?;
+    let text: Option<String> = row.get("text")?;
+    let delivery: u8 = row.get("delivery")?;
+    let edit_state: u8 = row.get("edit_state")?;
+    let mentions: Option<String> = row.get("mentions")?;
+
+    let message = match ContentKind::from_repr(kind) {
+        Some(ContentKind::File) => {
+            let media_kind: Option<u8> = row.get("media_kind")?;
+            let path: Option<String> = row.get("path")?;
+            let file_id: Option<String> = row.get("file_id")?;
+            wr::MessageContent::File(wr::FileContent {
+                kind: media_kind
+                    .and_then(wr::FileKind::from_repr)
+                    .unwrap_or_default(),
+                path: path.unwrap_or_default().into(),
+                file_id: file_id.unwrap_or_default().into(),
+                caption: text.map(|t| t.into()),
+            })
+        }
+        Some(ContentKind::GroupChange) => {
+            let change_kind: Option<u8> = row.get("change_kind")?;
+            let participants: Option<String> = row.get("participants")?;
+            wr::MessageContent::GroupChange(wr::GroupChange {
+                kind: change_kind
+                    .and_then(wr::GroupChangeKind::from_repr)
+                    .unwrap_or_default(),
+                participants: split_jids(&participants.unwrap_or_default()),
+                text: text.unwrap_or_default().into(),
+            })
+        }
+        Some(ContentKind::Text) | None => wr::MessageContent::Text(text.unwrap_or_default().into()),
+    };
+
+    Ok(wr::Message {
+        info: wr::MessageInfo {
+            id: id.into(),
+            chat: chat_jid.into(),
+            sender: sender_jid.into(),
+            timestamp: row.get("timestamp")?,
+            quote_id: quote_id.map(|q| q.into()),
+            is_from_me: row.get("is_from_me")?,
+            delivery: wr::DeliveryState::from_repr(delivery).unwrap_or_default(),
+            edit_state: wr::EditState::from_repr(edit_state).unwrap_or_default(),
+            mentions: split_jids(&mentions.unwrap_or_default()),
+        },
+        message,
+    })
+}
+
+/// JIDs never contain spaces, so a list of them is stored space separated.
+fn join_jids(jids: &[wr::JID]) -> String {
+    jids.iter()
+        .map(|jid| jid.0.as_ref())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn split_jids(jids: &str) -> Vec<wr::JID> {
+    jids.split_whitespace()
+        .map(|jid| jid.to_string().into())
+        .collect()
+}
@@ -0,0 +1,322 @@
+//! Schema migrations. The version of a database is kept in `PRAGMA user_version`, and
+//! migration `n` (counting from one) takes it from version `n - 1` to `n`.
+
+use std::{fmt, path::Path, path::PathBuf};
+
+use log::info;
+use rusqlite::Connection;
+
+pub struct Migration {
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+/// Every migration, oldest first. Released migrations must never be edited or
+/// reordered, only appended to.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        // Databases from before versioning have some of these tables already.
+        description: "initial schema",
+        sql: "
+        CREATE TABLE IF NOT EXISTS chats (
+            jid TEXT PRIMARY KEY
+        );
+
+        CREATE TABLE IF NOT EXISTS contacts (
+            jid TEXT PRIMARY KEY,
+            name TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS reactions (
+            message_id TEXT,
+            chat_jid TEXT,
+            sender_jid TEXT,
+            emoji TEXT,
+            timestamp INTEGER,
+            is_from_me INTEGER,
+            PRIMARY KEY (message_id, sender_jid)
+        );
+
+        CREATE TABLE IF NOT EXISTS outbox (
+            id TEXT PRIMARY KEY,
+            chat_jid TEXT,
+            timestamp INTEGER,
+            quote_id TEXT,
+            text TEXT,
+            file_kind INTEGER,
+            file_path TEXT,
+            attempts INTEGER,
+            failed INTEGER
+        );
+
+        CREATE TABLE IF NOT EXISTS message_status (
+            message_id TEXT PRIMARY KEY,
+            state INTEGER
+        );
+
+        CREATE TABLE IF NOT EXISTS receipts (
+            message_id TEXT,
+            participant_jid TEXT,
+            delivered_at INTEGER,
+            read_at INTEGER,
+            played_at INTEGER,
+            PRIMARY KEY (message_id, participant_jid)
+        );
+
+        CREATE TABLE IF NOT EXISTS message_edits (
+            message_id TEXT PRIMARY KEY,
+            state INTEGER
+        );
+
+        -- Outbox entries keep their mentions here too, under their local id.
+        CREATE TABLE IF NOT EXISTS message_mentions (
+            message_id TEXT PRIMARY KEY,
+            jids TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS text_messages (
+            id TEXT PRIMARY KEY,
+            chat_jid TEXT,
+            sender_jid TEXT,
+            timestamp INTEGER,
+            quote_id TEXT,
+            is_from_me INTEGER,
+            read INTEGER,
+
+            message TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS file_messages (
+            id TEXT PRIMARY KEY,
+            chat_jid TEXT,
+            sender_jid TEXT,
+            timestamp INTEGER,
+            quote_id TEXT,
+            is_from_me INTEGER,
+            read INTEGER,
+
+            kind INTEGER,
+            path TEXT,
+            file_id TEXT,
+            caption TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS group_change_messages (
+            id TEXT PRIMARY KEY,
+            chat_jid TEXT,
+            sender_jid TEXT,
+            timestamp INTEGER,
+            quote_id TEXT,
+            is_from_me INTEGER,
+            read INTEGER,
+
+            kind INTEGER,
+            participants TEXT,
+            text TEXT
+        );
+    ",
+    },
+    Migration {
+        description: "one messages table with side tables for media and group changes",
+        sql: "
+        -- `kind` is the kind of content: 0 text, 1 file, 2 group change. `text` is the
+        -- text, the caption or the text of the group change.
+        CREATE TABLE messages (
+            id TEXT PRIMARY KEY,
+            chat_jid TEXT NOT NULL,
+            sender_jid TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            quote_id TEXT,
+            is_from_me INTEGER NOT NULL,
+            kind INTEGER NOT NULL,
+            text TEXT,
+            delivery INTEGER NOT NULL,
+            edit_state INTEGER NOT NULL DEFAULT 0,
+            mentions TEXT
+        );
+        CREATE INDEX messages_chat_timestamp ON messages (chat_jid, timestamp);
+
+        CREATE TABLE message_media (
+            message_id TEXT PRIMARY KEY,
+            kind INTEGER NOT NULL,
+            path TEXT NOT NULL,
+            file_id TEXT NOT NULL
+        );
+
+        CREATE TABLE group_changes (
+            message_id TEXT PRIMARY KEY,
+            kind INTEGER NOT NULL,
+            participants TEXT NOT NULL
+        );
+
+        -- Rows written before delivery states existed only know whether the message
+        -- was read.
+        INSERT OR IGNORE INTO messages
+        SELECT m.id, m.chat_jid, m.sender_jid, m.timestamp, m.quote_id, m.is_from_me,
+            m.kind, m.text,
+            COALESCE(s.state, CASE WHEN m.read THEN 3 ELSE 1 END),
+            COALESCE(e.state, 0),
+            mm.jids
+        FROM (
+            SELECT id, chat_jid, sender_jid, timestamp, quote_id, is_from_me, read,
+                0 AS kind, message AS text
+            FROM text_messages
+            UNION ALL
+            SELECT id, chat_jid, sender_jid, timestamp, quote_id, is_from_me, read,
+                1, caption
+            FROM file_messages
+            UNION ALL
+            SELECT id, chat_jid, sender_jid, timestamp, quote_id, is_from_me, read,
+                2, text
+            FROM group_change_messages
+        ) m
+        LEFT JOIN message_status s ON s.message_id = m.id
+        LEFT JOIN message_edits e ON e.message_id = m.id
+        LEFT JOIN message_mentions mm ON mm.message_id = m.id;
+
+        INSERT OR IGNORE INTO message_media
+        SELECT id, kind, path, file_id FROM file_messages;
+
+        INSERT OR IGNORE INTO group_changes
+        SELECT id, kind, participants FROM group_change_messages;
+
+        ALTER TABLE outbox ADD COLUMN mentions TEXT;
+        UPDATE outbox SET mentions = (
+            SELECT jids FROM message_mentions WHERE message_id = outbox.id
+        );
+
+        DROP TABLE text_messages;
+        DROP TABLE file_messages;
+        DROP TABLE group_change_messages;
+        DROP TABLE message_status;
+        DROP TABLE message_edits;
+        DROP TABLE message_mentions;
+    ",
+    },
+    Migration {
+        description: "index messages by chat in the order history pages are read",
+        sql: "
+        DROP INDEX messages_chat_timestamp;
+        CREATE INDEX messages_chat_timestamp_id ON messages (chat_jid, timestamp, id);
+    ",
+    },
+    Migration {
+        description: "full-text search of messages",
+        sql: "
+        -- The text or caption of text and file messages, kept in sync by the writer.
+        CREATE VIRTUAL TABLE message_search USING fts5 (
+            message_id UNINDEXED,
+            text,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        INSERT INTO message_search (message_id, text)
+        SELECT id, text FROM messages
+        WHERE kind IN (0, 1) AND edit_state != 2 AND text != '';
+    ",
+    },
+    Migration {
+        description: "unread messages per chat",
+        sql: "
+        ALTER TABLE chats ADD COLUMN unread INTEGER NOT NULL DEFAULT 0;
+    ",
+    },
+];
+
+/// The version a database has once every migration ran.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.len() as u32
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The database was written by a newer wstui, which may have changed it in ways
+    /// this one does not understand.
+    NewerVersion {
+        found: u32,
+        supported: u32,
+    },
+    Backup(PathBuf, rusqlite::Error),
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::NewerVersion { found, supported } => write!(
+                f,
+                "the database is at version {found}, but this wstui only supports up to version {supported}. Please upgrade wstui"
+            ),
+            MigrationError::Backup(path, err) => {
+                write!(
+                    f,
+                    "failed to back up the database to {}: {err}",
+                    path.display()
+                )
+            }
+            MigrationError::Sqlite(err) => write!(f, "failed to migrate the database: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(err: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(err)
+    }
+}
+
+pub fn user_version(db: &Connection) -> rusqlite::Result<u32> {
+    db.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Where the copy of a database at `version` is kept before migrating it.
+pub fn backup_path(db_path: &Path, version: u32) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(format!(".v{version}.bak"));
+    path.into()
+}
+
+/// Brings the database at `db_path` up to the latest version. A database that has any
+/// tables is copied to `backup_path` first. Each migration runs in its own transaction,
+/// so a failed one leaves the database at the version before it.
+pub fn migrate(db: &mut Connection, db_path: &Path) -> Result<(), MigrationError> {
+    let version = user_version(db)?;
+    let latest = latest_version();
+    if version > latest {
+        return Err(MigrationError::NewerVersion {
+            found: version,
+            supported: latest,
+        });
+    }
+    if version == latest {
+        return Ok(());
+    }
+
+    let has_tables: bool = db.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table')",
+        [],
+        |row| row.get(0),
+    )?;
+    // A backup left by an earlier attempt is of this same version, and still good.
+    let backup = backup_path(db_path, version);
+    if has_tables && !backup.exists() {
+        info!("Backing up the database to {}", backup.display());
+        db.execute("VACUUM INTO ?", [backup.to_string_lossy()])
+            .map_err(|err| MigrationError::Backup(backup.clone(), err))?;
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let to = index as u32 + 1;
+        info!(
+            "Migrating the database to version {to}: {}",
+            migration.description
+        );
+        let tx = db.transaction()?;
+        tx.execute_batch(migration.sql)?;
+        tx.pragma_update(None, "user_version", to)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,597 @@
+//! Writes the messages of a chat out of the database, for people to read without wstui.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use strum::{Display, EnumIter};
+use whatsrust as wr;
+
+use crate::app::mentions::resolve_mentions;
+use crate::app::{Chat, describe_group_change, is_group, jid_user};
+use crate::db::DatabaseHandler;
+
+/// Written in place of the content of messages deleted for everyone, as WhatsApp does.
+const REVOKED_TEXT: &str = "This message was deleted.";
+
+#[derive(Clone, Copy, Debug, Display, EnumIter, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Markdown,
+    /// One page that needs nothing but the media directory next to it.
+    #[strum(to_string = "HTML")]
+    Html,
+    /// One JSON object per message.
+    #[strum(to_string = "JSON Lines")]
+    Jsonl,
+    /// The format of the "Export chat" of WhatsApp.
+    #[strum(to_string = "WhatsApp text")]
+    Txt,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Txt => "txt",
+        }
+    }
+}
+
+/// What to export. The days are in local time and both are included.
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    pub chat: wr::JID,
+    pub format: ExportFormat,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+    /// The directory the export is written to. The media of the messages is copied to
+    /// `media/` in it and linked from there, so the export can be moved along with it.
+    /// Without one, media is linked to where it is kept.
+    pub out_dir: Option<PathBuf>,
+}
+
+impl ExportOptions {
+    /// The timestamps the days cover, the end excluded.
+    pub fn time_range(&self) -> (Option<i64>, Option<i64>) {
+        let since = self.since.map(start_of_day);
+        let until = self
+            .until
+            .map(|day| start_of_day(day.succ_opt().unwrap_or(NaiveDate::MAX)));
+        (since, until)
+    }
+}
+
+/// Writes the messages of the chat to `out` and returns how many there were. Names
+/// come from the contacts table, and `self_jid`, when known, is called "you" in group
+/// changes. Media is kept under `media_path`.
+pub fn export_chat(
+    db: &DatabaseHandler,
+    media_path: &Path,
+    self_jid: Option<&wr::JID>,
+    options: &ExportOptions,
+    out: &mut dyn Write,
+) -> io::Result<usize> {
+    let (since, until) = options.time_range();
+    let messages = db.get_messages_between(&options.chat, since, until);
+    export_messages(db, media_path, self_jid, options, &messages, out)?;
+    Ok(messages.len())
+}
+
+/// Writes `messages`, all from the chat of `options`, to `out` like `export_chat`
+/// does, copying their media to the directory of the export if it has one.
+pub fn export_messages(
+    db: &DatabaseHandler,
+    media_path: &Path,
+    self_jid: Option<&wr::JID>,
+    options: &ExportOptions,
+    messages: &[wr::Message],
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    if let Some(out_dir) = &options.out_dir {
+        copy_media(media_path, &out_dir.join(EXPORT_MEDIA_DIR), messages)?;
+    }
+    let contacts = db.get_contacts().into_iter().collect();
+    let exporter = Exporter {
+        db,
+        media_path,
+        relative_media: options.out_dir.is_some(),
+        self_jid,
+        contacts: &contacts,
+    };
+    exporter.write(&options.chat, options.format, messages, out)
+}
+
+/// Writes `messages`, all from `chat`, to `out` like `export_chat` does.
+pub fn write_messages(
+    db: &DatabaseHandler,
+    media_path: &Path,
+    self_jid: Option<&wr::JID>,
+    chat: &wr::JID,
+    format: ExportFormat,
+    messages: &[wr::Message],
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let contacts = db.get_contacts().into_iter().collect();
+    let exporter = Exporter {
+        db,
+        media_path,
+        relative_media: false,
+        self_jid,
+        contacts: &contacts,
+    };
+    exporter.write(chat, format, messages, out)
+}
+
+/// The object a message is written as in the JSON Lines export, with names taken
+/// from `contacts`.
+pub fn message_json(
+    db: &DatabaseHandler,
+    media_path: &Path,
+    self_jid: Option<&wr::JID>,
+    contacts: &HashMap<wr::JID, Arc<str>>,
+    message: &wr::Message,
+) -> serde_json::Value {
+    let exporter = Exporter {
+        db,
+        media_path,
+        relative_media: false,
+        self_jid,
+        contacts,
+    };
+    exporter.json(message)
+}
+
+/// The object a chat is listed as by `wstui chats`, with its name taken from
+/// `contacts`.
+pub fn chat_json(contacts: &HashMap<wr::JID, Arc<str>>, chat: &Chat) -> serde_json::Value {
+    let name = match contacts.get(&chat.jid) {
+        Some(name) => name.to_string(),
+        None if is_group(&chat.jid) => jid_user(&chat.jid).to_string(),
+        None => format!("+{}", jid_user(&chat.jid)),
+    };
+    serde_json::json!({
+        "jid": chat.jid.0.as_ref(),
+        "name": name,
+        "group": is_group(&chat.jid),
+        "last_message_time": chat.last_message_time,
+        "unread": chat.unread,
+    })
+}
+
+/// The first second of `day` in local time.
+fn start_of_day(day: NaiveDate) -> i64 {
+    let midnight = day.and_hms_opt(0, 0, 0).unwrap();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map_or_else(|| midnight.and_utc().timestamp(), |time| time.timestamp())
+}
+
+fn local_time(timestamp: i64) -> DateTime<Local> {
+    DateTime::from_timestamp(timestamp, 0).unwrap().into()
+}
+
+/// The directory next to an export its media is copied to.
+const EXPORT_MEDIA_DIR: &str = "media";
+
+/// Copies the files of `messages` that were downloaded from `media_path` to `to`,
+/// under the same relative paths. Files already there are left alone.
+fn copy_media(media_path: &Path, to: &Path, messages: &[wr::Message]) -> io::Result<()> {
+    for message in messages {
+        let wr::MessageContent::File(file) = &message.message else {
+            continue;
+        };
+        let from = media_path.join(file.path.as_ref());
+        let dest = to.join(file.path.as_ref());
+        if message.info.edit_state == wr::EditState::Revoked || !from.exists() || dest.exists() {
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&from, &dest)?;
+    }
+    Ok(())
+}
+
+struct Exporter<'a> {
+    db: &'a DatabaseHandler,
+    media_path: &'a Path,
+    /// Whether media is linked relative to the export, in `EXPORT_MEDIA_DIR`.
+    relative_media: bool,
+    self_jid: Option<&'a wr::JID>,
+    contacts: &'a HashMap<wr::JID, Arc<str>>,
+}
+
+impl Exporter<'_> {
+    fn write(
+        &self,
+        chat: &wr::JID,
+        format: ExportFormat,
+        messages: &[wr::Message],
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
+        match format {
+            ExportFormat::Markdown => self.write_markdown(chat, messages, out),
+            ExportFormat::Html => self.write_html(chat, messages, out),
+            ExportFormat::Jsonl => self.write_jsonl(messages, out),
+            ExportFormat::Txt => self.write_txt(chat, messages, out),
+        }
+    }
+
+    /// The contact name, or the number for people who are not in the contacts.
+    fn name(&self, jid: &wr::JID) -> Arc<str> {
+        match self.contacts.get(jid) {
+            Some(name) => name.clone(),
+            None if is_group(jid) => jid_user(jid).into(),
+            None => format!("+{}", jid_user(jid)).into(),
+        }
+    }
+
+    fn sender(&self, info: &wr::MessageInfo) -> Arc<str> {
+        if info.is_from_me {
+            "You".into()
+        } else {
+            self.name(&info.sender)
+        }
+    }
+
+    /// The text of a message, the caption of a file or the description of a group
+    /// change, with mentions resolved. Empty for files without a caption.
+    fn text(&self, message: &wr::Message) -> String {
+        let info = &message.info;
+        match &message.message {
+            _ if info.edit_state == wr::EditState::Revoked => REVOKED_TEXT.to_string(),
+            wr::MessageContent::Text(text) => resolve_mentions(text, &info.mentions, self.contacts),
+            wr::MessageContent::File(file) => file
+                .caption
+                .as_ref()
+                .map(|caption| resolve_mentions(caption, &info.mentions, self.contacts))
+                .unwrap_or_default(),
+            wr::MessageContent::GroupChange(change) => {
+                describe_group_change(info, change, self.self_jid, &|jid| self.name(jid))
+            }
+        }
+    }
+
+    /// The file of a message that was not deleted.
+    fn file<'m>(&self, message: &'m wr::Message) -> Option<&'m wr::FileContent> {
+        match &message.message {
+            wr::MessageContent::File(file) if message.info.edit_state != wr::EditState::Revoked => {
+                Some(file)
+            }
+            _ => None,
+        }
+    }
+
+    /// Who wrote the message `message` quotes, and its text, if it is still around.
+    fn quote(&self, message: &wr::Message) -> Option<(Arc<str>, String)> {
+        let quoted = self.db.get_message(message.info.quote_id.as_ref()?)?;
+        let mut text = self.text(&quoted);
+        if text.is_empty()
+            && let Some(file) = self.file(&quoted)
+        {
+            text = format!("[{}]", file_kind_name(&file.kind));
+        }
+        Some((self.sender(&quoted.info), text))
+    }
+
+    /// Where the file of a message is, relative to the export if its media is copied.
+    fn media_file(&self, file: &wr::FileContent) -> PathBuf {
+        if self.relative_media {
+            Path::new(EXPORT_MEDIA_DIR).join(file.path.as_ref())
+        } else {
+            self.media_path.join(file.path.as_ref())
+        }
+    }
+
+    /// A link to the file of a message.
+    fn media_url(&self, file: &wr::FileContent) -> String {
+        let path = self.media_file(file);
+        let path = path.to_string_lossy();
+        let mut url = String::from(if self.relative_media { "" } else { "file://" });
+        for c in path.chars() {
+            match c {
+                ' ' | '"' | '#' | '%' | '?' | '<' | '>' | '(' | ')' => {
+                    write!(url, "%{:02X}", c as u32).unwrap()
+                }
+                _ => url.push(c),
+            }
+        }
+        url
+    }
+
+    fn chat_title(&self, chat: &wr::JID) -> String {
+        format!("Chat with {}", self.name(chat))
+    }
+
+    fn write_markdown(
+        &self,
+        chat: &wr::JID,
+        messages: &[wr::Message],
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
+        writeln!(out, "# {}", escape_markdown(&self.chat_title(chat)))?;
+        let mut day = None;
+        for message in messages {
+            let info = &message.info;
+            let time = local_time(info.timestamp);
+            if day != Some(time.date_naive()) {
+                day = Some(time.date_naive());
+                writeln!(out, "\n## {}", time.format("%A, %-d %B %Y"))?;
+            }
+            writeln!(out)?;
+
+            let text = self.text(message);
+            if let wr::MessageContent::GroupChange(_) = message.message {
+                writeln!(
+                    out,
+                    "*{} · {}*",
+                    escape_markdown(&text),
+                    time.format("%H:%M")
+                )?;
+                continue;
+            }
+
+            let mut lines = vec![format!(
+                "**{}** · {}{}",
+                escape_markdown(&self.sender(info)),
+                time.format("%H:%M"),
+                if info.edit_state == wr::EditState::Edited {
+                    " (edited)"
+                } else {
+                    ""
+                }
+            )];
+            if let Some((sender, quoted)) = self.quote(message) {
+                lines.push(String::new());
+                lines.push(format!(
+                    "> **{}**: {}",
+                    escape_markdown(&sender),
+                    escape_markdown(&quoted.replace('\n', " "))
+                ));
+                // Ends the quote, which would take the next lines too.
+                lines.push(String::new());
+            }
+            if let Some(file) = self.file(message) {
+                let name = file_name(file);
+                lines.push(match file.kind {
+                    wr::FileKind::Image | wr::FileKind::Sticker => {
+                        format!("![{}](<{}>)", escape_markdown(name), self.media_url(file))
+                    }
+                    _ => format!("[{}](<{}>)", escape_markdown(name), self.media_url(file)),
+                });
+            }
+            lines.extend(text.lines().map(escape_markdown));
+            // A trailing backslash breaks the line without starting a new paragraph.
+            let last = lines.len() - 1;
+            for (i, line) in lines.iter().enumerate() {
+                let hard_break = i < last && !line.is_empty() && !lines[i + 1].is_empty();
+                writeln!(out, "{line}{}", if hard_break { "\\" } else { "" })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_html(
+        &self,
+        chat: &wr::JID,
+        messages: &[wr::Message],
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
+        let title = escape_html(&self.chat_title(chat));
+        writeln!(
+            out,
+            "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 48em; margin: 0 auto; padding: 1em; background: #efeae2; }}
+h2 {{ text-align: center; font-size: 0.9em; color: #54656f; }}
+.message {{ background: #fff; border-radius: 0.5em; padding: 0.4em 0.6em; margin: 0.3em 0; max-width: 80%; width: fit-content; }}
+.message.me {{ background: #d9fdd3; margin-left: auto; }}
+.sender {{ font-weight: bold; font-size: 0.9em; }}
+.time {{ color: #667781; font-size: 0.8em; margin-left: 0.5em; }}
+.quote {{ border-left: 3px solid #06cf9c; padding-left: 0.5em; color: #54656f; font-size: 0.9em; }}
+.deleted {{ font-style: italic; color: #667781; }}
+.event {{ text-align: center; color: #54656f; font-size: 0.85em; margin: 0.6em 0; }}
+img {{ max-width: 100%; max-height: 20em; display: block; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>"
+        )?;
+
+        let mut day = None;
+        for message in messages {
+            let info = &message.info;
+            let time = local_time(info.timestamp);
+            if day != Some(time.date_naive()) {
+                day = Some(time.date_naive());
+                writeln!(out, "<h2>{}</h2>", time.format("%A, %-d %B %Y"))?;
+            }
+
+            let text = escape_html(&self.text(message)).replace('\n', "<br>");
+            if let wr::MessageContent::GroupChange(_) = message.message {
+                writeln!(out, "<div class=\"event\">{text}</div>")?;
+                continue;
+            }
+
+            let class = if info.is_from_me {
+                "message me"
+            } else {
+                "message"
+            };
+            writeln!(out, "<div class=\"{class}\">")?;
+            writeln!(
+                out,
+                "<div><span class=\"sender\">{}</span><span class=\"time\">{}{}</span></div>",
+                escape_html(&self.sender(info)),
+                time.format("%H:%M"),
+                if info.edit_state == wr::EditState::Edited {
+                    " · edited"
+                } else {
+                    ""
+                }
+            )?;
+            if let Some((sender, quoted)) = self.quote(message) {
+                writeln!(
+                    out,
+                    "<div class=\"quote\"><b>{}</b><br>{}</div>",
+                    escape_html(&sender),
+                    escape_html(&quoted).replace('\n', "<br>")
+                )?;
+            }
+            if let Some(file) = self.file(message) {
+                let url = escape_html(&self.media_url(file));
+                let name = escape_html(file_name(file));
+                match file.kind {
+                    wr::FileKind::Image | wr::FileKind::Sticker => writeln!(
+                        out,
+                        "<a href=\"{url}\"><img src=\"{url}\" alt=\"{name}\"></a>"
+                    )?,
+                    wr::FileKind::Video => writeln!(
+                        out,
+                        "<video controls src=\"{url}\"></video><div><a href=\"{url}\">{name}</a></div>"
+                    )?,
+                    wr::FileKind::Audio => writeln!(
+                        out,
+                        "<audio controls src=\"{url}\"></audio><div><a href=\"{url}\">{name}</a></div>"
+                    )?,
+                    wr::FileKind::Document => {
+                        writeln!(out, "<div><a href=\"{url}\">📎 {name}</a></div>")?
+                    }
+                }
+            }
+            if info.edit_state == wr::EditState::Revoked {
+                writeln!(out, "<div class=\"deleted\">{text}</div>")?;
+            } else if !text.is_empty() {
+                writeln!(out, "<div>{text}</div>")?;
+            }
+            writeln!(out, "</div>")?;
+        }
+        writeln!(out, "</body>\n</html>")
+    }
+
+    fn write_jsonl(&self, messages: &[wr::Message], out: &mut dyn Write) -> io::Result<()> {
+        for message in messages {
+            writeln!(out, "{}", self.json(message))?;
+        }
+        Ok(())
+    }
+
+    fn json(&self, message: &wr::Message) -> serde_json::Value {
+        let info = &message.info;
+        let file = self.file(message);
+        serde_json::json!({
+            "id": info.id.as_ref(),
+            "chat": info.chat.0.as_ref(),
+            "chat_name": self.name(&info.chat).as_ref(),
+            "sender": info.sender.0.as_ref(),
+            "sender_name": self.sender(info).as_ref(),
+            "from_me": info.is_from_me,
+            "timestamp": info.timestamp,
+            "time": local_time(info.timestamp).to_rfc3339(),
+            "type": match &message.message {
+                wr::MessageContent::Text(_) => "text",
+                wr::MessageContent::File(file) => file_kind_name(&file.kind),
+                wr::MessageContent::GroupChange(_) => "group_change",
+            },
+            "text": self.text(message),
+            "file": file.map(|file| self.media_file(file).to_string_lossy().into_owned()),
+            "quote_id": info.quote_id.as_deref(),
+            "edited": info.edit_state == wr::EditState::Edited,
+            "deleted": info.edit_state == wr::EditState::Revoked,
+            "mentions": info.mentions.iter().map(|jid| jid.0.as_ref()).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Like the `_chat.txt` of WhatsApp on iOS: one `[date, time] sender: text` line
+    /// per message, with the lines after the first of a message as they are. Group
+    /// changes are sent by the group.
+    fn write_txt(
+        &self,
+        chat: &wr::JID,
+        messages: &[wr::Message],
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
+        for message in messages {
+            let info = &message.info;
+            let time = local_time(info.timestamp).format("%d/%m/%Y, %H:%M:%S");
+            let text = self.text(message);
+            let line = match (&message.message, self.file(message)) {
+                (wr::MessageContent::GroupChange(_), _) => {
+                    writeln!(out, "[{time}] {}: \u{200e}{text}", self.name(chat))?;
+                    continue;
+                }
+                (_, Some(file)) if text.is_empty() => {
+                    format!("\u{200e}<attached: {}>", file_name(file))
+                }
+                (_, Some(file)) => format!("\u{200e}<attached: {}>\n{text}", file_name(file)),
+                _ if info.edit_state == wr::EditState::Revoked => format!("\u{200e}{text}"),
+                _ => text,
+            };
+            write!(out, "[{time}] {}: {line}", self.sender(info))?;
+            if info.edit_state == wr::EditState::Edited {
+                write!(out, " \u{200e}<This message was edited>")?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+fn file_name(file: &wr::FileContent) -> &str {
+    Path::new(file.path.as_ref())
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(&file.path)
+}
+
+fn file_kind_name(kind: &wr::FileKind) -> &'static str {
+    match kind {
+        wr::FileKind::Image => "image",
+        wr::FileKind::Video => "video",
+        wr::FileKind::Audio => "audio",
+        wr::FileKind::Document => "document",
+        wr::FileKind::Sticker => "sticker",
+    }
+}
+
+/// `text` with the characters Markdown would take as formatting escaped.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '~'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,531 @@
+//! Reads the chats WhatsApp exports on a phone ("Export chat") into the database: a
+//! `_chat.txt`, usually zipped together with the media.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+use regex::Regex;
+use whatsrust as wr;
+use zip::ZipArchive;
+
+use crate::app::{Chat, is_group, jid_user};
+use crate::db::DatabaseHandler;
+use crate::util::stable_hash;
+
+/// The order of the day, the month and the year in the dates of an export, which
+/// depends on the language of the phone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum DateOrder {
+    Dmy,
+    Mdy,
+    Ymd,
+}
+
+/// Where to import a chat to.
+#[derive(Clone, Debug)]
+pub struct ImportOptions {
+    pub chat: wr::JID,
+    /// The name the owner of the phone appears under, whose messages are ours. In a
+    /// private chat it is guessed when the other person is in the contacts.
+    pub me: Option<String>,
+    /// The sender of our messages, when known. Otherwise they are sent by the chat.
+    pub self_jid: Option<wr::JID>,
+    /// Guessed from the dates themselves when not given.
+    pub date_order: Option<DateOrder>,
+}
+
+/// What an import added.
+#[derive(Clone, Debug, Default)]
+pub struct ImportSummary {
+    pub messages: usize,
+    pub files: usize,
+    /// Attachments the chat mentions that were not in the archive.
+    pub missing_files: Vec<String>,
+    /// Notices like "Messages and calls are end-to-end encrypted", which are left out.
+    pub skipped: usize,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    Zip(zip::result::ZipError),
+    /// The archive has no `.txt` with the chat in it.
+    NoChatText,
+    /// Nothing in the chat looks like a message.
+    NoMessages,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(err) => write!(f, "{err}"),
+            ImportError::Zip(err) => write!(f, "failed to read the archive: {err}"),
+            ImportError::NoChatText => write!(f, "the archive has no _chat.txt"),
+            ImportError::NoMessages => write!(f, "the chat has no messages in a known format"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<io::Error> for ImportError {
+    fn from(err: io::Error) -> Self {
+        ImportError::Io(err)
+    }
+}
+
+impl From<zip::result::ZipError> for ImportError {
+    fn from(err: zip::result::ZipError) -> Self {
+        ImportError::Zip(err)
+    }
+}
+
+/// One message as the export wrote it, with the lines after the first included.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportedMessage {
+    pub time: NaiveDateTime,
+    /// `None` for notices that are not sent by anyone.
+    pub sender: Option<String>,
+    pub text: String,
+}
+
+const DATE: &str = r"(\d{1,4})[./-](\d{1,2})[./-](\d{1,4})";
+const TIME: &str = r"(\d{1,2})[:.](\d{2})(?:[:.](\d{2}))?(?:\s?([AaPp])\.?\s?[Mm]\.?)?";
+
+/// `[05/03/2024, 09:15:30] Alice: hi`, as iOS writes them.
+static BRACKETED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r"^\[{DATE},?\s+{TIME}\]\s(.*)$")).unwrap());
+/// `05/03/2024, 09:15 - Alice: hi`, as Android writes them.
+static DASHED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r"^{DATE},?\s+{TIME}\s+-\s(.*)$")).unwrap());
+
+/// The first line of a message before the date is known to be day or month first.
+struct Header {
+    date: [u32; 3],
+    /// The first number of the date has four digits, so it is the year.
+    year_first: bool,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    pm: Option<bool>,
+    rest: String,
+}
+
+/// Splits the text of an export into messages. A line that does not start with a date
+/// and a time continues the message before it.
+pub fn parse_chat(text: &str, order: Option<DateOrder>) -> Vec<ExportedMessage> {
+    let mut headers: Vec<Header> = Vec::new();
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let Some(captures) = BRACKETED
+            .captures(line.trim_start_matches('\u{200e}'))
+            .or_else(|| DASHED.captures(line.trim_start_matches('\u{200e}')))
+        else {
+            if let Some(header) = headers.last_mut() {
+                header.rest.push('\n');
+                header.rest.push_str(line);
+            }
+            continue;
+        };
+        let number = |i| {
+            captures
+                .get(i)
+                .map_or(0, |m| m.as_str().parse().unwrap_or(0))
+        };
+        headers.push(Header {
+            date: [number(1), number(2), number(3)],
+            year_first: captures[1].len() == 4,
+            hour: number(4),
+            minute: number(5),
+            second: number(6),
+            pm: captures
+                .get(7)
+                .map(|m| m.as_str().eq_ignore_ascii_case("p")),
+            rest: captures[8].to_string(),
+        });
+    }
+
+    let order = order.unwrap_or_else(|| guess_date_order(&headers));
+    headers
+        .into_iter()
+        .filter_map(|header| {
+            let [a, b, c] = header.date;
+            let (year, month, day) = match order {
+                DateOrder::Dmy => (c, b, a),
+                DateOrder::Mdy => (c, a, b),
+                DateOrder::Ymd => (a, b, c),
+            };
+            let year = if year < 100 { year + 2000 } else { year };
+            let hour = match header.pm {
+                Some(pm) => header.hour % 12 + if pm { 12 } else { 0 },
+                None => header.hour,
+            };
+            let time = NaiveDate::from_ymd_opt(year as i32, month, day)?.and_hms_opt(
+                hour,
+                header.minute,
+                header.second,
+            )?;
+            let (sender, text) = match header.rest.split_once(": ") {
+                Some((sender, text)) => (Some(sender.to_string()), text.to_string()),
+                None => (None, header.rest),
+            };
+            Some(ExportedMessage { time, sender, text })
+        })
+        .collect()
+}
+
+/// A date with a first number over 12 can only be day first, and one with a second
+/// number over 12 month first. Otherwise, 12-hour times are a hint of month first.
+fn guess_date_order(headers: &[Header]) -> DateOrder {
+    if headers.iter().any(|h| h.year_first) {
+        DateOrder::Ymd
+    } else if headers.iter().any(|h| h.date[0] > 12) {
+        DateOrder::Dmy
+    } else if headers.iter().any(|h| h.date[1] > 12) || headers.iter().any(|h| h.pm.is_some()) {
+        DateOrder::Mdy
+    } else {
+        DateOrder::Dmy
+    }
+}
+
+/// What the text of an exported message holds.
+#[derive(Debug)]
+enum Body {
+    Text(String),
+    File {
+        name: String,
+        caption: String,
+    },
+    Deleted,
+    /// Something WhatsApp wrote, like a group change or the encryption notice.
+    Notice,
+}
+
+const DELETED: &[&str] = &[
+    "This message was deleted",
+    "This message was deleted.",
+    "You deleted this message",
+    "You deleted this message.",
+];
+
+/// The body of a message and whether it was edited.
+fn parse_body(text: &str) -> (Body, bool) {
+    let (text, edited) = match text.trim_end().strip_suffix("<This message was edited>") {
+        Some(text) => (text.trim_end_matches([' ', '\u{200e}']), true),
+        None => (text, false),
+    };
+    // iOS marks what it wrote itself, attachments included, with a left-to-right mark.
+    let marked = text.starts_with('\u{200e}');
+    let text = text.trim_start_matches('\u{200e}');
+    let (first, rest) = text.split_once('\n').unwrap_or((text, ""));
+
+    let body = if let Some(name) = first
+        .strip_prefix("<attached: ")
+        .and_then(|name| name.strip_suffix('>'))
+    {
+        Body::File {
+            name: name.to_string(),
+            caption: rest.to_string(),
+        }
+    } else if let Some(name) = first.strip_suffix(" (file attached)") {
+        Body::File {
+            name: name.trim_start_matches('\u{200e}').to_string(),
+            caption: rest.to_string(),
+        }
+    } else if DELETED.contains(&text.trim()) {
+        Body::Deleted
+    } else if marked {
+        Body::Notice
+    } else {
+        Body::Text(text.to_string())
+    };
+    (body, edited)
+}
+
+/// The kind of file WhatsApp would send `name` as, going by its extension.
+pub(crate) fn file_kind(name: &str) -> wr::FileKind {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match extension.as_str() {
+        "webp" => wr::FileKind::Sticker,
+        "jpg" | "jpeg" | "png" | "gif" | "heic" => wr::FileKind::Image,
+        "mp4" | "mov" | "3gp" | "mkv" | "webm" => wr::FileKind::Video,
+        "opus" | "ogg" | "m4a" | "mp3" | "aac" | "wav" | "amr" => wr::FileKind::Audio,
+        _ => wr::FileKind::Document,
+    }
+}
+
+/// The JID a phone number or a JID stands for, if `query` is one.
+pub fn parse_address(query: &str) -> Option<wr::JID> {
+    let query = query.trim();
+    if query.contains('@') {
+        return Some(query.to_string().into());
+    }
+    let digits = query
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>();
+    let is_number = query
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '+' | ' ' | '-' | '(' | ')'));
+    (is_number && digits.len() >= 6).then(|| format!("{digits}@s.whatsapp.net").into())
+}
+
+/// The name of the chat in an archive named like WhatsApp names them, as in
+/// "WhatsApp Chat - Alice.zip" or "WhatsApp Chat with Alice.txt".
+pub fn chat_name_from_archive(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    ["WhatsApp Chat - ", "WhatsApp Chat with "]
+        .iter()
+        .find_map(|prefix| stem.strip_prefix(prefix))
+        .map(str::to_string)
+}
+
+/// An export: a zip, or a `.txt` with the media in the same directory.
+enum Archive {
+    Zip(ZipArchive<File>),
+    Directory(PathBuf),
+}
+
+impl Archive {
+    fn open(path: &Path) -> Result<Self, ImportError> {
+        let is_zip = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+        if is_zip {
+            Ok(Archive::Zip(ZipArchive::new(File::open(path)?)?))
+        } else {
+            Ok(Archive::Directory(path.to_path_buf()))
+        }
+    }
+
+    fn chat_text(&mut self) -> Result<String, ImportError> {
+        match self {
+            Archive::Zip(zip) => {
+                let names = zip.file_names().map(str::to_string).collect::<Vec<_>>();
+                let name = names
+                    .iter()
+                    .find(|name| file_name(name) == "_chat.txt")
+                    .or_else(|| names.iter().find(|name| name.ends_with(".txt")))
+                    .ok_or(ImportError::NoChatText)?;
+                let mut text = String::new();
+                zip.by_name(name)?.read_to_string(&mut text)?;
+                Ok(text)
+            }
+            Archive::Directory(path) => Ok(fs::read_to_string(path)?),
+        }
+    }
+
+    /// Copies the file called `name` to `to`. `false` if the archive does not have it.
+    fn copy_file(&mut self, name: &str, to: &Path) -> Result<bool, ImportError> {
+        match self {
+            Archive::Zip(zip) => {
+                let Some(entry) = zip
+                    .file_names()
+                    .find(|entry| file_name(entry) == name)
+                    .map(str::to_string)
+                else {
+                    return Ok(false);
+                };
+                let mut file = zip.by_name(&entry)?;
+                io::copy(&mut file, &mut File::create(to)?)?;
+            }
+            Archive::Directory(path) => {
+                let from = path.with_file_name(name);
+                if !from.exists() {
+                    return Ok(false);
+                }
+                fs::copy(from, to)?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// The last component of a path in an archive, which may use either separator.
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// Who sent the messages of a chat, by the name the export gives them.
+enum Sender {
+    Me,
+    Other(wr::JID),
+}
+
+/// Finds who each name is: a contact with that name, a phone number, or in a private
+/// chat the other person. Anyone else gets a made up JID and a contact with the name.
+fn resolve_senders(
+    db: &DatabaseHandler,
+    names: &BTreeSet<&str>,
+    options: &ImportOptions,
+) -> HashMap<String, Sender> {
+    let contacts = db.get_contacts();
+    let known = |name: &str| {
+        contacts
+            .iter()
+            .find(|(_, contact)| contact.as_ref() == name)
+            .or_else(|| {
+                contacts
+                    .iter()
+                    .find(|(_, contact)| contact.to_lowercase() == name.to_lowercase())
+            })
+            .map(|(jid, _)| jid.clone())
+            .or_else(|| parse_address(name).filter(|_| !name.contains('@')))
+    };
+    let private = !is_group(&options.chat);
+    // The one name that is not the other person is ours.
+    let me = options.me.clone().or_else(|| {
+        let others = names
+            .iter()
+            .filter(|name| known(name).as_ref() != Some(&options.chat))
+            .collect::<Vec<_>>();
+        (private && names.len() == 2 && others.len() == 1).then(|| others[0].to_string())
+    });
+
+    names
+        .iter()
+        .map(|&name| {
+            let sender = if Some(name) == me.as_deref() || name == "You" {
+                Sender::Me
+            } else if let Some(jid) = known(name) {
+                Sender::Other(jid)
+            } else if private {
+                Sender::Other(options.chat.clone())
+            } else {
+                let jid: wr::JID = format!("{:016x}@imported", stable_hash(&[name])).into();
+                db.add_contact(&jid, name);
+                Sender::Other(jid)
+            };
+            (name.to_string(), sender)
+        })
+        .collect()
+}
+
+/// Reads the export at `archive` into the database as messages of `options.chat`, and
+/// copies its media under `media_path`. Each message gets an id made from its content,
+/// so importing the same export again changes nothing.
+pub fn import_archive(
+    db: &DatabaseHandler,
+    media_path: &Path,
+    archive: &Path,
+    options: &ImportOptions,
+) -> Result<ImportSummary, ImportError> {
+    let mut archive = Archive::open(archive)?;
+    let exported = parse_chat(&archive.chat_text()?, options.date_order);
+    if exported.is_empty() {
+        return Err(ImportError::NoMessages);
+    }
+
+    let names = exported
+        .iter()
+        .filter_map(|message| message.sender.as_deref())
+        .collect::<BTreeSet<_>>();
+    let senders = resolve_senders(db, &names, options);
+    let media_dir = Path::new("imported").join(jid_user(&options.chat));
+    fs::create_dir_all(media_path.join(&media_dir))?;
+
+    let mut summary = ImportSummary::default();
+    // Identical messages sent in the same second still need ids of their own.
+    let mut occurrences = HashMap::<u64, u32>::new();
+    for message in &exported {
+        let Some(sender_name) = &message.sender else {
+            summary.skipped += 1;
+            continue;
+        };
+        let (body, edited) = parse_body(&message.text);
+        let (content, edit_state) = match body {
+            Body::Notice => {
+                summary.skipped += 1;
+                continue;
+            }
+            Body::Deleted => (wr::MessageContent::Text("".into()), wr::EditState::Revoked),
+            Body::Text(text) => (
+                wr::MessageContent::Text(text.into()),
+                wr::EditState::Original,
+            ),
+            Body::File { name, caption } => {
+                let name = file_name(&name).to_string();
+                let path = media_dir.join(&name);
+                if archive.copy_file(&name, &media_path.join(&path))? {
+                    summary.files += 1;
+                } else {
+                    summary.missing_files.push(name.clone());
+                }
+                let content = wr::MessageContent::File(wr::FileContent {
+                    kind: file_kind(&name),
+                    path: path.to_string_lossy().into(),
+                    // Nothing to download, the file is where it would be downloaded to.
+                    file_id: "".into(),
+                    caption: (!caption.is_empty()).then(|| caption.into()),
+                });
+                (content, wr::EditState::Original)
+            }
+        };
+        let edit_state = if edited {
+            wr::EditState::Edited
+        } else {
+            edit_state
+        };
+
+        let timestamp = Local
+            .from_local_datetime(&message.time)
+            .earliest()
+            .map_or_else(
+                || message.time.and_utc().timestamp(),
+                |time| time.timestamp(),
+            );
+        // The time as the export wrote it, so importing again in another time zone, or
+        // after a change to daylight saving time, makes the same ids.
+        let hash = stable_hash(&[
+            &options.chat.0,
+            &message.time.to_string(),
+            sender_name,
+            &message.text,
+        ]);
+        let occurrence = occurrences.entry(hash).or_default();
+        let id = format!("import-{hash:016X}-{occurrence}");
+        *occurrence += 1;
+
+        let (sender, is_from_me) = match &senders[sender_name] {
+            Sender::Me => (
+                options.self_jid.clone().unwrap_or(options.chat.clone()),
+                true,
+            ),
+            Sender::Other(jid) => (jid.clone(), false),
+        };
+        db.add_message(&wr::Message {
+            info: wr::MessageInfo {
+                id: id.into(),
+                chat: options.chat.clone(),
+                sender,
+                timestamp,
+                is_from_me,
+                quote_id: None,
+                delivery: if is_from_me {
+                    wr::DeliveryState::Read
+                } else {
+                    wr::DeliveryState::Delivered
+                },
+                edit_state,
+                mentions: Vec::new(),
+            },
+            message: content,
+        });
+        summary.messages += 1;
+    }
+
+    db.add_chat(&Chat {
+        jid: options.chat.clone(),
+        last_message_time: None,
+        unread: 0,
+    });
+    Ok(summary)
+}
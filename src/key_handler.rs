@@ -1,5 +1,9 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui_textarea::{self, Input};
+use strum::{EnumIter, EnumString, IntoEnumIterator, IntoStaticStr};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Key {
@@ -54,10 +58,386 @@ impl Into<Input> for Key {
     }
 }
 
+/// Parses keys written as in Vim: `gg`, `<C-q>`, `<Space>ar`. Letters and symbols
+/// stand for themselves, anything else goes between `<` and `>`, with `C-`, `S-` and
+/// `A-` for Ctrl, Shift and Alt.
+pub fn parse_keys(notation: &str) -> Result<Vec<Key>, String> {
+    let mut keys = Vec::new();
+    let mut chars = notation.chars();
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            keys.push(Key::c(c));
+            continue;
+        }
+        let mut name = String::new();
+        loop {
+            match chars.next() {
+                Some('>') => break,
+                Some(c) => name.push(c),
+                None => return Err(format!("\"<{name}\" is missing its closing \">\"")),
+            }
+        }
+        keys.push(parse_special_key(&name).ok_or_else(|| format!("unknown key <{name}>"))?);
+    }
+    if keys.is_empty() {
+        return Err("no keys given".to_string());
+    }
+    Ok(keys)
+}
+
+/// Writes keys the way `parse_keys` reads them.
+pub fn key_notation(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|key| {
+            let mut modifiers = key.modifiers;
+            let name = match key.code {
+                KeyCode::Char(c) => {
+                    // An uppercase letter says it was typed with Shift already.
+                    if c.is_ascii_uppercase() {
+                        modifiers.remove(KeyModifiers::SHIFT);
+                    }
+                    match c {
+                        ' ' => "Space".to_string(),
+                        '<' => "lt".to_string(),
+                        c => c.to_string(),
+                    }
+                }
+                KeyCode::Esc => "Esc".to_string(),
+                KeyCode::Enter => "Enter".to_string(),
+                KeyCode::Tab => "Tab".to_string(),
+                KeyCode::Backspace => "BS".to_string(),
+                KeyCode::Delete => "Del".to_string(),
+                KeyCode::F(n) => format!("F{n}"),
+                code => format!("{code:?}"),
+            };
+            let mut prefix = String::new();
+            for (modifier, letter) in [
+                (KeyModifiers::CONTROL, "C-"),
+                (KeyModifiers::SHIFT, "S-"),
+                (KeyModifiers::ALT, "A-"),
+            ] {
+                if modifiers.contains(modifier) {
+                    prefix.push_str(letter);
+                }
+            }
+            if prefix.is_empty() && name.chars().count() == 1 {
+                name
+            } else {
+                format!("<{prefix}{name}>")
+            }
+        })
+        .collect()
+}
+
+/// The key named between `<` and `>`.
+fn parse_special_key(name: &str) -> Option<Key> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+    while let Some((modifier, key)) = rest.split_once('-')
+        && !key.is_empty()
+    {
+        modifiers |= match modifier.to_ascii_uppercase().as_str() {
+            "C" => KeyModifiers::CONTROL,
+            "S" => KeyModifiers::SHIFT,
+            "A" | "M" => KeyModifiers::ALT,
+            _ => return None,
+        };
+        rest = key;
+    }
+
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => {
+            if c.is_ascii_uppercase() {
+                modifiers |= KeyModifiers::SHIFT;
+            }
+            KeyCode::Char(c)
+        }
+        _ => match rest.to_ascii_lowercase().as_str() {
+            "esc" => KeyCode::Esc,
+            "enter" | "cr" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "bs" | "backspace" => KeyCode::Backspace,
+            "del" | "delete" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "space" => KeyCode::Char(' '),
+            "lt" => KeyCode::Char('<'),
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            name => KeyCode::F(name.strip_prefix('f')?.parse().ok().filter(|n| *n >= 1)?),
+        },
+    };
+    Some(Key { code, modifiers })
+}
+
+/// Every operation of the app, by the name it is bound to keys with in the `[keys]`
+/// section of the config and run with from the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIter, EnumString, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum Action {
+    Quit,
+    ToggleLogs,
+    ToggleParticipants,
+    Search,
+    SwitchProfile,
+    CommandLine,
+    FocusLeft,
+    FocusRight,
+    FocusUp,
+    FocusDown,
+
+    NextChat,
+    PreviousChat,
+    OpenChat,
+    FilterChats,
+    ExportChat,
+    Mute,
+    Unmute,
+    NewProfile,
+    NextCandidate,
+    PreviousCandidate,
+
+    ScrollUp,
+    ScrollDown,
+    NextMessage,
+    PreviousMessage,
+    FirstMessage,
+    LastMessage,
+    SearchInChat,
+    NextMatch,
+    PreviousMatch,
+    ClearSelection,
+    OpenMessage,
+    Reply,
+    EditMessage,
+    DeleteMessage,
+    Resend,
+    Discard,
+    React,
+    ViewMessage,
+    MessageInfo,
+    CopyMessage,
+    GoToQuote,
+
+    Send,
+    ExternalEditor,
+    CancelReply,
+    CancelEdit,
+    RemoveAttachment,
+    AttachImage,
+    AttachDocument,
+    PasteClipboard,
+}
+
+impl Action {
+    /// The keys bound to the action unless the config says otherwise.
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Action::Quit => &["<C-q>"],
+            Action::ToggleLogs => &["<C-S-l>"],
+            Action::ToggleParticipants => &["<C-g>"],
+            Action::Search => &["<C-s>"],
+            Action::SwitchProfile => &["<C-a>"],
+            Action::CommandLine => &[":"],
+            Action::FocusLeft => &["<C-h>"],
+            Action::FocusRight => &["<C-l>"],
+            Action::FocusUp => &["<C-k>"],
+            Action::FocusDown => &["<C-j>"],
+
+            Action::NextChat => &["j"],
+            Action::PreviousChat => &["k"],
+            Action::OpenChat => &["<Enter>"],
+            Action::FilterChats => &["/"],
+            Action::ExportChat => &["E"],
+            Action::Mute => &[],
+            Action::Unmute => &[],
+            Action::NewProfile => &["n"],
+            Action::NextCandidate => &["<C-n>"],
+            Action::PreviousCandidate => &["<C-p>"],
+
+            Action::ScrollUp => &["<C-e>"],
+            Action::ScrollDown => &["<C-y>"],
+            Action::NextMessage => &["j"],
+            Action::PreviousMessage => &["k"],
+            Action::FirstMessage => &["gg"],
+            Action::LastMessage => &["G"],
+            Action::SearchInChat => &["/"],
+            Action::NextMatch => &["n"],
+            Action::PreviousMatch => &["N"],
+            Action::ClearSelection => &["<Esc>"],
+            Action::OpenMessage => &["o"],
+            Action::Reply => &["r"],
+            Action::EditMessage => &["e"],
+            Action::DeleteMessage => &["dd"],
+            Action::Resend => &["R"],
+            Action::Discard => &["dd"],
+            Action::React => &["+"],
+            Action::ViewMessage => &["<Enter>"],
+            Action::MessageInfo => &["i"],
+            Action::CopyMessage => &["y"],
+            Action::GoToQuote => &["gq"],
+
+            Action::Send => &["<C-x>"],
+            Action::ExternalEditor => &["<C-e>"],
+            Action::CancelReply => &["<Space>r"],
+            Action::CancelEdit => &["<Space>e"],
+            Action::RemoveAttachment => &["<Space>ar"],
+            Action::AttachImage => &["<Space>ai"],
+            Action::AttachDocument => &["<Space>ad"],
+            Action::PasteClipboard => &["<Space>p"],
+        }
+    }
+
+    /// What the action does, as listed in the command line.
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::ToggleLogs => "Toggle logs",
+            Action::ToggleParticipants => "Toggle group participants",
+            Action::Search => "Search messages in every chat",
+            Action::SwitchProfile => "Switch profile",
+            Action::CommandLine => "Open the command line",
+            Action::FocusLeft => "Focus the chat list",
+            Action::FocusRight => "Focus the message list from the chat list",
+            Action::FocusUp => "Focus the message list from the input",
+            Action::FocusDown => "Focus the input from the message list",
+
+            Action::NextChat => "Next chat",
+            Action::PreviousChat => "Previous chat",
+            Action::OpenChat => "Open chat",
+            Action::FilterChats => "Search contacts",
+            Action::ExportChat => "Export chat",
+            Action::Mute => "Mute the chat, for good or for a while like 8h",
+            Action::Unmute => "Unmute the chat",
+            Action::NewProfile => "Create a profile",
+            Action::NextCandidate => "Next completion in the command line",
+            Action::PreviousCandidate => "Previous completion in the command line",
+
+            Action::ScrollUp => "Scroll up",
+            Action::ScrollDown => "Scroll down",
+            Action::NextMessage => "Next message",
+            Action::PreviousMessage => "Previous message",
+            Action::FirstMessage => "First message",
+            Action::LastMessage => "Last message",
+            Action::SearchInChat => "Search in the chat",
+            Action::NextMatch => "Next (older) match",
+            Action::PreviousMatch => "Previous (newer) match",
+            Action::ClearSelection => "Reset selection and clear the search",
+            Action::OpenMessage => "Open (external)",
+            Action::Reply => "Reply to message",
+            Action::EditMessage => "Edit own message",
+            Action::DeleteMessage => "Delete own message for everyone",
+            Action::Resend => "Resend a failed message",
+            Action::Discard => "Discard a failed message",
+            Action::React => "React to message",
+            Action::ViewMessage => "View full content",
+            Action::MessageInfo => "Message info",
+            Action::CopyMessage => "Copy to clipboard",
+            Action::GoToQuote => "Go to quoted message",
+
+            Action::Send => "Send message (or save edit), or the text given",
+            Action::ExternalEditor => "Edit with external editor",
+            Action::CancelReply => "Cancel reply",
+            Action::CancelEdit => "Cancel edit",
+            Action::RemoveAttachment => "Remove attachment",
+            Action::AttachImage => "Attach image",
+            Action::AttachDocument => "Attach document",
+            Action::PasteClipboard => "Paste from clipboard",
+        }
+    }
+
+    /// The name of the action in the config and on the command line.
+    pub fn name(self) -> &'static str {
+        self.into()
+    }
+}
+
+/// The key sequences bound to each action.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<Action, Vec<Vec<Key>>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = Action::iter()
+            .map(|action| {
+                let keys = action
+                    .default_keys()
+                    .iter()
+                    .map(|notation| parse_keys(notation).expect("the default keys are valid"))
+                    .collect();
+                (action, keys)
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// Binds `action` to `keys` in place of its current keys. With no keys the action
+    /// is left unbound.
+    pub fn bind(&mut self, action: Action, keys: Vec<Vec<Key>>) {
+        self.bindings.insert(action, keys);
+    }
+
+    pub fn keys(&self, action: Action) -> &[Vec<Key>] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// The keys of `action` that another of `actions` is bound to as well, with that
+    /// action. Where both are active, only one of them runs.
+    pub fn conflicts(&self, action: Action, actions: &[Action]) -> Vec<(Vec<Key>, Action)> {
+        self.keys(action)
+            .iter()
+            .filter_map(|keys| {
+                actions
+                    .iter()
+                    .find(|other| **other != action && self.keys(**other).contains(keys))
+                    .map(|other| (keys.clone(), *other))
+            })
+            .collect()
+    }
+
+    /// Removes `keys` from the keys of `action`.
+    pub fn unbind(&mut self, action: Action, keys: &[Key]) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|binding| binding != keys);
+        }
+    }
+
+    /// Whether `keys` are some binding, or the start of one.
+    fn starts_binding(&self, keys: &[Key]) -> bool {
+        self.bindings
+            .values()
+            .flatten()
+            .any(|binding| binding.starts_with(keys))
+    }
+}
+
+/// How long keys that are bound to an action, but also start a longer binding, wait for
+/// the rest of it unless the config says otherwise.
+pub const DEFAULT_KEY_TIMEOUT: Duration = Duration::from_millis(1000);
+
 #[derive(Debug, Clone)]
 pub struct KeybindHandler {
     pub key_buffer: Vec<Key>,
     pub key_sequence_active: bool,
+    pub keymap: Keymap,
+    /// How long keys bound to an action wait when they also start a longer binding. With
+    /// no time at all the shorter binding always runs.
+    pub timeout: Duration,
+    /// The action bound to the keys pressed so far, run unless they go on to a longer
+    /// binding in time.
+    pending: Option<Action>,
+    /// Counts the pending actions, to tell which one a timeout is for.
+    pending_id: u64,
 }
 
 impl Default for KeybindHandler {
@@ -65,6 +445,10 @@ impl Default for KeybindHandler {
         Self {
             key_buffer: Vec::new(),
             key_sequence_active: false,
+            keymap: Keymap::default(),
+            timeout: DEFAULT_KEY_TIMEOUT,
+            pending: None,
+            pending_id: 0,
         }
     }
 }
@@ -116,20 +500,83 @@ impl KeybindHandler {
     }
 
     pub fn kp(&mut self, expected: &[Key]) -> bool {
-        if self
-            .key_buffer
+        sequence_pressed(
+            &mut self.key_buffer,
+            &mut self.key_sequence_active,
+            expected,
+        )
+    }
+
+    /// The first of `actions` bound to the keys pressed so far, which clears them. Keys
+    /// that also start a longer binding of one of `actions` wait for the rest of it
+    /// instead, and their action is left pending.
+    pub fn pressed_action(&mut self, actions: &[Action]) -> Option<Action> {
+        if self.key_buffer.is_empty() {
+            return None;
+        }
+        let buffer = self.key_buffer.as_slice();
+        let exact = actions
             .iter()
-            .zip(expected.iter())
-            .all(|(a, b)| a == b)
-        {
-            if self.key_buffer.len() == expected.len() {
-                self.key_buffer.clear();
-                return true;
-            } else {
+            .copied()
+            .find(|action| self.keymap.keys(*action).iter().any(|keys| keys == buffer));
+        let longer = actions.iter().any(|action| {
+            self.keymap
+                .keys(*action)
+                .iter()
+                .any(|keys| keys.len() > buffer.len() && keys.starts_with(buffer))
+        });
+        match exact {
+            Some(action) if longer && !self.timeout.is_zero() => {
+                self.pending = Some(action);
+                self.pending_id += 1;
                 self.key_sequence_active = true;
+                None
+            }
+            Some(action) => {
+                self.key_buffer.clear();
+                Some(action)
+            }
+            None => {
+                if longer {
+                    self.key_sequence_active = true;
+                }
+                None
             }
         }
-        false
+    }
+
+    /// The id of the action left pending by the last key, which `timed_out` takes.
+    pub fn pending_id(&self) -> Option<u64> {
+        self.pending.map(|_| self.pending_id)
+    }
+
+    /// Call this before `pressed_start`. The pending action, if `key` does not go on
+    /// with a binding: it runs first, and `key` starts a sequence of its own. `Esc` drops
+    /// the pending action instead.
+    pub fn interrupt_pending(&mut self, event: &KeyEvent) -> Option<Action> {
+        let action = self.pending.take()?;
+        let key = Key {
+            code: event.code,
+            modifiers: event.modifiers,
+        };
+        let mut keys = self.key_buffer.clone();
+        keys.push(key.clone());
+        if key == Key::k(KeyCode::Esc) || self.keymap.starts_binding(&keys) {
+            return None;
+        }
+        self.key_buffer.clear();
+        Some(action)
+    }
+
+    /// The pending action if it is still the one with `id`, as no more keys came in time.
+    pub fn timed_out(&mut self, id: u64) -> Option<Action> {
+        if self.pending_id != id {
+            return None;
+        }
+        let action = self.pending.take()?;
+        self.key_buffer.clear();
+        self.key_sequence_active = false;
+        Some(action)
     }
 
     pub fn kp_partial(&mut self, expected: &[Key]) -> Option<Vec<Key>> {
@@ -150,3 +597,21 @@ impl KeybindHandler {
         // false
     }
 }
+
+/// Whether the keys pressed so far are `expected`, which clears them. Marks a sequence
+/// as started if they are the beginning of it.
+fn sequence_pressed(
+    key_buffer: &mut Vec<Key>,
+    sequence_active: &mut bool,
+    expected: &[Key],
+) -> bool {
+    if key_buffer.iter().zip(expected.iter()).all(|(a, b)| a == b) {
+        if key_buffer.len() == expected.len() {
+            key_buffer.clear();
+            return true;
+        } else {
+            *sequence_active = true;
+        }
+    }
+    false
+}
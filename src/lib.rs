@@ -1,5 +1,12 @@
+pub mod backend;
+pub mod cli;
+pub mod clipboard;
+pub mod config;
 pub mod db;
+pub mod export;
+pub mod import;
 pub mod ui;
+pub mod util;
 pub mod vim;
 // pub mod vim_v2;
 pub mod app;
@@ -1,12 +1,102 @@
-use wstui::app::App;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use clap::Parser;
+use wstui::app::profiles::{parse_profile_name, profile_dir};
+use wstui::app::{App, default_data_dir};
+use wstui::backend::FfiBackend;
+use wstui::cli::{Session, parse_time, write_chat_messages, write_chats};
+use wstui::db::DatabaseHandler;
+use wstui::export::{ExportFormat, ExportOptions, export_chat};
+use wstui::import::{
+    DateOrder, ImportOptions, chat_name_from_archive, import_archive, parse_address,
+};
+
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
     #[clap(short, long)]
     phone: Option<String>,
+
+    /// Use the account of this profile, each with its own data. Without it the default
+    /// profile is used
+    #[clap(long, global = true, value_parser = parse_profile_name)]
+    profile: Option<String>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Export a chat from the local database
+    Export {
+        /// The chat: its JID, the phone number or the name of the contact
+        chat: String,
+        #[clap(short, long, value_enum, default_value_t = ExportFormat::Markdown)]
+        format: ExportFormat,
+        /// The file to write, instead of standard output
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// Leave out messages from before this day (YYYY-MM-DD)
+        #[clap(long)]
+        since: Option<NaiveDate>,
+        /// Leave out messages from after this day (YYYY-MM-DD)
+        #[clap(long)]
+        until: Option<NaiveDate>,
+    },
+    /// Import a chat exported by WhatsApp on a phone
+    Import {
+        /// The zip the phone made, or the _chat.txt with the media next to it
+        archive: PathBuf,
+        /// The chat to import into: its JID, the phone number or the name of the contact.
+        /// Taken from the name of the archive if not given
+        #[clap(long)]
+        chat: Option<String>,
+        /// The name you appear under in the chat
+        #[clap(long)]
+        me: Option<String>,
+        /// The order of the day, month and year in the dates, guessed if not given
+        #[clap(long, value_enum)]
+        date_order: Option<DateOrder>,
+    },
+    /// Send a message, without opening the interface
+    Send {
+        /// The chat: its JID, the phone number or the name of the contact
+        chat: String,
+        /// The text, or the caption of the file
+        text: String,
+        /// A file to send along
+        #[clap(short, long)]
+        file: Option<PathBuf>,
+    },
+    /// List the chats as JSON lines, the most recent first
+    Chats,
+    /// Print the messages of a chat, as JSON lines by default
+    Messages {
+        /// The chat: its JID, the phone number or the name of the contact
+        chat: String,
+        /// Leave out messages from before this time: 2024-01-31, 2024-01-31 18:30, or
+        /// how long ago like 30m, 2h or 7d
+        #[clap(long, value_parser = parse_time)]
+        since: Option<i64>,
+        /// Leave out messages from this time on, given like --since
+        #[clap(long, value_parser = parse_time)]
+        until: Option<i64>,
+        /// Print only this many of the most recent messages
+        #[clap(short = 'n', long)]
+        limit: Option<usize>,
+        #[clap(short, long, value_enum, default_value_t = ExportFormat::Jsonl)]
+        format: ExportFormat,
+    },
+    /// Print the messages received from now on as JSON lines, until interrupted
+    Tail,
 }
 
 fn main() {
@@ -19,7 +109,174 @@ fn main() {
     // }
 
     let args = Args::parse();
+    let data_dir = profile_dir(&default_data_dir(), args.profile.as_deref());
+
+    match args.command {
+        Some(command) => {
+            if let Err(err) = run_command(&data_dir, command) {
+                eprintln!("error: {err}");
+                std::process::exit(1);
+            }
+        }
+        None => {
+            let mut data_dir = data_dir;
+            let mut phone = args.phone;
+            // Switching profiles quits the app, to open it again on the other one.
+            loop {
+                let mut app = App::open(&data_dir);
+                app.run(phone.take());
+                match app.switch_to.take() {
+                    Some(dir) => data_dir = dir,
+                    None => break,
+                }
+            }
+        }
+    }
+}
+
+fn run_command(data_dir: &Path, command: Command) -> Result<(), Box<dyn Error>> {
+    let db_path = data_dir.join("whatsapp.db");
+    if !db_path.exists() {
+        return Err(format!("there is no database at {}", db_path.display()).into());
+    }
+    let mut db = DatabaseHandler::new(&db_path);
+    let result = db
+        .init()
+        .map_err(Into::into)
+        .and_then(|()| run(&db, data_dir, command));
+    db.stop();
+    result
+}
+
+fn run(db: &DatabaseHandler, data_dir: &Path, command: Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Export {
+            chat,
+            format,
+            output,
+            since,
+            until,
+        } => {
+            let options = ExportOptions {
+                chat: find_chat(db, &chat)?,
+                format,
+                since,
+                until,
+                // Media goes next to the file, a stream has it linked where it is kept.
+                out_dir: output
+                    .as_ref()
+                    .map(|path| path.parent().unwrap_or(Path::new("")).to_path_buf()),
+            };
+            let media_path = data_dir.join("media");
+            let count = match &output {
+                Some(path) => {
+                    let mut out = BufWriter::new(File::create(path)?);
+                    let count = export_chat(db, &media_path, None, &options, &mut out)?;
+                    out.flush()?;
+                    eprintln!("Exported {count} messages to {}", path.display());
+                    count
+                }
+                None => export_chat(db, &media_path, None, &options, &mut io::stdout().lock())?,
+            };
+            if count == 0 {
+                eprintln!("There were no messages to export");
+            }
+        }
+        Command::Import {
+            archive,
+            chat,
+            me,
+            date_order,
+        } => {
+            let query = chat
+                .or_else(|| chat_name_from_archive(&archive))
+                .ok_or("the chat to import into is not in the name of the archive, use --chat")?;
+            let chat = find_chat(db, &query).or_else(|err| parse_address(&query).ok_or(err))?;
+            let options = ImportOptions {
+                chat,
+                me,
+                self_jid: None,
+                date_order,
+            };
+            let summary = import_archive(db, &data_dir.join("media"), &archive, &options)?;
+            eprintln!(
+                "Imported {} messages and {} files into {}",
+                summary.messages, summary.files, options.chat.0
+            );
+            if !summary.missing_files.is_empty() {
+                eprintln!(
+                    "These attachments were not in the archive: {}",
+                    summary.missing_files.join(", ")
+                );
+            }
+        }
+        Command::Send { chat, text, file } => {
+            let chat = find_chat(db, &chat).or_else(|err| parse_address(&chat).ok_or(err))?;
+            let session = Session::open(Arc::new(FfiBackend), data_dir)?;
+            let result = session.send(db, &chat, &text, file.as_deref());
+            session.close()?;
+            eprintln!("Sent {}", result?);
+        }
+        Command::Chats => {
+            write_chats(db, &mut io::stdout().lock())?;
+        }
+        Command::Messages {
+            chat,
+            since,
+            until,
+            limit,
+            format,
+        } => {
+            write_chat_messages(
+                db,
+                &data_dir.join("media"),
+                &find_chat(db, &chat)?,
+                (since, until),
+                limit,
+                format,
+                &mut io::stdout().lock(),
+            )?;
+        }
+        Command::Tail => {
+            let stop = Arc::new(AtomicBool::new(false));
+            {
+                let stop = Arc::clone(&stop);
+                ctrlc::set_handler(move || stop.store(true, Ordering::Relaxed))?;
+            }
+            let session = Session::open(Arc::new(FfiBackend), data_dir)?;
+            let result = session.tail(db, &data_dir.join("media"), &stop, &mut io::stdout());
+            session.close()?;
+            // The reader going away is the usual way for a pipe to end.
+            if let Err(err) = result
+                && err.kind() != io::ErrorKind::BrokenPipe
+            {
+                return Err(err.into());
+            }
+        }
+    }
+    Ok(())
+}
 
-    let mut app = App::default();
-    app.run(args.phone);
+/// The one chat `query` refers to.
+fn find_chat(db: &DatabaseHandler, query: &str) -> Result<whatsrust::JID, Box<dyn Error>> {
+    let mut chats = db.find_chats(query);
+    match chats.len() {
+        0 => Err(format!("no chat matches \"{query}\"").into()),
+        1 => Ok(chats.remove(0)),
+        _ => {
+            let contacts = db
+                .get_contacts()
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>();
+            let candidates = chats
+                .iter()
+                .map(|jid| match contacts.get(jid) {
+                    Some(name) => format!("  {name} ({})", jid.0),
+                    None => format!("  {}", jid.0),
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            Err(format!("\"{query}\" matches more than one chat:\n{candidates}").into())
+        }
+    }
 }
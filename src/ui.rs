@@ -1,20 +1,45 @@
+pub mod chat_search;
+pub mod command_line;
+pub mod config_errors;
+pub mod export;
+pub mod mentions;
+pub mod message_info;
 pub mod message_list;
+pub mod participants;
+pub mod profiles;
+pub mod reaction_picker;
+pub mod search;
 pub mod text_input;
+pub mod which_key;
 
 use crate::app::{App, SelectedWidget};
+use crate::vim;
+use command_line::render_command_line;
+use config_errors::render_config_errors;
+use export::render_export;
 use log::trace;
+use mentions::render_mention_popup;
+use message_info::render_message_info;
 use message_list::{get_quoted_text, render_messages};
+use participants::render_participants;
+use profiles::render_profile_switcher;
 use ratatui::{
     Frame,
     layout::{Constraint, Layout, Position, Rect},
     style::{Style, Stylize},
+    text::{Line, Span},
     widgets::{Block, Borders, List, Paragraph},
 };
 use ratatui_image::{Resize, StatefulImage};
+use reaction_picker::render_reaction_picker;
+use search::render_search;
 use tui_logger::TuiLoggerWidget;
 use whatsrust as wr;
+use which_key::render_which_key;
 
 pub fn draw(frame: &mut Frame, app: &mut App) {
+    app.mouse.clear_areas();
+
     if let SelectedWidget::MessageView = app.selected_widget {
         let msg_id = app.message_list_state.get_selected_message().unwrap();
 
@@ -23,9 +48,9 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
             .borders(Borders::ALL)
             .border_style(Style::default().fg(
                 if let SelectedWidget::MessageView = app.selected_widget {
-                    ratatui::style::Color::Green
+                    app.config.theme.focused_border
                 } else {
-                    ratatui::style::Color::White
+                    app.config.theme.border
                 },
             ));
 
@@ -35,7 +60,7 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
         if let Some(msg) = app.messages.get(&msg_id) {
             match msg.message {
                 wr::MessageContent::Text(ref text) => {
-                    let paragraph = Paragraph::new(text.to_string());
+                    let paragraph = Paragraph::new(app.resolve_mentions(text, &msg.info.mentions));
                     frame.render_widget(paragraph, area);
                 }
                 wr::MessageContent::File(ref file) => match file.kind {
@@ -62,6 +87,7 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
                         frame.render_widget(paragraph, area);
                     }
                 },
+                wr::MessageContent::GroupChange(_) => {}
             }
         }
 
@@ -70,7 +96,7 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
 
     if app.show_logs {
         let [contacts_area, chat_area, logs_area] = Layout::horizontal([
-            Constraint::Min(30),
+            Constraint::Min(app.config.layout.chat_list_width),
             Constraint::Percentage(50),
             Constraint::Percentage(50),
         ])
@@ -80,13 +106,45 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
         render_contacts(frame, app, contacts_area);
         render_chats(frame, app, chat_area);
     } else {
-        let [contacts_area, chat_area] =
-            Layout::horizontal([Constraint::Min(30), Constraint::Percentage(100)])
-                .areas(frame.area());
+        let [contacts_area, chat_area] = Layout::horizontal([
+            Constraint::Min(app.config.layout.chat_list_width),
+            Constraint::Percentage(100),
+        ])
+        .areas(frame.area());
 
         render_contacts(frame, app, contacts_area);
         render_chats(frame, app, chat_area);
     }
+
+    if let SelectedWidget::ReactionPicker = app.selected_widget {
+        render_reaction_picker(frame, app, frame.area());
+    }
+
+    if let SelectedWidget::MessageInfo = app.selected_widget {
+        render_message_info(frame, app, frame.area());
+    }
+
+    if let SelectedWidget::Search = app.selected_widget {
+        render_search(frame, app, frame.area());
+    }
+
+    if let SelectedWidget::Export = app.selected_widget {
+        render_export(frame, app, frame.area());
+    }
+
+    if let SelectedWidget::Profiles = app.selected_widget {
+        render_profile_switcher(frame, app, frame.area());
+    }
+
+    if let SelectedWidget::ConfigErrors = app.selected_widget {
+        render_config_errors(frame, app, frame.area());
+    }
+
+    if let SelectedWidget::CommandLine = app.selected_widget {
+        render_command_line(frame, app, frame.area());
+    }
+
+    render_which_key(frame, app, frame.area());
 }
 
 fn render_logs(frame: &mut Frame, area: Rect) {
@@ -105,9 +163,24 @@ fn render_contacts(frame: &mut Frame, app: &mut App, area: Rect) {
     } else {
         &app.filtered_chats
     };
+    // Inside the borders, with the unread count right-aligned.
+    let width = area.width.saturating_sub(2) as usize;
     let items = chats
         .iter()
-        .map(|chat| app.contact_name(&chat).to_string())
+        .map(|chat| {
+            let name = app.contact_name(chat).to_string();
+            match app.chats.get(chat).map_or(0, |chat| chat.unread) {
+                0 => Line::from(name),
+                unread => {
+                    let name = Span::raw(name).bold();
+                    let badge = Span::raw(format!(" {unread} "))
+                        .bold()
+                        .fg(app.config.theme.unread);
+                    let padding = width.saturating_sub(name.width() + badge.width());
+                    Line::from(vec![name, Span::raw(" ".repeat(padding)), badge])
+                }
+            }
+        })
         .collect::<Vec<_>>();
 
     let mut list_area = area;
@@ -133,39 +206,60 @@ fn render_contacts(frame: &mut Frame, app: &mut App, area: Rect) {
     let list = List::new(items)
         .block(
             Block::bordered()
-                .title(if let Some(p) = app.history_sync_percent {
-                    format!("Contacts ({p}%)")
-                } else {
-                    "Contacts".to_string()
-                })
+                .title(
+                    match (&app.profile_switcher.current, app.history_sync_percent) {
+                        (Some(profile), Some(p)) => format!("Contacts · {profile} ({p}%)"),
+                        (Some(profile), None) => format!("Contacts · {profile}"),
+                        (None, Some(p)) => format!("Contacts ({p}%)"),
+                        (None, None) => "Contacts".to_string(),
+                    },
+                )
                 .border_style(Style::default().fg(
                     if let SelectedWidget::ChatList = app.selected_widget {
-                        ratatui::style::Color::Green
+                        app.config.theme.focused_border
                     } else {
-                        ratatui::style::Color::White
+                        app.config.theme.border
                     },
                 )),
         )
-        .highlight_style(ratatui::style::Style::default().fg(ratatui::style::Color::Green));
+        .highlight_style(Style::default().fg(app.config.theme.selected_chat));
+    app.mouse.chat_list = Block::bordered().inner(list_area);
 
     frame.render_stateful_widget(list, list_area, &mut app.chat_list_state);
 }
 
-pub fn render_chats(frame: &mut Frame, app: &mut App, area: Rect) {
+pub fn render_chats(frame: &mut Frame, app: &mut App, mut area: Rect) {
+    if let Some(info) = app
+        .get_selected_chat()
+        .filter(|_| app.show_participants)
+        .and_then(|jid| app.group_infos.get(&jid).cloned())
+    {
+        let [new_area, participants_area] =
+            Layout::horizontal([Constraint::Percentage(100), Constraint::Min(30)]).areas(area);
+        area = new_area;
+        render_participants(frame, app, &info, participants_area);
+    }
+
     let [chat_area, mut input_area] =
         Layout::vertical([Constraint::Percentage(100), Constraint::Min(10)]).areas(area);
 
     render_messages(frame, app, chat_area);
 
     if let Some(_chat_jid) = app.get_selected_chat() {
+        if matches!(app.selected_widget, SelectedWidget::Input) && app.vim.mode == vim::Mode::Insert
+        {
+            render_mention_popup(frame, app, input_area);
+        }
+
         let input_block = app.input_border.clone().border_style(Style::default().fg(
             if let SelectedWidget::Input = app.selected_widget {
-                ratatui::style::Color::Green
+                app.config.theme.focused_border
             } else {
-                ratatui::style::Color::White
+                app.config.theme.border
             },
         ));
         frame.render_widget(&input_block, input_area);
+        app.mouse.input = input_area;
 
         input_area = input_block.inner(input_area);
 
@@ -182,6 +276,19 @@ pub fn render_chats(frame: &mut Frame, app: &mut App, area: Rect) {
             );
         }
 
+        if let Some(msg) = &app.editing_message {
+            let [edit_area, input_areaa] =
+                Layout::vertical([Constraint::Length(1), Constraint::Percentage(100)])
+                    .areas(input_area);
+
+            input_area = input_areaa;
+
+            frame.render_widget(
+                Paragraph::new(format!("✎ Editing: {}", get_quoted_text(msg))).dark_gray(),
+                edit_area,
+            );
+        }
+
         if let Some((path, typ)) = &app.attached_file {
             let file_type_str = match typ {
                 wr::FileKind::Image => "Image",
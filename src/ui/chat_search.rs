@@ -0,0 +1,91 @@
+use ratatui::{
+    Frame,
+    layout::{Position, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::Paragraph,
+};
+use regex::Regex;
+
+use crate::app::App;
+
+/// Whether the message list needs a line for the `/` prompt.
+pub fn shows_chat_search(app: &App) -> bool {
+    app.chat_search.active || !app.chat_search.input.input.is_empty()
+}
+
+/// The `/` prompt, with the mode and whether the pattern is valid.
+pub fn render_chat_search_prompt(frame: &mut Frame, app: &App, area: Rect) {
+    let search = &app.chat_search;
+    let mut spans = vec![Span::raw(format!("/{}", search.input.input))];
+    if search.regex_mode {
+        spans.push("  regex".dark_gray());
+    }
+    if search.regex.is_none() && !search.input.input.is_empty() {
+        spans.push("  invalid pattern".red());
+    } else if search.active {
+        spans.push("  Ctrl+R: toggle regex".dark_gray());
+    }
+    frame.render_widget(Paragraph::new(Line::from(spans)), area);
+
+    if search.active {
+        frame.set_cursor_position(Position::new(
+            area.x + search.input.character_index as u16 + 1,
+            area.y,
+        ));
+    }
+}
+
+/// Splits the spans of `line` so the matches of `regex` stand out, keeping the style
+/// of the rest.
+pub fn highlight_matches(line: Line<'static>, regex: &Regex, color: Color) -> Line<'static> {
+    let text = line
+        .spans
+        .iter()
+        .map(|span| span.content.as_ref())
+        .collect::<String>();
+    let matches = regex
+        .find_iter(&text)
+        .filter(|m| !m.is_empty())
+        .map(|m| m.range())
+        .collect::<Vec<_>>();
+    if matches.is_empty() {
+        return line;
+    }
+
+    let highlight = Style::default().bg(color).fg(Color::Black);
+    let mut spans = Vec::new();
+    let mut offset = 0;
+    for span in &line.spans {
+        let content = span.content.as_ref();
+        let end = offset + content.len();
+        // Cut the span wherever a match starts or ends inside it.
+        let mut cuts = vec![offset, end];
+        for range in &matches {
+            cuts.extend(
+                [range.start, range.end]
+                    .into_iter()
+                    .filter(|&cut| cut > offset && cut < end),
+            );
+        }
+        cuts.sort_unstable();
+        cuts.dedup();
+        for piece in cuts.windows(2) {
+            let (start, stop) = (piece[0], piece[1]);
+            let in_match = matches
+                .iter()
+                .any(|range| range.start <= start && stop <= range.end);
+            let style = if in_match {
+                span.style.patch(highlight)
+            } else {
+                span.style
+            };
+            spans.push(Span::styled(
+                content[start - offset..stop - offset].to_string(),
+                style,
+            ));
+        }
+        offset = end;
+    }
+    Line::from(spans).style(line.style)
+}
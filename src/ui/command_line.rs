@@ -0,0 +1,83 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Position, Rect},
+    style::{Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
+};
+
+use crate::app::App;
+
+/// How many completions are listed at once.
+const MAX_CANDIDATES: usize = 10;
+
+/// The command line, along the bottom of the screen, with the completions of what is
+/// typed above it.
+pub fn render_command_line(frame: &mut Frame, app: &App, area: Rect) {
+    let line = &app.command_line;
+    let shown = line.candidates.len().min(MAX_CANDIDATES);
+    let error_height = usize::from(line.error.is_some());
+    let height = (shown + error_height + 3) as u16;
+    let popup = Rect {
+        y: area.bottom().saturating_sub(height),
+        height: height.min(area.height),
+        ..area
+    };
+    let block = Block::bordered()
+        .title("Command")
+        .title_bottom(" Tab: complete · Enter: run · Esc: close ".dark_gray())
+        .border_style(Style::default().fg(app.config.theme.focused_border));
+    frame.render_widget(Clear, popup);
+    frame.render_widget(&block, popup);
+
+    let [list_area, error_area, input_area] = Layout::vertical([
+        Constraint::Length(shown as u16),
+        Constraint::Length(error_height as u16),
+        Constraint::Length(1),
+    ])
+    .areas(block.inner(popup));
+
+    // Keep the selected completion in view.
+    let first = line.selected.saturating_sub(MAX_CANDIDATES - 1);
+    let name_width = line
+        .candidates
+        .iter()
+        .map(|candidate| candidate.text.chars().count() + candidate.usage.len() + 1)
+        .max()
+        .unwrap_or(0);
+    let lines = line
+        .candidates
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(shown)
+        .map(|(i, candidate)| {
+            let padding = name_width - candidate.text.chars().count() - candidate.usage.len();
+            let spans = vec![
+                Span::raw(format!(" {}", candidate.text)).bold(),
+                Span::raw(format!(" {}{}", candidate.usage, " ".repeat(padding))).dark_gray(),
+                Span::raw(format!(" {} ", candidate.description)),
+            ];
+            if i == line.selected {
+                Line::from(spans).style(
+                    Style::default()
+                        .bg(app.config.theme.selection_bg)
+                        .fg(app.config.theme.selection_fg),
+                )
+            } else {
+                Line::from(spans)
+            }
+        })
+        .collect::<Vec<_>>();
+    frame.render_widget(Paragraph::new(lines), list_area);
+
+    if let Some(err) = &line.error {
+        frame.render_widget(Paragraph::new(err.as_str()).red(), error_area);
+    }
+
+    frame.render_widget(Paragraph::new(format!(":{}", line.input.input)), input_area);
+    frame.set_cursor_position(Position::new(
+        input_area.x + line.input.character_index as u16 + 1,
+        input_area.y,
+    ));
+}
@@ -0,0 +1,33 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Rect},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Wrap},
+};
+
+use crate::app::App;
+
+/// What was wrong in the config, each setting that was left at its default.
+pub fn render_config_errors(frame: &mut Frame, app: &App, area: Rect) {
+    let lines = app
+        .config_errors
+        .iter()
+        .map(|err| Line::from(format!("• {err}")))
+        .collect::<Vec<_>>();
+    let popup = area.centered(
+        Constraint::Percentage(80),
+        Constraint::Length(lines.len() as u16 + 4),
+    );
+    let block = Block::bordered()
+        .title("Errors in the config")
+        .title_bottom(" These settings were left at their defaults · Esc: close ".dark_gray())
+        .border_style(Style::default().fg(Color::Red));
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(block),
+        popup,
+    );
+}
@@ -0,0 +1,103 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Position, Rect},
+    style::{Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph, Wrap},
+};
+use strum::IntoEnumIterator;
+
+use crate::app::App;
+use crate::app::chat_export::ExportField;
+use crate::export::ExportFormat;
+use crate::ui::text_input::TextInput;
+
+/// The export of the selected chat: the formats, the days to export and what came of
+/// the last export.
+pub fn render_export(frame: &mut Frame, app: &App, area: Rect) {
+    let Some(chat) = app.get_selected_chat() else {
+        return;
+    };
+    let export = &app.chat_export;
+    let formats = ExportFormat::iter().count() as u16;
+    let popup = area.centered(Constraint::Length(60), Constraint::Length(formats + 7));
+    let block = Block::bordered()
+        .title(format!("Export chat with {}", app.contact_name(&chat)))
+        .title_bottom(" Tab: next field · Enter: export ".dark_gray())
+        .border_style(Style::default().fg(app.config.theme.focused_border));
+    frame.render_widget(Clear, popup);
+    frame.render_widget(&block, popup);
+
+    let [formats_area, days_area, status_area] = Layout::vertical([
+        Constraint::Length(formats + 1),
+        Constraint::Length(2),
+        Constraint::Min(0),
+    ])
+    .areas(block.inner(popup));
+
+    let lines = ExportFormat::iter()
+        .enumerate()
+        .map(|(i, format)| {
+            let span = Span::raw(format!(" {} {format} ", i + 1));
+            if format == export.format {
+                let style = if export.focus == ExportField::Format {
+                    Style::default()
+                        .bg(app.config.theme.selection_bg)
+                        .fg(app.config.theme.selection_fg)
+                } else {
+                    Style::default().bold()
+                };
+                Line::from(span.style(style))
+            } else {
+                Line::from(span)
+            }
+        })
+        .collect::<Vec<_>>();
+    frame.render_widget(Paragraph::new(lines), formats_area);
+
+    let [since_area, until_area] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .areas(days_area);
+    render_day_field(
+        frame,
+        "From",
+        &export.since,
+        export.focus == ExportField::Since,
+        since_area,
+    );
+    render_day_field(
+        frame,
+        "To",
+        &export.until,
+        export.focus == ExportField::Until,
+        until_area,
+    );
+
+    let status = match &export.status {
+        Some(Ok(status)) => Paragraph::new(status.as_str()).green(),
+        Some(Err(err)) => Paragraph::new(err.as_str()).red(),
+        None => Paragraph::new(format!("To {}", export.dir.display())).dark_gray(),
+    };
+    frame.render_widget(status.wrap(Wrap { trim: false }), status_area);
+}
+
+fn render_day_field(frame: &mut Frame, label: &str, input: &TextInput, focused: bool, area: Rect) {
+    let label = format!(" {label}: ");
+    let value = if input.input.is_empty() && !focused {
+        Span::raw("any day").dark_gray()
+    } else {
+        Span::raw(input.input.as_str())
+    };
+    let label_span = if focused {
+        Span::raw(label.as_str()).bold()
+    } else {
+        Span::raw(label.as_str())
+    };
+    frame.render_widget(Paragraph::new(Line::from(vec![label_span, value])), area);
+    if focused {
+        frame.set_cursor_position(Position::new(
+            area.x + (label.chars().count() + input.character_index) as u16,
+            area.y,
+        ));
+    }
+}
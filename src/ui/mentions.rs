@@ -0,0 +1,76 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, List},
+};
+
+use crate::app::App;
+
+/// The people matching the mention being typed, drawn right above the input.
+pub fn render_mention_popup(frame: &mut Frame, app: &App, input_area: Rect) {
+    let candidates = app.mention_candidates();
+    if candidates.is_empty() {
+        return;
+    }
+
+    let items = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, (_, name))| {
+            let line = Line::from(format!(" @{name} "));
+            if i == app.mention_index {
+                line.style(
+                    Style::default()
+                        .bg(app.config.theme.selection_bg)
+                        .fg(app.config.theme.selection_fg),
+                )
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let width = candidates
+        .iter()
+        .map(|(_, name)| name.chars().count() as u16 + 5)
+        .max()
+        .unwrap_or_default()
+        .max(24)
+        .min(input_area.width);
+    let height = (candidates.len() as u16 + 2).min(input_area.y);
+    let popup = Rect::new(input_area.x, input_area.y - height, width, height);
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        List::new(items).block(
+            Block::bordered()
+                .title("Mention")
+                .title_bottom(" Tab: complete ".dark_gray())
+                .border_style(Style::default().fg(app.config.theme.focused_border)),
+        ),
+        popup,
+    );
+}
+
+/// Splits a line of a message so the resolved mentions in it stand out.
+pub fn highlight_mentions(line: &str, tags: &[String]) -> Line<'static> {
+    let mut spans = Vec::new();
+    let mut rest = line;
+    while let Some((start, tag)) = tags
+        .iter()
+        .filter_map(|tag| rest.find(tag.as_str()).map(|start| (start, tag)))
+        .min_by_key(|(start, tag)| (*start, std::cmp::Reverse(tag.len())))
+    {
+        if start > 0 {
+            spans.push(Span::raw(rest[..start].to_string()));
+        }
+        spans.push(Span::raw(tag.clone()).cyan().bold());
+        rest = &rest[start + tag.len()..];
+    }
+    if !rest.is_empty() || spans.is_empty() {
+        spans.push(Span::raw(rest.to_string()));
+    }
+    Line::from(spans)
+}
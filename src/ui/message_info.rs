@@ -0,0 +1,109 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, Cell, Clear, Paragraph, Row, Table},
+};
+use whatsrust as wr;
+
+use crate::app::App;
+use crate::ui::message_list::{delivery_ticks, format_timestamp};
+
+/// How far one of our messages got, as shown next to its ticks.
+fn delivery_label(state: wr::DeliveryState) -> &'static str {
+    match state {
+        wr::DeliveryState::Pending => "Waiting to be sent",
+        wr::DeliveryState::Sent => "Sent",
+        wr::DeliveryState::Delivered => "Delivered",
+        wr::DeliveryState::Read => "Read",
+        wr::DeliveryState::Played => "Played",
+        wr::DeliveryState::Failed => "Not sent",
+    }
+}
+
+fn format_optional(timestamp: Option<i64>) -> String {
+    timestamp
+        .map(format_timestamp)
+        .unwrap_or_else(|| "-".into())
+}
+
+/// Who received and read the selected message, and when.
+pub fn render_message_info(frame: &mut Frame, app: &App, area: Rect) {
+    let Some(message) = app
+        .message_list_state
+        .get_selected_message()
+        .and_then(|msg_id| app.messages.get(&msg_id))
+    else {
+        return;
+    };
+
+    let mut summary = vec![Line::from(vec![
+        if message.info.is_from_me {
+            "Sent ".into()
+        } else {
+            format!("From {} ", app.contact_name(&message.info.sender)).into()
+        },
+        format_timestamp(message.info.timestamp).italic(),
+    ])];
+    if message.info.is_from_me {
+        summary.push(Line::from(vec![
+            format!("Status: {}", delivery_label(message.info.delivery)).into(),
+            delivery_ticks(message.info.delivery),
+        ]));
+    }
+
+    let receipts: &[_] = app
+        .receipts
+        .get(&message.info.id)
+        .map_or(&[], |receipts| receipts.as_slice());
+    let rows = receipts.iter().map(|receipt| {
+        Row::new([
+            Cell::from(app.contact_name(&receipt.participant).to_string()),
+            Cell::from(format_optional(receipt.delivered_at)),
+            Cell::from(format_optional(receipt.read_at)),
+            Cell::from(format_optional(receipt.played_at)),
+        ])
+    });
+
+    let height = summary.len() as u16 + receipts.len().max(1) as u16 + 4;
+    let popup = area.centered(Constraint::Percentage(80), Constraint::Length(height));
+    let block = Block::bordered()
+        .title("Message info")
+        .border_style(Style::default().fg(app.config.theme.focused_border));
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(&block, popup);
+    let inner = block.inner(popup);
+
+    let [summary_area, _, receipts_area] = Layout::vertical([
+        Constraint::Length(summary.len() as u16),
+        Constraint::Length(1),
+        Constraint::Min(0),
+    ])
+    .areas(inner);
+    frame.render_widget(Paragraph::new(summary), summary_area);
+
+    if receipts.is_empty() {
+        let text = if message.info.is_from_me && message.info.delivery != wr::DeliveryState::Failed
+        {
+            "No receipts yet"
+        } else {
+            "No receipts"
+        };
+        frame.render_widget(Paragraph::new(text).dark_gray(), receipts_area);
+        return;
+    }
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Fill(1),
+            Constraint::Length(16),
+            Constraint::Length(16),
+            Constraint::Length(16),
+        ],
+    )
+    .header(Row::new(["Participant", "Delivered", "Read", "Played"]).bold());
+    frame.render_widget(table, receipts_area);
+}
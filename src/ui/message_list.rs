@@ -8,9 +8,9 @@ use log::trace;
 use ratatui::{
     Frame,
     buffer::Buffer,
-    layout::{Constraint, Layout, Rect},
+    layout::{Alignment, Constraint, Layout, Rect},
     style::{Style, Stylize},
-    text::Line,
+    text::{Line, Span},
     widgets::{Block, Borders, Paragraph, StatefulWidget, Widget},
 };
 use ratatui_image::StatefulImage;
@@ -18,10 +18,12 @@ use textwrap;
 use whatsrust::{self as wr, FileKind};
 
 use crate::app::events::{AppEvent, AppInput};
-use crate::app::{FileMeta, App, Metadata, SelectedWidget};
+use crate::app::{App, FileMeta, Metadata, SelectedWidget};
+use crate::ui::chat_search::{highlight_matches, render_chat_search_prompt, shows_chat_search};
+use crate::ui::mentions::highlight_mentions;
 
-pub const IMAGE_HEIGHT: usize = 12;
-pub const IMAGE_WIDTH: usize = IMAGE_HEIGHT * 3;
+/// Shown in place of the content of messages deleted for everyone.
+pub const REVOKED_TEXT: &str = "🚫 This message was deleted";
 
 fn file_content_height(id: &wr::MessageId, file: &wr::FileContent, app: &mut App) -> usize {
     match file.kind {
@@ -33,7 +35,7 @@ fn file_content_height(id: &wr::MessageId, file: &wr::FileContent, app: &mut App
                 | FileMeta::Downloaded
                 | FileMeta::LoadFailed
                 | FileMeta::Loading => 1,
-                FileMeta::Loaded => IMAGE_HEIGHT,
+                FileMeta::Loaded => app.config.layout.image_height as usize,
             },
         },
         FileKind::Video => 1,
@@ -43,6 +45,11 @@ fn file_content_height(id: &wr::MessageId, file: &wr::FileContent, app: &mut App
 }
 
 fn message_height(message: &wr::Message, width: usize, app: &mut App) -> usize {
+    // Group changes have no header, just a line (or a few) describing the change.
+    if let wr::MessageContent::GroupChange(change) = &message.message {
+        return textwrap::wrap(&group_change_line(app, &message.info, change), width).len();
+    }
+
     let header_height = if message.info.quote_id.is_some() {
         2
     } else {
@@ -50,13 +57,17 @@ fn message_height(message: &wr::Message, width: usize, app: &mut App) -> usize {
     };
 
     let content_height = match &message.message {
+        wr::MessageContent::Text(_) if message.info.edit_state == wr::EditState::Revoked => 1,
         wr::MessageContent::Text(text) => {
-            let lines = textwrap::wrap(text, width);
-            lines.len()
+            textwrap::wrap(&app.resolve_mentions(text, &message.info.mentions), width).len()
         }
         wr::MessageContent::File(data) => {
             let lines = if let Some(caption) = &data.caption {
-                textwrap::wrap(caption, width).len()
+                textwrap::wrap(
+                    &app.resolve_mentions(caption, &message.info.mentions),
+                    width,
+                )
+                .len()
             } else {
                 0
             };
@@ -64,9 +75,90 @@ fn message_height(message: &wr::Message, width: usize, app: &mut App) -> usize {
             let content_height = file_content_height(&message.info.id, data, app);
             content_height + lines
         }
+        wr::MessageContent::GroupChange(_) => 0,
+    };
+
+    let reactions_height = if app
+        .reactions
+        .get(&message.info.id)
+        .is_some_and(|reactions| !reactions.is_empty())
+    {
+        1
+    } else {
+        0
     };
 
-    header_height + content_height
+    header_height + content_height + reactions_height
+}
+
+fn group_change_line(app: &App, info: &wr::MessageInfo, change: &wr::GroupChange) -> String {
+    format!(
+        "{} ({})",
+        app.describe_group_change(info, change),
+        format_timestamp(info.timestamp)
+    )
+}
+
+/// The text of a message wrapped to `width`, with its mentions resolved and highlighted,
+/// and so are the matches of the `/` search.
+fn text_lines(app: &App, info: &wr::MessageInfo, text: &str, width: usize) -> Vec<Line<'static>> {
+    let tags = app.mention_tags(&info.mentions);
+    textwrap::wrap(&app.resolve_mentions(text, &info.mentions), width)
+        .iter()
+        .map(|line| {
+            let line = highlight_mentions(line, &tags);
+            match &app.chat_search.regex {
+                Some(regex) => highlight_matches(line, regex, app.config.theme.search_match),
+                None => line,
+            }
+        })
+        .collect()
+}
+
+/// One line summarizing the reactions to a message, e.g. "👍 2  ❤️ 1".
+/// Emojis are listed in the order they were first used.
+pub fn reaction_summary(reactions: &[wr::Reaction]) -> String {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for reaction in reactions {
+        if let Some((_, count)) = counts.iter_mut().find(|(e, _)| *e == &*reaction.emoji) {
+            *count += 1;
+        } else {
+            counts.push((&reaction.emoji, 1));
+        }
+    }
+    counts
+        .iter()
+        .map(|(emoji, count)| format!("{emoji} {count}"))
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+/// A timestamp relative to now: just the time for today, the weekday for the last week, etc.
+pub fn format_timestamp(timestamp: i64) -> String {
+    let local_time: DateTime<Local> = DateTime::from_timestamp(timestamp, 0).unwrap().into();
+    if local_time.date_naive() == Local::now().date_naive() {
+        local_time.format("%H:%M").to_string()
+    } else if local_time.date_naive() == (Local::now() - chrono::Duration::days(1)).date_naive() {
+        local_time.format("Yesterday %H:%M").to_string()
+    } else if local_time > (Local::now() - chrono::Duration::days(7)) {
+        local_time.format("%a %H:%M").to_string()
+    } else if local_time.year() == Local::now().year() {
+        local_time.format("%d %b %H:%M").to_string()
+    } else {
+        local_time.format("%Y %d %b %H:%M").to_string()
+    }
+}
+
+/// The ticks shown after the timestamp of our own messages.
+pub fn delivery_ticks(state: wr::DeliveryState) -> Span<'static> {
+    match state {
+        wr::DeliveryState::Pending => " ◷".dark_gray(),
+        wr::DeliveryState::Sent => " ✓".into(),
+        wr::DeliveryState::Delivered => " ✓✓".into(),
+        wr::DeliveryState::Read => " ✓✓".light_blue(),
+        wr::DeliveryState::Played => " ✓✓▶".light_blue(),
+        wr::DeliveryState::Failed => " ✗ not sent".red(),
+    }
 }
 
 /// When `render_image` is false (partial path and image fully off-screen), show a placeholder
@@ -82,11 +174,25 @@ fn render_message(
 ) {
     if is_selected {
         let style = Style::default()
-            .bg(ratatui::style::Color::Gray)
-            .fg(ratatui::style::Color::Black);
+            .bg(app.config.theme.selection_bg)
+            .fg(app.config.theme.selection_fg);
         buf.set_style(area, style);
     }
 
+    if let wr::MessageContent::GroupChange(change) = &message.message {
+        let text = group_change_line(app, &message.info, change);
+        let lines = textwrap::wrap(&text, area.width as usize)
+            .iter()
+            .map(|line| Line::raw(line.to_string()))
+            .collect::<Vec<_>>();
+        Paragraph::new(lines)
+            .centered()
+            .italic()
+            .dark_gray()
+            .render(area, buf);
+        return;
+    }
+
     let alignment = ratatui::layout::Alignment::Left;
     // let alignment = if message.info.is_from_me {
     //     ratatui::layout::Alignment::Right
@@ -94,24 +200,7 @@ fn render_message(
     //     ratatui::layout::Alignment::Left
     // };
 
-    let timestamp = {
-        let local_time: DateTime<Local> = DateTime::from_timestamp(message.info.timestamp, 0)
-            .unwrap()
-            .into();
-        if local_time.date_naive() == Local::now().date_naive() {
-            local_time.format("%H:%M").to_string()
-        } else if local_time.date_naive() == (Local::now() - chrono::Duration::days(1)).date_naive()
-        {
-            local_time.format("Yesterday %H:%M").to_string()
-        } else if local_time > (Local::now() - chrono::Duration::days(7)) {
-            local_time.format("%a %H:%M").to_string()
-        } else if local_time.year() == Local::now().year() {
-            local_time.format("%d %b %H:%M").to_string()
-        } else {
-            local_time.format("%Y %d %b %H:%M").to_string()
-        }
-    }
-    .italic();
+    let timestamp = format_timestamp(message.info.timestamp).italic();
 
     let sender_name = app.contact_name(&message.info.sender);
 
@@ -121,8 +210,18 @@ fn render_message(
         timestamp,
         ")".into(),
     ];
-    if message.info.read_by >= 1 {
-        header.push(" ✓".into());
+    if message.info.is_from_me {
+        header.push(delivery_ticks(message.info.delivery));
+    }
+    if app
+        .outbox
+        .get(&message.info.id)
+        .is_some_and(|entry| entry.failed)
+    {
+        header.push(" (R: resend, d d: discard)".dark_gray());
+    }
+    if message.info.edit_state == wr::EditState::Edited {
+        header.push(" (edited)".italic());
     }
     header.push(" ".into());
     let msg_block = Block::default().borders(Borders::NONE).title(header);
@@ -163,9 +262,16 @@ fn render_message(
     //     Constraint::Length(if quote_widget.is_some() { 1 } else { 0 }),
     //     Constraint::Min(1),
     // ])
-    let [quoted_area, content_area] = Layout::vertical([
+    let reactions = app
+        .reactions
+        .get(&message.info.id)
+        .filter(|reactions| !reactions.is_empty())
+        .map(|reactions| reaction_summary(reactions));
+
+    let [quoted_area, content_area, reactions_area] = Layout::vertical([
         Constraint::Length(if quote_widget.is_some() { 1 } else { 0 }),
         Constraint::Min(1),
+        Constraint::Length(if reactions.is_some() { 1 } else { 0 }),
     ])
     .areas(msg_area);
 
@@ -174,13 +280,23 @@ fn render_message(
     if let Some(quoted_widget) = quote_widget {
         quoted_widget.render(quoted_area, buf);
     }
+    if let Some(reactions) = reactions {
+        Line::from(reactions)
+            .alignment(alignment)
+            .dark_gray()
+            .render(reactions_area, buf);
+    }
 
     match &message.message {
+        wr::MessageContent::Text(_) if message.info.edit_state == wr::EditState::Revoked => {
+            Line::from(REVOKED_TEXT)
+                .alignment(alignment)
+                .italic()
+                .dark_gray()
+                .render(content_area, buf);
+        }
         wr::MessageContent::Text(text) => {
-            let lines = textwrap::wrap(text, content_area.width as usize)
-                .iter()
-                .map(|line| Line::raw(line.to_string()))
-                .collect::<Vec<_>>();
+            let lines = text_lines(app, &message.info, text, content_area.width as usize);
             Paragraph::new(lines)
                 .alignment(alignment)
                 .render(content_area, buf);
@@ -271,15 +387,13 @@ fn render_message(
             };
 
             if let Some(caption) = &data.caption {
-                let lines = textwrap::wrap(caption, content_area.width as usize)
-                    .iter()
-                    .map(|line| Line::raw(line.to_string()))
-                    .collect::<Vec<_>>();
+                let lines = text_lines(app, &message.info, caption, content_area.width as usize);
                 Paragraph::new(lines)
                     .alignment(alignment)
                     .render(caption_area, buf);
             }
         }
+        wr::MessageContent::GroupChange(_) => {}
     };
 }
 
@@ -288,30 +402,38 @@ pub fn render_messages(frame: &mut Frame, app: &mut App, area: Rect) -> Option<(
 
     let block = Block::bordered()
         .title(format!("Chat with {}", app.contact_name(&chat_jid)))
-        .title_bottom(format!("{:?}", app.kh.key_buffer))
         .border_style(Style::default().fg(
             if let SelectedWidget::MessageList = app.selected_widget {
-                ratatui::style::Color::Green
+                app.config.theme.focused_border
             } else {
-                ratatui::style::Color::White
+                app.config.theme.border
             },
         ));
     frame.render_widget(&block, area);
 
-    let list_area = block.inner(area);
+    let mut list_area = block.inner(area);
+    if shows_chat_search(app) {
+        let [new_list_area, prompt_area] =
+            Layout::vertical([Constraint::Percentage(100), Constraint::Length(1)]).areas(list_area);
+        list_area = new_list_area;
+        render_chat_search_prompt(frame, app, prompt_area);
+    }
+    app.mouse.message_list = area;
     if list_area.is_empty() {
         return Some(());
     }
 
     let items: Vec<_> = app
         .chat_messages
-        .get(&chat_jid)?
-        .iter()
+        .get(&chat_jid)
+        .into_iter()
+        .flatten()
         .rev()
         .filter_map(|msg_id| app.messages.get(msg_id).cloned())
         .collect();
 
     if items.is_empty() {
+        app.request_older_messages(&chat_jid);
         app.message_list_state.select(None);
         return Some(());
     }
@@ -374,7 +496,21 @@ pub fn render_messages(frame: &mut Frame, app: &mut App, area: Rect) -> Option<(
         }
     }
 
+    // Scrolled no further than the oldest message at the top.
+    if app.message_list_state.offset > 0 {
+        let total_height = items
+            .iter()
+            .map(|item| message_height(item, width as usize, app) + gap)
+            .sum::<usize>()
+            - gap;
+        app.message_list_state.offset = app
+            .message_list_state
+            .offset
+            .min(total_height.saturating_sub(list_area.height as usize));
+    }
+
     let mut y = list_area.bottom() as isize + app.message_list_state.offset as isize;
+    let mut oldest_shown = true;
     for (i, item) in items.iter().enumerate() {
         let height = message_height(item, width as usize, app) as isize;
 
@@ -382,11 +518,24 @@ pub fn render_messages(frame: &mut Frame, app: &mut App, area: Rect) -> Option<(
         let top = y - height;
 
         if bottom < list_area.top() as isize {
+            oldest_shown = false;
             break;
         }
 
         if top <= list_area.bottom() as isize {
             let is_selected = app.message_list_state.selected == Some(i);
+            let visible_top = max(top, list_area.top() as isize) as u16;
+            let visible_bottom = min(bottom, list_area.bottom() as isize) as u16;
+            app.mouse.messages.push((
+                i,
+                item.info.id.clone(),
+                Rect::new(
+                    list_area.left(),
+                    visible_top,
+                    list_area.width,
+                    visible_bottom.saturating_sub(visible_top),
+                ),
+            ));
 
             let too_low = top < list_area.top() as isize;
             let too_high = bottom > list_area.bottom() as isize;
@@ -412,7 +561,7 @@ pub fn render_messages(frame: &mut Frame, app: &mut App, area: Rect) -> Option<(
                         ) && matches!(data.kind, FileKind::Image | FileKind::Sticker) =>
                     {
                         let image_top = 1 + if item.info.quote_id.is_some() { 1 } else { 0 };
-                        let image_bottom = image_top + IMAGE_HEIGHT as u16;
+                        let image_bottom = image_top + app.config.layout.image_height;
                         let visible_buf_bottom = visible_buf_top + visible_buf_height;
                         visible_buf_top < image_bottom && visible_buf_bottom > image_top
                     }
@@ -447,7 +596,7 @@ pub fn render_messages(frame: &mut Frame, app: &mut App, area: Rect) -> Option<(
                         {
                             let first_row = 1 + if item.info.quote_id.is_some() { 1 } else { 0 };
                             let inject = mapped_area.y > first_row
-                                && mapped_area.y < first_row + IMAGE_HEIGHT as u16;
+                                && mapped_area.y < first_row + app.config.layout.image_height;
                             (inject, first_row)
                         }
                         _ => (false, 0),
@@ -482,9 +631,30 @@ pub fn render_messages(frame: &mut Frame, app: &mut App, area: Rect) -> Option<(
             }
         }
 
+        // In the gap above the first message that was unread.
+        let divider = top - 1;
+        if app.new_messages.as_ref() == Some(&item.info.id)
+            && divider >= list_area.top() as isize
+            && divider < list_area.bottom() as isize
+        {
+            let label = " New messages ";
+            let side = "─".repeat((width as usize).saturating_sub(label.len()) / 2);
+            frame.render_widget(
+                Paragraph::new(format!("{side}{label}{side}"))
+                    .alignment(Alignment::Center)
+                    .fg(app.config.theme.unread),
+                Rect::new(list_area.left(), divider as u16, width as u16, 1),
+            );
+        }
+
         y -= height + gap as isize;
     }
 
+    // Scrolling up to the oldest message read reads the page before it.
+    if oldest_shown {
+        app.request_older_messages(&chat_jid);
+    }
+
     None
 }
 
@@ -554,9 +724,13 @@ impl MessageListState {
 
 pub fn get_quoted_text(msg: &wr::Message) -> Arc<str> {
     match &msg.message {
+        wr::MessageContent::Text(_) if msg.info.edit_state == wr::EditState::Revoked => {
+            REVOKED_TEXT.into()
+        }
         wr::MessageContent::Text(text) => text.clone(),
         wr::MessageContent::File(data) => {
             format!("{}: {}", data.path, data.caption.as_deref().unwrap_or("")).into()
         }
+        wr::MessageContent::GroupChange(_) => "Group update".into(),
     }
 }
@@ -0,0 +1,63 @@
+use chrono::{DateTime, Local};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Rect},
+    style::Stylize,
+    text::Line,
+    widgets::{Block, List, Paragraph},
+};
+use whatsrust as wr;
+
+use crate::app::App;
+
+/// The description and participants of the selected group, owners and admins first.
+pub fn render_participants(frame: &mut Frame, app: &App, info: &wr::GroupInfo, area: Rect) {
+    let block = Block::bordered().title(format!("Participants ({})", info.participants.len()));
+    frame.render_widget(&block, area);
+    let inner = block.inner(area);
+
+    let mut about = Vec::new();
+    if info.created_at > 0
+        && let Some(created) = DateTime::from_timestamp(info.created_at, 0)
+    {
+        let created: DateTime<Local> = created.into();
+        about.push(Line::from(format!("Created {}", created.format("%d %b %Y"))).italic());
+    }
+    for line in info.description.lines() {
+        about.extend(
+            textwrap::wrap(line, inner.width.max(1) as usize)
+                .iter()
+                .map(|line| Line::from(line.to_string())),
+        );
+    }
+    // Leave a blank line before the participants, and at least half of the panel for them.
+    let about_height = if about.is_empty() {
+        0
+    } else {
+        (about.len() as u16 + 1).min(inner.height / 2)
+    };
+
+    let [about_area, list_area] =
+        Layout::vertical([Constraint::Length(about_height), Constraint::Min(0)]).areas(inner);
+    frame.render_widget(Paragraph::new(about).dark_gray(), about_area);
+
+    let mut participants = info.participants.iter().collect::<Vec<_>>();
+    participants.sort_by_cached_key(|p| {
+        (
+            !p.is_super_admin,
+            !p.is_admin,
+            app.contact_name(&p.jid).to_lowercase(),
+        )
+    });
+    let items = participants.iter().map(|p| {
+        let name = app.contact_name(&p.jid).to_string();
+        if p.is_super_admin {
+            Line::from(vec![name.into(), " owner".green()])
+        } else if p.is_admin {
+            Line::from(vec![name.into(), " admin".green()])
+        } else {
+            Line::from(name)
+        }
+    });
+    frame.render_widget(List::new(items), list_area);
+}
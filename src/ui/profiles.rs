@@ -0,0 +1,83 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Position, Rect},
+    style::{Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph, Wrap},
+};
+
+use crate::app::App;
+
+/// The profiles to switch to, the one in use marked, and the name of a new one while it
+/// is typed.
+pub fn render_profile_switcher(frame: &mut Frame, app: &App, area: Rect) {
+    let switcher = &app.profile_switcher;
+    let popup = area.centered(
+        Constraint::Length(50),
+        Constraint::Length(switcher.profiles.len() as u16 + 6),
+    );
+    let block = Block::bordered()
+        .title("Profiles")
+        .title_bottom(" Enter: switch · n: new profile ".dark_gray())
+        .border_style(Style::default().fg(app.config.theme.focused_border));
+    frame.render_widget(Clear, popup);
+    frame.render_widget(&block, popup);
+
+    let [list_area, new_area, status_area] = Layout::vertical([
+        Constraint::Length(switcher.profiles.len() as u16),
+        Constraint::Length(2),
+        Constraint::Min(0),
+    ])
+    .areas(block.inner(popup));
+
+    let lines = switcher
+        .profiles
+        .iter()
+        .enumerate()
+        .map(|(i, profile)| {
+            let name = profile.as_deref().unwrap_or("default");
+            let marker = if *profile == switcher.current {
+                "●"
+            } else {
+                " "
+            };
+            let span = Span::raw(format!(" {marker} {name} "));
+            if i == switcher.selected && switcher.new_name.is_none() {
+                Line::from(
+                    span.style(
+                        Style::default()
+                            .bg(app.config.theme.selection_bg)
+                            .fg(app.config.theme.selection_fg),
+                    ),
+                )
+            } else {
+                Line::from(span)
+            }
+        })
+        .collect::<Vec<_>>();
+    frame.render_widget(Paragraph::new(lines), list_area);
+
+    if let Some(input) = &switcher.new_name {
+        let label = " New profile: ";
+        let line = Line::from(vec![
+            Span::raw(label).bold(),
+            Span::raw(input.input.as_str()),
+        ]);
+        let [_, input_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Length(1)]).areas(new_area);
+        frame.render_widget(Paragraph::new(line), input_area);
+        frame.set_cursor_position(Position::new(
+            input_area.x + (label.chars().count() + input.character_index) as u16,
+            input_area.y,
+        ));
+    }
+
+    if let Some(err) = &switcher.error {
+        frame.render_widget(
+            Paragraph::new(err.as_str())
+                .red()
+                .wrap(Wrap { trim: false }),
+            status_area,
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Rect},
+    style::{Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
+};
+
+use crate::app::App;
+
+pub const REACTIONS: [&str; 6] = ["👍", "❤️", "😂", "😮", "😢", "🙏"];
+
+pub fn render_reaction_picker(frame: &mut Frame, app: &App, area: Rect) {
+    let spans = REACTIONS
+        .iter()
+        .enumerate()
+        .flat_map(|(i, emoji)| {
+            let span = Span::raw(format!(" {} {emoji} ", i + 1));
+            let span = if i == app.reaction_picker_index {
+                span.style(
+                    Style::default()
+                        .bg(app.config.theme.selection_bg)
+                        .fg(app.config.theme.selection_fg),
+                )
+            } else {
+                span
+            };
+            [span, Span::raw(" ")]
+        })
+        .collect::<Vec<_>>();
+
+    let width = REACTIONS.len() as u16 * 7 + 2;
+    let popup = area.centered(Constraint::Length(width), Constraint::Length(3));
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(Line::from(spans)).block(
+            Block::bordered()
+                .title("React")
+                .title_bottom(" x: remove ".dark_gray())
+                .border_style(Style::default().fg(app.config.theme.focused_border)),
+        ),
+        popup,
+    );
+}
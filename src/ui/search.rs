@@ -0,0 +1,85 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Position, Rect},
+    style::{Style, Stylize},
+    text::{Line, Span, Text},
+    widgets::{Block, Clear, List, ListItem, Paragraph},
+};
+
+use crate::app::App;
+use crate::db::{MATCH_END, MATCH_START};
+use crate::ui::message_list::format_timestamp;
+
+/// The search across every chat: the query, and the hits below it, newest first.
+pub fn render_search(frame: &mut Frame, app: &mut App, area: Rect) {
+    let popup = area.centered(Constraint::Percentage(80), Constraint::Percentage(80));
+    let block = Block::bordered()
+        .title("Search messages")
+        .title_bottom(" Enter: go to message ".dark_gray())
+        .border_style(Style::default().fg(app.config.theme.focused_border));
+    frame.render_widget(Clear, popup);
+    frame.render_widget(&block, popup);
+
+    let [input_area, list_area] =
+        Layout::vertical([Constraint::Length(2), Constraint::Min(0)]).areas(block.inner(popup));
+    frame.render_widget(
+        Paragraph::new(format!("/{}", app.search.input.input)),
+        input_area,
+    );
+    frame.set_cursor_position(Position::new(
+        input_area.x + app.search.input.character_index as u16 + 1,
+        input_area.y,
+    ));
+
+    if app.search.hits.is_empty() {
+        if !app.search.input.input.trim().is_empty() {
+            frame.render_widget(
+                Paragraph::new("No messages found").dark_gray().italic(),
+                list_area,
+            );
+        }
+        return;
+    }
+
+    let items = app
+        .search
+        .hits
+        .iter()
+        .map(|hit| {
+            let info = &hit.message.info;
+            let sender = if info.is_from_me {
+                "You".into()
+            } else {
+                app.contact_name(&info.sender)
+            };
+            let header = Line::from(vec![
+                app.contact_name(&info.chat).to_string().bold(),
+                format!(" · {sender} · {}", format_timestamp(info.timestamp)).dark_gray(),
+            ]);
+            ListItem::new(Text::from(vec![header, snippet_line(&hit.snippet)]))
+        })
+        .collect::<Vec<_>>();
+    let list = List::new(items).highlight_style(
+        Style::default()
+            .bg(app.config.theme.selection_bg)
+            .fg(app.config.theme.selection_fg),
+    );
+    frame.render_stateful_widget(list, list_area, &mut app.search.list_state);
+}
+
+/// The snippet of a hit, with the matches highlighted.
+fn snippet_line(snippet: &str) -> Line<'static> {
+    let mut spans = Vec::new();
+    for (i, part) in snippet.split(MATCH_START).enumerate() {
+        // Every part but the first starts with a match.
+        let (matched, rest) = match part.split_once(MATCH_END) {
+            Some((matched, rest)) if i > 0 => (matched, rest),
+            _ => ("", part),
+        };
+        if !matched.is_empty() {
+            spans.push(Span::raw(matched.to_string()).yellow().bold());
+        }
+        spans.push(Span::raw(rest.replace('\n', " ")));
+    }
+    Line::from(spans)
+}
@@ -0,0 +1,47 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
+};
+
+use crate::app::App;
+use crate::key_handler::key_notation;
+
+/// While keys wait for the rest of a sequence, what they can go on to, in the bottom
+/// right corner.
+pub fn render_which_key(frame: &mut Frame, app: &App, area: Rect) {
+    let continuations = app.key_continuations();
+    if continuations.is_empty() {
+        return;
+    }
+    let keys_width = continuations
+        .iter()
+        .map(|(keys, _)| keys.chars().count())
+        .max()
+        .unwrap_or(0);
+    let lines = continuations
+        .iter()
+        .map(|(keys, description)| {
+            Line::from(vec![
+                Span::raw(format!(" {keys:keys_width$} ")).bold(),
+                Span::raw(format!(" {description} ")),
+            ])
+        })
+        .collect::<Vec<_>>();
+
+    let width = lines.iter().map(Line::width).max().unwrap_or(0) as u16 + 2;
+    let height = lines.len() as u16 + 2;
+    let popup = Rect {
+        x: area.right().saturating_sub(width),
+        y: area.bottom().saturating_sub(height),
+        width: width.min(area.width),
+        height: height.min(area.height),
+    };
+    let block = Block::bordered()
+        .title(format!(" {} ", key_notation(&app.kh.key_buffer)))
+        .border_style(Style::default().fg(app.config.theme.focused_border));
+    frame.render_widget(Clear, popup);
+    frame.render_widget(Paragraph::new(lines).block(block), popup);
+}
@@ -0,0 +1,37 @@
+//! Small helpers shared by the app, the importer and the command line.
+
+use std::hash::Hasher;
+
+/// A 64-bit FNV-1a hasher, whose hashes stay the same across builds, unlike those of
+/// `DefaultHasher`.
+struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// A hash of `parts` that is the same in every build, for ids made from what something
+/// contains, which must come out the same when it is seen again.
+pub(crate) fn stable_hash(parts: &[&str]) -> u64 {
+    let mut hasher = StableHasher::default();
+    for part in parts {
+        hasher.write(part.as_bytes());
+        // Keeps ("ab", "c") apart from ("a", "bc").
+        hasher.write(&[0xff]);
+    }
+    hasher.finish()
+}
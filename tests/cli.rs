@@ -0,0 +1,312 @@
+mod common;
+
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use chrono::{Local, TimeZone};
+use common::{database, jid, reopen, text_message};
+use whatsrust as wr;
+use wstui::app::Chat;
+use wstui::backend::FakeBackend;
+use wstui::cli::{Session, SessionError, parse_time, write_chat_messages, write_chats};
+use wstui::db::DatabaseHandler;
+use wstui::export::ExportFormat;
+
+const ALICE: &str = "111@s.whatsapp.net";
+const GROUP: &str = "333@g.us";
+
+fn json_lines(out: Vec<u8>) -> Vec<serde_json::Value> {
+    String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+fn chats_database(path: &Path) -> DatabaseHandler {
+    let mut db = database(path);
+    db.add_contact(&jid(ALICE), "Alice");
+    db.add_contact(&jid(GROUP), "Climbing");
+    for chat in [GROUP, ALICE] {
+        db.add_chat(&Chat {
+            jid: jid(chat),
+            last_message_time: None,
+            unread: 0,
+        });
+    }
+    for (i, timestamp) in [100, 200, 300, 400].into_iter().enumerate() {
+        db.add_message(&text_message(
+            &format!("G{i}"),
+            GROUP,
+            ALICE,
+            timestamp,
+            &format!("m{i}"),
+        ));
+    }
+    db.add_message(&text_message("P1", ALICE, ALICE, 250, "private"));
+    reopen(&mut db, path);
+    db
+}
+
+#[test]
+fn chats_are_listed_most_recent_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = chats_database(&dir.path().join("whatsapp.db"));
+
+    let mut out = Vec::new();
+    assert_eq!(write_chats(&db, &mut out).unwrap(), 2);
+    db.stop();
+
+    let chats = json_lines(out);
+    assert_eq!(chats[0]["jid"], GROUP);
+    assert_eq!(chats[0]["name"], "Climbing");
+    assert_eq!(chats[0]["group"], true);
+    assert_eq!(chats[0]["last_message_time"], 400);
+    assert_eq!(chats[1]["name"], "Alice");
+}
+
+#[test]
+fn messages_are_filtered_by_time_and_limited_to_the_latest() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = chats_database(&dir.path().join("whatsapp.db"));
+    let messages = |range, limit| {
+        let mut out = Vec::new();
+        let media = Path::new("/data/media");
+        write_chat_messages(
+            &db,
+            media,
+            &jid(GROUP),
+            range,
+            limit,
+            ExportFormat::Jsonl,
+            &mut out,
+        )
+        .unwrap();
+        json_lines(out)
+            .into_iter()
+            .map(|object| object["text"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(messages((None, None), None), ["m0", "m1", "m2", "m3"]);
+    assert_eq!(messages((Some(200), Some(400)), None), ["m1", "m2"]);
+    assert_eq!(messages((None, None), Some(2)), ["m2", "m3"]);
+    assert_eq!(messages((None, Some(300)), Some(5)), ["m0", "m1"]);
+    db.stop();
+}
+
+#[test]
+fn times_are_days_times_or_how_long_ago() {
+    let local = |y, m, d, h, min| {
+        Local
+            .with_ymd_and_hms(y, m, d, h, min, 0)
+            .unwrap()
+            .timestamp()
+    };
+    assert_eq!(parse_time("2024-01-31"), Ok(local(2024, 1, 31, 0, 0)));
+    assert_eq!(
+        parse_time("2024-01-31 18:30"),
+        Ok(local(2024, 1, 31, 18, 30))
+    );
+    assert_eq!(
+        parse_time("2024-01-31T18:30"),
+        Ok(local(2024, 1, 31, 18, 30))
+    );
+
+    let ago = Local::now().timestamp() - parse_time("2h").unwrap();
+    assert!((7200..7205).contains(&ago), "{ago}");
+    let ago = Local::now().timestamp() - parse_time("7d").unwrap();
+    assert!((604800..604805).contains(&ago), "{ago}");
+
+    assert!(parse_time("yesterday").is_err());
+    assert!(parse_time("31/01/2024").is_err());
+}
+
+#[test]
+fn sent_messages_are_saved_to_the_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("whatsapp.db");
+    let mut db = chats_database(&db_path);
+    let backend = Arc::new(FakeBackend::default());
+
+    let session = Session::open(backend.clone(), dir.path()).unwrap();
+    assert!(backend.is_connected());
+    let id = session
+        .send(&db, &jid(ALICE), "build 42 passed", None)
+        .unwrap();
+
+    let attachment = dir.path().join("report.pdf");
+    std::fs::write(&attachment, b"%PDF").unwrap();
+    session
+        .send(&db, &jid(ALICE), "the report", Some(&attachment))
+        .unwrap();
+    assert!(
+        session
+            .send(&db, &jid(ALICE), "", Some(&dir.path().join("missing.png")))
+            .is_err()
+    );
+    session.close().unwrap();
+    assert!(!backend.is_connected());
+
+    let sent = backend.sent_messages();
+    assert_eq!(sent.len(), 2);
+    let wr::MessageContent::File(file) = &sent[1].content else {
+        panic!("{:?}", sent[1].content);
+    };
+    assert!(matches!(file.kind, wr::FileKind::Document));
+    assert_eq!(file.caption.as_deref(), Some("the report"));
+
+    reopen(&mut db, &db_path);
+    let saved = db.get_message(&id).unwrap();
+    assert!(saved.info.is_from_me);
+    assert!(
+        matches!(&saved.message, wr::MessageContent::Text(text) if &**text == "build 42 passed")
+    );
+    db.stop();
+}
+
+#[test]
+fn sessions_are_refused_while_the_interface_is_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let backend = Arc::new(FakeBackend::default());
+    let socket = dir.path().join("wstui.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+
+    let err = Session::open(backend.clone(), dir.path()).err().unwrap();
+    assert!(matches!(&err, SessionError::ClientRunning(path) if *path == socket));
+    assert!(err.to_string().contains("wstui is open"), "{err}");
+    assert!(!backend.is_connected());
+
+    // A socket left behind by an interface that crashed is no reason to refuse.
+    drop(listener);
+    let session = Session::open(backend.clone(), dir.path()).unwrap();
+    assert!(backend.is_connected());
+    session.close().unwrap();
+}
+
+#[test]
+fn tail_prints_incoming_messages_and_saves_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("whatsapp.db");
+    let mut db = chats_database(&db_path);
+    let backend = Arc::new(FakeBackend::default());
+    let session = Session::open(backend.clone(), dir.path()).unwrap();
+
+    backend.sync_message(text_message("S1", ALICE, ALICE, 500, "from a history sync"));
+    backend.receive_message(text_message(
+        "N1",
+        "444@s.whatsapp.net",
+        ALICE,
+        600,
+        "deploy done",
+    ));
+    backend.receive_message(text_message("N2", GROUP, ALICE, 700, "nice"));
+
+    let stop = AtomicBool::new(false);
+    let mut out = Vec::new();
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            std::thread::sleep(Duration::from_millis(300));
+            stop.store(true, Ordering::Relaxed);
+        });
+        session
+            .tail(&db, Path::new("/data/media"), &stop, &mut out)
+            .unwrap();
+    });
+    session.close().unwrap();
+
+    let lines = json_lines(out);
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["id"], "N1");
+    assert_eq!(lines[0]["chat"], "444@s.whatsapp.net");
+    assert_eq!(lines[0]["sender_name"], "Alice");
+    assert_eq!(lines[0]["text"], "deploy done");
+    assert_eq!(lines[1]["chat_name"], "Climbing");
+
+    reopen(&mut db, &db_path);
+    assert!(db.get_message(&"S1".into()).is_some());
+    assert_eq!(db.find_chats("444@s.whatsapp.net").len(), 1);
+    db.stop();
+}
+
+#[test]
+fn tail_prints_and_saves_edits_deletions_reactions_and_receipts() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("whatsapp.db");
+    let mut db = chats_database(&db_path);
+    let backend = Arc::new(FakeBackend::default());
+    let session = Session::open(backend.clone(), dir.path()).unwrap();
+    // Sent from the phone.
+    let mut own = text_message("M1", ALICE, ALICE, 590, "on my way");
+    own.info.is_from_me = true;
+    own.info.delivery = wr::DeliveryState::Sent;
+    let sent = own.info.id.clone();
+    backend.receive_message(own);
+    backend.receive_message(text_message("N1", ALICE, ALICE, 600, "see you at 8"));
+    backend.emit_event(wr::Event::MessageEdit {
+        chat: jid(ALICE),
+        sender: jid(ALICE),
+        message_id: "N1".into(),
+        text: "see you at 9".into(),
+        timestamp: 610,
+    });
+    backend.emit_event(wr::Event::MessageRevoke {
+        chat: jid(GROUP),
+        sender: jid(ALICE),
+        message_id: "G0".into(),
+        timestamp: 620,
+    });
+    backend.emit_event(wr::Event::Reaction(wr::Reaction {
+        chat: jid(ALICE),
+        sender: jid(ALICE),
+        message_id: sent.clone(),
+        emoji: "👍".into(),
+        is_from_me: false,
+        timestamp: 630,
+    }));
+    backend.emit_event(wr::Event::Receipt {
+        kind: wr::ReceiptKind::Read,
+        chat: jid(ALICE),
+        sender: jid(ALICE),
+        message_ids: vec![sent.clone()],
+        timestamp: 640,
+    });
+
+    let stop = AtomicBool::new(false);
+    let mut out = Vec::new();
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            std::thread::sleep(Duration::from_millis(300));
+            stop.store(true, Ordering::Relaxed);
+        });
+        session
+            .tail(&db, Path::new("/data/media"), &stop, &mut out)
+            .unwrap();
+    });
+    session.close().unwrap();
+
+    let lines = json_lines(out);
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[1]["text"], "see you at 8");
+    let events = lines[2..]
+        .iter()
+        .map(|line| line["event"]["type"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(events, ["edit", "revoke", "reaction", "receipt"]);
+    assert_eq!(lines[2]["event"]["text"], "see you at 9");
+
+    reopen(&mut db, &db_path);
+    let edited = db.get_message(&"N1".into()).unwrap();
+    assert_eq!(edited.info.edit_state, wr::EditState::Edited);
+    assert!(matches!(&edited.message, wr::MessageContent::Text(text) if &**text == "see you at 9"));
+    let revoked = db.get_message(&"G0".into()).unwrap();
+    assert_eq!(revoked.info.edit_state, wr::EditState::Revoked);
+    assert_eq!(db.get_reactions(&sent)[0].emoji.as_ref(), "👍");
+    assert!(db.get_receipts(&sent)[0].read_at.is_some());
+    let sent = db.get_message(&sent).unwrap();
+    assert_eq!(sent.info.delivery, wr::DeliveryState::Read);
+    db.stop();
+}
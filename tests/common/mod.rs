@@ -0,0 +1,304 @@
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ratatui::Terminal;
+use ratatui::backend::TestBackend;
+use ratatui::crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use ratatui_image::picker::Picker;
+use tempfile::TempDir;
+use whatsrust as wr;
+use wstui::app::App;
+use wstui::app::events::AppInput;
+use wstui::backend::FakeBackend;
+use wstui::clipboard::MemoryClipboard;
+use wstui::db::DatabaseHandler;
+use wstui::ui;
+
+pub struct Harness {
+    pub app: App<'static>,
+    pub backend: Arc<FakeBackend>,
+    pub terminal: Terminal<TestBackend>,
+    // Keeps the data directory alive for the duration of the test.
+    pub data_dir: TempDir,
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        let data_dir = tempfile::tempdir().unwrap();
+        let backend = Arc::new(FakeBackend::default());
+
+        Self {
+            app: start_app(data_dir.path(), backend.clone()),
+            backend,
+            terminal: Terminal::new(TestBackend::new(100, 30)).unwrap(),
+            data_dir,
+        }
+    }
+
+    /// Closes the app and starts a new one on the same data directory, as if wstui was
+    /// quit and opened again.
+    pub fn restart(&mut self) {
+        self.app.db_handler.stop();
+        // The old app goes first, so its socket is free for the new one.
+        drop(std::mem::replace(
+            &mut self.app,
+            new_app(self.data_dir.path(), self.backend.clone()),
+        ));
+        self.app.start(None).unwrap();
+    }
+
+    /// Opens the app again on the profile it quit to switch to, as `main` does.
+    pub fn follow_profile_switch(&mut self) {
+        let dir = self
+            .app
+            .switch_to
+            .take()
+            .expect("the app did not switch profiles");
+        drop(std::mem::replace(
+            &mut self.app,
+            new_app(&dir, self.backend.clone()),
+        ));
+        self.app.start(None).unwrap();
+    }
+
+    /// Feeds every queued input to the app, as the event loop in `App::run` would.
+    pub fn pump(&mut self) {
+        while let Ok(input) = self.app.rx.try_recv() {
+            self.app.update(input);
+        }
+    }
+
+    /// Pumps until `done` holds, for work that happens on the app's worker threads.
+    pub fn wait_until(&mut self, mut done: impl FnMut(&App<'static>) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        self.pump();
+        while !done(&self.app) {
+            assert!(Instant::now() < deadline, "timed out waiting for the app");
+            std::thread::sleep(Duration::from_millis(5));
+            self.pump();
+        }
+    }
+
+    pub fn press(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        let event = Event::Key(KeyEvent::new(code, modifiers));
+        self.app.update(AppInput::Terminal(event));
+    }
+
+    pub fn mouse(&mut self, kind: MouseEventKind, column: u16, row: u16) {
+        let event = Event::Mouse(MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        });
+        self.app.update(AppInput::Terminal(event));
+    }
+
+    pub fn click(&mut self, column: u16, row: u16) {
+        self.mouse(MouseEventKind::Down(MouseButton::Left), column, row);
+    }
+
+    pub fn type_str(&mut self, text: &str) {
+        for c in text.chars() {
+            self.press(KeyCode::Char(c), KeyModifiers::NONE);
+        }
+    }
+
+    /// Draws the UI and returns the screen contents, one line per row.
+    pub fn draw(&mut self) -> String {
+        self.pump();
+        self.terminal
+            .draw(|frame| ui::draw(frame, &mut self.app))
+            .unwrap();
+        let buffer = self.terminal.backend().buffer();
+        let area = buffer.area;
+        (area.top()..area.bottom())
+            .map(|y| {
+                (area.left()..area.right())
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// A script connected to the JSON-RPC socket of the app.
+pub struct RpcConnection {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+    notifications: VecDeque<serde_json::Value>,
+    next_id: u64,
+}
+
+impl RpcConnection {
+    pub fn connect(h: &Harness) -> Self {
+        let stream = UnixStream::connect(&h.app.rpc_socket).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(5)))
+            .unwrap();
+        Self {
+            stream,
+            buffer: Vec::new(),
+            notifications: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Sends a raw line, for requests that are not valid.
+    pub fn send_line(&mut self, line: &str) {
+        writeln!(self.stream, "{line}").unwrap();
+    }
+
+    /// Calls `method` and pumps the app until the response comes, which is returned
+    /// whole, with either its result or its error.
+    pub fn call(
+        &mut self,
+        h: &mut Harness,
+        method: &str,
+        params: serde_json::Value,
+    ) -> serde_json::Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        let request =
+            serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        self.send_line(&request.to_string());
+        self.next_response(h, |response| response["id"] == id)
+    }
+
+    /// The next response matching `matches`, keeping the notifications that come first.
+    pub fn next_response(
+        &mut self,
+        h: &mut Harness,
+        matches: impl Fn(&serde_json::Value) -> bool,
+    ) -> serde_json::Value {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            while let Some(line) = self.read_line() {
+                if line.get("id").is_some() && matches(&line) {
+                    return line;
+                }
+                self.notifications.push_back(line);
+            }
+            assert!(
+                Instant::now() < deadline,
+                "timed out waiting for a response"
+            );
+            h.pump();
+        }
+    }
+
+    pub fn notifications_are_empty(&mut self) -> bool {
+        while let Some(line) = self.read_line() {
+            self.notifications.push_back(line);
+        }
+        self.notifications.is_empty()
+    }
+
+    /// The next notification, pumping the app until there is one.
+    pub fn next_notification(&mut self, h: &mut Harness) -> serde_json::Value {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(notification) = self.notifications.pop_front() {
+                return notification;
+            }
+            if let Some(line) = self.read_line() {
+                self.notifications.push_back(line);
+                continue;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "timed out waiting for a notification"
+            );
+            h.pump();
+        }
+    }
+
+    fn read_line(&mut self) -> Option<serde_json::Value> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+                let line = self.buffer.drain(..=end).collect::<Vec<_>>();
+                return Some(serde_json::from_slice(&line).unwrap());
+            }
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return None,
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return None;
+                }
+                Err(err) => panic!("{err}"),
+            }
+        }
+    }
+}
+
+fn start_app(data_dir: &Path, backend: Arc<FakeBackend>) -> App<'static> {
+    let mut app = new_app(data_dir, backend);
+    app.start(None).unwrap();
+    app
+}
+
+fn new_app(data_dir: &Path, backend: Arc<FakeBackend>) -> App<'static> {
+    let mut app = App::new(
+        data_dir,
+        backend,
+        Box::new(MemoryClipboard::default()),
+        Picker::halfblocks(),
+    );
+    app.retry_delay = Duration::ZERO;
+    app
+}
+
+pub fn jid(s: &str) -> wr::JID {
+    s.to_string().into()
+}
+
+pub fn text_message(id: &str, chat: &str, sender: &str, timestamp: i64, text: &str) -> wr::Message {
+    wr::Message {
+        info: wr::MessageInfo {
+            id: id.into(),
+            chat: jid(chat),
+            sender: jid(sender),
+            timestamp,
+            is_from_me: false,
+            quote_id: None,
+            delivery: wr::DeliveryState::Sent,
+            edit_state: wr::EditState::Original,
+            mentions: Vec::new(),
+        },
+        message: wr::MessageContent::Text(text.into()),
+    }
+}
+
+/// A new database at `path`.
+pub fn database(path: &Path) -> DatabaseHandler {
+    let mut db = DatabaseHandler::new(path);
+    db.init().unwrap();
+    db
+}
+
+/// Opens the database at `path` again, once what was added to it is written.
+pub fn reopen(db: &mut DatabaseHandler, path: &Path) {
+    db.stop();
+    *db = database(path);
+}
+
+/// Collapses runs of spaces, so assertions don't depend on the width of wide glyphs.
+pub fn squash(screen: &str) -> String {
+    screen
+        .split(' ')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,208 @@
+use ratatui::crossterm::event::{KeyCode, KeyModifiers};
+use ratatui::style::Color;
+use std::time::Duration;
+use wstui::config::Config;
+
+use wstui::key_handler::{Action, Key, key_notation, parse_keys};
+use wstui::vim;
+
+#[test]
+fn keys_are_written_as_in_vim() {
+    assert_eq!(parse_keys("gg"), Ok(vec![Key::c('g'), Key::c('g')]));
+    assert_eq!(parse_keys("G"), Ok(vec![Key::c('G')]));
+    assert_eq!(parse_keys("<C-q>"), Ok(vec![Key::ctrl('q')]));
+    assert_eq!(parse_keys("<C-S-l>"), Ok(vec![Key::ctrl_shift('l')]));
+    assert_eq!(
+        parse_keys("<Space>ar"),
+        Ok(vec![Key::c(' '), Key::c('a'), Key::c('r')])
+    );
+    assert_eq!(
+        parse_keys("<esc><CR><lt>"),
+        Ok(vec![
+            Key::k(KeyCode::Esc),
+            Key::k(KeyCode::Enter),
+            Key::c('<')
+        ])
+    );
+    assert_eq!(
+        parse_keys("<A-F5>"),
+        Ok(vec![Key {
+            code: KeyCode::F(5),
+            modifiers: KeyModifiers::ALT,
+        }])
+    );
+
+    assert!(parse_keys("").is_err());
+    assert!(parse_keys("<C-q").is_err());
+    assert!(parse_keys("<Hyper-x>").is_err());
+    assert!(parse_keys("<Banana>").is_err());
+
+    for notation in [
+        "gg",
+        "G",
+        "<C-q>",
+        "<C-S-l>",
+        "<Space>ar",
+        "<Esc><Enter><lt>",
+        "<A-F5>",
+    ] {
+        assert_eq!(key_notation(&parse_keys(notation).unwrap()), notation);
+    }
+}
+
+#[test]
+fn every_setting_is_read() {
+    let (config, errors) = Config::parse(
+        r##"
+[keys]
+quit = "<C-w>"
+next_chat = ["j", "<Down>"]
+export_chat = []
+
+[theme]
+focused_border = "magenta"
+selection_bg = "#203040"
+
+[layout]
+chat_list_width = 40
+image_height = 8
+
+[notifications]
+show_text = false
+groups = false
+
+[input]
+vim_mode = "normal"
+timeout = 300
+"##,
+    );
+    assert_eq!(errors, []);
+
+    assert_eq!(config.keys.keys(Action::Quit), [vec![Key::ctrl('w')]]);
+    assert_eq!(
+        config.keys.keys(Action::NextChat),
+        [vec![Key::c('j')], vec![Key::k(KeyCode::Down)]]
+    );
+    assert!(config.keys.keys(Action::ExportChat).is_empty());
+    // Actions left out keep their keys.
+    assert_eq!(
+        config.keys.keys(Action::FirstMessage),
+        [vec![Key::c('g'), Key::c('g')]]
+    );
+
+    assert_eq!(config.theme.focused_border, Color::Magenta);
+    assert_eq!(config.theme.selection_bg, Color::Rgb(0x20, 0x30, 0x40));
+    assert_eq!(config.theme.border, Color::White);
+    assert_eq!(config.layout.chat_list_width, 40);
+    // The width follows the height unless it is given too.
+    assert_eq!(
+        (config.layout.image_height, config.layout.image_width),
+        (8, 24)
+    );
+    assert!(config.notifications.enabled);
+    assert!(!config.notifications.show_text);
+    assert!(!config.notifications.groups);
+    assert_eq!(config.vim_mode, vim::Mode::Normal);
+    assert_eq!(config.key_timeout, Duration::from_millis(300));
+}
+
+#[test]
+fn mistakes_are_reported_and_left_at_their_defaults() {
+    let (config, errors) = Config::parse(
+        r#"
+[keys]
+quit = "<C-w"
+fly = "f"
+reply = "<C-r>"
+
+[theme]
+border = "blurple"
+shadow = "black"
+
+[layout]
+image_height = 0
+
+[notifications]
+enabled = "yes"
+
+[input]
+timeout = -1
+
+[sounds]
+bell = true
+"#,
+    );
+    let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(
+        errors,
+        [
+            "input.timeout: expected a number from 0 to 10000",
+            "keys.fly: unknown action",
+            "keys.quit: \"<C-w\" is missing its closing \">\"",
+            "layout.image_height: expected a number from 1 to 100",
+            "notifications.enabled: expected true or false",
+            "sounds: unknown section",
+            "theme.border: \"blurple\" is not a color",
+            "theme.shadow: unknown setting",
+        ]
+    );
+
+    assert_eq!(config.keys.keys(Action::Quit), [vec![Key::ctrl('q')]]);
+    assert_eq!(config.keys.keys(Action::Reply), [vec![Key::ctrl('r')]]);
+    assert_eq!(config.theme.border, Color::White);
+    assert_eq!(config.layout.image_height, 12);
+    assert!(config.notifications.enabled);
+    assert_eq!(config.key_timeout, Duration::from_secs(1));
+}
+
+#[test]
+fn keys_bound_twice_where_both_actions_work_are_reported() {
+    let (config, errors) = Config::parse(
+        r#"
+[keys]
+next_chat = ["k", "<Down>"]
+reply = "dd"
+send = "<C-y>"
+next_message = "k"
+previous_message = "j"
+"#,
+    );
+    let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(
+        errors,
+        [
+            "keys.next_chat: \"k\" is also bound to previous_chat",
+            "keys.reply: \"dd\" is also bound to delete_message",
+        ]
+    );
+
+    assert_eq!(
+        config.keys.keys(Action::NextChat),
+        [vec![Key::k(KeyCode::Down)]]
+    );
+    assert!(config.keys.keys(Action::Reply).is_empty());
+    // Keys of actions that never work together, and keys swapped between two actions,
+    // are fine.
+    assert_eq!(config.keys.keys(Action::Send), [vec![Key::ctrl('y')]]);
+    assert_eq!(config.keys.keys(Action::NextMessage), [vec![Key::c('k')]]);
+    assert_eq!(
+        config.keys.keys(Action::PreviousMessage),
+        [vec![Key::c('j')]]
+    );
+    // Deleting shares its keys with discarding, which takes them over in the outbox.
+    let (_, errors) = Config::parse("[keys]\ndelete_message = \"x\"\ndiscard = \"x\"\n");
+    assert_eq!(errors, []);
+}
+
+#[test]
+fn a_file_that_is_not_toml_leaves_everything_at_its_default() {
+    let (config, errors) = Config::parse("[keys]\nquit = <C-w>\n");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].key.is_empty());
+    assert!(errors[0].message.contains("line 2"), "{}", errors[0]);
+    assert_eq!(config.keys.keys(Action::Quit), [vec![Key::ctrl('q')]]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let (_, errors) = Config::load(&dir.path().join("config.toml"));
+    assert_eq!(errors, []);
+}
@@ -0,0 +1,283 @@
+mod common;
+
+use std::path::Path;
+
+use chrono::{Local, NaiveDate, TimeZone};
+use common::{database, jid, reopen, text_message};
+use whatsrust as wr;
+use wstui::app::Chat;
+use wstui::db::DatabaseHandler;
+use wstui::export::{ExportFormat, ExportOptions, export_chat};
+
+const ALICE: &str = "111@s.whatsapp.net";
+const BOB: &str = "222@s.whatsapp.net";
+const CAROL: &str = "444@s.whatsapp.net";
+const GROUP: &str = "333@g.us";
+
+/// A timestamp in local time, as exports show them.
+fn at(day: u32, hour: u32, minute: u32, second: u32) -> i64 {
+    Local
+        .with_ymd_and_hms(2024, 3, day, hour, minute, second)
+        .unwrap()
+        .timestamp()
+}
+
+fn message(id: &str, sender: &str, timestamp: i64, content: wr::MessageContent) -> wr::Message {
+    wr::Message {
+        message: content,
+        ..text_message(id, GROUP, sender, timestamp, "")
+    }
+}
+
+fn text(s: &str) -> wr::MessageContent {
+    wr::MessageContent::Text(s.into())
+}
+
+/// A group chat over three days, with a message of every kind, and a message in
+/// another chat that must not show up.
+fn climbing_database(path: &Path) -> DatabaseHandler {
+    let messages = [
+        wr::Message {
+            info: wr::MessageInfo {
+                mentions: vec![jid(BOB)],
+                ..message("M1", ALICE, at(4, 20, 0, 0), text("")).info
+            },
+            message: text("see you @222 <tomorrow>"),
+        },
+        wr::Message {
+            info: wr::MessageInfo {
+                is_from_me: true,
+                quote_id: Some("M1".into()),
+                edit_state: wr::EditState::Edited,
+                ..message("M2", ALICE, at(5, 9, 15, 30), text("")).info
+            },
+            message: text("on my way\nbringing ropes"),
+        },
+        message(
+            "F1",
+            BOB,
+            at(5, 9, 16, 0),
+            wr::MessageContent::File(wr::FileContent {
+                kind: wr::FileKind::Image,
+                path: "photos/the wall.jpg".into(),
+                file_id: "file-1".into(),
+                caption: Some("the wall".into()),
+            }),
+        ),
+        wr::Message {
+            info: wr::MessageInfo {
+                edit_state: wr::EditState::Revoked,
+                ..message("R1", BOB, at(5, 9, 17, 0), text("")).info
+            },
+            message: text(""),
+        },
+        message(
+            "G1",
+            ALICE,
+            at(6, 10, 0, 0),
+            wr::MessageContent::GroupChange(wr::GroupChange {
+                kind: wr::GroupChangeKind::Join,
+                participants: vec![jid(CAROL)],
+                text: "".into(),
+            }),
+        ),
+        wr::Message {
+            info: wr::MessageInfo {
+                chat: jid(ALICE),
+                ..message("P1", ALICE, at(5, 12, 0, 0), text("")).info
+            },
+            message: text("private"),
+        },
+    ];
+
+    let mut db = database(path);
+    db.add_contact(&jid(ALICE), "Alice");
+    db.add_contact(&jid(BOB), "Bob");
+    db.add_contact(&jid(GROUP), "Climbing");
+    for chat in [GROUP, ALICE] {
+        db.add_chat(&Chat {
+            jid: jid(chat),
+            last_message_time: None,
+            unread: 0,
+        });
+    }
+    for message in &messages {
+        db.add_message(message);
+    }
+    reopen(&mut db, path);
+    db
+}
+
+fn export(
+    db: &DatabaseHandler,
+    format: ExportFormat,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+) -> (usize, String) {
+    let options = ExportOptions {
+        chat: jid(GROUP),
+        format,
+        since,
+        until,
+        out_dir: None,
+    };
+    let mut out = Vec::new();
+    let count = export_chat(db, Path::new("/data/media"), None, &options, &mut out).unwrap();
+    (count, String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn a_chat_is_exported_like_whatsapp_does() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = climbing_database(&dir.path().join("whatsapp.db"));
+
+    let (count, txt) = export(&db, ExportFormat::Txt, None, None);
+    db.stop();
+    assert_eq!(count, 5);
+    assert_eq!(
+        txt,
+        "[04/03/2024, 20:00:00] Alice: see you @Bob <tomorrow>
+[05/03/2024, 09:15:30] You: on my way
+bringing ropes \u{200e}<This message was edited>
+[05/03/2024, 09:16:00] Bob: \u{200e}<attached: the wall.jpg>
+the wall
+[05/03/2024, 09:17:00] Bob: \u{200e}This message was deleted.
+[06/03/2024, 10:00:00] Climbing: \u{200e}Alice added +444
+"
+    );
+}
+
+#[test]
+fn markdown_and_html_escape_the_text_and_link_the_media() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = climbing_database(&dir.path().join("whatsapp.db"));
+
+    let (_, markdown) = export(&db, ExportFormat::Markdown, None, None);
+    let (_, html) = export(&db, ExportFormat::Html, None, None);
+    db.stop();
+
+    assert!(markdown.starts_with("# Chat with Climbing\n"), "{markdown}");
+    assert!(markdown.contains("## Monday, 4 March 2024"), "{markdown}");
+    assert!(
+        markdown.contains("see you @Bob \\<tomorrow\\>"),
+        "{markdown}"
+    );
+    assert!(markdown.contains("**You** · 09:15 (edited)"), "{markdown}");
+    assert!(
+        markdown.contains("> **Alice**: see you @Bob \\<tomorrow\\>"),
+        "{markdown}"
+    );
+    assert!(
+        markdown.contains("on my way\\\nbringing ropes\n"),
+        "{markdown}"
+    );
+    assert!(
+        markdown.contains("![the wall.jpg](<file:///data/media/photos/the%20wall.jpg>)"),
+        "{markdown}"
+    );
+    assert!(
+        markdown.contains("*Alice added +444 · 10:00*"),
+        "{markdown}"
+    );
+
+    assert!(html.starts_with("<!DOCTYPE html>"), "{html}");
+    assert!(html.contains("<title>Chat with Climbing</title>"), "{html}");
+    assert!(html.contains("see you @Bob &lt;tomorrow&gt;"), "{html}");
+    assert!(html.contains("on my way<br>bringing ropes"), "{html}");
+    assert!(
+        html.contains("<img src=\"file:///data/media/photos/the%20wall.jpg\""),
+        "{html}"
+    );
+    assert!(html.contains("<div class=\"message me\">"), "{html}");
+    assert!(html.contains("<div class=\"deleted\">This message was deleted.</div>"));
+    assert!(html.trim_end().ends_with("</html>"), "{html}");
+}
+
+#[test]
+fn exports_to_a_directory_take_their_media_along() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = climbing_database(&dir.path().join("whatsapp.db"));
+    let media_path = dir.path().join("media");
+    std::fs::create_dir_all(media_path.join("photos")).unwrap();
+    std::fs::write(media_path.join("photos/the wall.jpg"), b"jpeg").unwrap();
+    let out_dir = dir.path().join("exports");
+
+    let options = ExportOptions {
+        chat: jid(GROUP),
+        format: ExportFormat::Html,
+        since: None,
+        until: None,
+        out_dir: Some(out_dir.clone()),
+    };
+    let mut out = Vec::new();
+    export_chat(&db, &media_path, None, &options, &mut out).unwrap();
+    db.stop();
+
+    let html = String::from_utf8(out).unwrap();
+    assert!(
+        html.contains("<img src=\"media/photos/the%20wall.jpg\""),
+        "{html}"
+    );
+    assert!(!html.contains("file://"), "{html}");
+    assert_eq!(
+        std::fs::read(out_dir.join("media/photos/the wall.jpg")).unwrap(),
+        b"jpeg"
+    );
+}
+
+#[test]
+fn json_lines_have_one_object_per_message() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = climbing_database(&dir.path().join("whatsapp.db"));
+
+    let (_, jsonl) = export(&db, ExportFormat::Jsonl, None, None);
+    db.stop();
+
+    let objects = jsonl
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(objects.len(), 5);
+    assert_eq!(objects[0]["sender_name"], "Alice");
+    assert_eq!(objects[0]["text"], "see you @Bob <tomorrow>");
+    assert_eq!(objects[0]["mentions"], serde_json::json!([BOB]));
+    assert_eq!(objects[1]["from_me"], true);
+    assert_eq!(objects[1]["quote_id"], "M1");
+    assert_eq!(objects[1]["edited"], true);
+    assert_eq!(objects[2]["type"], "image");
+    assert_eq!(objects[2]["file"], "/data/media/photos/the wall.jpg");
+    assert_eq!(objects[3]["deleted"], true);
+    assert_eq!(objects[4]["type"], "group_change");
+    assert_eq!(objects[4]["timestamp"], at(6, 10, 0, 0));
+}
+
+#[test]
+fn only_the_days_asked_for_are_exported() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = climbing_database(&dir.path().join("whatsapp.db"));
+    let day = |d| NaiveDate::from_ymd_opt(2024, 3, d);
+
+    let (count, txt) = export(&db, ExportFormat::Txt, day(5), day(5));
+    assert_eq!(count, 3);
+    assert!(txt.starts_with("[05/03/2024, 09:15:30]"), "{txt}");
+    assert!(!txt.contains("06/03/2024"), "{txt}");
+
+    assert_eq!(export(&db, ExportFormat::Txt, day(6), None).0, 1);
+    assert_eq!(export(&db, ExportFormat::Txt, None, day(4)).0, 1);
+    assert_eq!(export(&db, ExportFormat::Txt, day(7), None).0, 0);
+    db.stop();
+}
+
+#[test]
+fn chats_are_found_by_jid_number_or_name() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = climbing_database(&dir.path().join("whatsapp.db"));
+
+    assert_eq!(db.find_chats(GROUP), [jid(GROUP)]);
+    assert_eq!(db.find_chats("+111"), [jid(ALICE)]);
+    assert_eq!(db.find_chats("alice"), [jid(ALICE)]);
+    assert_eq!(db.find_chats("climb"), [jid(GROUP)]);
+    // Bob has no chat of his own.
+    assert!(db.find_chats("Bob").is_empty());
+    db.stop();
+}
@@ -0,0 +1,1572 @@
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+
+use common::{Harness, RpcConnection, jid, squash, text_message};
+use ratatui::crossterm::event::{KeyCode, KeyModifiers, MouseEventKind};
+use serde_json::json;
+use whatsrust as wr;
+use wstui::app::HISTORY_PAGE_SIZE;
+use wstui::app::SelectedWidget;
+use wstui::app::outbox::{OutboxEntry, SEND_ATTEMPTS};
+use wstui::backend::{Backend, FakeBackend};
+use wstui::config::Config;
+use wstui::import::{ImportOptions, import_archive};
+
+const ALICE: &str = "111@s.whatsapp.net";
+const BOB: &str = "222@s.whatsapp.net";
+
+fn with_contacts(backend: &FakeBackend) {
+    backend.set_contacts(vec![(jid(ALICE), "Alice".into()), (jid(BOB), "Bob".into())]);
+    backend.emit_event(wr::Event::AppStateSyncComplete);
+}
+
+#[test]
+fn synced_messages_show_up_in_chat_list_and_message_list() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "hello from alice"));
+    h.backend
+        .sync_message(text_message("B1", BOB, BOB, 2_000, "hello from bob"));
+
+    let screen = h.draw();
+    let bob_line = screen.lines().position(|l| l.starts_with("│Bob")).unwrap();
+    let alice_line = screen
+        .lines()
+        .position(|l| l.starts_with("│Alice"))
+        .unwrap();
+    assert!(bob_line < alice_line, "most recent chat first:\n{screen}");
+    // The chat that was selected when the newer message arrived stays selected.
+    assert!(screen.contains("Chat with Alice"), "{screen}");
+    assert!(screen.contains("hello from alice"), "{screen}");
+
+    h.press(KeyCode::Char('k'), KeyModifiers::NONE);
+    let screen = h.draw();
+    assert!(screen.contains("Chat with Bob"), "{screen}");
+    assert!(screen.contains("hello from bob"), "{screen}");
+}
+
+#[test]
+fn sending_from_input_goes_through_the_backend() {
+    let mut h = Harness::new();
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "ping"));
+    h.pump();
+
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    h.type_str("pong");
+    h.press(KeyCode::Char('x'), KeyModifiers::CONTROL);
+    h.wait_until(|app| app.outbox.is_empty());
+
+    let sent = h.backend.sent_messages();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].jid, jid(ALICE));
+    assert!(matches!(&sent[0].content, wr::MessageContent::Text(t) if t.as_ref() == "pong"));
+
+    let screen = h.draw();
+    assert!(screen.contains("pong"), "{screen}");
+    assert!(h.app.input_widget.is_empty());
+}
+
+fn receipt(kind: wr::ReceiptKind, chat: &str, sender: &str, id: &str, ts: i64) -> wr::Event {
+    wr::Event::Receipt {
+        kind,
+        chat: jid(chat),
+        sender: jid(sender),
+        message_ids: vec![id.into()],
+        timestamp: ts,
+    }
+}
+
+#[test]
+fn receipts_advance_the_delivery_state() {
+    let mut h = Harness::new();
+    let mut message = text_message("A1", ALICE, ALICE, 1_000, "seen?");
+    message.info.is_from_me = true;
+    h.backend.sync_message(message);
+    let screen = h.draw();
+    assert!(screen.contains('✓') && !screen.contains("✓✓"), "{screen}");
+
+    h.backend.emit_event(receipt(
+        wr::ReceiptKind::Delivered,
+        ALICE,
+        ALICE,
+        "A1",
+        1_001,
+    ));
+    assert!(h.draw().contains("✓✓"));
+    assert_eq!(
+        h.app.messages["A1"].info.delivery,
+        wr::DeliveryState::Delivered
+    );
+
+    h.backend
+        .emit_event(receipt(wr::ReceiptKind::Read, ALICE, ALICE, "A1", 1_002));
+    // A late delivery receipt does not move the state back.
+    h.backend.emit_event(receipt(
+        wr::ReceiptKind::Delivered,
+        ALICE,
+        ALICE,
+        "A1",
+        1_003,
+    ));
+    h.pump();
+    assert_eq!(h.app.messages["A1"].info.delivery, wr::DeliveryState::Read);
+}
+
+fn with_group_members(backend: &FakeBackend, group: &str, members: &[&str]) {
+    let participants = members
+        .iter()
+        .map(|member| jid(member))
+        .chain([backend.self_jid.clone()])
+        .map(|jid| wr::GroupParticipant {
+            jid,
+            is_admin: false,
+            is_super_admin: false,
+        })
+        .collect();
+    backend.set_group_info(wr::GroupInfo {
+        jid: jid(group),
+        name: "Climbing".into(),
+        description: "".into(),
+        created_at: 0,
+        participants,
+    });
+}
+
+#[test]
+fn group_messages_are_read_once_every_participant_read_them() {
+    const GROUP: &str = "333@g.us";
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    with_group_members(&h.backend, GROUP, &[ALICE, BOB]);
+    let mut message = text_message("G1", GROUP, "0@s.whatsapp.net", 1_000, "hi all");
+    message.info.is_from_me = true;
+    h.backend.sync_message(message);
+
+    for sender in [ALICE, BOB] {
+        h.backend.emit_event(receipt(
+            wr::ReceiptKind::Delivered,
+            GROUP,
+            sender,
+            "G1",
+            1_001,
+        ));
+    }
+    h.backend
+        .emit_event(receipt(wr::ReceiptKind::Read, GROUP, ALICE, "G1", 1_002));
+    h.pump();
+    assert_eq!(
+        h.app.messages["G1"].info.delivery,
+        wr::DeliveryState::Delivered
+    );
+
+    h.press(KeyCode::Char('l'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Char('k'), KeyModifiers::NONE);
+    h.draw();
+    h.press(KeyCode::Char('i'), KeyModifiers::NONE);
+    let screen = h.draw();
+    assert!(screen.contains("Message info"), "{screen}");
+    assert!(screen.contains("Status: Delivered"), "{screen}");
+    let alice = screen.lines().find(|l| l.contains("Alice")).unwrap();
+    let bob = screen.lines().find(|l| l.contains("Bob")).unwrap();
+    assert_eq!(squash(alice).matches(':').count(), 2, "{screen}");
+    assert_eq!(squash(bob).matches(':').count(), 1, "{screen}");
+
+    h.backend
+        .emit_event(receipt(wr::ReceiptKind::Read, GROUP, BOB, "G1", 1_003));
+    h.wait_until(|app| app.messages["G1"].info.delivery == wr::DeliveryState::Read);
+}
+
+#[test]
+fn group_messages_are_not_read_while_a_participant_has_not_received_them() {
+    const GROUP: &str = "333@g.us";
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    with_group_members(&h.backend, GROUP, &[ALICE, BOB]);
+    let mut message = text_message("G1", GROUP, "0@s.whatsapp.net", 1_000, "hi all");
+    message.info.is_from_me = true;
+    h.backend.sync_message(message);
+
+    // Bob is offline: only Alice has the message, and she reads it right away.
+    h.backend.emit_event(receipt(
+        wr::ReceiptKind::Delivered,
+        GROUP,
+        ALICE,
+        "G1",
+        1_001,
+    ));
+    h.backend
+        .emit_event(receipt(wr::ReceiptKind::Read, GROUP, ALICE, "G1", 1_002));
+    h.pump();
+    assert_eq!(
+        h.app.messages["G1"].info.delivery,
+        wr::DeliveryState::Delivered
+    );
+
+    h.backend
+        .emit_event(receipt(wr::ReceiptKind::Delivered, GROUP, BOB, "G1", 1_010));
+    h.pump();
+    assert_eq!(
+        h.app.messages["G1"].info.delivery,
+        wr::DeliveryState::Delivered
+    );
+
+    h.backend
+        .emit_event(receipt(wr::ReceiptKind::Read, GROUP, BOB, "G1", 1_011));
+    h.wait_until(|app| app.messages["G1"].info.delivery == wr::DeliveryState::Read);
+}
+
+#[test]
+fn reactions_are_summarized_and_can_be_sent() {
+    let mut h = Harness::new();
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "lunch?"));
+    h.backend.emit_event(wr::Event::Reaction(wr::Reaction {
+        chat: jid(ALICE),
+        sender: jid(BOB),
+        message_id: "A1".into(),
+        emoji: "❤️".into(),
+        timestamp: 1_001,
+        is_from_me: false,
+    }));
+    let screen = squash(&h.draw());
+    assert!(screen.contains("❤️ 1"), "{screen}");
+
+    h.press(KeyCode::Char('l'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Char('k'), KeyModifiers::NONE);
+    h.draw();
+    h.press(KeyCode::Char('+'), KeyModifiers::NONE);
+    assert!(h.draw().contains("React"));
+    h.press(KeyCode::Char('1'), KeyModifiers::NONE);
+
+    let sent = h.backend.sent_reactions();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].message_id.as_ref(), "A1");
+    assert_eq!(sent[0].emoji.as_ref(), "👍");
+
+    let screen = squash(&h.draw());
+    assert!(screen.contains("❤️ 1 👍 1"), "{screen}");
+}
+
+#[test]
+fn edits_and_revokes_update_messages() {
+    let mut h = Harness::new();
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "see you at 5"));
+    h.backend.emit_event(wr::Event::MessageEdit {
+        chat: jid(ALICE),
+        sender: jid(ALICE),
+        message_id: "A1".into(),
+        text: "see you at 6".into(),
+        timestamp: 1_010,
+    });
+    let screen = h.draw();
+    assert!(screen.contains("see you at 6"), "{screen}");
+    assert!(screen.contains("(edited)"), "{screen}");
+
+    // Only the sender can edit a message.
+    h.backend.emit_event(wr::Event::MessageEdit {
+        chat: jid(ALICE),
+        sender: jid(BOB),
+        message_id: "A1".into(),
+        text: "hijacked".into(),
+        timestamp: 1_020,
+    });
+    // Re-syncing the original does not undo the edit.
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "see you at 5"));
+    let screen = h.draw();
+    assert!(screen.contains("see you at 6"), "{screen}");
+
+    h.backend.emit_event(wr::Event::MessageRevoke {
+        chat: jid(ALICE),
+        sender: jid(ALICE),
+        message_id: "A1".into(),
+        timestamp: 1_030,
+    });
+    let screen = h.draw();
+    assert!(!screen.contains("see you at"), "{screen}");
+    assert!(screen.contains("This message was deleted"), "{screen}");
+}
+
+#[test]
+fn own_messages_can_be_edited_and_deleted() {
+    let mut h = Harness::new();
+    let mut message = text_message("A1", ALICE, h.backend.self_jid.0.as_ref(), 1_000, "helo");
+    message.info.is_from_me = true;
+    h.backend.sync_message(message);
+    h.pump();
+
+    h.press(KeyCode::Char('l'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Char('k'), KeyModifiers::NONE);
+    h.draw();
+    h.press(KeyCode::Char('e'), KeyModifiers::NONE);
+    assert!(h.draw().contains("Editing: helo"));
+    h.press(KeyCode::Backspace, KeyModifiers::NONE);
+    h.press(KeyCode::Backspace, KeyModifiers::NONE);
+    h.type_str("llo");
+    h.press(KeyCode::Char('x'), KeyModifiers::CONTROL);
+
+    assert_eq!(h.backend.edits(), vec![("A1".into(), "hello".into())]);
+    assert!(h.backend.sent_messages().is_empty());
+    let screen = h.draw();
+    assert!(screen.contains("hello"), "{screen}");
+    assert!(screen.contains("(edited)"), "{screen}");
+
+    h.press(KeyCode::Char('k'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Char('k'), KeyModifiers::NONE);
+    h.draw();
+    h.press(KeyCode::Char('d'), KeyModifiers::NONE);
+    h.press(KeyCode::Char('d'), KeyModifiers::NONE);
+    assert_eq!(h.backend.revokes(), vec![wr::MessageId::from("A1")]);
+    assert!(h.draw().contains("This message was deleted"));
+}
+
+#[test]
+fn failed_sends_can_be_resent_or_discarded() {
+    let mut h = Harness::new();
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "ping"));
+    h.pump();
+    h.backend.fail_next_sends(SEND_ATTEMPTS as usize);
+
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    h.type_str("first");
+    h.press(KeyCode::Char('x'), KeyModifiers::CONTROL);
+    h.wait_until(|app| app.outbox.values().all(|entry| entry.failed));
+    let screen = h.draw();
+    assert!(screen.contains("first"), "{screen}");
+    assert!(screen.contains("not sent"), "{screen}");
+    assert!(h.backend.sent_messages().is_empty());
+
+    h.press(KeyCode::Char('k'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Char('k'), KeyModifiers::NONE);
+    h.draw();
+    h.press(KeyCode::Char('R'), KeyModifiers::SHIFT);
+    h.wait_until(|app| app.outbox.is_empty());
+    let screen = h.draw();
+    assert!(!screen.contains("not sent"), "{screen}");
+    assert_eq!(h.backend.sent_messages().len(), 1);
+
+    h.backend.fail_next_sends(SEND_ATTEMPTS as usize);
+    h.press(KeyCode::Char('j'), KeyModifiers::CONTROL);
+    h.type_str("second");
+    h.press(KeyCode::Char('x'), KeyModifiers::CONTROL);
+    h.wait_until(|app| app.outbox.values().all(|entry| entry.failed));
+
+    h.press(KeyCode::Char('k'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Esc, KeyModifiers::NONE);
+    h.press(KeyCode::Char('k'), KeyModifiers::NONE);
+    h.draw();
+    h.press(KeyCode::Char('d'), KeyModifiers::NONE);
+    h.press(KeyCode::Char('d'), KeyModifiers::NONE);
+    assert!(h.app.outbox.is_empty());
+    assert!(!h.draw().contains("second"));
+    assert_eq!(h.backend.sent_messages().len(), 1);
+}
+
+#[test]
+fn replies_left_in_the_outbox_keep_their_quote_when_resent_after_a_restart() {
+    let mut h = Harness::new();
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "lunch?"));
+    h.pump();
+    h.app.db_handler.save_outbox_entry(&OutboxEntry {
+        id: "OUTBOX-1".into(),
+        chat: jid(ALICE),
+        content: wr::MessageContent::Text("sure".into()),
+        quote_id: Some("A1".into()),
+        mentions: Vec::new(),
+        timestamp: 1_010,
+        attempts: 1,
+        failed: false,
+    });
+
+    // No page of the chat is read before the outbox is sent again.
+    h.restart();
+    let backend = h.backend.clone();
+    h.wait_until(|_| !backend.sent_messages().is_empty());
+    assert_eq!(h.backend.sent_messages()[0].quote_id, Some("A1".into()));
+}
+
+fn group_change(sender: Option<&str>, kind: wr::GroupChangeKind, ts: i64) -> wr::Event {
+    wr::Event::GroupChange {
+        chat: jid("333@g.us"),
+        sender: sender.map(jid),
+        change: wr::GroupChange {
+            kind,
+            participants: vec![jid(BOB)],
+            text: "".into(),
+        },
+        timestamp: ts,
+    }
+}
+
+#[test]
+fn group_changes_show_up_as_messages_and_in_the_participants_panel() {
+    const GROUP: &str = "333@g.us";
+    let mut h = Harness::new();
+    h.backend.set_contacts(vec![
+        (jid(ALICE), "Alice".into()),
+        (jid(BOB), "Bob".into()),
+        (jid(GROUP), "Climbing".into()),
+    ]);
+    h.backend.emit_event(wr::Event::AppStateSyncComplete);
+    h.backend.set_group_info(wr::GroupInfo {
+        jid: jid(GROUP),
+        name: "Climbing".into(),
+        description: "Saturdays at the wall".into(),
+        created_at: 1_000,
+        participants: vec![
+            wr::GroupParticipant {
+                jid: jid(ALICE),
+                is_admin: true,
+                is_super_admin: true,
+            },
+            wr::GroupParticipant {
+                jid: h.backend.self_jid.clone(),
+                is_admin: false,
+                is_super_admin: false,
+            },
+        ],
+    });
+    h.backend
+        .sync_message(text_message("G1", GROUP, ALICE, 1_000, "who's in?"));
+    h.pump();
+
+    h.press(KeyCode::Char('g'), KeyModifiers::CONTROL);
+    h.wait_until(|app| app.group_infos.contains_key(&jid(GROUP)));
+    let screen = squash(&h.draw());
+    assert!(screen.contains("Participants (2)"), "{screen}");
+    assert!(screen.contains("Alice owner"), "{screen}");
+    assert!(screen.contains("Saturdays at the wall"), "{screen}");
+
+    h.backend
+        .emit_event(group_change(Some(ALICE), wr::GroupChangeKind::Join, 1_010));
+    h.backend.emit_event(group_change(
+        Some(ALICE),
+        wr::GroupChangeKind::Promote,
+        1_020,
+    ));
+    let screen = squash(&h.draw());
+    assert!(screen.contains("Alice added Bob"), "{screen}");
+    assert!(screen.contains("Alice made Bob admin"), "{screen}");
+    assert!(screen.contains("Participants (3)"), "{screen}");
+    assert!(screen.contains("Bob admin"), "{screen}");
+
+    h.backend.emit_event(wr::Event::GroupChange {
+        chat: jid(GROUP),
+        sender: Some(jid(BOB)),
+        change: wr::GroupChange {
+            kind: wr::GroupChangeKind::Subject,
+            participants: Vec::new(),
+            text: "Bouldering".into(),
+        },
+        timestamp: 1_030,
+    });
+    h.backend
+        .emit_event(group_change(Some(BOB), wr::GroupChangeKind::Leave, 1_040));
+    let screen = squash(&h.draw());
+    assert!(screen.contains("Chat with Bouldering"), "{screen}");
+    assert!(screen.contains("Bob changed the subject"), "{screen}");
+    assert!(screen.contains("Bob left"), "{screen}");
+    assert!(screen.contains("Participants (2)"), "{screen}");
+
+    h.press(KeyCode::Char('g'), KeyModifiers::CONTROL);
+    assert!(!h.draw().contains("Participants"));
+}
+
+#[test]
+fn group_changes_of_a_kind_in_the_same_second_are_all_kept() {
+    const GROUP: &str = "333@g.us";
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("G1", GROUP, ALICE, 1_000, "who's in?"));
+    for member in [ALICE, BOB] {
+        h.backend.emit_event(wr::Event::GroupChange {
+            chat: jid(GROUP),
+            sender: None,
+            change: wr::GroupChange {
+                kind: wr::GroupChangeKind::Join,
+                participants: vec![jid(member)],
+                text: "".into(),
+            },
+            timestamp: 1_010,
+        });
+    }
+    h.pump();
+
+    let screen = squash(&h.draw());
+    assert!(screen.contains("Alice joined"), "{screen}");
+    assert!(screen.contains("Bob joined"), "{screen}");
+    h.restart();
+    h.press(KeyCode::Char('j'), KeyModifiers::NONE);
+    h.draw();
+    let screen = squash(&h.draw());
+    assert!(screen.contains("Alice joined"), "{screen}");
+    assert!(screen.contains("Bob joined"), "{screen}");
+}
+
+#[test]
+fn admins_add_promote_and_remove_participants_from_the_command_line() {
+    const GROUP: &str = "333@g.us";
+    let mut h = Harness::new();
+    h.backend.set_contacts(vec![
+        (jid(ALICE), "Alice".into()),
+        (jid(BOB), "Bob".into()),
+        (jid(GROUP), "Climbing".into()),
+    ]);
+    h.backend.emit_event(wr::Event::AppStateSyncComplete);
+    h.backend.set_group_info(wr::GroupInfo {
+        jid: jid(GROUP),
+        name: "Climbing".into(),
+        description: "".into(),
+        created_at: 0,
+        participants: vec![
+            wr::GroupParticipant {
+                jid: h.backend.self_jid.clone(),
+                is_admin: true,
+                is_super_admin: true,
+            },
+            wr::GroupParticipant {
+                jid: jid(ALICE),
+                is_admin: false,
+                is_super_admin: false,
+            },
+        ],
+    });
+    h.backend
+        .sync_message(text_message("G1", GROUP, ALICE, 1_000, "who's in?"));
+    h.draw();
+    h.type_str(":goto Climbing");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    h.press(KeyCode::Esc, KeyModifiers::NONE);
+    h.press(KeyCode::Char('g'), KeyModifiers::CONTROL);
+    h.wait_until(|app| app.group_infos.contains_key(&jid(GROUP)));
+
+    let participant = |app: &wstui::app::App, user: &str| {
+        app.group_infos[&jid(GROUP)]
+            .participants
+            .iter()
+            .find(|p| p.jid == jid(user))
+            .cloned()
+    };
+
+    // Only contacts not in the group yet are offered.
+    h.type_str(":add ");
+    let candidates = h
+        .app
+        .command_line
+        .candidates
+        .iter()
+        .map(|c| c.text.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(candidates, ["Bob"]);
+    h.type_str("bo");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    h.wait_until(|app| participant(app, BOB).is_some());
+    let screen = squash(&h.draw());
+    assert!(screen.contains("You added Bob"), "{screen}");
+    assert!(screen.contains("Participants (3)"), "{screen}");
+
+    h.type_str(":promote Bob");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    h.wait_until(|app| participant(app, BOB).is_some_and(|p| p.is_admin));
+    assert!(squash(&h.draw()).contains("You made Bob admin"));
+
+    // Someone who is not a contact is added by phone number.
+    h.type_str(":add +44 4444");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    h.wait_until(|app| participant(app, "444444@s.whatsapp.net").is_some());
+
+    h.type_str(":remove Alice");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    h.wait_until(|app| participant(app, ALICE).is_none());
+    assert!(squash(&h.draw()).contains("You removed Alice"));
+
+    // Failures from the server are shown where the command was typed.
+    h.backend.disconnect().unwrap();
+    h.type_str(":demote Bob");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    h.wait_until(|app| app.command_line.error.is_some());
+    let screen = h.draw();
+    assert!(screen.contains("Failed to demote in Climbing"), "{screen}");
+    assert!(participant(&h.app, BOB).unwrap().is_admin);
+}
+
+#[test]
+fn mentions_are_completed_sent_and_resolved() {
+    const GROUP: &str = "333@g.us";
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend.set_group_info(wr::GroupInfo {
+        jid: jid(GROUP),
+        name: "Climbing".into(),
+        description: "".into(),
+        created_at: 0,
+        participants: vec![
+            wr::GroupParticipant {
+                jid: jid(ALICE),
+                is_admin: false,
+                is_super_admin: false,
+            },
+            wr::GroupParticipant {
+                jid: h.backend.self_jid.clone(),
+                is_admin: false,
+                is_super_admin: false,
+            },
+        ],
+    });
+    let mut mention = text_message("G1", GROUP, ALICE, 1_000, "@222 are you coming?");
+    mention.info.mentions = vec![jid(BOB)];
+    h.backend.sync_message(mention);
+    h.pump();
+
+    let screen = squash(&h.draw());
+    assert!(screen.contains("@Bob are you coming?"), "{screen}");
+
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    h.type_str("hi @al");
+    let screen = squash(&h.draw());
+    assert!(screen.contains("│ @Alice │"), "{screen}");
+    h.press(KeyCode::Tab, KeyModifiers::NONE);
+    // Group participants come first, then the other contacts.
+    h.type_str("and @");
+    h.press(KeyCode::Down, KeyModifiers::NONE);
+    h.press(KeyCode::Tab, KeyModifiers::NONE);
+    assert_eq!(h.app.input_widget.lines(), ["hi @Alice and @Bob "]);
+
+    h.press(KeyCode::Char('x'), KeyModifiers::CONTROL);
+    h.wait_until(|app| app.outbox.is_empty());
+    let sent = h.backend.sent_messages();
+    assert!(
+        matches!(&sent[0].content, wr::MessageContent::Text(t) if t.as_ref() == "hi @111 and @222 ")
+    );
+    assert_eq!(sent[0].mentions, vec![jid(ALICE), jid(BOB)]);
+    let screen = squash(&h.draw());
+    assert!(screen.contains("hi @Alice and @Bob"), "{screen}");
+}
+
+#[test]
+fn mentions_complete_from_the_contacts_until_the_participants_arrive() {
+    const GROUP: &str = "333@g.us";
+    const CAROL: &str = "444@s.whatsapp.net";
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    with_group_members(&h.backend, GROUP, &[BOB, CAROL]);
+    h.backend
+        .sync_message(text_message("G1", GROUP, BOB, 1_000, "who's in?"));
+    h.draw();
+
+    // Typing does not wait for the participants to be fetched.
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    h.type_str("@");
+    let names = |app: &wstui::app::App| {
+        app.mention_candidates()
+            .into_iter()
+            .map(|(_, name)| name.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(&h.app), ["Alice", "Bob"]);
+
+    h.wait_until(|app| app.group_infos.contains_key(&jid(GROUP)));
+    assert_eq!(names(&h.app), ["444", "Bob", "Alice"]);
+    let screen = squash(&h.draw());
+    assert!(screen.contains("│ @444 │"), "{screen}");
+}
+
+#[test]
+fn history_is_read_a_page_at_a_time() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    let total = HISTORY_PAGE_SIZE * 2 + 10;
+    for i in 0..total {
+        let text = format!("message {i}");
+        h.backend.sync_message(text_message(
+            &format!("A{i}"),
+            ALICE,
+            ALICE,
+            i as i64,
+            &text,
+        ));
+    }
+    h.pump();
+    h.restart();
+
+    // Only the chat list is read on start.
+    assert!(h.app.messages.is_empty());
+    assert_eq!(
+        h.app.chats[&jid(ALICE)].last_message_time,
+        Some(total as i64 - 1)
+    );
+
+    // Edits still reach messages that were not read yet.
+    h.backend.emit_event(wr::Event::MessageEdit {
+        chat: jid(ALICE),
+        sender: jid(ALICE),
+        message_id: "A0".into(),
+        text: "the first message".into(),
+        timestamp: 10_000,
+    });
+
+    h.press(KeyCode::Char('j'), KeyModifiers::NONE);
+    h.draw();
+    let screen = h.draw();
+    assert!(
+        screen.contains(&format!("message {}", total - 1)),
+        "{screen}"
+    );
+    assert_eq!(h.app.chat_messages[&jid(ALICE)].len(), HISTORY_PAGE_SIZE);
+
+    // Reaching the oldest message read reads the page before it, until there is none.
+    h.press(KeyCode::Char('l'), KeyModifiers::CONTROL);
+    for pages in [2, 3, 3] {
+        h.press(KeyCode::Char('g'), KeyModifiers::NONE);
+        h.press(KeyCode::Char('g'), KeyModifiers::NONE);
+        h.draw();
+        h.draw();
+        let read = h.app.chat_messages[&jid(ALICE)].len();
+        assert_eq!(read, total.min(HISTORY_PAGE_SIZE * pages));
+    }
+    assert!(!h.app.has_older_messages(&jid(ALICE)));
+
+    h.press(KeyCode::Char('g'), KeyModifiers::NONE);
+    h.press(KeyCode::Char('g'), KeyModifiers::NONE);
+    let screen = h.draw();
+    assert!(screen.contains("the first message"), "{screen}");
+    assert!(screen.contains("(edited)"), "{screen}");
+}
+
+#[test]
+fn messages_are_found_across_chats_and_can_be_jumped_to() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("A0", ALICE, ALICE, 0, "dinner at eight?"));
+    // Enough newer messages that the hit is not in the first page of the chat.
+    for i in 1..=HISTORY_PAGE_SIZE {
+        let text = format!("message {i}");
+        h.backend.sync_message(text_message(
+            &format!("A{i}"),
+            ALICE,
+            ALICE,
+            i as i64,
+            &text,
+        ));
+    }
+    h.backend
+        .sync_message(text_message("B1", BOB, BOB, 2_000, "no dinner for me"));
+    h.pump();
+    // Messages are indexed once they are saved.
+    h.restart();
+
+    h.press(KeyCode::Char('s'), KeyModifiers::CONTROL);
+    h.type_str("DINN");
+    let screen = squash(&h.draw());
+    assert!(screen.contains("Bob · Bob ·"), "{screen}");
+    assert!(screen.contains("no dinner for me"), "{screen}");
+    assert!(screen.contains("Alice · Alice ·"), "{screen}");
+    assert!(screen.contains("dinner at eight?"), "{screen}");
+    assert_eq!(h.app.search.hits.len(), 2);
+    // Newest first.
+    assert_eq!(h.app.search.hits[0].message.info.id.as_ref(), "B1");
+
+    h.press(KeyCode::Down, KeyModifiers::NONE);
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    let screen = h.draw();
+    assert!(screen.contains("Chat with Alice"), "{screen}");
+    assert!(screen.contains("dinner at eight?"), "{screen}");
+    assert_eq!(
+        h.app.message_list_state.get_selected_message(),
+        Some("A0".into())
+    );
+
+    h.press(KeyCode::Char('s'), KeyModifiers::CONTROL);
+    h.type_str(" lunch");
+    assert!(squash(&h.draw()).contains("No messages found"));
+}
+
+#[test]
+fn the_chat_is_searched_as_the_pattern_is_typed_and_n_moves_between_matches() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("C0", ALICE, ALICE, 0, "cake from last week"));
+    for i in 1..=HISTORY_PAGE_SIZE {
+        let text = format!("message {i}");
+        h.backend.sync_message(text_message(
+            &format!("A{i}"),
+            ALICE,
+            ALICE,
+            i as i64,
+            &text,
+        ));
+    }
+    h.backend
+        .sync_message(text_message("C1", ALICE, ALICE, 100, "Cake is ready"));
+    h.backend
+        .sync_message(text_message("C2", ALICE, ALICE, 101, "the cake?"));
+    h.pump();
+    h.restart();
+    h.press(KeyCode::Char('j'), KeyModifiers::NONE);
+    h.draw();
+    h.draw();
+    let selected = |h: &Harness| h.app.message_list_state.get_selected_message();
+
+    // Matches are found as the pattern is typed, ignoring case, newest first.
+    h.press(KeyCode::Char('l'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Char('/'), KeyModifiers::NONE);
+    h.type_str("cak");
+    let screen = h.draw();
+    assert!(screen.contains("/cak"), "{screen}");
+    assert_eq!(selected(&h), Some("C2".into()));
+
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    h.press(KeyCode::Char('n'), KeyModifiers::NONE);
+    assert_eq!(selected(&h), Some("C1".into()));
+    // The oldest match is in a page that was not read yet.
+    h.press(KeyCode::Char('n'), KeyModifiers::NONE);
+    assert_eq!(selected(&h), Some("C0".into()));
+    h.press(KeyCode::Char('n'), KeyModifiers::NONE);
+    assert_eq!(selected(&h), Some("C2".into()));
+    h.press(KeyCode::Char('N'), KeyModifiers::SHIFT);
+    assert_eq!(selected(&h), Some("C0".into()));
+
+    // Uppercase makes the search case sensitive.
+    h.press(KeyCode::Char('/'), KeyModifiers::NONE);
+    h.type_str("Cake");
+    assert_eq!(selected(&h), Some("C1".into()));
+    h.press(KeyCode::Esc, KeyModifiers::NONE);
+    assert_eq!(selected(&h), Some("C0".into()));
+
+    h.press(KeyCode::Char('/'), KeyModifiers::NONE);
+    h.press(KeyCode::Char('r'), KeyModifiers::CONTROL);
+    h.type_str("^the .*\\?$");
+    assert_eq!(selected(&h), Some("C2".into()));
+    h.type_str("(");
+    let screen = h.draw();
+    assert!(screen.contains("invalid pattern"), "{screen}");
+}
+
+#[test]
+fn the_selected_chat_is_exported_to_a_file() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "hello from alice"));
+    h.backend
+        .sync_message(text_message("B1", BOB, BOB, 2_000, "hello from bob"));
+    h.pump();
+    h.restart();
+    let dir = h.data_dir.path().join("exports");
+    h.app.chat_export.dir = dir.clone();
+
+    h.press(KeyCode::Char('j'), KeyModifiers::NONE);
+    // Messages that may not have reached the database yet are exported too.
+    h.backend
+        .receive_message(text_message("B2", BOB, BOB, 3_000, "still there?"));
+    h.pump();
+    h.press(KeyCode::Char('E'), KeyModifiers::SHIFT);
+    let screen = squash(&h.draw());
+    assert!(screen.contains("Export chat with Bob"), "{screen}");
+    assert!(screen.contains("4 WhatsApp text"), "{screen}");
+
+    h.press(KeyCode::Char('4'), KeyModifiers::NONE);
+    h.press(KeyCode::Tab, KeyModifiers::NONE);
+    h.type_str("yesterday");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    let screen = squash(&h.draw());
+    assert!(screen.contains("\"yesterday\" is not a day"), "{screen}");
+
+    for _ in 0.."yesterday".len() {
+        h.press(KeyCode::Backspace, KeyModifiers::NONE);
+    }
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    let screen = squash(&h.draw());
+    assert!(screen.contains("Exported 2 messages"), "{screen}");
+    let txt = std::fs::read_to_string(dir.join("Bob.txt")).unwrap();
+    assert!(txt.contains("] Bob: hello from bob\n"), "{txt}");
+    assert!(txt.ends_with("] Bob: still there?\n"), "{txt}");
+
+    // A second export does not overwrite the first.
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    assert!(dir.join("Bob (1).txt").exists());
+
+    h.press(KeyCode::Esc, KeyModifiers::NONE);
+    assert!(!squash(&h.draw()).contains("Export chat with"));
+}
+
+#[test]
+fn imported_history_shows_up_with_its_media() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.pump();
+    let archive = h.data_dir.path().join("export").join("_chat.txt");
+    std::fs::create_dir_all(archive.parent().unwrap()).unwrap();
+    std::fs::write(
+        &archive,
+        "[05/03/2024, 09:15:30] Alice: the old days
+[05/03/2024, 09:16:00] Alice: \u{200e}<attached: notes.pdf>
+",
+    )
+    .unwrap();
+    std::fs::write(archive.with_file_name("notes.pdf"), b"%PDF").unwrap();
+
+    let options = ImportOptions {
+        chat: jid(ALICE),
+        me: None,
+        self_jid: None,
+        date_order: None,
+    };
+    import_archive(&h.app.db_handler, &h.app.media_path, &archive, &options).unwrap();
+    h.restart();
+    // Nothing imported is new.
+    assert_eq!(h.app.chats[&jid(ALICE)].unread, 0);
+
+    h.press(KeyCode::Char('j'), KeyModifiers::NONE);
+    // Reads the history, then asks for the file.
+    for _ in 0..2 {
+        h.draw();
+        h.pump();
+    }
+    let screen = h.draw();
+    assert!(screen.contains("Chat with Alice"), "{screen}");
+    assert!(screen.contains("the old days"), "{screen}");
+    // The file is already there, so nothing is downloaded.
+    assert!(screen.contains("notes.pdf ✓"), "{screen}");
+}
+
+#[test]
+fn scripts_can_list_chats_read_and_send_messages_over_the_socket() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .receive_message(text_message("A1", ALICE, ALICE, 1_000, "hello from alice"));
+    h.backend
+        .receive_message(text_message("B1", BOB, BOB, 2_000, "hello from bob"));
+    h.pump();
+    let mut rpc = RpcConnection::connect(&h);
+
+    // Only we can connect, and nothing is left from binding it.
+    let mode = std::fs::metadata(&h.app.rpc_socket)
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o600);
+    let entries = std::fs::read_dir(h.app.rpc_socket.parent().unwrap()).unwrap();
+    assert!(
+        entries
+            .map(|entry| entry.unwrap().file_name())
+            .all(|name| !name.to_string_lossy().ends_with(".tmp"))
+    );
+
+    let chats = rpc.call(&mut h, "chats", json!({}));
+    assert_eq!(chats["result"][0]["jid"], BOB);
+    assert_eq!(chats["result"][1]["name"], "Alice");
+
+    // Straight away, before the messages reach the database.
+    let messages = rpc.call(&mut h, "messages", json!({ "chat": "Alice" }));
+    assert_eq!(messages["result"][0]["id"], "A1");
+    assert_eq!(messages["result"][0]["text"], "hello from alice");
+    let messages = rpc.call(&mut h, "messages", json!({ "chat": BOB, "since": 2_001 }));
+    assert_eq!(messages["result"], json!([]));
+
+    let sent = rpc.call(
+        &mut h,
+        "send",
+        json!({ "chat": "Bob", "text": "build 42 passed" }),
+    );
+    assert!(sent["result"]["id"].is_string(), "{sent}");
+    h.wait_until(|app| app.outbox.is_empty());
+    let sent = h.backend.sent_messages();
+    assert_eq!(sent[0].jid, jid(BOB));
+    assert!(matches!(&sent[0].content, wr::MessageContent::Text(t) if &**t == "build 42 passed"));
+
+    let error = rpc.call(&mut h, "send", json!({ "chat": "Nobody", "text": "hi" }));
+    assert_eq!(error["error"]["code"], -32000);
+    assert!(
+        error["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Nobody")
+    );
+    let error = rpc.call(&mut h, "send", json!({ "chat": "Bob" }));
+    assert_eq!(error["error"]["code"], -32602);
+    let error = rpc.call(&mut h, "shout", json!({}));
+    assert_eq!(error["error"]["code"], -32601);
+    rpc.send_line("{ not json");
+    let error = rpc.next_response(&mut h, |response| response["id"].is_null());
+    assert_eq!(error["error"]["code"], -32700);
+
+    // The socket goes away with the app, and the next one listens on it again.
+    h.restart();
+    let mut rpc = RpcConnection::connect(&h);
+    let chats = rpc.call(&mut h, "chats", json!({}));
+    assert_eq!(chats["result"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn subscribers_hear_about_messages_and_events_and_chats_can_be_marked_read() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("B0", BOB, BOB, 1_000, "from before"));
+    h.pump();
+    let mut rpc = RpcConnection::connect(&h);
+    assert_eq!(rpc.call(&mut h, "subscribe", json!({}))["result"], true);
+
+    h.backend
+        .receive_message(text_message("B1", BOB, BOB, 2_000, "deploy?"));
+    h.backend
+        .receive_message(text_message("B2", BOB, BOB, 2_001, "anyone?"));
+    let notification = rpc.next_notification(&mut h);
+    assert_eq!(notification["method"], "message");
+    assert_eq!(notification["params"]["id"], "B1");
+    assert_eq!(notification["params"]["sender_name"], "Bob");
+    assert_eq!(rpc.next_notification(&mut h)["params"]["text"], "anyone?");
+
+    h.backend.emit_event(wr::Event::Reaction(wr::Reaction {
+        chat: jid(BOB),
+        sender: jid(BOB),
+        message_id: "B1".into(),
+        emoji: "👍".into(),
+        timestamp: 2_002,
+        is_from_me: false,
+    }));
+    let notification = rpc.next_notification(&mut h);
+    assert_eq!(notification["method"], "event");
+    assert_eq!(notification["params"]["type"], "reaction");
+    assert_eq!(notification["params"]["emoji"], "👍");
+
+    // Only what came in since we last wrote is marked read.
+    let mut own = text_message("M1", BOB, "0@s.whatsapp.net", 1_500, "ok");
+    own.info.is_from_me = true;
+    h.backend.sync_message(own);
+    h.pump();
+    let read = rpc.call(&mut h, "mark_read", json!({ "chat": "Bob" }));
+    assert_eq!(read["result"]["messages"], 2);
+    let backend = h.backend.clone();
+    h.wait_until(|_| !backend.read_receipts().is_empty());
+    assert_eq!(
+        h.backend.read_receipts(),
+        [(jid(BOB), jid(BOB), vec!["B1".into(), "B2".into()])]
+    );
+
+    assert_eq!(rpc.call(&mut h, "unsubscribe", json!({}))["result"], true);
+    h.backend
+        .receive_message(text_message("B3", BOB, BOB, 3_000, "fine"));
+    let response = rpc.call(&mut h, "chats", json!({}));
+    assert!(response["result"].is_array());
+    assert!(rpc.notifications_are_empty());
+}
+
+#[test]
+fn profiles_keep_their_chats_apart_and_can_be_switched() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "hello from alice"));
+    assert!(h.draw().contains("hello from alice"));
+
+    h.press(KeyCode::Char('a'), KeyModifiers::CONTROL);
+    let screen = h.draw();
+    assert!(screen.contains("Profiles"), "{screen}");
+    assert!(screen.contains("● default"), "{screen}");
+
+    // Names are directories, they cannot lead out of the profiles directory.
+    h.press(KeyCode::Char('n'), KeyModifiers::NONE);
+    h.type_str("../work");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    assert!(!h.app.should_quit);
+    assert!(h.draw().contains("not a plain directory name"));
+
+    for _ in 0.."../work".len() {
+        h.press(KeyCode::Backspace, KeyModifiers::NONE);
+    }
+    h.type_str("work");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    assert!(h.app.should_quit);
+    let work_dir = h.data_dir.path().join("profiles").join("work");
+    assert_eq!(h.app.switch_to.as_deref(), Some(work_dir.as_path()));
+
+    h.follow_profile_switch();
+    assert_eq!(h.app.rpc_socket, work_dir.join("wstui.sock"));
+    let screen = h.draw();
+    assert!(screen.contains("Contacts · work"), "{screen}");
+    assert!(!screen.contains("│Alice"), "{screen}");
+
+    h.press(KeyCode::Char('a'), KeyModifiers::CONTROL);
+    let screen = h.draw();
+    assert!(screen.contains("default"), "{screen}");
+    assert!(screen.contains("● work"), "{screen}");
+    h.press(KeyCode::Char('k'), KeyModifiers::NONE);
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    assert_eq!(h.app.switch_to.as_deref(), Some(h.data_dir.path()));
+
+    h.follow_profile_switch();
+    let screen = h.draw();
+    assert!(screen.contains("│Alice"), "{screen}");
+    assert!(!screen.contains("Contacts ·"), "{screen}");
+}
+
+#[test]
+fn switching_profiles_survives_the_backend_logging_while_the_client_is_created() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend.log_on_new_client("Client created");
+
+    h.press(KeyCode::Char('a'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Char('n'), KeyModifiers::NONE);
+    h.type_str("work");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    h.follow_profile_switch();
+
+    h.backend
+        .receive_message(text_message("A1", ALICE, ALICE, 1_000, "hello at work"));
+    let screen = h.draw();
+    assert!(screen.contains("Contacts · work"), "{screen}");
+    assert!(screen.contains("hello at work"), "{screen}");
+}
+
+#[test]
+fn configured_keys_replace_the_defaults_and_mistakes_are_shown() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "hello from alice"));
+    h.draw();
+
+    let (config, errors) = Config::parse(
+        r#"
+[keys]
+quit = "<C-w>"
+reply = "<Space>r"
+
+[theme]
+border = "nope"
+"#,
+    );
+    h.app.set_config(config, errors);
+    let screen = h.draw();
+    assert!(screen.contains("Errors in the config"), "{screen}");
+    assert!(
+        screen.contains("theme.border: \"nope\" is not a color"),
+        "{screen}"
+    );
+    h.press(KeyCode::Esc, KeyModifiers::NONE);
+    assert!(!h.draw().contains("Errors in the config"));
+
+    h.press(KeyCode::Char('l'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Char('k'), KeyModifiers::NONE);
+    h.draw();
+    h.press(KeyCode::Char('r'), KeyModifiers::NONE);
+    assert!(h.app.quoting_message.is_none());
+    h.press(KeyCode::Char(' '), KeyModifiers::NONE);
+    h.press(KeyCode::Char('r'), KeyModifiers::NONE);
+    assert!(h.app.quoting_message.is_some());
+
+    h.press(KeyCode::Char('q'), KeyModifiers::CONTROL);
+    assert!(!h.app.should_quit);
+    h.press(KeyCode::Char('w'), KeyModifiers::CONTROL);
+    assert!(h.app.should_quit);
+}
+
+#[test]
+fn popups_move_with_the_configured_keys() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "hello from alice"));
+    h.draw();
+    std::fs::create_dir_all(h.data_dir.path().join("profiles").join("work")).unwrap();
+
+    let (config, errors) = Config::parse(
+        r#"
+[keys]
+next_chat = "<C-n>"
+previous_chat = "<C-p>"
+new_profile = "+"
+next_candidate = "<C-f>"
+previous_candidate = "<C-b>"
+"#,
+    );
+    assert!(errors.is_empty(), "{errors:?}");
+    h.app.set_config(config, errors);
+
+    h.press(KeyCode::Char('a'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Char('j'), KeyModifiers::NONE);
+    assert_eq!(h.app.profile_switcher.selected, 0);
+    h.press(KeyCode::Char('n'), KeyModifiers::CONTROL);
+    assert_eq!(h.app.profile_switcher.selected, 1);
+    h.press(KeyCode::Char('p'), KeyModifiers::CONTROL);
+    assert_eq!(h.app.profile_switcher.selected, 0);
+    h.press(KeyCode::Char('n'), KeyModifiers::NONE);
+    assert!(h.app.profile_switcher.new_name.is_none());
+    h.press(KeyCode::Char('+'), KeyModifiers::NONE);
+    assert!(h.app.profile_switcher.new_name.is_some());
+    h.press(KeyCode::Esc, KeyModifiers::NONE);
+    h.press(KeyCode::Esc, KeyModifiers::NONE);
+
+    h.press(KeyCode::Char('E'), KeyModifiers::SHIFT);
+    let format = h.app.chat_export.format;
+    h.press(KeyCode::Char('n'), KeyModifiers::CONTROL);
+    assert_ne!(h.app.chat_export.format, format);
+    h.press(KeyCode::Char('p'), KeyModifiers::CONTROL);
+    assert_eq!(h.app.chat_export.format, format);
+    h.press(KeyCode::Esc, KeyModifiers::NONE);
+
+    h.type_str(":to");
+    assert!(h.app.command_line.candidates.len() > 1);
+    h.press(KeyCode::Char('n'), KeyModifiers::CONTROL);
+    assert_eq!(h.app.command_line.selected, 0);
+    h.press(KeyCode::Char('f'), KeyModifiers::CONTROL);
+    assert_eq!(h.app.command_line.selected, 1);
+    h.press(KeyCode::Char('b'), KeyModifiers::CONTROL);
+    assert_eq!(h.app.command_line.selected, 0);
+}
+
+#[test]
+fn the_command_line_runs_actions_by_a_fuzzy_name_and_takes_arguments() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "hello from alice"));
+    h.backend
+        .sync_message(text_message("B1", BOB, BOB, 2_000, "hello from bob"));
+    h.draw();
+
+    h.type_str(":tgl");
+    let screen = h.draw();
+    assert!(screen.contains(":tgl"), "{screen}");
+    assert!(screen.contains("toggle_logs"), "{screen}");
+    assert!(screen.contains("Toggle logs"), "{screen}");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    assert!(h.app.show_logs);
+    assert!(!h.draw().contains(":tgl"));
+
+    h.type_str(":goto ali");
+    assert!(h.draw().contains("Alice"));
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    assert_eq!(h.app.get_selected_chat(), Some(jid(ALICE)));
+
+    h.press(KeyCode::Char('h'), KeyModifiers::CONTROL);
+    h.type_str(":send hi there");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    h.wait_until(|app| app.outbox.is_empty());
+    let sent = h.backend.sent_messages();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].jid, jid(ALICE));
+    assert!(matches!(&sent[0].content, wr::MessageContent::Text(text) if &**text == "hi there"));
+
+    h.type_str(":mute soon");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    let screen = h.draw();
+    assert!(
+        screen.contains("\"soon\" is not a duration like 30m, 8h or 2w"),
+        "{screen}"
+    );
+    for _ in 0.."soon".len() {
+        h.press(KeyCode::Backspace, KeyModifiers::NONE);
+    }
+    h.type_str("8h");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    let muted_until = h
+        .backend
+        .get_chat_settings(&jid(ALICE))
+        .unwrap()
+        .muted_until;
+    let now = chrono::Utc::now().timestamp();
+    assert!((now + 8 * 60 * 60 - 5..=now + 8 * 60 * 60).contains(&muted_until));
+
+    h.type_str(":unmute");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    assert_eq!(
+        h.backend
+            .get_chat_settings(&jid(ALICE))
+            .unwrap()
+            .muted_until,
+        0
+    );
+
+    h.type_str(":frobnicate");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    assert!(h.draw().contains("Unknown command \"frobnicate\""));
+    h.press(KeyCode::Esc, KeyModifiers::NONE);
+    assert!(!h.draw().contains("frobnicate"));
+}
+
+#[test]
+fn the_command_line_attaches_files_and_runs_message_actions() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "hello from alice"));
+    h.draw();
+
+    let path = h.data_dir.path().join("notes.pdf");
+    std::fs::write(&path, b"%PDF").unwrap();
+    h.type_str(&format!(":attach {}", path.display()));
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    let (attached, kind) = h.app.attached_file.clone().expect("a file is attached");
+    assert_eq!(*attached, *path.canonicalize().unwrap().to_string_lossy());
+    assert!(matches!(kind, wr::FileKind::Document));
+
+    h.press(KeyCode::Char('k'), KeyModifiers::CONTROL);
+    h.type_str(":reply");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    let screen = h.draw();
+    assert!(screen.contains("No message is selected"), "{screen}");
+    h.press(KeyCode::Esc, KeyModifiers::NONE);
+
+    h.press(KeyCode::Char('k'), KeyModifiers::NONE);
+    h.draw();
+    h.type_str(":reply");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    let quoted = h
+        .app
+        .quoting_message
+        .as_ref()
+        .expect("the message is quoted");
+    assert_eq!(&*quoted.info.id, "A1");
+}
+
+#[test]
+fn pending_keys_list_what_they_can_go_on_to() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "hello from alice"));
+    h.draw();
+
+    h.press(KeyCode::Char('l'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Char('g'), KeyModifiers::NONE);
+    let screen = h.draw();
+    assert!(screen.contains("g  First message"), "{screen}");
+    assert!(screen.contains("q  Go to quoted message"), "{screen}");
+    h.press(KeyCode::Esc, KeyModifiers::NONE);
+    assert!(!h.draw().contains("First message"));
+
+    h.press(KeyCode::Char('j'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Esc, KeyModifiers::NONE);
+    h.press(KeyCode::Char(' '), KeyModifiers::NONE);
+    let screen = h.draw();
+    assert!(screen.contains("r   Cancel reply"), "{screen}");
+    assert!(screen.contains("ai  Attach image"), "{screen}");
+    h.press(KeyCode::Char('a'), KeyModifiers::NONE);
+    let screen = h.draw();
+    assert!(screen.contains("i  Attach image"), "{screen}");
+    assert!(screen.contains("r  Remove attachment"), "{screen}");
+    assert!(!screen.contains("Cancel reply"), "{screen}");
+}
+
+#[test]
+fn keys_that_start_a_longer_binding_wait_for_it_until_the_timeout() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("A1", ALICE, ALICE, 1_000, "first"));
+    h.backend
+        .sync_message(text_message("A2", ALICE, ALICE, 2_000, "second"));
+    h.draw();
+    let (config, errors) = Config::parse("[keys]\nreply = \"g\"\n\n[input]\ntimeout = 500\n");
+    assert_eq!(errors, []);
+    h.app.set_config(config, errors);
+
+    h.press(KeyCode::Char('l'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Char('k'), KeyModifiers::NONE);
+    h.draw();
+    h.press(KeyCode::Char('g'), KeyModifiers::NONE);
+    assert!(h.app.quoting_message.is_none());
+    assert!(h.draw().contains("g  First message"));
+    h.press(KeyCode::Char('g'), KeyModifiers::NONE);
+    h.draw();
+    assert!(h.app.quoting_message.is_none());
+    assert_eq!(
+        h.app.message_list_state.get_selected_message().as_deref(),
+        Some("A1")
+    );
+
+    // A key that goes nowhere runs the shorter binding first.
+    h.press(KeyCode::Char('g'), KeyModifiers::NONE);
+    h.press(KeyCode::Char('j'), KeyModifiers::NONE);
+    assert_eq!(
+        h.app.quoting_message.as_ref().map(|msg| &*msg.info.id),
+        Some("A1")
+    );
+    h.app.quoting_message = None;
+
+    h.press(KeyCode::Char('h'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Char('l'), KeyModifiers::CONTROL);
+    h.press(KeyCode::Char('g'), KeyModifiers::NONE);
+    h.wait_until(|app| app.quoting_message.is_some());
+}
+
+/// Where `text` is on the screen, as a column and row.
+fn position_of(screen: &str, text: &str) -> (u16, u16) {
+    screen
+        .lines()
+        .enumerate()
+        .find_map(|(row, line)| {
+            let byte = line.find(text)?;
+            Some((line[..byte].chars().count() as u16, row as u16))
+        })
+        .unwrap_or_else(|| panic!("{text:?} is not on the screen:\n{screen}"))
+}
+
+#[test]
+fn clicks_focus_and_select_and_the_wheel_scrolls_the_messages() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    for i in 0..20 {
+        h.backend.sync_message(text_message(
+            &format!("A{i}"),
+            ALICE,
+            ALICE,
+            1_000 + i,
+            &format!("message {i}"),
+        ));
+    }
+    h.backend
+        .sync_message(text_message("B1", BOB, BOB, 2_000, "hello from bob"));
+    let screen = h.draw();
+
+    let (column, row) = position_of(&screen, "Bob");
+    h.click(column, row);
+    let screen = h.draw();
+    assert!(screen.contains("Chat with Bob"), "{screen}");
+    assert_eq!(h.app.selected_widget, SelectedWidget::ChatList);
+
+    let (column, row) = position_of(&screen, "hello from bob");
+    h.click(column, row);
+    h.draw();
+    assert_eq!(h.app.selected_widget, SelectedWidget::MessageList);
+    assert_eq!(
+        h.app.message_list_state.get_selected_message().as_deref(),
+        Some("B1")
+    );
+
+    // A second click on the same message opens it.
+    h.click(column, row);
+    assert_eq!(h.app.selected_widget, SelectedWidget::MessageView);
+    h.press(KeyCode::Esc, KeyModifiers::NONE);
+
+    h.click(50, 25);
+    assert_eq!(h.app.selected_widget, SelectedWidget::Input);
+
+    let screen = h.draw();
+    let (column, row) = position_of(&screen, "Alice");
+    h.click(column, row);
+    let screen = h.draw();
+    assert!(screen.contains("message 19"), "{screen}");
+    assert!(!screen.contains("message 0"), "{screen}");
+
+    // The wheel only scrolls over the messages.
+    h.mouse(MouseEventKind::ScrollUp, column, row);
+    assert_eq!(h.app.message_list_state.offset, 0);
+    for _ in 0..20 {
+        h.mouse(MouseEventKind::ScrollUp, 50, 10);
+    }
+    let screen = h.draw();
+    assert!(screen.contains("message 0"), "{screen}");
+    assert!(!screen.contains("message 19"), "{screen}");
+    // Past the oldest message, it stops.
+    let top = h.app.message_list_state.offset;
+    assert!(top < 60, "{top}");
+    h.mouse(MouseEventKind::ScrollDown, 50, 10);
+    assert_eq!(h.app.message_list_state.offset, top - 3);
+}
+
+fn unread(h: &Harness, chat: &str) -> usize {
+    h.app.chats[&jid(chat)].unread
+}
+
+#[test]
+fn unread_messages_are_counted_until_the_chat_is_opened_here_or_elsewhere() {
+    let mut h = Harness::new();
+    with_contacts(&h.backend);
+    h.backend
+        .sync_message(text_message("A0", ALICE, ALICE, 1_000, "old news"));
+    let mut own = text_message("B0", BOB, "0@s.whatsapp.net", 1_001, "see you");
+    own.info.is_from_me = true;
+    h.backend.sync_message(own);
+    h.backend
+        .receive_message(text_message("A1", ALICE, ALICE, 2_000, "first new"));
+    h.backend
+        .receive_message(text_message("A2", ALICE, ALICE, 2_001, "second new"));
+    h.backend
+        .receive_message(text_message("B1", BOB, BOB, 2_002, "hi"));
+    h.pump();
+    assert_eq!((unread(&h, ALICE), unread(&h, BOB)), (2, 1));
+    let screen = h.draw();
+    assert!(
+        screen
+            .lines()
+            .any(|l| l.starts_with("│Alice") && l.contains(" 2 ││")),
+        "{screen}"
+    );
+    assert!(h.backend.read_receipts().is_empty());
+
+    h.restart();
+    assert_eq!((unread(&h, ALICE), unread(&h, BOB)), (2, 1));
+
+    h.backend.emit_event(wr::Event::ChatRead {
+        chat: jid(BOB),
+        read: true,
+        timestamp: 2_003,
+    });
+    h.pump();
+    assert_eq!(unread(&h, BOB), 0);
+    assert!(h.backend.read_receipts().is_empty());
+
+    h.press(KeyCode::Char(':'), KeyModifiers::NONE);
+    h.type_str("goto Alice");
+    h.press(KeyCode::Enter, KeyModifiers::NONE);
+    assert_eq!(unread(&h, ALICE), 0);
+    // Only the unread messages, which are sent from a worker.
+    let backend = h.backend.clone();
+    h.wait_until(|_| !backend.read_receipts().is_empty());
+    assert_eq!(
+        h.backend.read_receipts(),
+        [(jid(ALICE), jid(ALICE), vec!["A1".into(), "A2".into()])]
+    );
+    // Reads the history first.
+    h.draw();
+    h.pump();
+    let screen = h.draw();
+    let line = |text: &str| screen.lines().position(|l| l.contains(text)).unwrap();
+    assert!(
+        line("old news") < line("New messages") && line("New messages") < line("first new"),
+        "{screen}"
+    );
+    assert!(!screen.contains(" 2 ││"), "{screen}");
+
+    // What comes in while in the chat is read right away.
+    h.backend
+        .receive_message(text_message("A3", ALICE, ALICE, 3_000, "still there?"));
+    h.wait_until(|_| backend.read_receipts().len() == 2);
+    assert_eq!(unread(&h, ALICE), 0);
+    assert_eq!(
+        h.backend.read_receipts().last(),
+        Some(&(jid(ALICE), jid(ALICE), vec!["A3".into()]))
+    );
+
+    h.backend
+        .receive_message(text_message("B2", BOB, BOB, 3_001, "ping"));
+    h.pump();
+    assert_eq!(unread(&h, BOB), 1);
+    h.backend
+        .emit_event(receipt(wr::ReceiptKind::ReadSelf, BOB, BOB, "B2", 3_002));
+    h.pump();
+    assert_eq!(unread(&h, BOB), 0);
+
+    h.backend.emit_event(wr::Event::ChatRead {
+        chat: jid(BOB),
+        read: false,
+        timestamp: 3_003,
+    });
+    h.pump();
+    assert_eq!(unread(&h, BOB), 1);
+}
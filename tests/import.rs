@@ -0,0 +1,238 @@
+mod common;
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{Local, NaiveDate, TimeZone};
+use common::{database, jid};
+use whatsrust as wr;
+use wstui::export::{ExportFormat, ExportOptions, export_chat};
+use wstui::import::{DateOrder, ImportOptions, import_archive, parse_chat};
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+const ALICE: &str = "111@s.whatsapp.net";
+const GROUP: &str = "333@g.us";
+
+fn time(
+    year: i32,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+) -> chrono::NaiveDateTime {
+    NaiveDate::from_ymd_opt(year, month, day)
+        .unwrap()
+        .and_hms_opt(hour, minute, second)
+        .unwrap()
+}
+
+fn timestamp(year: i32, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> i64 {
+    Local
+        .from_local_datetime(&time(year, month, day, hour, minute, second))
+        .unwrap()
+        .timestamp()
+}
+
+/// A `_chat.txt` as WhatsApp on iOS writes it, in English (UK).
+const IOS_CHAT: &str =
+    "\u{200e}[04/03/2024, 19:58:01] Alice: \u{200e}Messages and calls are end-to-end encrypted.
+[04/03/2024, 20:00:00] Alice: see you tomorrow
+at the wall
+[05/03/2024, 09:15:30] Me: on my way \u{200e}<This message was edited>
+\u{200e}[05/03/2024, 09:16:00] Alice: \u{200e}<attached: 00000003-PHOTO-2024-03-05-09-16-00.jpg>
+the wall
+\u{200e}[05/03/2024, 09:16:05] Alice: \u{200e}<attached: 00000004-AUDIO-2024-03-05-09-16-05.opus>
+[05/03/2024, 09:17:00] Alice: \u{200e}This message was deleted.
+[05/03/2024, 09:18:00] Me: ok
+[05/03/2024, 09:18:00] Me: ok
+";
+
+fn zip_archive(path: &Path, chat: &str, files: &[(&str, &[u8])]) {
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+    zip.start_file("_chat.txt", SimpleFileOptions::default())
+        .unwrap();
+    zip.write_all(chat.as_bytes()).unwrap();
+    for (name, data) in files {
+        zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+        zip.write_all(data).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+#[test]
+fn dates_are_read_in_the_order_of_the_phone_language() {
+    // Android in English (US), with the narrow no-break space newer versions write.
+    let us = parse_chat(
+        "3/5/24, 9:15\u{202f}PM - Alice: hi\n12/31/23, 12:05 AM - Bob: happy new year\nand more",
+        None,
+    );
+    assert_eq!(us.len(), 2);
+    assert_eq!(us[0].time, time(2024, 3, 5, 21, 15, 0));
+    assert_eq!(us[0].sender.as_deref(), Some("Alice"));
+    assert_eq!(us[1].time, time(2023, 12, 31, 0, 5, 0));
+    assert_eq!(us[1].text, "happy new year\nand more");
+
+    // Android in German.
+    let de = parse_chat(
+        "05.03.24, 21:15 - Alice: hallo\n13.03.24, 08:00 - Alice: na?",
+        None,
+    );
+    assert_eq!(de[0].time, time(2024, 3, 5, 21, 15, 0));
+    assert_eq!(de[1].time, time(2024, 3, 13, 8, 0, 0));
+
+    // Nothing tells 05/03 apart, unless the order is given.
+    let ambiguous = "[05/03/2024, 09:15:30] Alice: hi";
+    assert_eq!(
+        parse_chat(ambiguous, None)[0].time,
+        time(2024, 3, 5, 9, 15, 30)
+    );
+    assert_eq!(
+        parse_chat(ambiguous, Some(DateOrder::Mdy))[0].time,
+        time(2024, 5, 3, 9, 15, 30)
+    );
+
+    // Notices have no sender.
+    let notice = parse_chat("05/03/2024, 09:00 - Alice created group \"Climbing\"", None);
+    assert_eq!(notice[0].sender, None);
+}
+
+#[test]
+fn an_ios_archive_is_imported_with_its_media() {
+    let dir = tempfile::tempdir().unwrap();
+    let archive = dir.path().join("WhatsApp Chat - Alice.zip");
+    zip_archive(
+        &archive,
+        IOS_CHAT,
+        &[("00000003-PHOTO-2024-03-05-09-16-00.jpg", b"jpeg")],
+    );
+    let media_path = dir.path().join("media");
+    let mut db = database(&dir.path().join("whatsapp.db"));
+    db.add_contact(&jid(ALICE), "Alice");
+    let options = ImportOptions {
+        chat: jid(ALICE),
+        me: None,
+        self_jid: None,
+        date_order: None,
+    };
+
+    let summary = import_archive(&db, &media_path, &archive, &options).unwrap();
+    assert_eq!(summary.messages, 7);
+    assert_eq!(summary.files, 1);
+    assert_eq!(
+        summary.missing_files,
+        ["00000004-AUDIO-2024-03-05-09-16-05.opus"]
+    );
+    assert_eq!(summary.skipped, 1);
+    db.stop();
+
+    let mut db = database(&dir.path().join("whatsapp.db"));
+    let messages = db.get_messages_between(&jid(ALICE), None, None);
+    assert_eq!(messages.len(), 7);
+    assert!(
+        db.get_chat_summaries()
+            .iter()
+            .any(|chat| chat.jid == jid(ALICE))
+    );
+
+    let first = &messages[0];
+    assert_eq!(first.info.timestamp, timestamp(2024, 3, 4, 20, 0, 0));
+    assert!(!first.info.is_from_me);
+    assert_eq!(first.info.sender, jid(ALICE));
+    assert!(
+        matches!(&first.message, wr::MessageContent::Text(t) if t.as_ref() == "see you tomorrow\nat the wall")
+    );
+
+    // "Me" is not in the contacts, so it must be the owner of the phone.
+    let edited = &messages[1];
+    assert!(edited.info.is_from_me);
+    assert_eq!(edited.info.edit_state, wr::EditState::Edited);
+    assert!(matches!(&edited.message, wr::MessageContent::Text(t) if t.as_ref() == "on my way"));
+
+    let wr::MessageContent::File(photo) = &messages[2].message else {
+        panic!("{:?}", messages[2]);
+    };
+    assert!(matches!(photo.kind, wr::FileKind::Image));
+    assert_eq!(photo.caption.as_deref(), Some("the wall"));
+    assert_eq!(
+        std::fs::read(media_path.join(photo.path.as_ref())).unwrap(),
+        b"jpeg"
+    );
+    assert!(
+        matches!(&messages[3].message, wr::MessageContent::File(f) if matches!(f.kind, wr::FileKind::Audio))
+    );
+    assert_eq!(messages[4].info.edit_state, wr::EditState::Revoked);
+    // The same text twice in the same second is still two messages.
+    assert_ne!(messages[5].info.id, messages[6].info.id);
+    // Ids come from the export alone, whatever the time zone it is imported in.
+    assert_eq!(first.info.id.as_ref(), "import-D316D50972DC9F2A-0");
+
+    // Importing again replaces the same messages.
+    import_archive(&db, &media_path, &archive, &options).unwrap();
+    db.stop();
+    let mut db = database(&dir.path().join("whatsapp.db"));
+    assert_eq!(db.get_messages_between(&jid(ALICE), None, None).len(), 7);
+    db.stop();
+}
+
+#[test]
+fn people_in_a_group_who_are_not_contacts_keep_their_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let archive = dir.path().join("_chat.txt");
+    std::fs::write(
+        &archive,
+        "05/03/2024, 09:00 - Alice: hi all
+05/03/2024, 09:01 - Dave: hi Alice
+05/03/2024, 09:02 - +44 7700 900123: who is this?
+05/03/2024, 09:03 - Me: welcome
+",
+    )
+    .unwrap();
+    let mut db = database(&dir.path().join("whatsapp.db"));
+    db.add_contact(&jid(ALICE), "Alice");
+    let options = ImportOptions {
+        chat: jid(GROUP),
+        me: Some("Me".to_string()),
+        self_jid: Some(jid("999@s.whatsapp.net")),
+        date_order: None,
+    };
+    import_archive(&db, &dir.path().join("media"), &archive, &options).unwrap();
+    db.stop();
+
+    let mut db = database(&dir.path().join("whatsapp.db"));
+    let messages = db.get_messages_between(&jid(GROUP), None, None);
+    let contacts = db.get_contacts();
+    let name = |jid: &wr::JID| {
+        contacts
+            .iter()
+            .find(|(contact, _)| contact == jid)
+            .map(|(_, name)| name.to_string())
+    };
+    assert_eq!(messages[0].info.sender, jid(ALICE));
+    assert_eq!(name(&messages[1].info.sender).as_deref(), Some("Dave"));
+    assert_eq!(messages[2].info.sender, jid("447700900123@s.whatsapp.net"));
+    assert!(messages[3].info.is_from_me);
+    assert_eq!(messages[3].info.sender, jid("999@s.whatsapp.net"));
+
+    // What wstui exports as text reads back the same.
+    let export = dir.path().join("export.txt");
+    let mut out = File::create(&export).unwrap();
+    let export_options = ExportOptions {
+        chat: jid(GROUP),
+        format: ExportFormat::Txt,
+        since: None,
+        until: None,
+        out_dir: None,
+    };
+    export_chat(&db, Path::new("media"), None, &export_options, &mut out).unwrap();
+    drop(out);
+    let text = std::fs::read_to_string(&export).unwrap();
+    let exported = parse_chat(&text, None);
+    assert_eq!(exported.len(), 4);
+    assert_eq!(exported[1].sender.as_deref(), Some("Dave"));
+    assert_eq!(exported[3].sender.as_deref(), Some("You"));
+    assert_eq!(exported[3].text, "welcome");
+    db.stop();
+}
@@ -0,0 +1,490 @@
+use std::path::Path;
+
+use rusqlite::Connection;
+use whatsrust as wr;
+use wstui::db::migrations::{
+    MIGRATIONS, MigrationError, backup_path, latest_version, migrate, user_version,
+};
+use wstui::db::{DatabaseHandler, MATCH_END, MATCH_START};
+
+fn jid(s: &str) -> wr::JID {
+    s.to_string().into()
+}
+
+/// The columns and indexes of a database, to compare databases that took different
+/// paths. The SQL text itself differs with how each table was first written.
+fn schema(db: &Connection) -> Vec<String> {
+    let mut stmt = db
+        .prepare(
+            "SELECT m.type, m.name, p.name, p.type, p.pk
+             FROM sqlite_master m JOIN pragma_table_info(m.name) p
+             UNION ALL
+             SELECT type, name, tbl_name, '', 0 FROM sqlite_master WHERE type = 'index'
+             ORDER BY 1, 2, 3",
+        )
+        .unwrap();
+    stmt.query_map([], |row| {
+        Ok(format!(
+            "{} {}.{} {} {}",
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, i64>(4)?,
+        ))
+    })
+    .unwrap()
+    .map(|column| column.unwrap())
+    .collect()
+}
+
+/// A database as written by wstui before versioning, with one message in it.
+fn legacy_database(path: &Path) -> Connection {
+    let db = Connection::open(path).unwrap();
+    db.execute_batch(
+        "CREATE TABLE chats (jid TEXT PRIMARY KEY);
+         CREATE TABLE contacts (jid TEXT PRIMARY KEY, name TEXT NOT NULL);
+         CREATE TABLE text_messages (
+             id TEXT PRIMARY KEY, chat_jid TEXT, sender_jid TEXT, timestamp INTEGER,
+             quote_id TEXT, is_from_me INTEGER, read INTEGER, message TEXT
+         );
+         CREATE TABLE file_messages (
+             id TEXT PRIMARY KEY, chat_jid TEXT, sender_jid TEXT, timestamp INTEGER,
+             quote_id TEXT, is_from_me INTEGER, read INTEGER,
+             kind INTEGER, path TEXT, file_id TEXT, caption TEXT
+         );
+         INSERT INTO chats VALUES ('111@s.whatsapp.net');
+         INSERT INTO text_messages VALUES
+             ('A1', '111@s.whatsapp.net', '111@s.whatsapp.net', 1000, NULL, 0, 1, 'hello');",
+    )
+    .unwrap();
+    db
+}
+
+fn count(db: &Connection, table: &str) -> u32 {
+    db.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+        row.get(0)
+    })
+    .unwrap()
+}
+
+#[test]
+fn a_new_database_is_created_at_the_latest_version_without_a_backup() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("whatsapp.db");
+    let mut db = Connection::open(&path).unwrap();
+
+    migrate(&mut db, &path).unwrap();
+    assert_eq!(user_version(&db).unwrap(), latest_version());
+    assert!(!backup_path(&path, 0).exists());
+
+    // Migrating again is a no-op.
+    let before = schema(&db);
+    migrate(&mut db, &path).unwrap();
+    assert_eq!(schema(&db), before);
+}
+
+#[test]
+fn an_old_database_is_backed_up_and_keeps_its_messages() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("whatsapp.db");
+    let mut db = legacy_database(&path);
+
+    migrate(&mut db, &path).unwrap();
+    assert_eq!(user_version(&db).unwrap(), latest_version());
+    assert_eq!(count(&db, "messages"), 1);
+
+    let backup = Connection::open(backup_path(&path, 0)).unwrap();
+    assert_eq!(user_version(&backup).unwrap(), 0);
+    assert_eq!(count(&backup, "text_messages"), 1);
+}
+
+#[test]
+fn a_migrated_database_matches_a_new_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let fresh_path = dir.path().join("fresh.db");
+    let mut fresh = Connection::open(&fresh_path).unwrap();
+    migrate(&mut fresh, &fresh_path).unwrap();
+
+    let path = dir.path().join("whatsapp.db");
+    let mut db = legacy_database(&path);
+    migrate(&mut db, &path).unwrap();
+
+    assert_eq!(schema(&db), schema(&fresh));
+}
+
+#[test]
+fn a_failed_migration_leaves_the_previous_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("whatsapp.db");
+    let mut db = legacy_database(&path);
+    // Takes the name of a table the first migration creates after others.
+    db.execute_batch("CREATE INDEX message_edits ON chats (jid)")
+        .unwrap();
+
+    let result = migrate(&mut db, &path);
+    assert!(
+        matches!(result, Err(MigrationError::Sqlite(_))),
+        "{result:?}"
+    );
+    assert_eq!(user_version(&db).unwrap(), 0);
+    let outbox: bool = db
+        .query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'outbox')",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert!(!outbox, "the migration was not rolled back");
+}
+
+#[test]
+fn a_database_from_a_newer_version_is_refused_and_left_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("whatsapp.db");
+    let newer = latest_version() + 1;
+    {
+        let db = legacy_database(&path);
+        db.pragma_update(None, "user_version", newer).unwrap();
+    }
+
+    let mut handler = DatabaseHandler::new(&path);
+    let result = handler.init();
+    handler.stop();
+    assert!(
+        matches!(
+            result,
+            Err(MigrationError::NewerVersion { found, supported })
+                if found == newer && supported == latest_version()
+        ),
+        "{result:?}"
+    );
+
+    let db = Connection::open(&path).unwrap();
+    assert_eq!(user_version(&db).unwrap(), newer);
+    assert_eq!(count(&db, "text_messages"), 1);
+    assert!(!backup_path(&path, newer).exists());
+}
+
+#[test]
+fn messages_are_moved_into_one_table() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("whatsapp.db");
+    {
+        // The layout of version 1, with a table per kind of content.
+        let db = legacy_database(&path);
+        db.execute_batch(MIGRATIONS[0].sql).unwrap();
+        db.execute_batch(
+            "INSERT INTO text_messages VALUES
+                 ('A2', '111@s.whatsapp.net', '0@s.whatsapp.net', 1001, 'A1', 1, 0, 'hi @222');
+             INSERT INTO message_status VALUES ('A2', 2);
+             INSERT INTO message_edits VALUES ('A2', 1);
+             INSERT INTO message_mentions VALUES ('A2', '222@s.whatsapp.net');
+             INSERT INTO file_messages VALUES
+                 ('F1', '111@s.whatsapp.net', '111@s.whatsapp.net', 1002, NULL, 0, 0,
+                  3, 'report.pdf', 'file-1', 'the report');
+             INSERT INTO group_change_messages VALUES
+                 ('G1', '333@g.us', '111@s.whatsapp.net', 1003, NULL, 0, 0,
+                  0, '222@s.whatsapp.net 444@s.whatsapp.net', '');
+             INSERT INTO outbox VALUES
+                 ('OUTBOX-1', '333@g.us', 1004, NULL, 'hey @222', NULL, NULL, 1, 0);
+             INSERT INTO message_mentions VALUES ('OUTBOX-1', '222@s.whatsapp.net');",
+        )
+        .unwrap();
+        db.pragma_update(None, "user_version", 1).unwrap();
+    }
+
+    let mut handler = DatabaseHandler::new(&path);
+    handler.init().unwrap();
+    let messages = handler.get_messages();
+    let outbox = handler.get_outbox();
+    handler.stop();
+
+    let ids = messages
+        .iter()
+        .map(|m| m.info.id.as_ref())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, ["A1", "A2", "F1", "G1"]);
+
+    let [read, edited, file, change] = &messages[..] else {
+        unreachable!()
+    };
+    // Written before delivery states, and marked as read.
+    assert_eq!(read.info.delivery, wr::DeliveryState::Read);
+    assert!(matches!(&read.message, wr::MessageContent::Text(t) if t.as_ref() == "hello"));
+
+    assert_eq!(edited.info.delivery, wr::DeliveryState::Delivered);
+    assert_eq!(edited.info.edit_state, wr::EditState::Edited);
+    assert_eq!(edited.info.quote_id.as_deref(), Some("A1"));
+    assert!(edited.info.is_from_me);
+    assert_eq!(edited.info.mentions, [jid("222@s.whatsapp.net")]);
+
+    let wr::MessageContent::File(file) = &file.message else {
+        panic!("{file:?}");
+    };
+    assert!(matches!(file.kind, wr::FileKind::Document));
+    assert_eq!(file.path.as_ref(), "report.pdf");
+    assert_eq!(file.file_id.as_ref(), "file-1");
+    assert_eq!(file.caption.as_deref(), Some("the report"));
+
+    let wr::MessageContent::GroupChange(change) = &change.message else {
+        panic!("{change:?}");
+    };
+    assert_eq!(change.kind, wr::GroupChangeKind::Join);
+    assert_eq!(
+        change.participants,
+        [jid("222@s.whatsapp.net"), jid("444@s.whatsapp.net")]
+    );
+
+    assert_eq!(outbox.len(), 1);
+    assert_eq!(outbox[0].mentions, [jid("222@s.whatsapp.net")]);
+
+    let db = Connection::open(&path).unwrap();
+    let old_tables: u32 = db
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE name IN
+                 ('text_messages', 'file_messages', 'group_change_messages',
+                  'message_status', 'message_edits', 'message_mentions')",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(old_tables, 0);
+}
+
+#[test]
+fn saved_messages_read_back_the_same() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("whatsapp.db");
+    let info = |id: &str, timestamp| wr::MessageInfo {
+        id: id.into(),
+        chat: jid("333@g.us"),
+        sender: jid("111@s.whatsapp.net"),
+        timestamp,
+        is_from_me: false,
+        quote_id: None,
+        delivery: wr::DeliveryState::Delivered,
+        edit_state: wr::EditState::Original,
+        mentions: Vec::new(),
+    };
+    let messages = [
+        wr::Message {
+            info: wr::MessageInfo {
+                mentions: vec![jid("222@s.whatsapp.net")],
+                ..info("T1", 1000)
+            },
+            message: wr::MessageContent::Text("hi @222".into()),
+        },
+        wr::Message {
+            info: info("F1", 1001),
+            message: wr::MessageContent::File(wr::FileContent {
+                kind: wr::FileKind::Image,
+                path: "cat.jpg".into(),
+                file_id: "file-1".into(),
+                caption: None,
+            }),
+        },
+        wr::Message {
+            info: info("G1", 1002),
+            message: wr::MessageContent::GroupChange(wr::GroupChange {
+                kind: wr::GroupChangeKind::Subject,
+                participants: Vec::new(),
+                text: "Climbing".into(),
+            }),
+        },
+    ];
+
+    let mut handler = DatabaseHandler::new(&path);
+    handler.init().unwrap();
+    for message in &messages {
+        handler.add_message(message);
+    }
+    handler.stop();
+
+    let mut handler = DatabaseHandler::new(&path);
+    handler.init().unwrap();
+    let saved = handler.get_messages();
+    handler.stop();
+    assert_eq!(format!("{saved:?}"), format!("{messages:?}"));
+}
+
+#[test]
+fn history_is_read_in_pages_newest_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("whatsapp.db");
+    let message = |id: &str, chat: &str, timestamp| wr::Message {
+        info: wr::MessageInfo {
+            id: id.into(),
+            chat: jid(chat),
+            sender: jid(chat),
+            timestamp,
+            is_from_me: false,
+            quote_id: None,
+            delivery: wr::DeliveryState::Delivered,
+            edit_state: wr::EditState::Original,
+            mentions: Vec::new(),
+        },
+        message: wr::MessageContent::Text(id.into()),
+    };
+
+    let mut handler = DatabaseHandler::new(&path);
+    handler.init().unwrap();
+    handler.add_chat(&wstui::app::Chat {
+        jid: jid("111@s.whatsapp.net"),
+        last_message_time: None,
+        unread: 0,
+    });
+    handler.add_chat(&wstui::app::Chat {
+        jid: jid("222@s.whatsapp.net"),
+        last_message_time: None,
+        unread: 0,
+    });
+    // Messages sent in the same second are told apart by their id.
+    for (id, timestamp) in [("A1", 1000), ("A2", 1001), ("A3", 1001), ("A4", 1002)] {
+        handler.add_message(&message(id, "111@s.whatsapp.net", timestamp));
+    }
+    handler.add_message(&message("B1", "333@s.whatsapp.net", 1003));
+    handler.stop();
+
+    let mut handler = DatabaseHandler::new(&path);
+    handler.init().unwrap();
+    let ids = |messages: &[wr::Message]| {
+        messages
+            .iter()
+            .map(|m| m.info.id.to_string())
+            .collect::<Vec<_>>()
+    };
+    let chat = jid("111@s.whatsapp.net");
+    let latest = handler.get_messages_before(&chat, None, 2);
+    assert_eq!(ids(&latest), ["A3", "A4"]);
+    let cursor = (latest[0].info.timestamp, latest[0].info.id.clone());
+    let older = handler.get_messages_before(&chat, Some(&cursor), 2);
+    assert_eq!(ids(&older), ["A1", "A2"]);
+    let cursor = (older[0].info.timestamp, older[0].info.id.clone());
+    assert!(
+        handler
+            .get_messages_before(&chat, Some(&cursor), 2)
+            .is_empty()
+    );
+
+    assert_eq!(
+        handler.get_message(&"A2".into()).unwrap().info.timestamp,
+        1001
+    );
+    assert!(handler.get_message(&"Z9".into()).is_none());
+
+    let mut summaries = handler
+        .get_chat_summaries()
+        .into_iter()
+        .map(|chat| (chat.jid.0.to_string(), chat.last_message_time))
+        .collect::<Vec<_>>();
+    summaries.sort();
+    handler.stop();
+    assert_eq!(
+        summaries,
+        [
+            ("111@s.whatsapp.net".to_string(), Some(1002)),
+            ("222@s.whatsapp.net".to_string(), None),
+        ]
+    );
+}
+
+#[test]
+fn the_search_index_follows_edits_and_revokes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("whatsapp.db");
+    // Messages from before the index are indexed by the migration.
+    drop(legacy_database(&path));
+    let text = |id: &str, text: &str, edit_state| wr::Message {
+        info: wr::MessageInfo {
+            id: id.into(),
+            chat: jid("111@s.whatsapp.net"),
+            sender: jid("111@s.whatsapp.net"),
+            timestamp: 2000,
+            is_from_me: false,
+            quote_id: None,
+            delivery: wr::DeliveryState::Delivered,
+            edit_state,
+            mentions: Vec::new(),
+        },
+        message: wr::MessageContent::Text(text.into()),
+    };
+
+    let mut handler = DatabaseHandler::new(&path);
+    handler.init().unwrap();
+    handler.add_message(&text("A2", "see you at five", wr::EditState::Original));
+    handler.add_message(&text("A3", "bring the cake", wr::EditState::Original));
+    handler.add_message(&wr::Message {
+        info: text("F1", "", wr::EditState::Original).info,
+        message: wr::MessageContent::File(wr::FileContent {
+            kind: wr::FileKind::Image,
+            path: "cake.jpg".into(),
+            file_id: "file-1".into(),
+            caption: Some("the cake".into()),
+        }),
+    });
+    handler.add_message(&text("A2", "see you at six", wr::EditState::Edited));
+    handler.add_message(&text("A3", "", wr::EditState::Revoked));
+    handler.stop();
+
+    let mut handler = DatabaseHandler::new(&path);
+    handler.init().unwrap();
+    let ids = |query: &str| {
+        handler
+            .search_messages(query, 10)
+            .iter()
+            .map(|hit| hit.message.info.id.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ids("hello"), ["A1"]);
+    assert!(ids("five").is_empty());
+    assert_eq!(ids("six"), ["A2"]);
+    assert_eq!(ids("cake"), ["F1"]);
+    assert_eq!(ids("see yo"), ["A2"]);
+    // FTS5 syntax is taken literally.
+    assert!(ids("\"six OR").is_empty());
+    assert!(ids("  ").is_empty());
+
+    let hits = handler.search_messages("six", 10);
+    handler.stop();
+    assert_eq!(
+        hits[0].snippet,
+        format!("see you at {MATCH_START}six{MATCH_END}")
+    );
+}
+
+#[test]
+fn unread_counts_survive_chats_saved_again() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("whatsapp.db");
+    let chat = |jid_str: &str| wstui::app::Chat {
+        jid: jid(jid_str),
+        last_message_time: None,
+        unread: 0,
+    };
+
+    let mut handler = DatabaseHandler::new(&path);
+    handler.init().unwrap();
+    // Counted before the chat itself is written.
+    handler.set_unread(&jid("111@s.whatsapp.net"), 3);
+    handler.add_chat(&chat("111@s.whatsapp.net"));
+    handler.add_chat(&chat("222@s.whatsapp.net"));
+    handler.stop();
+
+    let mut handler = DatabaseHandler::new(&path);
+    handler.init().unwrap();
+    let mut unread = handler
+        .get_chat_summaries()
+        .into_iter()
+        .map(|chat| (chat.jid.0.to_string(), chat.unread))
+        .collect::<Vec<_>>();
+    unread.sort();
+    handler.stop();
+    assert_eq!(
+        unread,
+        [
+            ("111@s.whatsapp.net".to_string(), 3),
+            ("222@s.whatsapp.net".to_string(), 0)
+        ]
+    );
+}
@@ -3,6 +3,7 @@ package main
 import (
 	"context"
 	"encoding/json"
+	"fmt"
 	"os"
 	"path"
 
@@ -93,12 +94,6 @@ func DownloadableMessageToFileId(client *whatsmeow.Client, msg whatsmeow.Downloa
 	return str
 }
 
-const (
-	FileStatusNone = iota - 1
-	FileStatusDownloaded
-	FileStatusDownloadFailed
-)
-
 // TODO: Implement URL download
 func DownloadFromFileInfo(client *whatsmeow.Client, info DownloadInfo) ([]byte, error) {
 	return client.DownloadMediaWithPath(context.Background(), info.DirectPath, info.FileEncSha256, info.FileSha256, info.MediaKey, info.Size, info.MediaType, mediaTypeToMMSType[info.MediaType])
@@ -110,43 +105,33 @@ func FileIdToDownloadInfo(fileId string) (DownloadInfo, error) {
 		return info, err
 	}
 	if info.Version != downloadInfoVersion {
-		return info, err
+		return info, fmt.Errorf("unsupported file id version %d", info.Version)
 	}
 	return info, nil
 }
-func DownloadFromFileId(client *whatsmeow.Client, fileId string, basePath string) int {
+
+// DownloadFromFileId downloads the file to its target path under basePath, unless
+// it is already there. Errors are tagged with ErrorCodeDownloadFailed.
+func DownloadFromFileId(client *whatsmeow.Client, fileId string, basePath string) error {
 	info, err := FileIdToDownloadInfo(fileId)
 	if err != nil {
-		return FileStatusDownloadFailed
+		return withCode(ErrorCodeDownloadFailed, fmt.Errorf("invalid file id: %w", err))
 	}
 
 	targetPath := path.Join(basePath, info.TargetPath)
-	fileStatus := FileStatusNone
-
-	os.MkdirAll(path.Dir(targetPath), os.ModePerm)
-
-	// download if not yet present
-	if _, statErr := os.Stat(targetPath); os.IsNotExist(statErr) {
-		data, err := DownloadFromFileInfo(client, info)
-		if err != nil {
-			fileStatus = FileStatusDownloadFailed
-		} else {
-			file, err := os.Create(targetPath)
-			defer file.Close()
-			if err != nil {
-				fileStatus = FileStatusDownloadFailed
-			} else {
-				_, err = file.Write(data)
-				if err != nil {
-					fileStatus = FileStatusDownloadFailed
-				} else {
-					fileStatus = FileStatusDownloaded
-				}
-			}
-		}
-	} else {
-		fileStatus = FileStatusDownloaded
+	if _, statErr := os.Stat(targetPath); !os.IsNotExist(statErr) {
+		return nil
 	}
 
-	return fileStatus
+	if err := os.MkdirAll(path.Dir(targetPath), os.ModePerm); err != nil {
+		return withCode(ErrorCodeDownloadFailed, err)
+	}
+	data, err := DownloadFromFileInfo(client, info)
+	if err != nil {
+		return withCode(ErrorCodeDownloadFailed, err)
+	}
+	if err := os.WriteFile(targetPath, data, 0o644); err != nil {
+		return withCode(ErrorCodeDownloadFailed, err)
+	}
+	return nil
 }
@@ -5,74 +5,8 @@ package main
 #include <stdint.h>
 #include <stdbool.h>
 #include <stdio.h>
+#include "whatsrust.h"
 
-typedef const char* JID;
-
-typedef struct {
-	bool found;
-	const char* first_name;
-	const char* full_name;
-	const char* push_name;
-	const char* business_name;
-} Contact;
-
-typedef struct {
-	JID jid;
-	const char* name;
-} ContactEntry;
-
-typedef struct {
-	bool found;
-	int64_t muted_until;
-	bool pinned;
-	bool archived;
-} ChatSettings;
-
-typedef struct {
-	ContactEntry* entries;
-	uint32_t size;
-} GetContactsResult;
-
-typedef struct {
-	char* id;
-	JID chat;
-	JID sender;
-	int64_t timestamp;
-	bool isFromMe;
-	char* quoteID;
-	uint16_t readBy;
-} MessageInfo;
-
-typedef struct {
-	char* text;
-} TextMessage;
-
-typedef struct {
-	uint8_t kind;
-	char* path;
-	char* fileID;
-	char* caption;
-} FileMessage;
-
-typedef struct {
-	MessageInfo info;
-	uint8_t messageType;
-	void* message;
-} Message;
-
-typedef struct {
-	uint8_t kind;
-	JID id;
-	char* const* messageIDs;
-	size_t size;
-} ReceiptEvent;
-
-typedef struct {
-	uint8_t kind;
-	void* data;
-} Event;
-
-typedef void (*EventCallback)(const Event*, void*);
 typedef struct {
 	EventCallback callback;
 	void* user_data;
@@ -81,12 +15,10 @@ static void callEventCallback(EventHandler hdl, const Event* event) {
 	hdl.callback(event, hdl.user_data);
 }
 
-typedef void (*QrCallback)(const char*, void*);
 static void callQrCallback(QrCallback cb, const char* code, void* user_data) {
 	cb(code, user_data);
 }
 
-typedef void (*MessageHandlerCallback)(const Message*, bool, void*);
 typedef struct {
 	MessageHandlerCallback callback;
 	void* user_data;
@@ -104,7 +36,6 @@ static void callHistorySync(HistorySyncHandler hdl, uint32_t percent) {
 	hdl.callback(percent, hdl.user_data);
 }
 
-typedef void (*LogHandlerCallback)(const char*, uint8_t, void*);
 typedef struct {
 	LogHandlerCallback callback;
 	void* user_data;
@@ -116,6 +47,7 @@ static void callLogInfo(LogHandler hdl, const char* msg, uint8_t level) {
 import "C"
 import (
 	"context"
+	"errors"
 	"fmt"
 	"math"
 	"mime"
@@ -123,6 +55,7 @@ import (
 	"path/filepath"
 	"slices"
 	"sort"
+	"strings"
 	"time"
 	"unsafe"
 
@@ -243,6 +176,57 @@ func StrFromJid(jid types.JID) string {
 	return jid.User + "@" + jid.Server
 }
 
+// codedError attaches one of the ErrorCode* constants to an error that whatsmeow
+// does not classify itself.
+type codedError struct {
+	code int
+	err  error
+}
+
+func (e *codedError) Error() string { return e.err.Error() }
+func (e *codedError) Unwrap() error { return e.err }
+
+func withCode(code int, err error) error {
+	return &codedError{code: code, err: err}
+}
+
+func errorCode(err error) int {
+	var coded *codedError
+	switch {
+	case errors.Is(err, whatsmeow.ErrNotConnected):
+		return ErrorCodeNotConnected
+	case errors.Is(err, whatsmeow.ErrNotLoggedIn), errors.Is(err, whatsmeow.ErrClientIsNil):
+		return ErrorCodeNotLoggedIn
+	case errors.Is(err, whatsmeow.ErrIQTimedOut), errors.Is(err, context.DeadlineExceeded):
+		return ErrorCodeTimeout
+	case errors.Is(err, whatsmeow.ErrMediaDownloadFailedWith404), errors.Is(err, whatsmeow.ErrMediaDownloadFailedWith410):
+		return ErrorCodeMediaExpired
+	case errors.As(err, &coded):
+		return coded.code
+	default:
+		return ErrorCodeOther
+	}
+}
+
+// errorToC converts err for the Rust side. A nil err becomes ErrorCodeNone.
+func errorToC(err error) C.Error {
+	if err == nil {
+		return C.Error{code: ErrorCodeNone, message: nil}
+	}
+	return C.Error{code: C.uint8_t(errorCode(err)), message: C.CString(err.Error())}
+}
+
+// checkClient fails when there is no client to talk to, or it was never paired.
+func checkClient() error {
+	if client == nil {
+		return withCode(ErrorCodeNotConnected, errors.New("client was not created"))
+	}
+	if client.Store.ID == nil {
+		return whatsmeow.ErrNotLoggedIn
+	}
+	return nil
+}
+
 // Convert Go JID to C JID
 func jidToC(jid types.JID) C.JID {
 	return C.CString(jid.ToNonAD().String())
@@ -250,12 +234,13 @@ func jidToC(jid types.JID) C.JID {
 }
 
 // Convert C JID to Go JID
-func cToJid(cjid C.JID) types.JID {
-	jid, err := types.ParseJID(C.GoString(cjid))
+func cToJid(cjid C.JID) (types.JID, error) {
+	str := C.GoString(cjid)
+	jid, err := types.ParseJID(str)
 	if err != nil {
-		panic(err)
+		return jid, withCode(ErrorCodeInvalidJid, fmt.Errorf("%s: %w", str, err))
 	}
-	return jid
+	return jid, nil
 }
 
 // contactDisplayName returns the display name for a contact (same order as Rust get_contact_name).
@@ -300,16 +285,20 @@ func C_SetMessageHandler(callback C.MessageHandlerCallback, data unsafe.Pointer)
 }
 
 //export C_NewClient
-func C_NewClient(dbPath *C.char) {
+func C_NewClient(dbPath *C.char) C.Error {
 	goPath := C.GoString(dbPath)
 	dbLog := &WrLogger{}
 	container, err := sqlstore.New(context.Background(), "sqlite3", "file:"+goPath+"?_foreign_keys=on", dbLog)
 	if err != nil {
-		panic(err)
+		return errorToC(fmt.Errorf("open %s: %w", goPath, err))
+	}
+	deviceStore, err := container.GetFirstDevice(context.Background())
+	if err != nil {
+		return errorToC(fmt.Errorf("load device: %w", err))
 	}
-	deviceStore, _ := container.GetFirstDevice(context.Background())
 	clientLog := &WrLogger{}
 	client = whatsmeow.NewClient(deviceStore, clientLog)
+	return errorToC(nil)
 }
 
 func ParseWebMessageInfo(selfJid types.JID, chatJid types.JID, webMsg *waWeb.WebMessageInfo) *types.MessageInfo {
@@ -365,6 +354,50 @@ const (
 	EventTypeSyncProgress = iota
 	EventTypeAppStateSyncComplete
 	EventTypeReceipt
+	EventTypeReaction
+	EventTypeMessageEdit
+	EventTypeMessageRevoke
+	EventTypeGroupChange
+	EventTypeChatRead
+)
+
+const (
+	MessageStatusPending = iota
+	MessageStatusSent
+	MessageStatusDelivered
+	MessageStatusRead
+	MessageStatusPlayed
+	MessageStatusFailed
+)
+
+const (
+	ReceiptKindDelivered = iota
+	ReceiptKindRead
+	ReceiptKindReadSelf
+	ReceiptKindPlayed
+	ReceiptKindPlayedSelf
+	ReceiptKindServerError
+)
+
+const (
+	GroupChangeJoin = iota
+	GroupChangeLeave
+	GroupChangePromote
+	GroupChangeDemote
+	GroupChangeSubject
+	GroupChangeDescription
+)
+
+const (
+	ErrorCodeNone = iota
+	ErrorCodeNotConnected
+	ErrorCodeNotLoggedIn
+	ErrorCodeInvalidJid
+	ErrorCodeUploadFailed
+	ErrorCodeDownloadFailed
+	ErrorCodeMediaExpired
+	ErrorCodeTimeout
+	ErrorCodeOther
 )
 
 const (
@@ -380,7 +413,7 @@ const (
 	FileTypeSticker
 )
 
-func ContentToWaE2EMessage(messageType C.uint8_t, messageContent unsafe.Pointer, contextInfo *waE2E.ContextInfo) *waE2E.Message {
+func ContentToWaE2EMessage(messageType C.uint8_t, messageContent unsafe.Pointer, contextInfo *waE2E.ContextInfo) (*waE2E.Message, error) {
 	switch messageType {
 	case C.uint8_t(MessageTypeText):
 		textMsg := (*C.TextMessage)(messageContent)
@@ -390,7 +423,7 @@ func ContentToWaE2EMessage(messageType C.uint8_t, messageContent unsafe.Pointer,
 				Text:        &text,
 				ContextInfo: contextInfo,
 			},
-		}
+		}, nil
 
 	case C.uint8_t(MessageTypeFile):
 		fileMsg := (*C.FileMessage)(messageContent)
@@ -399,7 +432,7 @@ func ContentToWaE2EMessage(messageType C.uint8_t, messageContent unsafe.Pointer,
 		filePath := C.GoString(fileMsg.path)
 		data, err := os.ReadFile(filePath)
 		if err != nil {
-			panic(fmt.Sprintf("read file %s err %#v", filePath, err))
+			return nil, withCode(ErrorCodeUploadFailed, fmt.Errorf("read file %s: %w", filePath, err))
 		}
 		mimetype := mime.TypeByExtension(filepath.Ext(filePath))
 
@@ -407,7 +440,7 @@ func ContentToWaE2EMessage(messageType C.uint8_t, messageContent unsafe.Pointer,
 		case FileTypeImage:
 			uploaded, upErr := client.Upload(context.Background(), data, whatsmeow.MediaImage)
 			if upErr != nil {
-				panic(fmt.Sprintf("upload error %#v", upErr))
+				return nil, withCode(ErrorCodeUploadFailed, fmt.Errorf("upload %s: %w", filePath, upErr))
 			}
 			return &waE2E.Message{
 				ImageMessage: &waE2E.ImageMessage{
@@ -421,11 +454,11 @@ func ContentToWaE2EMessage(messageType C.uint8_t, messageContent unsafe.Pointer,
 					FileLength:    proto.Uint64(uint64(len(data))),
 					ContextInfo:   contextInfo,
 				},
-			}
+			}, nil
 		case FileTypeDocument:
 			uploaded, upErr := client.Upload(context.Background(), data, whatsmeow.MediaDocument)
 			if upErr != nil {
-				panic(fmt.Sprintf("upload error %#v", upErr))
+				return nil, withCode(ErrorCodeUploadFailed, fmt.Errorf("upload %s: %w", filePath, upErr))
 			}
 			fileName := filepath.Base(filePath)
 			return &waE2E.Message{
@@ -441,28 +474,106 @@ func ContentToWaE2EMessage(messageType C.uint8_t, messageContent unsafe.Pointer,
 					FileName:      proto.String(fileName),
 					ContextInfo:   contextInfo,
 				},
-			}
+			}, nil
 		default:
-			panic(fmt.Sprintf("Unsupported file type: %v", kind))
+			return nil, fmt.Errorf("unsupported file type: %v", kind)
 		}
 
 	default:
-		panic(fmt.Sprintf("Unsupported message type: %d", messageType))
+		return nil, fmt.Errorf("unsupported message type: %d", messageType)
 	}
 }
 
-func HandleMessage(info types.MessageInfo, msg *waE2E.Message, isSync bool) {
-	// Normalize chat and sender ids (LID→PN, broadcast→per-sender) so Rust sees canonical ids.
-	if normalizedChat := GetChatId(client, &info.Chat, &info.Sender); normalizedChat != "" {
+// NormalizeIds rewrites chat and sender ids (LID→PN, broadcast→per-sender) so Rust sees canonical ids.
+func NormalizeIds(chat *types.JID, sender *types.JID) {
+	if normalizedChat := GetChatId(client, chat, sender); normalizedChat != "" {
 		if jid, err := types.ParseJID(normalizedChat); err == nil {
-			info.Chat = jid
+			*chat = jid
 		}
 	}
-	if normalizedSender := GetUserId(client, &info.Chat, &info.Sender); normalizedSender != "" {
+	if normalizedSender := GetUserId(client, chat, sender); normalizedSender != "" {
 		if jid, err := types.ParseJID(normalizedSender); err == nil {
-			info.Sender = jid
+			*sender = jid
 		}
 	}
+}
+
+// WebMessageStatus maps the status stored in history syncs to a MessageStatus.
+func WebMessageStatus(webMsg *waWeb.WebMessageInfo) int {
+	switch webMsg.GetStatus() {
+	case waWeb.WebMessageInfo_ERROR:
+		return MessageStatusFailed
+	case waWeb.WebMessageInfo_PENDING:
+		return MessageStatusPending
+	case waWeb.WebMessageInfo_DELIVERY_ACK:
+		return MessageStatusDelivered
+	case waWeb.WebMessageInfo_READ:
+		return MessageStatusRead
+	case waWeb.WebMessageInfo_PLAYED:
+		return MessageStatusPlayed
+	default:
+		return MessageStatusSent
+	}
+}
+
+// contextInfoOf returns the ContextInfo of the content of msg, nil if it has none.
+func contextInfoOf(msg *waE2E.Message) *waE2E.ContextInfo {
+	switch {
+	case msg.ExtendedTextMessage != nil:
+		return msg.GetExtendedTextMessage().GetContextInfo()
+	case msg.ImageMessage != nil:
+		return msg.GetImageMessage().GetContextInfo()
+	case msg.VideoMessage != nil:
+		return msg.GetVideoMessage().GetContextInfo()
+	case msg.AudioMessage != nil:
+		return msg.GetAudioMessage().GetContextInfo()
+	case msg.DocumentMessage != nil:
+		return msg.GetDocumentMessage().GetContextInfo()
+	case msg.StickerMessage != nil:
+		return msg.GetStickerMessage().GetContextInfo()
+	}
+	return nil
+}
+
+// normalizeMentions maps mentioned LIDs to phone numbers, like NormalizeIds does for
+// senders. The text refers to each mention as @user, so the returned replacer rewrites
+// the text to match.
+func normalizeMentions(mentioned []string) ([]types.JID, *strings.Replacer) {
+	var jids []types.JID
+	var replacements []string
+	for _, str := range mentioned {
+		jid, err := types.ParseJID(str)
+		if err != nil {
+			LOG_WARN("Ignoring invalid mention %q: %v", str, err)
+			continue
+		}
+		if normalized := GetUserId(client, nil, &jid); normalized != "" {
+			if pn, err := types.ParseJID(normalized); err == nil && pn.User != jid.User {
+				replacements = append(replacements, "@"+jid.User, "@"+pn.User)
+				jid = pn
+			}
+		}
+		jids = append(jids, jid)
+	}
+	return jids, strings.NewReplacer(replacements...)
+}
+
+func HandleMessage(info types.MessageInfo, msg *waE2E.Message, status int, isSync bool) {
+	NormalizeIds(&info.Chat, &info.Sender)
+
+	if msg.ReactionMessage != nil {
+		HandleReaction(info, msg.GetReactionMessage())
+		return
+	}
+
+	// Edits we send are wrapped in an EditedMessage, received ones are already unwrapped.
+	if edited := msg.GetEditedMessage().GetMessage(); edited != nil {
+		msg = edited
+	}
+	if msg.ProtocolMessage != nil {
+		HandleProtocolMessage(info, msg.GetProtocolMessage())
+		return
+	}
 
 	chat := info.Chat
 	sender := info.Sender
@@ -475,7 +586,29 @@ func HandleMessage(info types.MessageInfo, msg *waE2E.Message, isSync bool) {
 		timestamp: C.int64_t(timestamp),
 		isFromMe:  C.bool(info.IsFromMe),
 		quoteID:   nil,
-		readBy:    C.uint16_t(0),
+		status:    C.uint8_t(status),
+	}
+	// quoteID is set below, so free whatever it ends up pointing to.
+	defer func() {
+		C.free(unsafe.Pointer(cinfo.id))
+		C.free(unsafe.Pointer(cinfo.chat))
+		C.free(unsafe.Pointer(cinfo.sender))
+		C.free(unsafe.Pointer(cinfo.quoteID))
+		for _, mention := range unsafe.Slice(cinfo.mentions, int(cinfo.mentionCount)) {
+			C.free(unsafe.Pointer(mention))
+		}
+		C.free(unsafe.Pointer(cinfo.mentions))
+	}()
+
+	mentions, mentionText := normalizeMentions(contextInfoOf(msg).GetMentionedJID())
+	if n := len(mentions); n > 0 {
+		cmentions := (*C.JID)(C.malloc(C.size_t(n) * C.size_t(unsafe.Sizeof(uintptr(0)))))
+		mentionList := unsafe.Slice(cmentions, n)
+		for i, jid := range mentions {
+			mentionList[i] = jidToC(jid)
+		}
+		cinfo.mentions = cmentions
+		cinfo.mentionCount = C.size_t(n)
 	}
 
 	if msg.Conversation != nil {
@@ -497,7 +630,7 @@ func HandleMessage(info types.MessageInfo, msg *waE2E.Message, isSync bool) {
 	if msg.ExtendedTextMessage != nil {
 		ext_msg := msg.GetExtendedTextMessage()
 
-		text := ext_msg.GetText()
+		text := mentionText.Replace(ext_msg.GetText())
 		ctext := C.CString(text)
 		defer C.free(unsafe.Pointer(ctext))
 
@@ -529,7 +662,7 @@ func HandleMessage(info types.MessageInfo, msg *waE2E.Message, isSync bool) {
 		}
 
 		ext := ExtensionByType(img.GetMimetype(), ".jpg")
-		caption := img.GetCaption()
+		caption := mentionText.Replace(img.GetCaption())
 
 		context_info := img.GetContextInfo()
 		if context_info != nil {
@@ -549,9 +682,9 @@ func HandleMessage(info types.MessageInfo, msg *waE2E.Message, isSync bool) {
 		defer C.free(unsafe.Pointer(cpath))
 
 		// set caption or nil
-		ccaption := C.CString(caption)
-		if caption == "" {
-			ccaption = nil
+		var ccaption *C.char
+		if caption != "" {
+			ccaption = C.CString(caption)
 		}
 		defer C.free(unsafe.Pointer(ccaption))
 
@@ -577,7 +710,7 @@ func HandleMessage(info types.MessageInfo, msg *waE2E.Message, isSync bool) {
 		}
 
 		ext := ExtensionByType(vid.GetMimetype(), ".mp4")
-		caption := vid.GetCaption()
+		caption := mentionText.Replace(vid.GetCaption())
 
 		context_info := vid.GetContextInfo()
 		if context_info != nil {
@@ -595,9 +728,9 @@ func HandleMessage(info types.MessageInfo, msg *waE2E.Message, isSync bool) {
 		cpath := C.CString(filePath)
 		defer C.free(unsafe.Pointer(cpath))
 
-		ccaption := C.CString(caption)
-		if caption == "" {
-			ccaption = nil
+		var ccaption *C.char
+		if caption != "" {
+			ccaption = C.CString(caption)
 		}
 		defer C.free(unsafe.Pointer(ccaption))
 
@@ -660,7 +793,7 @@ func HandleMessage(info types.MessageInfo, msg *waE2E.Message, isSync bool) {
 			return
 		}
 
-		caption := doc.GetCaption()
+		caption := mentionText.Replace(doc.GetCaption())
 
 		context_info := doc.GetContextInfo()
 		if context_info != nil {
@@ -678,9 +811,9 @@ func HandleMessage(info types.MessageInfo, msg *waE2E.Message, isSync bool) {
 		cpath := C.CString(filePath)
 		defer C.free(unsafe.Pointer(cpath))
 
-		ccaption := C.CString(caption)
-		if caption == "" {
-			ccaption = nil
+		var ccaption *C.char
+		if caption != "" {
+			ccaption = C.CString(caption)
 		}
 		defer C.free(unsafe.Pointer(ccaption))
 
@@ -739,12 +872,240 @@ func HandleMessage(info types.MessageInfo, msg *waE2E.Message, isSync bool) {
 	}
 }
 
+func HandleReaction(info types.MessageInfo, reaction *waE2E.ReactionMessage) {
+	cchat := jidToC(info.Chat)
+	defer C.free(unsafe.Pointer(cchat))
+	csender := jidToC(info.Sender)
+	defer C.free(unsafe.Pointer(csender))
+	cmessageId := C.CString(reaction.GetKey().GetID())
+	defer C.free(unsafe.Pointer(cmessageId))
+	// An empty reaction means the sender removed theirs.
+	creaction := C.CString(reaction.GetText())
+	defer C.free(unsafe.Pointer(creaction))
+
+	creactionEvent := C.ReactionEvent{
+		chat:      cchat,
+		sender:    csender,
+		messageID: cmessageId,
+		reaction:  creaction,
+		timestamp: C.int64_t(info.Timestamp.Unix()),
+		isFromMe:  C.bool(info.IsFromMe),
+	}
+
+	cevent := C.Event{
+		kind: C.uint8_t(EventTypeReaction),
+		data: unsafe.Pointer(&creactionEvent),
+	}
+	C.callEventCallback(eventHandler, &cevent)
+}
+
+func HandleReceipt(evt *events.Receipt) {
+	var kind int
+	switch evt.Type {
+	case types.ReceiptTypeDelivered:
+		kind = ReceiptKindDelivered
+	case types.ReceiptTypeRead:
+		kind = ReceiptKindRead
+	case types.ReceiptTypeReadSelf:
+		kind = ReceiptKindReadSelf
+	case types.ReceiptTypePlayed:
+		kind = ReceiptKindPlayed
+	case types.ReceiptTypePlayedSelf:
+		kind = ReceiptKindPlayedSelf
+	case types.ReceiptTypeServerError:
+		kind = ReceiptKindServerError
+	default:
+		LOG_DEBUG("Ignoring %q receipt for %#v", evt.Type, evt.MessageIDs)
+		return
+	}
+	LOG_DEBUG("%#v got a %q receipt from %s at %s", evt.MessageIDs, evt.Type, evt.SourceString(), evt.Timestamp)
+
+	chat := evt.Chat
+	sender := evt.Sender
+	NormalizeIds(&chat, &sender)
+
+	n := len(evt.MessageIDs)
+	cmessageIds := (**C.char)(C.malloc(C.size_t(n) * C.size_t(unsafe.Sizeof(uintptr(0)))))
+	defer C.free(unsafe.Pointer(cmessageIds))
+	messageIds := unsafe.Slice(cmessageIds, n)
+	for i, id := range evt.MessageIDs {
+		messageIds[i] = C.CString(id)
+		defer C.free(unsafe.Pointer(messageIds[i]))
+	}
+
+	cchat := jidToC(chat)
+	defer C.free(unsafe.Pointer(cchat))
+	csender := jidToC(sender)
+	defer C.free(unsafe.Pointer(csender))
+
+	creceipt := C.ReceiptEvent{
+		kind:       C.uint8_t(kind),
+		chat:       cchat,
+		sender:     csender,
+		messageIDs: cmessageIds,
+		size:       C.size_t(n),
+		timestamp:  C.int64_t(evt.Timestamp.Unix()),
+	}
+
+	cevent := C.Event{
+		kind: C.uint8_t(EventTypeReceipt),
+		data: unsafe.Pointer(&creceipt),
+	}
+	C.callEventCallback(eventHandler, &cevent)
+}
+
+// editedText returns the new text of an edited message: the body for text messages
+// and the caption for media messages.
+func editedText(msg *waE2E.Message) string {
+	switch {
+	case msg.Conversation != nil:
+		return msg.GetConversation()
+	case msg.ExtendedTextMessage != nil:
+		return msg.GetExtendedTextMessage().GetText()
+	case msg.ImageMessage != nil:
+		return msg.GetImageMessage().GetCaption()
+	case msg.VideoMessage != nil:
+		return msg.GetVideoMessage().GetCaption()
+	case msg.DocumentMessage != nil:
+		return msg.GetDocumentMessage().GetCaption()
+	}
+	return ""
+}
+
+func HandleProtocolMessage(info types.MessageInfo, protocolMsg *waE2E.ProtocolMessage) {
+	var kind int
+	var ctext *C.char
+	switch protocolMsg.GetType() {
+	case waE2E.ProtocolMessage_MESSAGE_EDIT:
+		kind = EventTypeMessageEdit
+		ctext = C.CString(editedText(protocolMsg.GetEditedMessage()))
+		defer C.free(unsafe.Pointer(ctext))
+	case waE2E.ProtocolMessage_REVOKE:
+		kind = EventTypeMessageRevoke
+	default:
+		LOG_DEBUG("Ignoring protocol message of type %v", protocolMsg.GetType())
+		return
+	}
+
+	cchat := jidToC(info.Chat)
+	defer C.free(unsafe.Pointer(cchat))
+	csender := jidToC(info.Sender)
+	defer C.free(unsafe.Pointer(csender))
+	cmessageId := C.CString(protocolMsg.GetKey().GetID())
+	defer C.free(unsafe.Pointer(cmessageId))
+
+	cupdate := C.MessageUpdateEvent{
+		chat:      cchat,
+		sender:    csender,
+		messageID: cmessageId,
+		text:      ctext,
+		timestamp: C.int64_t(info.Timestamp.Unix()),
+		isFromMe:  C.bool(info.IsFromMe),
+	}
+
+	cevent := C.Event{
+		kind: C.uint8_t(kind),
+		data: unsafe.Pointer(&cupdate),
+	}
+	C.callEventCallback(eventHandler, &cevent)
+}
+
+// HandleMarkChatAsRead reports a chat marked as read or unread on another device.
+func HandleMarkChatAsRead(evt *events.MarkChatAsRead) {
+	chat := evt.JID
+	sender := evt.JID
+	NormalizeIds(&chat, &sender)
+
+	cchat := jidToC(chat)
+	defer C.free(unsafe.Pointer(cchat))
+
+	cread := C.ChatReadEvent{
+		chat:      cchat,
+		read:      C.bool(evt.Action.GetRead()),
+		timestamp: C.int64_t(evt.Timestamp.Unix()),
+	}
+
+	cevent := C.Event{
+		kind: C.uint8_t(EventTypeChatRead),
+		data: unsafe.Pointer(&cread),
+	}
+	C.callEventCallback(eventHandler, &cevent)
+}
+
+// HandleGroupInfo reports every change in a group update as its own event, so a
+// single update that renames the group and adds someone becomes two events.
+func HandleGroupInfo(evt *events.GroupInfo) {
+	if evt.Name != nil {
+		emitGroupChange(evt, GroupChangeSubject, nil, &evt.Name.Name)
+	}
+	if evt.Topic != nil {
+		emitGroupChange(evt, GroupChangeDescription, nil, &evt.Topic.Topic)
+	}
+	if len(evt.Join) > 0 {
+		emitGroupChange(evt, GroupChangeJoin, evt.Join, nil)
+	}
+	if len(evt.Leave) > 0 {
+		emitGroupChange(evt, GroupChangeLeave, evt.Leave, nil)
+	}
+	if len(evt.Promote) > 0 {
+		emitGroupChange(evt, GroupChangePromote, evt.Promote, nil)
+	}
+	if len(evt.Demote) > 0 {
+		emitGroupChange(evt, GroupChangeDemote, evt.Demote, nil)
+	}
+}
+
+func emitGroupChange(evt *events.GroupInfo, kind int, participants []types.JID, text *string) {
+	cchat := jidToC(evt.JID)
+	defer C.free(unsafe.Pointer(cchat))
+	var csender C.JID
+	if evt.Sender != nil {
+		csender = jidToC(*evt.Sender)
+		defer C.free(unsafe.Pointer(csender))
+	}
+	var ctext *C.char
+	if text != nil {
+		ctext = C.CString(*text)
+		defer C.free(unsafe.Pointer(ctext))
+	}
+
+	n := len(participants)
+	var cparticipants *C.JID
+	if n > 0 {
+		cparticipants = (*C.JID)(C.malloc(C.size_t(n) * C.size_t(unsafe.Sizeof(uintptr(0)))))
+		defer C.free(unsafe.Pointer(cparticipants))
+		participantList := unsafe.Slice(cparticipants, n)
+		for i, jid := range participants {
+			participantList[i] = jidToC(jid)
+			defer C.free(unsafe.Pointer(participantList[i]))
+		}
+	}
+
+	cchange := C.GroupChangeEvent{
+		kind:         C.uint8_t(kind),
+		chat:         cchat,
+		sender:       csender,
+		participants: cparticipants,
+		size:         C.size_t(n),
+		text:         ctext,
+		timestamp:    C.int64_t(evt.Timestamp.Unix()),
+	}
+
+	cevent := C.Event{
+		kind: C.uint8_t(EventTypeGroupChange),
+		data: unsafe.Pointer(&cchange),
+	}
+	C.callEventCallback(eventHandler, &cevent)
+}
+
 //export C_DownloadFile
-func C_DownloadFile(fileId *C.char, basePath *C.char) C.uint8_t {
+func C_DownloadFile(fileId *C.char, basePath *C.char) C.Error {
+	if client == nil {
+		return errorToC(checkClient())
+	}
 	goFileId := C.GoString(fileId)
 	goBasePath := C.GoString(basePath)
-	status := DownloadFromFileId(client, goFileId, goBasePath)
-	return C.uint8_t(status)
+	return errorToC(DownloadFromFileId(client, goFileId, goBasePath))
 }
 
 func AddEventHandlers() {
@@ -752,6 +1113,7 @@ func AddEventHandlers() {
 		switch evt := rawEvt.(type) {
 		case *events.MarkChatAsRead:
 			LOG_DEBUG("MarkChatAsRead %v", evt.JID)
+			HandleMarkChatAsRead(evt)
 
 		case *events.AppStateSyncComplete:
 			LOG_INFO("AppStateSyncComplete %v", evt)
@@ -766,38 +1128,17 @@ func AddEventHandlers() {
 			}
 
 		case *events.Message:
-			HandleMessage(evt.Info, evt.Message, false)
-
-		case *events.Receipt:
-
-			receiptKind := -1
-			if evt.Type == types.ReceiptTypeRead || evt.Type == types.ReceiptTypeReadSelf {
-				receiptKind = 0
+			status := MessageStatusSent
+			if !evt.Info.IsFromMe {
+				status = MessageStatusDelivered
 			}
+			HandleMessage(evt.Info, evt.Message, status, false)
 
-			if receiptKind != -1 {
-				LOG_DEBUG("%#v was read by %s at %s", evt.MessageIDs, evt.SourceString(), evt.Timestamp)
-				n := len(evt.MessageIDs)
-				cmessageIds := (**C.char)(C.malloc(C.size_t(n) * C.size_t(unsafe.Sizeof(uintptr(0)))))
-				messageIds := unsafe.Slice(cmessageIds, len(evt.MessageIDs))
-				for i, id := range evt.MessageIDs {
-					messageIds[i] = C.CString(id)
-				}
-
-				cchatId := jidToC(evt.MessageSource.Chat)
-
-				creceipt := (*C.ReceiptEvent)(C.malloc(C.sizeof_ReceiptEvent))
-				creceipt.kind = C.uint8_t(EventTypeReceipt)
-				creceipt.id = cchatId
-				creceipt.messageIDs = cmessageIds
-				creceipt.size = C.size_t(n)
+		case *events.Receipt:
+			HandleReceipt(evt)
 
-				cevent := C.Event{
-					kind: C.uint8_t(EventTypeReceipt),
-					data: unsafe.Pointer(creceipt),
-				}
-				C.callEventCallback(eventHandler, &cevent)
-			}
+		case *events.GroupInfo:
+			HandleGroupInfo(evt)
 
 		case *events.HistorySync:
 			selfJid := *client.Store.ID
@@ -829,7 +1170,7 @@ func AddEventHandlers() {
 						continue
 					}
 
-					HandleMessage(*messageInfo, message, true)
+					HandleMessage(*messageInfo, message, WebMessageStatus(webMessageInfo), true)
 				}
 			}
 		}
@@ -837,45 +1178,79 @@ func AddEventHandlers() {
 }
 
 //export C_Connect
-func C_Connect(handler C.QrCallback, data unsafe.Pointer) {
+func C_Connect(handler C.QrCallback, data unsafe.Pointer) C.Error {
+	if client == nil {
+		return errorToC(checkClient())
+	}
 	if client.Store.ID == nil {
-		qrChan, _ = client.GetQRChannel(context.Background())
-		err := client.Connect()
+		var err error
+		qrChan, err = client.GetQRChannel(context.Background())
+		if err != nil {
+			return errorToC(err)
+		}
+		err = client.Connect()
 		if err != nil {
-			panic(err)
+			return errorToC(err)
 		}
 
 		for evt := range qrChan {
-			if evt.Event == "code" {
+			switch evt.Event {
+			case "code":
 				code := C.CString(evt.Code)
-				defer C.free(unsafe.Pointer(code))
 				C.callQrCallback(handler, code, data)
+				C.free(unsafe.Pointer(code))
+			case whatsmeow.QRChannelSuccess.Event:
+			case whatsmeow.QRChannelTimedOut.Event:
+				client.Disconnect()
+				return errorToC(withCode(ErrorCodeTimeout, errors.New("QR code was not scanned in time")))
+			default:
+				client.Disconnect()
+				if evt.Error != nil {
+					return errorToC(evt.Error)
+				}
+				return errorToC(fmt.Errorf("pairing failed: %s", evt.Event))
 			}
 		}
 	} else {
 		err := client.Connect()
 		if err != nil {
-			panic(err)
+			return errorToC(err)
 		}
 	}
 
 	AddEventHandlers()
+	return errorToC(nil)
 }
 
+// C_PairPhone requests a pairing code for phone. On success the code is stored in
+// code and owned by the caller.
+//
 //export C_PairPhone
-func C_PairPhone(phone *C.char) *C.char {
+func C_PairPhone(phone *C.char, code **C.char) C.Error {
+	if client == nil {
+		return errorToC(checkClient())
+	}
 	goPhone := C.GoString(phone)
-	code, err := client.PairPhone(context.Background(), goPhone, true, whatsmeow.PairClientChrome, "Chrome (Linux)")
+	goCode, err := client.PairPhone(context.Background(), goPhone, true, whatsmeow.PairClientChrome, "Chrome (Linux)")
 	if err != nil {
-		panic(err)
+		return errorToC(err)
 	}
-	cCode := C.CString(code)
-	return cCode
+	*code = C.CString(goCode)
+	return errorToC(nil)
 }
 
+// C_SendMessage sends a message. On success the id the server gave it is stored in
+// id and owned by the caller.
+//
 //export C_SendMessage
-func C_SendMessage(cjid C.JID, messageType C.uint8_t, messageContent unsafe.Pointer, quoteId *C.char, quoteSender C.JID) {
-	jid := cToJid(cjid)
+func C_SendMessage(cjid C.JID, messageType C.uint8_t, messageContent unsafe.Pointer, quoteId *C.char, quoteSender C.JID, mentions *C.JID, mentionCount C.size_t, id **C.char) C.Error {
+	if err := checkClient(); err != nil {
+		return errorToC(err)
+	}
+	jid, err := cToJid(cjid)
+	if err != nil {
+		return errorToC(err)
+	}
 
 	contextInfo := &waE2E.ContextInfo{}
 	if quoteId != nil {
@@ -884,48 +1259,205 @@ func C_SendMessage(cjid C.JID, messageType C.uint8_t, messageContent unsafe.Poin
 		sender := C.GoString(quoteSender)
 		contextInfo.Participant = &sender
 	}
+	for _, cmention := range unsafe.Slice(mentions, int(mentionCount)) {
+		mention, err := cToJid(cmention)
+		if err != nil {
+			return errorToC(err)
+		}
+		contextInfo.MentionedJID = append(contextInfo.MentionedJID, mention.String())
+	}
 
-	message := ContentToWaE2EMessage(messageType, messageContent, contextInfo)
+	message, err := ContentToWaE2EMessage(messageType, messageContent, contextInfo)
+	if err != nil {
+		LOG_ERROR("Failed to prepare message for %s: %v", jid, err)
+		return errorToC(err)
+	}
 
-	sendResponse, err := client.SendMessage(context.Background(), jid, message)
+	messageId, err := sendAndEcho(jid, message)
 	if err != nil {
-		panic(err)
-	} else {
-		var messageInfo types.MessageInfo
-		messageInfo.Chat = jid
-		messageInfo.IsFromMe = true
-		messageInfo.Sender = *client.Store.ID
+		return errorToC(err)
+	}
+	LOG_INFO("Message sent: %s %s", messageId, jid)
+	*id = C.CString(messageId)
+	return errorToC(nil)
+}
+
+// C_GetSelfJid returns our own JID, or an empty string before pairing. Free with C_Free.
+//
+//export C_GetSelfJid
+func C_GetSelfJid() *C.char {
+	if client == nil {
+		return C.CString("")
+	}
+	return C.CString(GetSelfId(client))
+}
+
+// C_Free releases a string returned by one of the C_* functions.
+//
+//export C_Free
+func C_Free(ptr unsafe.Pointer) {
+	C.free(ptr)
+}
+
+// ownOrSender is the JID to put in the key of a message we are reacting to or
+// revoking: ours if we sent it, the parsed csender otherwise.
+func ownOrSender(csender C.JID, isFromMe C.bool) (types.JID, error) {
+	if bool(isFromMe) {
+		return client.Store.ID.ToNonAD(), nil
+	}
+	return cToJid(csender)
+}
+
+//export C_SendReaction
+func C_SendReaction(cchat C.JID, csender C.JID, messageId *C.char, isFromMe C.bool, reaction *C.char) C.Error {
+	if err := checkClient(); err != nil {
+		return errorToC(err)
+	}
+	chat, err := cToJid(cchat)
+	if err != nil {
+		return errorToC(err)
+	}
+	sender, err := ownOrSender(csender, isFromMe)
+	if err != nil {
+		return errorToC(err)
+	}
 
-		messageInfo.ID = sendResponse.ID
-		messageInfo.Timestamp = sendResponse.Timestamp
+	message := client.BuildReaction(chat, sender, C.GoString(messageId), C.GoString(reaction))
+	_, err = sendAndEcho(chat, message)
+	return errorToC(err)
+}
 
-		LOG_INFO("Message sent: %s %s", messageInfo.ID, messageInfo.Chat)
-		HandleMessage(messageInfo, message, false)
+// sendAndEcho sends message and hands it to the message handler, like the messages
+// we send from other devices. Returns the id the server gave it.
+func sendAndEcho(chat types.JID, message *waE2E.Message) (string, error) {
+	sendResponse, err := client.SendMessage(context.Background(), chat, message)
+	if err != nil {
+		LOG_ERROR("Failed to send message to %s: %v", chat, err)
+		return "", err
 	}
+
+	var messageInfo types.MessageInfo
+	messageInfo.Chat = chat
+	messageInfo.IsFromMe = true
+	messageInfo.Sender = *client.Store.ID
+	messageInfo.ID = sendResponse.ID
+	messageInfo.Timestamp = sendResponse.Timestamp
+
+	HandleMessage(messageInfo, message, MessageStatusSent, false)
+	return sendResponse.ID, nil
 }
 
-// TODO: Free the memory allocated for C.JID and C.Contact
+//export C_EditMessage
+func C_EditMessage(cchat C.JID, messageId *C.char, text *C.char) C.Error {
+	if err := checkClient(); err != nil {
+		return errorToC(err)
+	}
+	chat, err := cToJid(cchat)
+	if err != nil {
+		return errorToC(err)
+	}
+	newText := C.GoString(text)
+	message := client.BuildEdit(chat, C.GoString(messageId), &waE2E.Message{
+		Conversation: &newText,
+	})
+	_, err = sendAndEcho(chat, message)
+	return errorToC(err)
+}
 
+//export C_RevokeMessage
+func C_RevokeMessage(cchat C.JID, csender C.JID, messageId *C.char, isFromMe C.bool) C.Error {
+	if err := checkClient(); err != nil {
+		return errorToC(err)
+	}
+	chat, err := cToJid(cchat)
+	if err != nil {
+		return errorToC(err)
+	}
+	sender, err := ownOrSender(csender, isFromMe)
+	if err != nil {
+		return errorToC(err)
+	}
+	message := client.BuildRevoke(chat, sender, C.GoString(messageId))
+	_, err = sendAndEcho(chat, message)
+	return errorToC(err)
+}
+
+// C_MarkRead sends read receipts for the messageCount ids in messageIds, all sent by
+// csender in cchat.
+//
+//export C_MarkRead
+func C_MarkRead(cchat C.JID, csender C.JID, messageIds **C.char, messageCount C.size_t) C.Error {
+	if err := checkClient(); err != nil {
+		return errorToC(err)
+	}
+	chat, err := cToJid(cchat)
+	if err != nil {
+		return errorToC(err)
+	}
+	sender, err := cToJid(csender)
+	if err != nil {
+		return errorToC(err)
+	}
+	ids := make([]types.MessageID, 0, int(messageCount))
+	for _, cid := range unsafe.Slice(messageIds, int(messageCount)) {
+		ids = append(ids, C.GoString(cid))
+	}
+	if err := client.MarkRead(context.Background(), ids, time.Now(), chat, sender); err != nil {
+		return errorToC(fmt.Errorf("mark read: %w", err))
+	}
+	return errorToC(nil)
+}
+
+// C_MuteChat mutes cchat for the given number of seconds, or until it is unmuted when
+// seconds is 0. With mute false it unmutes it instead. The setting is synced to the
+// other devices through the app state.
+//
+//export C_MuteChat
+func C_MuteChat(cchat C.JID, mute C.bool, seconds C.int64_t) C.Error {
+	if err := checkClient(); err != nil {
+		return errorToC(err)
+	}
+	chat, err := cToJid(cchat)
+	if err != nil {
+		return errorToC(err)
+	}
+	patch := appstate.BuildMute(chat.ToNonAD(), bool(mute), time.Duration(seconds)*time.Second)
+	if err := client.SendAppState(context.Background(), patch); err != nil {
+		return errorToC(fmt.Errorf("mute %s: %w", chat, err))
+	}
+	return errorToC(nil)
+}
+
+// C_GetContacts stores all contacts and joined groups in result, to be freed with
+// C_FreeContacts. The entries are only converted once everything was fetched, so
+// nothing is allocated on failure.
+//
 //export C_GetContacts
-func C_GetContacts() C.GetContactsResult {
+func C_GetContacts(result *C.GetContactsResult) C.Error {
+	if err := checkClient(); err != nil {
+		return errorToC(err)
+	}
 	ctx := context.Background()
-	var entries []C.ContactEntry
+	type entry struct {
+		jid  types.JID
+		name string
+	}
+	var entries []entry
 
 	// Contacts (with LID aliases so group senders keyed by LID resolve to a name).
 	contacts, err := client.Store.Contacts.GetAllContacts(ctx)
 	if err != nil {
-		panic(err)
+		return errorToC(fmt.Errorf("get contacts: %w", err))
 	}
 	for jid, contact := range contacts {
 		name := contactDisplayName(contact)
 		if name == "" {
 			continue
 		}
-		cName := C.CString(name)
-		entries = append(entries, C.ContactEntry{jid: jidToC(jid), name: cName})
+		entries = append(entries, entry{jid, name})
 		if jid.Server != types.HiddenUserServer {
 			if lid, _ := client.Store.LIDs.GetLIDForPN(ctx, jid); !lid.IsEmpty() {
-				entries = append(entries, C.ContactEntry{jid: jidToC(lid), name: cName})
+				entries = append(entries, entry{lid, name})
 			}
 		}
 	}
@@ -933,37 +1465,163 @@ func C_GetContacts() C.GetContactsResult {
 	// Groups.
 	groups, err := client.GetJoinedGroups(ctx)
 	if err != nil {
-		panic(err)
+		return errorToC(fmt.Errorf("get joined groups: %w", err))
 	}
 	for _, group := range groups {
-		entries = append(entries, C.ContactEntry{
-			jid:  jidToC(group.JID),
-			name: C.CString(group.GroupName.Name),
-		})
+		entries = append(entries, entry{group.JID, group.GroupName.Name})
 	}
 
 	n := len(entries)
 	c_entries := C.malloc(C.size_t(n) * C.size_t(unsafe.Sizeof(C.ContactEntry{})))
 	entryList := unsafe.Slice((*C.ContactEntry)(c_entries), n)
-	for i := range n {
-		entryList[i] = entries[i]
+	for i, e := range entries {
+		entryList[i] = C.ContactEntry{jid: jidToC(e.jid), name: C.CString(e.name)}
 	}
 
-	return C.GetContactsResult{
+	*result = C.GetContactsResult{
 		entries: (*C.ContactEntry)(c_entries),
 		size:    C.uint32_t(n),
 	}
+	return errorToC(nil)
+}
+
+// C_FreeContacts frees a result of C_GetContacts, including its strings.
+//
+//export C_FreeContacts
+func C_FreeContacts(result C.GetContactsResult) {
+	entries := unsafe.Slice(result.entries, int(result.size))
+	for _, entry := range entries {
+		C.free(unsafe.Pointer(entry.jid))
+		C.free(unsafe.Pointer(entry.name))
+	}
+	C.free(unsafe.Pointer(result.entries))
+}
+
+// C_GetGroupInfo stores the metadata and participants of a group in result, to be
+// freed with C_FreeGroupInfo.
+//
+//export C_GetGroupInfo
+func C_GetGroupInfo(cjid C.JID, result *C.GroupInfo) C.Error {
+	if err := checkClient(); err != nil {
+		return errorToC(err)
+	}
+	jid, err := cToJid(cjid)
+	if err != nil {
+		return errorToC(err)
+	}
+
+	info, err := client.GetGroupInfo(context.Background(), jid)
+	if err != nil {
+		return errorToC(fmt.Errorf("get group info for %s: %w", jid, err))
+	}
+
+	n := len(info.Participants)
+	cparticipants := C.malloc(C.size_t(n) * C.size_t(unsafe.Sizeof(C.GroupParticipant{})))
+	participantList := unsafe.Slice((*C.GroupParticipant)(cparticipants), n)
+	for i, p := range info.Participants {
+		participantList[i] = C.GroupParticipant{
+			jid:          jidToC(p.JID),
+			isAdmin:      C.bool(p.IsAdmin),
+			isSuperAdmin: C.bool(p.IsSuperAdmin),
+		}
+	}
+
+	created := int64(0)
+	if !info.GroupCreated.IsZero() {
+		created = info.GroupCreated.Unix()
+	}
+
+	*result = C.GroupInfo{
+		jid:          jidToC(info.JID),
+		name:         C.CString(info.GroupName.Name),
+		topic:        C.CString(info.GroupTopic.Topic),
+		created:      C.int64_t(created),
+		participants: (*C.GroupParticipant)(cparticipants),
+		size:         C.uint32_t(n),
+	}
+	return errorToC(nil)
+}
+
+// C_FreeGroupInfo frees a result of C_GetGroupInfo, including its strings.
+//
+//export C_FreeGroupInfo
+func C_FreeGroupInfo(info C.GroupInfo) {
+	participants := unsafe.Slice(info.participants, int(info.size))
+	for _, participant := range participants {
+		C.free(unsafe.Pointer(participant.jid))
+	}
+	C.free(unsafe.Pointer(info.participants))
+	C.free(unsafe.Pointer(info.jid))
+	C.free(unsafe.Pointer(info.name))
+	C.free(unsafe.Pointer(info.topic))
+}
+
+// C_UpdateGroupParticipants adds, removes, promotes or demotes the count participants
+// of cgroup, depending on action: 0 add, 1 remove, 2 promote, 3 demote. It fails
+// unless every participant could be changed. The change is reported back as a
+// GroupChange event.
+//
+//export C_UpdateGroupParticipants
+func C_UpdateGroupParticipants(cgroup C.JID, cparticipants *C.JID, count C.size_t, action C.uint8_t) C.Error {
+	if err := checkClient(); err != nil {
+		return errorToC(err)
+	}
+	group, err := cToJid(cgroup)
+	if err != nil {
+		return errorToC(err)
+	}
+	participants := make([]types.JID, 0, int(count))
+	for _, cparticipant := range unsafe.Slice(cparticipants, int(count)) {
+		participant, err := cToJid(cparticipant)
+		if err != nil {
+			return errorToC(err)
+		}
+		participants = append(participants, participant)
+	}
+
+	var change whatsmeow.ParticipantChange
+	switch action {
+	case 0:
+		change = whatsmeow.ParticipantChangeAdd
+	case 1:
+		change = whatsmeow.ParticipantChangeRemove
+	case 2:
+		change = whatsmeow.ParticipantChangePromote
+	case 3:
+		change = whatsmeow.ParticipantChangeDemote
+	default:
+		return errorToC(fmt.Errorf("unknown participant action %d", action))
+	}
+
+	changed, err := client.UpdateGroupParticipants(context.Background(), group, participants, change)
+	if err != nil {
+		return errorToC(fmt.Errorf("%s participants of %s: %w", change, group, err))
+	}
+	// Participants that could not be changed, like someone whose privacy settings keep
+	// them from being added, come back with an error code instead.
+	for _, participant := range changed {
+		if participant.Error != 0 {
+			return errorToC(fmt.Errorf("%s %s in %s: error %d", change, participant.JID, group, participant.Error))
+		}
+	}
+	return errorToC(nil)
 }
 
 //export C_GetChatSettings
-func C_GetChatSettings(cjid C.JID) C.ChatSettings {
+func C_GetChatSettings(cjid C.JID, result *C.ChatSettings) C.Error {
+	if err := checkClient(); err != nil {
+		return errorToC(err)
+	}
 	ctx := context.Background()
-	jid := cToJid(cjid).ToNonAD()
+	jid, err := cToJid(cjid)
+	if err != nil {
+		return errorToC(err)
+	}
+	jid = jid.ToNonAD()
 
 	settings, err := client.Store.ChatSettings.GetChatSettings(ctx, jid)
 	if err != nil {
-		LOG_WARN("failed to get chat settings for %s: %v", jid, err)
-		return C.ChatSettings{}
+		return errorToC(fmt.Errorf("get chat settings for %s: %w", jid, err))
 	}
 
 	if !settings.Found {
@@ -988,17 +1646,22 @@ func C_GetChatSettings(cjid C.JID) C.ChatSettings {
 		mutedUntil = settings.MutedUntil.Unix()
 	}
 
-	return C.ChatSettings{
+	*result = C.ChatSettings{
 		found:       C.bool(settings.Found),
 		muted_until: C.int64_t(mutedUntil),
 		pinned:      C.bool(settings.Pinned),
 		archived:    C.bool(settings.Archived),
 	}
+	return errorToC(nil)
 }
 
 //export C_Disconnect
-func C_Disconnect() {
+func C_Disconnect() C.Error {
+	if client == nil {
+		return errorToC(checkClient())
+	}
 	client.Disconnect()
+	return errorToC(nil)
 }
 
 func main() {} // Required for CGO
@@ -0,0 +1,153 @@
+// Types shared by the Go bridge and its Rust bindings in ../src/lib.rs, which mirror
+// them with #[repr(C)] structs. Keep both sides in sync.
+#ifndef WHATSRUST_H
+#define WHATSRUST_H
+
+#include <stddef.h>
+#include <stdint.h>
+#include <stdbool.h>
+
+// Memory ownership across the boundary:
+//  - Arguments passed to the C_* functions are borrowed for the duration of the call.
+//  - Everything passed to the callbacks is borrowed until the callback returns, Go
+//    frees it afterwards.
+//  - Everything returned by the C_* functions, directly or through an out parameter,
+//    is owned by the caller: strings (including Error.message) are released with
+//    C_Free, GetContactsResult with C_FreeContacts and GroupInfo with C_FreeGroupInfo.
+
+typedef const char* JID;
+
+typedef struct {
+	bool found;
+	const char* first_name;
+	const char* full_name;
+	const char* push_name;
+	const char* business_name;
+} Contact;
+
+typedef struct {
+	JID jid;
+	const char* name;
+} ContactEntry;
+
+typedef struct {
+	bool found;
+	int64_t muted_until;
+	bool pinned;
+	bool archived;
+} ChatSettings;
+
+typedef struct {
+	ContactEntry* entries;
+	uint32_t size;
+} GetContactsResult;
+
+typedef struct {
+	JID jid;
+	bool isAdmin;
+	bool isSuperAdmin;
+} GroupParticipant;
+
+typedef struct {
+	JID jid;
+	char* name;
+	char* topic;
+	int64_t created;
+	GroupParticipant* participants;
+	uint32_t size;
+} GroupInfo;
+
+typedef struct {
+	char* id;
+	JID chat;
+	JID sender;
+	int64_t timestamp;
+	bool isFromMe;
+	char* quoteID;
+	uint8_t status;
+	// Mentioned users, each one written in the text as @ followed by its user part.
+	JID const* mentions;
+	size_t mentionCount;
+} MessageInfo;
+
+typedef struct {
+	char* text;
+} TextMessage;
+
+typedef struct {
+	uint8_t kind;
+	char* path;
+	char* fileID;
+	char* caption;
+} FileMessage;
+
+typedef struct {
+	MessageInfo info;
+	uint8_t messageType;
+	void* message;
+} Message;
+
+// code is ErrorCodeNone on success. Otherwise message describes the failure and
+// is owned by the caller.
+typedef struct {
+	uint8_t code;
+	char* message;
+} Error;
+
+typedef struct {
+	uint8_t kind;
+	JID chat;
+	JID sender;
+	char* const* messageIDs;
+	size_t size;
+	int64_t timestamp;
+} ReceiptEvent;
+
+typedef struct {
+	JID chat;
+	JID sender;
+	char* messageID;
+	char* reaction;
+	int64_t timestamp;
+	bool isFromMe;
+} ReactionEvent;
+
+typedef struct {
+	JID chat;
+	JID sender;
+	char* messageID;
+	char* text;
+	int64_t timestamp;
+	bool isFromMe;
+} MessageUpdateEvent;
+
+// sender is NULL when the change was not made by a participant. text is the new
+// subject or description, NULL for the other kinds.
+typedef struct {
+	uint8_t kind;
+	JID chat;
+	JID sender;
+	JID const* participants;
+	size_t size;
+	char* text;
+	int64_t timestamp;
+} GroupChangeEvent;
+
+// chat was marked as read, or as unread when read is false, on another device.
+typedef struct {
+	JID chat;
+	bool read;
+	int64_t timestamp;
+} ChatReadEvent;
+
+typedef struct {
+	uint8_t kind;
+	void* data;
+} Event;
+
+typedef void (*EventCallback)(const Event*, void*);
+typedef void (*QrCallback)(const char*, void*);
+typedef void (*MessageHandlerCallback)(const Message*, bool, void*);
+typedef void (*LogHandlerCallback)(const char*, uint8_t, void*);
+
+#endif
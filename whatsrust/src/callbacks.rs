@@ -10,48 +10,60 @@ pub trait CallbackTranslator<T> {
 
 macro_rules! setup_handler {
     ($fn_name:ident, $c_func:ident) => {
-        setup_handler!($fn_name, $c_func,);
+        setup_handler!(pub fn $fn_name, $c_func -> (),);
     };
     (
         $fn_name:ident,
         $c_func:ident,
+        $( $params:tt )*
+    ) => {
+        setup_handler!(pub fn $fn_name, $c_func -> (), $( $params )*);
+    };
+    // Returns whatever `$c_func` returns, so the caller can convert errors.
+    (
+        $vis:vis fn $fn_name:ident,
+        $c_func:ident -> $ret:ty,
         $(
             $param_name:ident : $c_type:ty => $rs_type:ty
         ),* $(,)?
     ) => {
-        pub fn $fn_name<F>(callback: F)
+        $vis fn $fn_name<F>(callback: F) -> $ret
         where
-            F: FnMut($($rs_type),*) + 'static,
+            F: FnMut($($rs_type),*) + Send + 'static,
         {
-            // Double-box the closure to erase its type
-            type CallbackType = dyn FnMut($($rs_type),*);
-            let callback: Box<CallbackType> = Box::new(callback);
-            let callback_box = Box::new(Arc::new(Mutex::new(callback)));
-            let user_data = Box::into_raw(callback_box) as *mut c_void;
+            // The closure is owned here rather than by Go. Registering a new one drops
+            // the previous one, after any call to it that is in progress has returned.
+            type CallbackType = dyn FnMut($($rs_type),*) + Send;
+            static CALLBACK: Mutex<Option<Box<CallbackType>>> = Mutex::new(None);
+            thread_local! {
+                // Set while this thread runs the callback, which holds the lock.
+                static IN_CALLBACK: Cell<bool> = const { Cell::new(false) };
+            }
+            *CALLBACK.lock().unwrap_or_else(PoisonError::into_inner) = Some(Box::new(callback));
 
-            // Shim callback compatible with C
+            // Shim callback compatible with C. A panic must not unwind into Go, and must
+            // not keep later calls from running either. A call made from inside the
+            // callback itself is dropped, as it would wait for the lock forever.
             extern "C" fn shim(
                 $( $param_name: $c_type, )*
-                user_data: *mut c_void
+                _user_data: *mut c_void
             ) {
-                unsafe {
-                    let callback_box =
-                        Box::from_raw(user_data as *mut Arc<Mutex<Box<CallbackType>>>);
-                    let closure = Arc::clone(&callback_box);
-                    let mut guard = closure.lock().unwrap();
-
-                    guard($(
-                        <$rs_type>::to_rust($param_name),
-                    )*);
-
-                    // TODO: Make sure this works
-                    let _ = Box::into_raw(callback_box);
+                if IN_CALLBACK.get() {
+                    return;
                 }
+                IN_CALLBACK.set(true);
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+                    let mut callback = CALLBACK.lock().unwrap_or_else(PoisonError::into_inner);
+                    if let Some(callback) = callback.as_mut() {
+                        callback($(
+                            unsafe { <$rs_type>::to_rust($param_name) },
+                        )*);
+                    }
+                }));
+                IN_CALLBACK.set(false);
             }
 
-            unsafe {
-                $c_func(shim, user_data);
-            }
+            unsafe { $c_func(shim, std::ptr::null_mut()) }
         }
     };
 }
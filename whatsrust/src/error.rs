@@ -0,0 +1,93 @@
+use std::{
+    ffi::{NulError, c_char},
+    fmt,
+    sync::Arc,
+};
+
+use strum::FromRepr;
+
+use crate::owned::GoString;
+
+/// Why a call into whatsmeow failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    NotConnected,
+    /// The device is not paired, or was logged out from the phone.
+    NotLoggedIn,
+    InvalidJid(Arc<str>),
+    UploadFailed(Arc<str>),
+    DownloadFailed(Arc<str>),
+    /// The media is no longer on the WhatsApp servers.
+    MediaExpired,
+    Timeout,
+    /// An argument that cannot be passed to Go, like a string with a NUL byte.
+    InvalidArgument(Arc<str>),
+    Other(Arc<str>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotConnected => write!(f, "not connected"),
+            Error::NotLoggedIn => write!(f, "not logged in"),
+            Error::InvalidJid(msg) => write!(f, "invalid JID: {msg}"),
+            Error::UploadFailed(msg) => write!(f, "upload failed: {msg}"),
+            Error::DownloadFailed(msg) => write!(f, "download failed: {msg}"),
+            Error::MediaExpired => write!(f, "media is no longer available"),
+            Error::Timeout => write!(f, "timed out"),
+            Error::InvalidArgument(msg) => write!(f, "invalid argument: {msg}"),
+            Error::Other(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<NulError> for Error {
+    fn from(err: NulError) -> Self {
+        Error::InvalidArgument(err.to_string().into())
+    }
+}
+
+/// Mirrors the `ErrorCode*` constants on the Go side.
+#[derive(FromRepr)]
+#[repr(u8)]
+enum ErrorCode {
+    None = 0,
+    NotConnected = 1,
+    NotLoggedIn = 2,
+    InvalidJid = 3,
+    UploadFailed = 4,
+    DownloadFailed = 5,
+    MediaExpired = 6,
+    Timeout = 7,
+    Other = 8,
+}
+
+#[repr(C)]
+pub(crate) struct CError {
+    code: u8,
+    message: *mut c_char,
+}
+
+impl CError {
+    /// Converts the error returned by a Go call, freeing its message.
+    pub(crate) fn into_result(self) -> Result<(), Error> {
+        let message: Arc<str> = unsafe { GoString::from_raw(self.message) }
+            .to_string_lossy()
+            .into();
+
+        let error = match ErrorCode::from_repr(self.code).unwrap_or(ErrorCode::Other) {
+            ErrorCode::None => return Ok(()),
+            ErrorCode::NotConnected => Error::NotConnected,
+            ErrorCode::NotLoggedIn => Error::NotLoggedIn,
+            ErrorCode::InvalidJid => Error::InvalidJid(message),
+            ErrorCode::UploadFailed => Error::UploadFailed(message),
+            ErrorCode::DownloadFailed => Error::DownloadFailed(message),
+            ErrorCode::MediaExpired => Error::MediaExpired,
+            ErrorCode::Timeout => Error::Timeout,
+            ErrorCode::Other => Error::Other(message),
+        };
+        Err(error)
+    }
+}
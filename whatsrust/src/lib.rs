@@ -1,12 +1,20 @@
 use std::{
-    ffi::{c_char, c_void, CStr, CString},
+    cell::Cell,
+    ffi::{CStr, CString, c_char, c_void},
+    panic::{self, AssertUnwindSafe},
     path::Path,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
 };
 
 #[macro_use]
 mod callbacks;
+mod error;
+mod owned;
 use callbacks::CallbackTranslator;
+use error::CError;
+pub use error::Error;
+use owned::{ContactList, GoString, GroupInfoResult};
 use strum::{EnumIter, FromRepr};
 
 type CJID = *const c_char;
@@ -30,9 +38,11 @@ impl From<&CJID> for JID {
         JID(unsafe { CStr::from_ptr(*cjid) }.to_string_lossy().into())
     }
 }
-impl From<&JID> for CJID {
-    fn from(jid: &JID) -> Self {
-        CString::new(jid.0.as_ref()).unwrap().into_raw()
+impl TryFrom<&JID> for CString {
+    type Error = Error;
+
+    fn try_from(jid: &JID) -> Result<Self, Error> {
+        CString::new(jid.0.as_ref()).map_err(|_| Error::InvalidJid(jid.0.clone()))
     }
 }
 
@@ -51,6 +61,7 @@ struct CContactEntry {
     name: *const c_char,
 }
 
+#[derive(Clone, Copy)]
 #[repr(C)]
 struct CGetContactsResult {
     entries: *const CContactEntry,
@@ -65,6 +76,24 @@ struct CChatSettings {
     archived: bool,
 }
 
+#[repr(C)]
+struct CGroupParticipant {
+    jid: CJID,
+    is_admin: bool,
+    is_super_admin: bool,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct CGroupInfo {
+    jid: CJID,
+    name: *const c_char,
+    topic: *const c_char,
+    created: i64,
+    participants: *const CGroupParticipant,
+    size: u32,
+}
+
 #[repr(C)]
 struct CMessageInfo {
     id: *const c_char,
@@ -73,7 +102,9 @@ struct CMessageInfo {
     timestamp: i64,
     is_from_me: bool,
     quote_id: *const c_char,
-    read_by: u16,
+    status: u8,
+    mentions: *const CJID,
+    mention_count: usize,
 }
 
 #[repr(C)]
@@ -100,8 +131,48 @@ struct CMessage {
 struct CReceipt {
     kind: u8,
     chat: CJID,
+    sender: CJID,
     message_ids: *const *const c_char,
-    count: u32,
+    count: usize,
+    timestamp: i64,
+}
+
+#[repr(C)]
+struct CReaction {
+    chat: CJID,
+    sender: CJID,
+    message_id: *const c_char,
+    reaction: *const c_char,
+    timestamp: i64,
+    is_from_me: bool,
+}
+
+#[repr(C)]
+struct CMessageUpdate {
+    chat: CJID,
+    sender: CJID,
+    message_id: *const c_char,
+    text: *const c_char,
+    timestamp: i64,
+    is_from_me: bool,
+}
+
+#[repr(C)]
+struct CGroupChange {
+    kind: u8,
+    chat: CJID,
+    sender: CJID,
+    participants: *const CJID,
+    count: usize,
+    text: *const c_char,
+    timestamp: i64,
+}
+
+#[repr(C)]
+struct CChatRead {
+    chat: CJID,
+    read: bool,
+    timestamp: i64,
 }
 
 #[derive(Clone, Debug)]
@@ -121,7 +192,52 @@ pub struct MessageInfo {
     pub timestamp: i64,
     pub is_from_me: bool,
     pub quote_id: Option<Arc<str>>,
-    pub read_by: u16,
+    pub delivery: DeliveryState,
+    pub edit_state: EditState,
+    /// The users mentioned in the text. Each one is written in the text as `@` followed
+    /// by the user part of their JID.
+    pub mentions: Vec<JID>,
+}
+
+/// How far a message got on its way to the recipients. Only advances, so a failed
+/// message stays failed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, FromRepr)]
+#[repr(u8)]
+pub enum DeliveryState {
+    /// Not yet acknowledged by the server.
+    Pending = 0,
+    #[default]
+    Sent = 1,
+    Delivered = 2,
+    Read = 3,
+    /// Voice and video messages that were listened to or watched.
+    Played = 4,
+    Failed = 5,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromRepr)]
+#[repr(u8)]
+pub enum ReceiptKind {
+    Delivered = 0,
+    Read = 1,
+    /// We read the messages on another device.
+    ReadSelf = 2,
+    Played = 3,
+    /// We played the messages on another device.
+    PlayedSelf = 4,
+    /// The server could not deliver the messages.
+    ServerError = 5,
+}
+
+/// Whether a message was changed after it was sent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, FromRepr)]
+#[repr(u8)]
+pub enum EditState {
+    #[default]
+    Original = 0,
+    Edited = 1,
+    /// Deleted for everyone. The content is gone, only a tombstone is kept.
+    Revoked = 2,
 }
 
 #[derive(FromRepr)]
@@ -148,16 +264,56 @@ enum EventType {
     SyncProgress = 0,
     AppStateSyncComplete = 1,
     Receipt = 2,
+    Reaction = 3,
+    MessageEdit = 4,
+    MessageRevoke = 5,
+    GroupChange = 6,
+    ChatRead = 7,
 }
 
 #[derive(Clone, Debug)]
 pub enum Event {
     SyncProgress(u8),
     AppStateSyncComplete,
+    /// `sender` received, read or played `message_ids`.
     Receipt {
-        kind: u8,
+        kind: ReceiptKind,
         chat: JID,
+        sender: JID,
         message_ids: Vec<MessageId>,
+        timestamp: i64,
+    },
+    Reaction(Reaction),
+    /// `message_id` was edited by its sender. `text` replaces the body of text
+    /// messages and the caption of file messages.
+    MessageEdit {
+        chat: JID,
+        sender: JID,
+        message_id: MessageId,
+        text: Arc<str>,
+        timestamp: i64,
+    },
+    /// `message_id` was deleted for everyone.
+    MessageRevoke {
+        chat: JID,
+        sender: JID,
+        message_id: MessageId,
+        timestamp: i64,
+    },
+    /// Something changed in the group `chat`. `sender` made the change, it is `None`
+    /// when it was not made by a participant, like someone joining through a link.
+    GroupChange {
+        chat: JID,
+        sender: Option<JID>,
+        change: GroupChange,
+        timestamp: i64,
+    },
+    /// `chat` was marked as read, or as unread when `read` is false, on another of our
+    /// devices.
+    ChatRead {
+        chat: JID,
+        read: bool,
+        timestamp: i64,
     },
 }
 
@@ -171,10 +327,59 @@ pub struct FileContent {
     pub caption: Option<Arc<str>>,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, FromRepr)]
+#[repr(u8)]
+pub enum GroupChangeKind {
+    #[default]
+    Join = 0,
+    Leave = 1,
+    /// Participants were made admins.
+    Promote = 2,
+    /// Participants are no longer admins.
+    Demote = 3,
+    Subject = 4,
+    Description = 5,
+}
+
+/// What an admin can do to participants of a group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+#[repr(u8)]
+pub enum ParticipantAction {
+    Add = 0,
+    Remove = 1,
+    /// Makes them admins.
+    Promote = 2,
+    /// Makes them regular participants again.
+    Demote = 3,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GroupChange {
+    pub kind: GroupChangeKind,
+    /// Who joined, left, was promoted or demoted. Empty for the other kinds.
+    pub participants: Vec<JID>,
+    /// The new subject or description. Empty for the other kinds.
+    pub text: Arc<str>,
+}
+
 #[derive(Clone, Debug, EnumIter)]
 pub enum MessageContent {
     Text(Arc<str>),
     File(FileContent),
+    /// A change to a group, shown in its chat like a message. Only ever received.
+    GroupChange(GroupChange),
+}
+
+#[derive(Clone, Debug)]
+pub struct Reaction {
+    pub chat: JID,
+    pub sender: JID,
+    /// The message being reacted to.
+    pub message_id: MessageId,
+    /// Empty when the sender removed their reaction.
+    pub emoji: Arc<str>,
+    pub timestamp: i64,
+    pub is_from_me: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -200,10 +405,23 @@ pub struct ChatSettings {
     pub archived: bool,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupParticipant {
+    pub jid: JID,
+    pub is_admin: bool,
+    /// The creator of the group, who cannot be demoted.
+    pub is_super_admin: bool,
+}
+
 #[derive(Clone, Debug)]
 pub struct GroupInfo {
     pub jid: JID,
+    /// The subject of the group.
     pub name: Arc<str>,
+    pub description: Arc<str>,
+    /// Unix timestamp, 0 if unknown.
+    pub created_at: i64,
+    pub participants: Vec<GroupParticipant>,
 }
 
 impl From<&CContact> for Contact {
@@ -240,51 +458,82 @@ type CQrCallback = extern "C" fn(*const c_char, *mut c_void);
 type CMessageCallback = extern "C" fn(*const CMessage, bool, *mut c_void);
 type CEventCallback = extern "C" fn(*const CEvent, *mut c_void);
 unsafe extern "C" {
-    fn C_NewClient(db_path: *const c_char);
-    fn C_Connect(qr_cb: CQrCallback, data: *mut c_void);
+    fn C_NewClient(db_path: *const c_char) -> CError;
+    fn C_Connect(qr_cb: CQrCallback, data: *mut c_void) -> CError;
     fn C_SendMessage(
         jid: CJID,
         message_type: u8,
         message_content: *const c_void,
         quote_id: *const c_char,
         quote_sender: CJID,
-    );
-    fn C_GetContacts() -> CGetContactsResult;
-    fn C_GetChatSettings(jid: CJID) -> CChatSettings;
-    fn C_Disconnect();
-    fn C_PairPhone(phone: *const c_char) -> *const c_char;
-    fn C_DownloadFile(file_id: *const c_char, base_path: *const c_char) -> u8;
+        mentions: *const CJID,
+        mention_count: usize,
+        id: *mut *mut c_char,
+    ) -> CError;
+    fn C_SendReaction(
+        chat: CJID,
+        sender: CJID,
+        message_id: *const c_char,
+        is_from_me: bool,
+        reaction: *const c_char,
+    ) -> CError;
+    fn C_EditMessage(chat: CJID, message_id: *const c_char, text: *const c_char) -> CError;
+    fn C_RevokeMessage(
+        chat: CJID,
+        sender: CJID,
+        message_id: *const c_char,
+        is_from_me: bool,
+    ) -> CError;
+    fn C_MarkRead(
+        chat: CJID,
+        sender: CJID,
+        message_ids: *const *const c_char,
+        message_count: usize,
+    ) -> CError;
+    fn C_MuteChat(chat: CJID, mute: bool, seconds: i64) -> CError;
+    fn C_GetContacts(result: *mut CGetContactsResult) -> CError;
+    fn C_GetSelfJid() -> *mut c_char;
+    fn C_Free(ptr: *mut c_void);
+    fn C_FreeContacts(result: CGetContactsResult);
+    fn C_GetChatSettings(jid: CJID, result: *mut CChatSettings) -> CError;
+    fn C_GetGroupInfo(jid: CJID, result: *mut CGroupInfo) -> CError;
+    fn C_FreeGroupInfo(info: CGroupInfo);
+    fn C_UpdateGroupParticipants(
+        group: CJID,
+        participants: *const CJID,
+        count: usize,
+        action: u8,
+    ) -> CError;
+    fn C_Disconnect() -> CError;
+    fn C_PairPhone(phone: *const c_char, code: *mut *mut c_char) -> CError;
+    fn C_DownloadFile(file_id: *const c_char, base_path: *const c_char) -> CError;
 
     fn C_SetMessageHandler(message_cb: CMessageCallback, data: *mut c_void);
     fn C_SetEventHandler(event_cb: CEventCallback, data: *mut c_void);
     fn C_SetLogHandler(log_fn: CLogCallback, data: *mut c_void);
 }
 
-pub struct DownloadFailed;
-
-pub fn download_file(file_id: &FileId, base_path: &Path) -> Result<(), DownloadFailed> {
-    let file_id_c = CString::new(file_id.as_ref()).unwrap();
-    let base_path_c = CString::new(base_path.to_str().unwrap()).unwrap();
-    let code = unsafe { C_DownloadFile(file_id_c.as_ptr(), base_path_c.as_ptr()) };
-    if code == 0 {
-        Ok(())
-    } else {
-        Err(DownloadFailed)
-    }
+/// Downloads a file to its target path under `base_path`, unless it is already there.
+pub fn download_file(file_id: &FileId, base_path: &Path) -> Result<(), Error> {
+    let file_id_c = CString::new(file_id.as_ref())?;
+    let base_path = base_path.to_str().ok_or_else(|| {
+        Error::InvalidArgument(format!("{} is not valid UTF-8", base_path.display()).into())
+    })?;
+    let base_path_c = CString::new(base_path)?;
+    unsafe { C_DownloadFile(file_id_c.as_ptr(), base_path_c.as_ptr()) }.into_result()
 }
 
-pub fn pair_phone(phone: &str) -> String {
-    let phone_c = CString::new(phone).unwrap();
-    let result = unsafe { C_PairPhone(phone_c.as_ptr()) };
-    let result_str = unsafe { CStr::from_ptr(result) }
-        .to_string_lossy()
-        .into_owned();
-    result_str
+/// Requests a code to pair with `phone` instead of scanning the QR code.
+pub fn pair_phone(phone: &str) -> Result<String, Error> {
+    let phone_c = CString::new(phone)?;
+    let mut code = std::ptr::null_mut();
+    unsafe { C_PairPhone(phone_c.as_ptr(), &mut code) }.into_result()?;
+    Ok(unsafe { GoString::from_raw(code) }.to_string_lossy())
 }
 
-pub fn new_client(db_path: &str) {
-    let db_path_c = CString::new(db_path).unwrap();
-    unsafe { C_NewClient(db_path_c.as_ptr()) }
+pub fn new_client(db_path: &str) -> Result<(), Error> {
+    let db_path_c = CString::new(db_path)?;
+    unsafe { C_NewClient(db_path_c.as_ptr()) }.into_result()
 }
 
 impl CallbackTranslator<*const CEvent> for Event {
@@ -299,22 +548,107 @@ impl CallbackTranslator<*const CEvent> for Event {
             EventType::Receipt => {
                 let receipt = unsafe { &(*(event.data as *const CReceipt)) };
                 let chat: JID = (&receipt.chat).into();
-                let message_ids = unsafe {
-                    std::slice::from_raw_parts(receipt.message_ids, receipt.count as usize)
+                let sender: JID = (&receipt.sender).into();
+                let message_ids =
+                    unsafe { std::slice::from_raw_parts(receipt.message_ids, receipt.count) }
+                        .iter()
+                        .map(|&id| {
+                            unsafe { CStr::from_ptr(id) }
+                                .to_string_lossy()
+                                .into_owned()
+                                .into()
+                        })
+                        .collect();
+
+                Event::Receipt {
+                    kind: ReceiptKind::from_repr(receipt.kind).unwrap(),
+                    chat,
+                    sender,
+                    message_ids,
+                    timestamp: receipt.timestamp,
                 }
-                .iter()
-                .map(|&id| {
-                    unsafe { CStr::from_ptr(id) }
+            }
+            EventType::Reaction => {
+                let reaction = unsafe { &(*(event.data as *const CReaction)) };
+                Event::Reaction(Reaction {
+                    chat: (&reaction.chat).into(),
+                    sender: (&reaction.sender).into(),
+                    message_id: unsafe { CStr::from_ptr(reaction.message_id) }
                         .to_string_lossy()
                         .into_owned()
-                        .into()
+                        .into(),
+                    emoji: unsafe { CStr::from_ptr(reaction.reaction) }
+                        .to_string_lossy()
+                        .into_owned()
+                        .into(),
+                    timestamp: reaction.timestamp,
+                    is_from_me: reaction.is_from_me,
                 })
-                .collect();
+            }
+            EventType::MessageEdit => {
+                let update = unsafe { &(*(event.data as *const CMessageUpdate)) };
+                Event::MessageEdit {
+                    chat: (&update.chat).into(),
+                    sender: (&update.sender).into(),
+                    message_id: unsafe { CStr::from_ptr(update.message_id) }
+                        .to_string_lossy()
+                        .into_owned()
+                        .into(),
+                    text: unsafe { CStr::from_ptr(update.text) }
+                        .to_string_lossy()
+                        .into_owned()
+                        .into(),
+                    timestamp: update.timestamp,
+                }
+            }
+            EventType::MessageRevoke => {
+                let update = unsafe { &(*(event.data as *const CMessageUpdate)) };
+                Event::MessageRevoke {
+                    chat: (&update.chat).into(),
+                    sender: (&update.sender).into(),
+                    message_id: unsafe { CStr::from_ptr(update.message_id) }
+                        .to_string_lossy()
+                        .into_owned()
+                        .into(),
+                    timestamp: update.timestamp,
+                }
+            }
+            EventType::GroupChange => {
+                let change = unsafe { &(*(event.data as *const CGroupChange)) };
+                let participants = if change.count == 0 {
+                    Vec::new()
+                } else {
+                    unsafe { std::slice::from_raw_parts(change.participants, change.count) }
+                        .iter()
+                        .map(JID::from)
+                        .collect()
+                };
+                let text = if change.text.is_null() {
+                    "".into()
+                } else {
+                    unsafe { CStr::from_ptr(change.text) }
+                        .to_string_lossy()
+                        .into_owned()
+                        .into()
+                };
 
-                Event::Receipt {
-                    kind: receipt.kind,
-                    chat,
-                    message_ids,
+                Event::GroupChange {
+                    chat: (&change.chat).into(),
+                    sender: (!change.sender.is_null()).then(|| (&change.sender).into()),
+                    change: GroupChange {
+                        kind: GroupChangeKind::from_repr(change.kind).unwrap(),
+                        participants,
+                        text,
+                    },
+                    timestamp: change.timestamp,
+                }
+            }
+            EventType::ChatRead => {
+                let read = unsafe { &(*(event.data as *const CChatRead)) };
+                Event::ChatRead {
+                    chat: (&read.chat).into(),
+                    read: read.read,
+                    timestamp: read.timestamp,
                 }
             }
         }
@@ -349,6 +683,15 @@ impl CallbackTranslator<*const CMessage> for Message {
             )
         };
 
+        let mentions = if msg.info.mention_count == 0 {
+            Vec::new()
+        } else {
+            unsafe { std::slice::from_raw_parts(msg.info.mentions, msg.info.mention_count) }
+                .iter()
+                .map(JID::from)
+                .collect()
+        };
+
         let message = match MessageType::from_repr(msg.message_type).unwrap() {
             MessageType::Text => {
                 let text_message = unsafe { &*(msg.message as *const CTextMessage) };
@@ -399,7 +742,9 @@ impl CallbackTranslator<*const CMessage> for Message {
                 timestamp: msg.info.timestamp,
                 is_from_me: msg.info.is_from_me,
                 quote_id,
-                read_by: msg.info.read_by,
+                delivery: DeliveryState::from_repr(msg.info.status).unwrap_or_default(),
+                edit_state: EditState::Original,
+                mentions,
             },
             message,
         }
@@ -439,10 +784,16 @@ setup_handler!(
     level: u8 => u8
 );
 
-setup_handler!(connect, C_Connect, qr: *const c_char => String);
+setup_handler!(fn connect_with_qr_handler, C_Connect -> CError, qr: *const c_char => String);
+
+/// Blocks until connected. Before the device is paired, `qr_callback` receives every
+/// QR code that should be shown to the user.
+pub fn connect<F: FnMut(String) + Send + 'static>(qr_callback: F) -> Result<(), Error> {
+    connect_with_qr_handler(qr_callback).into_result()
+}
 
-pub fn disconnect() {
-    unsafe { C_Disconnect() }
+pub fn disconnect() -> Result<(), Error> {
+    unsafe { C_Disconnect() }.into_result()
 }
 
 /// Keeps CStrings and C structs alive for the duration of an FFI call.
@@ -453,27 +804,30 @@ enum ContentHolder {
     File(CString, CString, Option<CString>, Box<CFileMessage>),
 }
 
-fn build_content_for_ffi(content: &MessageContent) -> (u8, *const c_void, ContentHolder) {
+fn build_content_for_ffi(
+    content: &MessageContent,
+) -> Result<(u8, *const c_void, ContentHolder), Error> {
     match content {
         MessageContent::Text(text) => {
-            let text_c = CString::new(text.as_ref()).unwrap();
+            let text_c = CString::new(text.as_ref())?;
             let c_text = Box::new(CTextMessage {
                 text: text_c.as_ptr(),
             });
             let ptr = &*c_text as *const _ as *const c_void;
-            (
+            Ok((
                 MessageType::Text as u8,
                 ptr,
                 ContentHolder::Text(text_c, c_text),
-            )
+            ))
         }
         MessageContent::File(file) => {
-            let path_c = CString::new(file.path.as_ref()).unwrap();
-            let file_id_c = CString::new(file.file_id.as_ref()).unwrap();
+            let path_c = CString::new(file.path.as_ref())?;
+            let file_id_c = CString::new(file.file_id.as_ref())?;
             let caption_c = file
                 .caption
                 .as_ref()
-                .map(|c| CString::new(c.as_ref()).unwrap());
+                .map(|c| CString::new(c.as_ref()))
+                .transpose()?;
             let caption_ptr = caption_c.as_ref().map_or(std::ptr::null(), |c| c.as_ptr());
             let c_file = Box::new(CFileMessage {
                 kind: file.kind.clone() as u8,
@@ -482,41 +836,174 @@ fn build_content_for_ffi(content: &MessageContent) -> (u8, *const c_void, Conten
                 caption: caption_ptr,
             });
             let ptr = &*c_file as *const _ as *const c_void;
-            (
+            Ok((
                 MessageType::File as u8,
                 ptr,
                 ContentHolder::File(path_c, file_id_c, caption_c, c_file),
-            )
+            ))
         }
+        MessageContent::GroupChange(_) => Err(Error::InvalidArgument(
+            "group changes cannot be sent".into(),
+        )),
     }
 }
 
-fn quote_to_ffi(quoted: Option<&Message>) -> (CString, *const c_char, CJID) {
-    match quoted {
-        Some(qm) => {
-            let id_c = CString::new(qm.info.id.as_ref()).unwrap();
-            let id_ptr = id_c.as_ptr();
-            let sender = CJID::from(&qm.info.sender);
-            (id_c, id_ptr, sender)
-        }
-        None => (CString::default(), std::ptr::null(), std::ptr::null()),
+/// The id and sender of the quoted message, if any.
+fn quote_to_ffi(quoted: Option<&Message>) -> Result<Option<(CString, CString)>, Error> {
+    quoted
+        .map(|qm| {
+            Ok((
+                CString::new(qm.info.id.as_ref())?,
+                (&qm.info.sender).try_into()?,
+            ))
+        })
+        .transpose()
+}
+
+/// Sends a message and returns the id the server assigned to it. The sent message is
+/// also delivered to the message handler. The text must refer to each of `mentions`
+/// as `@` followed by the user part of its JID.
+pub fn send_message(
+    jid: &JID,
+    content: &MessageContent,
+    quoted_message: Option<&Message>,
+    mentions: &[JID],
+) -> Result<MessageId, Error> {
+    let jid_c = CString::try_from(jid)?;
+    let mentions_c = mentions
+        .iter()
+        .map(CString::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mention_ptrs = mentions_c.iter().map(|m| m.as_ptr()).collect::<Vec<_>>();
+    let (msg_type, content_ptr, _holder) = build_content_for_ffi(content)?;
+    let quote = quote_to_ffi(quoted_message)?;
+    let (quote_id, quote_sender) = quote
+        .as_ref()
+        .map_or((std::ptr::null(), std::ptr::null()), |(id, sender)| {
+            (id.as_ptr(), sender.as_ptr())
+        });
+
+    let mut id = std::ptr::null_mut();
+    unsafe {
+        C_SendMessage(
+            jid_c.as_ptr(),
+            msg_type,
+            content_ptr,
+            quote_id,
+            quote_sender,
+            mention_ptrs.as_ptr(),
+            mention_ptrs.len(),
+            &mut id,
+        )
     }
+    .into_result()?;
+    Ok(unsafe { GoString::from_raw(id) }.to_string_lossy().into())
 }
 
-pub fn send_message(jid: &JID, content: &MessageContent, quoted_message: Option<&Message>) {
-    let jid_c = CJID::from(jid);
-    let (msg_type, content_ptr, _holder) = build_content_for_ffi(content);
-    let (_quote_id_owner, quote_id, quote_sender) = quote_to_ffi(quoted_message);
+/// Our own JID. Fails with [`Error::NotLoggedIn`] until the device is paired.
+pub fn get_self_jid() -> Result<JID, Error> {
+    let jid = unsafe { GoString::from_raw(C_GetSelfJid()) }.to_string_lossy();
+    if jid.is_empty() {
+        Err(Error::NotLoggedIn)
+    } else {
+        Ok(jid.into())
+    }
+}
 
-    unsafe { C_SendMessage(jid_c, msg_type, content_ptr, quote_id, quote_sender) }
+/// Reacts to `message` with `emoji`. An empty `emoji` removes our reaction.
+pub fn send_reaction(message: &Message, emoji: &str) -> Result<(), Error> {
+    let chat_c = CString::try_from(&message.info.chat)?;
+    let sender_c = CString::try_from(&message.info.sender)?;
+    let id_c = CString::new(message.info.id.as_ref())?;
+    let emoji_c = CString::new(emoji)?;
+
+    unsafe {
+        C_SendReaction(
+            chat_c.as_ptr(),
+            sender_c.as_ptr(),
+            id_c.as_ptr(),
+            message.info.is_from_me,
+            emoji_c.as_ptr(),
+        )
+    }
+    .into_result()
+}
+
+/// Replaces the text of one of our own messages.
+pub fn edit_message(message: &Message, text: &str) -> Result<(), Error> {
+    let chat_c = CString::try_from(&message.info.chat)?;
+    let id_c = CString::new(message.info.id.as_ref())?;
+    let text_c = CString::new(text)?;
+
+    unsafe { C_EditMessage(chat_c.as_ptr(), id_c.as_ptr(), text_c.as_ptr()) }.into_result()
+}
+
+/// Deletes `message` for everyone.
+pub fn revoke_message(message: &Message) -> Result<(), Error> {
+    let chat_c = CString::try_from(&message.info.chat)?;
+    let sender_c = CString::try_from(&message.info.sender)?;
+    let id_c = CString::new(message.info.id.as_ref())?;
+
+    unsafe {
+        C_RevokeMessage(
+            chat_c.as_ptr(),
+            sender_c.as_ptr(),
+            id_c.as_ptr(),
+            message.info.is_from_me,
+        )
+    }
+    .into_result()
+}
+
+/// Sends read receipts for `message_ids`, all sent by `sender` in `chat`. Our other
+/// devices see them too and mark the messages as read.
+pub fn mark_read(chat: &JID, sender: &JID, message_ids: &[MessageId]) -> Result<(), Error> {
+    let chat_c = CString::try_from(chat)?;
+    let sender_c = CString::try_from(sender)?;
+    let ids_c = message_ids
+        .iter()
+        .map(|id| CString::new(id.as_ref()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let id_ptrs = ids_c.iter().map(|id| id.as_ptr()).collect::<Vec<_>>();
+
+    unsafe {
+        C_MarkRead(
+            chat_c.as_ptr(),
+            sender_c.as_ptr(),
+            id_ptrs.as_ptr(),
+            id_ptrs.len(),
+        )
+    }
+    .into_result()
+}
+
+/// Mutes `chat` for `duration`, or until it is unmuted when it is `None`.
+pub fn mute_chat(chat: &JID, duration: Option<Duration>) -> Result<(), Error> {
+    let chat_c = CString::try_from(chat)?;
+    // Zero seconds mutes for good, so a duration under a second is rounded up.
+    let seconds = duration.map_or(0, |duration| duration.as_secs().max(1));
+    let seconds = i64::try_from(seconds)
+        .map_err(|_| Error::InvalidArgument("the mute is too long".into()))?;
+    unsafe { C_MuteChat(chat_c.as_ptr(), true, seconds) }.into_result()
+}
+
+/// Lets a muted chat notify again.
+pub fn unmute_chat(chat: &JID) -> Result<(), Error> {
+    let chat_c = CString::try_from(chat)?;
+    unsafe { C_MuteChat(chat_c.as_ptr(), false, 0) }.into_result()
 }
 
 /// Returns all contacts and groups as (JID, display name). Includes LID aliases for contacts.
-pub fn get_contacts() -> Vec<(JID, Arc<str>)> {
-    let result = unsafe { C_GetContacts() };
-    let entries = unsafe { std::slice::from_raw_parts(result.entries, result.size as usize) };
+pub fn get_contacts() -> Result<Vec<(JID, Arc<str>)>, Error> {
+    let mut result = CGetContactsResult {
+        entries: std::ptr::null(),
+        size: 0,
+    };
+    unsafe { C_GetContacts(&mut result) }.into_result()?;
+    let contacts = unsafe { ContactList::from_raw(result) };
 
-    entries
+    Ok(contacts
+        .entries()
         .iter()
         .map(|e| {
             let jid: JID = (&e.jid).into();
@@ -526,17 +1013,90 @@ pub fn get_contacts() -> Vec<(JID, Arc<str>)> {
                 .into();
             (jid, name)
         })
-        .collect()
+        .collect())
 }
 
-pub fn get_chat_settings(jid: &JID) -> ChatSettings {
-    let jid_c = CJID::from(jid);
-    let settings = unsafe { C_GetChatSettings(jid_c) };
+pub fn get_chat_settings(jid: &JID) -> Result<ChatSettings, Error> {
+    let jid_c = CString::try_from(jid)?;
+    let mut settings = CChatSettings {
+        found: false,
+        muted_until: 0,
+        pinned: false,
+        archived: false,
+    };
+    unsafe { C_GetChatSettings(jid_c.as_ptr(), &mut settings) }.into_result()?;
 
-    ChatSettings {
+    Ok(ChatSettings {
         found: settings.found,
         muted_until: settings.muted_until,
         pinned: settings.pinned,
         archived: settings.archived,
+    })
+}
+
+/// The subject, description and participants of a group.
+pub fn get_group_info(jid: &JID) -> Result<GroupInfo, Error> {
+    let jid_c = CString::try_from(jid)?;
+    let mut result = CGroupInfo {
+        jid: std::ptr::null(),
+        name: std::ptr::null(),
+        topic: std::ptr::null(),
+        created: 0,
+        participants: std::ptr::null(),
+        size: 0,
+    };
+    unsafe { C_GetGroupInfo(jid_c.as_ptr(), &mut result) }.into_result()?;
+    let group = unsafe { GroupInfoResult::from_raw(result) };
+
+    let participants = group
+        .participants()
+        .iter()
+        .map(|p| GroupParticipant {
+            jid: (&p.jid).into(),
+            is_admin: p.is_admin,
+            is_super_admin: p.is_super_admin,
+        })
+        .collect();
+    let info = group.info();
+
+    Ok(GroupInfo {
+        jid: (&info.jid).into(),
+        name: unsafe { CStr::from_ptr(info.name) }
+            .to_string_lossy()
+            .into_owned()
+            .into(),
+        description: unsafe { CStr::from_ptr(info.topic) }
+            .to_string_lossy()
+            .into_owned()
+            .into(),
+        created_at: info.created,
+        participants,
+    })
+}
+
+/// Adds, removes, promotes or demotes `participants` of `group`, which takes being an
+/// admin. The change comes back as a [`Event::GroupChange`].
+pub fn update_group_participants(
+    group: &JID,
+    participants: &[JID],
+    action: ParticipantAction,
+) -> Result<(), Error> {
+    let group_c = CString::try_from(group)?;
+    let participants_c = participants
+        .iter()
+        .map(CString::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let participant_ptrs = participants_c
+        .iter()
+        .map(|p| p.as_ptr())
+        .collect::<Vec<_>>();
+    unsafe {
+        C_UpdateGroupParticipants(
+            group_c.as_ptr(),
+            participant_ptrs.as_ptr(),
+            participant_ptrs.len(),
+            action as u8,
+        )
     }
+    .into_result()
 }
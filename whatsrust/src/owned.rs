@@ -0,0 +1,84 @@
+use std::ffi::{CStr, c_char, c_void};
+
+use crate::{
+    C_Free, C_FreeContacts, C_FreeGroupInfo, CContactEntry, CGetContactsResult, CGroupInfo,
+    CGroupParticipant,
+};
+
+/// A string returned by the Go side. Freed with `C_Free` when dropped.
+pub(crate) struct GoString(*mut c_char);
+
+impl GoString {
+    /// Takes ownership of `ptr`, which must be null or returned by one of the C_* functions.
+    pub(crate) unsafe fn from_raw(ptr: *mut c_char) -> Self {
+        GoString(ptr)
+    }
+
+    /// The string, empty if it is null.
+    pub(crate) fn to_string_lossy(&self) -> String {
+        if self.0.is_null() {
+            return String::new();
+        }
+        unsafe { CStr::from_ptr(self.0) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl Drop for GoString {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { C_Free(self.0 as *mut c_void) };
+        }
+    }
+}
+
+/// The result of `C_GetContacts`. Freed with `C_FreeContacts` when dropped.
+pub(crate) struct ContactList(CGetContactsResult);
+
+impl ContactList {
+    /// Takes ownership of a result filled in by a successful `C_GetContacts`.
+    pub(crate) unsafe fn from_raw(result: CGetContactsResult) -> Self {
+        ContactList(result)
+    }
+
+    pub(crate) fn entries(&self) -> &[CContactEntry] {
+        if self.0.size == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.0.entries, self.0.size as usize) }
+    }
+}
+
+impl Drop for ContactList {
+    fn drop(&mut self) {
+        unsafe { C_FreeContacts(self.0) };
+    }
+}
+
+/// The result of `C_GetGroupInfo`. Freed with `C_FreeGroupInfo` when dropped.
+pub(crate) struct GroupInfoResult(CGroupInfo);
+
+impl GroupInfoResult {
+    /// Takes ownership of a result filled in by a successful `C_GetGroupInfo`.
+    pub(crate) unsafe fn from_raw(result: CGroupInfo) -> Self {
+        GroupInfoResult(result)
+    }
+
+    pub(crate) fn info(&self) -> &CGroupInfo {
+        &self.0
+    }
+
+    pub(crate) fn participants(&self) -> &[CGroupParticipant] {
+        if self.0.size == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.0.participants, self.0.size as usize) }
+    }
+}
+
+impl Drop for GroupInfoResult {
+    fn drop(&mut self) {
+        unsafe { C_FreeGroupInfo(self.0) };
+    }
+}
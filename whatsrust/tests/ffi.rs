@@ -0,0 +1,593 @@
+//! Tests of the FFI glue against `tests/stub.c`, which stands in for the Go archive.
+//! The stub counts the allocations it hands to Rust, and a counting allocator catches
+//! leaks on the Rust side. Run with `cargo test --features c-stub`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::ffi::{CStr, CString, c_char, c_long};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use whatsrust as wr;
+
+unsafe extern "C" {
+    fn stub_outstanding() -> c_long;
+    fn stub_fail_next(code: u8, message: *const c_char);
+    fn stub_last_text() -> *const c_char;
+    fn stub_last_jid() -> *const c_char;
+    fn stub_last_mentions() -> *const c_char;
+    fn stub_emit_text(
+        id: *const c_char,
+        chat: *const c_char,
+        sender: *const c_char,
+        text: *const c_char,
+        is_sync: bool,
+        mentions: *const *const c_char,
+        mention_count: usize,
+    );
+    fn stub_emit_receipt(
+        chat: *const c_char,
+        sender: *const c_char,
+        ids: *const *const c_char,
+        size: usize,
+    );
+    fn stub_emit_group_change(
+        kind: u8,
+        chat: *const c_char,
+        sender: *const c_char,
+        participants: *const *const c_char,
+        size: usize,
+        text: *const c_char,
+    );
+    fn stub_emit_chat_read(chat: *const c_char, read: bool);
+    fn stub_log(msg: *const c_char, level: u8);
+}
+
+/// Counts the bytes the current thread allocated and did not free.
+struct CountingAlloc;
+
+thread_local! {
+    static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = LIVE_BYTES.try_with(|live| live.set(live.get() + layout.size() as isize));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _ = LIVE_BYTES.try_with(|live| live.set(live.get() - layout.size() as isize));
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// How many bytes `f` leaks per call.
+fn rust_leak(f: impl Fn()) -> isize {
+    f();
+    let before = LIVE_BYTES.with(Cell::get);
+    for _ in 0..100 {
+        f();
+    }
+    (LIVE_BYTES.with(Cell::get) - before) / 100
+}
+
+/// The stub keeps its state in globals, so the tests take turns.
+fn serial() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    LOCK.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn c(s: &str) -> CString {
+    CString::new(s).unwrap()
+}
+
+fn fail_next(code: u8, message: &str) {
+    unsafe { stub_fail_next(code, c(message).as_ptr()) }
+}
+
+fn last_text() -> String {
+    unsafe { CStr::from_ptr(stub_last_text()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn last_jid() -> String {
+    unsafe { CStr::from_ptr(stub_last_jid()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn last_mentions() -> String {
+    unsafe { CStr::from_ptr(stub_last_mentions()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn outstanding() -> c_long {
+    unsafe { stub_outstanding() }
+}
+
+fn jid(s: &str) -> wr::JID {
+    s.to_string().into()
+}
+
+fn message(id: &str, text: &str) -> wr::Message {
+    wr::Message {
+        info: wr::MessageInfo {
+            id: id.into(),
+            chat: jid("111@s.whatsapp.net"),
+            sender: jid("111@s.whatsapp.net"),
+            timestamp: 1_000,
+            is_from_me: false,
+            quote_id: None,
+            delivery: wr::DeliveryState::Delivered,
+            edit_state: wr::EditState::Original,
+            mentions: Vec::new(),
+        },
+        message: wr::MessageContent::Text(text.into()),
+    }
+}
+
+#[test]
+fn returned_strings_are_freed() {
+    let _serial = serial();
+
+    assert_eq!(wr::get_self_jid(), Ok(jid("0@s.whatsapp.net")));
+    assert_eq!(wr::pair_phone("123").as_deref(), Ok("ABCD-EFGH"));
+    let id = wr::send_message(
+        &jid("222@s.whatsapp.net"),
+        &wr::MessageContent::Text("hello".into()),
+        None,
+        &[],
+    );
+    assert_eq!(id.as_deref(), Ok("STUB-1"));
+    assert_eq!(last_jid(), "222@s.whatsapp.net");
+    assert_eq!(last_text(), "hello");
+
+    assert_eq!(outstanding(), 0);
+}
+
+#[test]
+fn contacts_are_freed() {
+    let _serial = serial();
+
+    let contacts = wr::get_contacts().unwrap();
+    assert_eq!(
+        contacts,
+        vec![
+            (jid("111@s.whatsapp.net"), "Alice".into()),
+            (jid("222@s.whatsapp.net"), "Bob".into()),
+        ]
+    );
+    assert_eq!(outstanding(), 0);
+}
+
+#[test]
+fn group_info_is_freed() {
+    let _serial = serial();
+
+    let info = wr::get_group_info(&jid("333@g.us")).unwrap();
+    assert_eq!(info.jid, jid("333@g.us"));
+    assert_eq!(info.name.as_ref(), "Stub group");
+    assert_eq!(info.description.as_ref(), "About the group");
+    assert_eq!(info.created_at, 1000);
+    assert_eq!(
+        info.participants,
+        vec![
+            wr::GroupParticipant {
+                jid: jid("111@s.whatsapp.net"),
+                is_admin: true,
+                is_super_admin: true,
+            },
+            wr::GroupParticipant {
+                jid: jid("222@s.whatsapp.net"),
+                is_admin: false,
+                is_super_admin: false,
+            },
+        ]
+    );
+    assert_eq!(outstanding(), 0);
+
+    fail_next(3, "333@g.us");
+    assert_eq!(
+        wr::get_group_info(&jid("333@g.us")).map(|i| i.created_at),
+        Err(wr::Error::InvalidJid("333@g.us".into()))
+    );
+    assert_eq!(
+        rust_leak(|| {
+            wr::get_group_info(&jid("333@g.us")).unwrap();
+        }),
+        0
+    );
+    assert_eq!(outstanding(), 0);
+}
+
+#[test]
+fn errors_are_converted_and_freed() {
+    let _serial = serial();
+    let text = wr::MessageContent::Text("hi".into());
+
+    fail_next(1, "not connected");
+    assert_eq!(
+        wr::send_message(&jid("111@s.whatsapp.net"), &text, None, &[]),
+        Err(wr::Error::NotConnected)
+    );
+    fail_next(3, "bad@jid");
+    assert_eq!(
+        wr::get_chat_settings(&jid("bad@jid")).map(|s| s.found),
+        Err(wr::Error::InvalidJid("bad@jid".into()))
+    );
+    fail_next(5, "status 500");
+    assert_eq!(
+        wr::download_file(&"file".into(), Path::new("/tmp")),
+        Err(wr::Error::DownloadFailed("status 500".into()))
+    );
+    fail_next(7, "context deadline exceeded");
+    assert_eq!(
+        wr::send_reaction(&message("A1", "hi"), "👍"),
+        Err(wr::Error::Timeout)
+    );
+    fail_next(42, "something else");
+    assert_eq!(
+        wr::disconnect(),
+        Err(wr::Error::Other("something else".into()))
+    );
+
+    assert_eq!(outstanding(), 0);
+}
+
+#[test]
+fn nul_bytes_are_rejected_before_calling_go() {
+    let _serial = serial();
+    wr::edit_message(&message("A1", "hi"), "before").unwrap();
+
+    let result = wr::send_message(
+        &jid("111@s.whatsapp.net"),
+        &wr::MessageContent::Text("a\0b".into()),
+        None,
+        &[],
+    );
+    assert!(matches!(result, Err(wr::Error::InvalidArgument(_))));
+    assert_eq!(
+        wr::get_chat_settings(&jid("1\0@s.whatsapp.net")).map(|s| s.found),
+        Err(wr::Error::InvalidJid("1\0@s.whatsapp.net".into()))
+    );
+    assert_eq!(last_text(), "before");
+}
+
+#[test]
+fn arguments_are_not_leaked() {
+    let _serial = serial();
+    let quoted = message("A1", "hi");
+    let content = wr::MessageContent::Text("reply".into());
+
+    assert_eq!(rust_leak(|| wr::send_reaction(&quoted, "👍").unwrap()), 0);
+    assert_eq!(
+        rust_leak(|| wr::edit_message(&quoted, "edited").unwrap()),
+        0
+    );
+    assert_eq!(rust_leak(|| wr::revoke_message(&quoted).unwrap()), 0);
+    assert_eq!(
+        rust_leak(|| {
+            wr::mark_read(&quoted.info.chat, &quoted.info.sender, &["A1".into()]).unwrap();
+        }),
+        0
+    );
+    assert_eq!(
+        rust_leak(|| {
+            wr::send_message(&quoted.info.chat, &content, Some(&quoted), &[]).unwrap();
+        }),
+        0
+    );
+    assert_eq!(
+        rust_leak(|| {
+            wr::get_chat_settings(&quoted.info.chat).unwrap();
+        }),
+        0
+    );
+    assert_eq!(outstanding(), 0);
+}
+
+#[test]
+fn callbacks_receive_converted_values() {
+    let _serial = serial();
+
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    {
+        let messages = Arc::clone(&messages);
+        wr::set_message_handler(move |message, is_sync| {
+            messages.lock().unwrap().push((message, is_sync));
+        });
+    }
+    for (id, is_sync) in [("M1", true), ("M2", false)] {
+        let (id, chat, text) = (c(id), c("111@s.whatsapp.net"), c("hi"));
+        unsafe {
+            stub_emit_text(
+                id.as_ptr(),
+                chat.as_ptr(),
+                chat.as_ptr(),
+                text.as_ptr(),
+                is_sync,
+                std::ptr::null(),
+                0,
+            )
+        };
+    }
+    let messages = messages.lock().unwrap();
+    assert_eq!(messages.len(), 2);
+    let (message, is_sync) = &messages[0];
+    assert!(*is_sync);
+    assert_eq!(message.info.id.as_ref(), "M1");
+    assert_eq!(message.info.chat, jid("111@s.whatsapp.net"));
+    assert_eq!(message.info.delivery, wr::DeliveryState::Delivered);
+    assert!(matches!(&message.message, wr::MessageContent::Text(t) if t.as_ref() == "hi"));
+    assert_eq!(messages[1].0.info.id.as_ref(), "M2");
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    {
+        let events = Arc::clone(&events);
+        wr::set_event_handler(move |event| events.lock().unwrap().push(event));
+    }
+    let (chat, sender) = (c("333@g.us"), c("111@s.whatsapp.net"));
+    let ids = [c("M1"), c("M2")];
+    let id_ptrs = ids.each_ref().map(|id| id.as_ptr());
+    unsafe { stub_emit_receipt(chat.as_ptr(), sender.as_ptr(), id_ptrs.as_ptr(), 2) };
+    let events = events.lock().unwrap();
+    let [
+        wr::Event::Receipt {
+            kind,
+            chat,
+            message_ids,
+            ..
+        },
+    ] = events.as_slice()
+    else {
+        panic!("expected one receipt: {events:?}");
+    };
+    assert_eq!(*kind, wr::ReceiptKind::Read);
+    assert_eq!(*chat, jid("333@g.us"));
+    assert_eq!(message_ids, &[wr::MessageId::from("M1"), "M2".into()]);
+}
+
+#[test]
+fn mentions_are_passed_both_ways() {
+    let _serial = serial();
+
+    let mentions = [jid("111@s.whatsapp.net"), jid("222@s.whatsapp.net")];
+    wr::send_message(
+        &jid("333@g.us"),
+        &wr::MessageContent::Text("hi @111 and @222".into()),
+        None,
+        &mentions,
+    )
+    .unwrap();
+    assert_eq!(last_mentions(), "111@s.whatsapp.net 222@s.whatsapp.net");
+    assert_eq!(outstanding(), 0);
+
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    {
+        let messages = Arc::clone(&messages);
+        wr::set_message_handler(move |message, _| messages.lock().unwrap().push(message));
+    }
+    let (id, chat, sender, text) = (c("M3"), c("333@g.us"), c("111@s.whatsapp.net"), c("@222"));
+    let mentioned = [c("222@s.whatsapp.net")];
+    let mentioned_ptrs = mentioned.each_ref().map(|m| m.as_ptr());
+    unsafe {
+        stub_emit_text(
+            id.as_ptr(),
+            chat.as_ptr(),
+            sender.as_ptr(),
+            text.as_ptr(),
+            false,
+            mentioned_ptrs.as_ptr(),
+            1,
+        )
+    };
+    let messages = messages.lock().unwrap();
+    assert_eq!(messages[0].info.mentions, vec![jid("222@s.whatsapp.net")]);
+}
+
+#[test]
+fn read_receipts_name_every_message() {
+    let _serial = serial();
+
+    wr::mark_read(
+        &jid("333@g.us"),
+        &jid("111@s.whatsapp.net"),
+        &["M1".into(), "M2".into()],
+    )
+    .unwrap();
+    assert_eq!(last_jid(), "333@g.us");
+    assert_eq!(last_text(), "M1 M2");
+    assert_eq!(outstanding(), 0);
+}
+
+#[test]
+fn chats_are_muted_for_a_while_or_for_good() {
+    let _serial = serial();
+
+    let chat = jid("333@g.us");
+    wr::mute_chat(&chat, Some(Duration::from_secs(8 * 60 * 60))).unwrap();
+    assert_eq!(last_jid(), "333@g.us");
+    assert_eq!(last_text(), "mute 28800");
+    wr::mute_chat(&chat, None).unwrap();
+    assert_eq!(last_text(), "mute 0");
+    wr::unmute_chat(&chat).unwrap();
+    assert_eq!(last_text(), "unmute");
+    assert_eq!(outstanding(), 0);
+}
+
+#[test]
+fn participants_are_updated_with_the_action() {
+    let _serial = serial();
+
+    let group = jid("333@g.us");
+    let participants = [jid("111@s.whatsapp.net"), jid("222@s.whatsapp.net")];
+    wr::update_group_participants(&group, &participants, wr::ParticipantAction::Promote).unwrap();
+    assert_eq!(last_jid(), "333@g.us");
+    assert_eq!(last_text(), "2 111@s.whatsapp.net 222@s.whatsapp.net");
+
+    fail_next(42, "not an admin");
+    assert_eq!(
+        wr::update_group_participants(&group, &participants[..1], wr::ParticipantAction::Remove),
+        Err(wr::Error::Other("not an admin".into()))
+    );
+    assert_eq!(last_text(), "1 111@s.whatsapp.net");
+    assert_eq!(outstanding(), 0);
+}
+
+#[test]
+fn group_changes_are_converted() {
+    let _serial = serial();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    {
+        let events = Arc::clone(&events);
+        wr::set_event_handler(move |event| events.lock().unwrap().push(event));
+    }
+    let (chat, sender) = (c("333@g.us"), c("111@s.whatsapp.net"));
+    let participants = [c("222@s.whatsapp.net")];
+    let participant_ptrs = participants.each_ref().map(|p| p.as_ptr());
+    let subject = c("New subject");
+    unsafe {
+        stub_emit_group_change(
+            0,
+            chat.as_ptr(),
+            std::ptr::null(),
+            participant_ptrs.as_ptr(),
+            1,
+            std::ptr::null(),
+        );
+        stub_emit_group_change(
+            4,
+            chat.as_ptr(),
+            sender.as_ptr(),
+            std::ptr::null(),
+            0,
+            subject.as_ptr(),
+        );
+    }
+    let events = events.lock().unwrap();
+    let [
+        wr::Event::GroupChange {
+            sender: None,
+            change: join,
+            ..
+        },
+        wr::Event::GroupChange {
+            sender: Some(renamed_by),
+            change: rename,
+            ..
+        },
+    ] = events.as_slice()
+    else {
+        panic!("expected a join and a subject change: {events:?}");
+    };
+    assert_eq!(join.kind, wr::GroupChangeKind::Join);
+    assert_eq!(join.participants, vec![jid("222@s.whatsapp.net")]);
+    assert_eq!(join.text.as_ref(), "");
+    assert_eq!(*renamed_by, jid("111@s.whatsapp.net"));
+    assert_eq!(rename.kind, wr::GroupChangeKind::Subject);
+    assert!(rename.participants.is_empty());
+    assert_eq!(rename.text.as_ref(), "New subject");
+}
+
+#[test]
+fn chats_read_elsewhere_are_converted() {
+    let _serial = serial();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    {
+        let events = Arc::clone(&events);
+        wr::set_event_handler(move |event| events.lock().unwrap().push(event));
+    }
+    let chat = c("111@s.whatsapp.net");
+    unsafe {
+        stub_emit_chat_read(chat.as_ptr(), true);
+        stub_emit_chat_read(chat.as_ptr(), false);
+    }
+    let events = events.lock().unwrap();
+    let [
+        wr::Event::ChatRead {
+            chat: read_chat,
+            read: true,
+            timestamp: 1003,
+        },
+        wr::Event::ChatRead { read: false, .. },
+    ] = events.as_slice()
+    else {
+        panic!("expected the chat read and then unread: {events:?}");
+    };
+    assert_eq!(*read_chat, jid("111@s.whatsapp.net"));
+}
+
+#[test]
+fn replacing_a_handler_drops_the_previous_one() {
+    let _serial = serial();
+
+    let token = Arc::new(());
+    {
+        let token = Arc::clone(&token);
+        wr::set_log_handler(move |_, _| {
+            let _ = &token;
+        });
+    }
+    assert_eq!(Arc::strong_count(&token), 2);
+
+    let logs = Arc::new(Mutex::new(Vec::new()));
+    {
+        let logs = Arc::clone(&logs);
+        wr::set_log_handler(move |msg, level| logs.lock().unwrap().push((msg, level)));
+    }
+    assert_eq!(Arc::strong_count(&token), 1);
+
+    unsafe { stub_log(c("hello").as_ptr(), 2) };
+    assert_eq!(*logs.lock().unwrap(), vec![("hello".to_string(), 2)]);
+}
+
+#[test]
+fn a_panicking_or_reentrant_handler_does_not_take_the_next_calls_down() {
+    let _serial = serial();
+
+    let logs = Arc::new(Mutex::new(Vec::new()));
+    {
+        let logs = Arc::clone(&logs);
+        wr::set_log_handler(move |msg, _| {
+            if msg == "panic" {
+                panic!("the handler failed");
+            }
+            if msg == "again" {
+                unsafe { stub_log(c("inner").as_ptr(), 2) };
+            }
+            logs.lock().unwrap().push(msg);
+        });
+    }
+
+    unsafe { stub_log(c("panic").as_ptr(), 2) };
+    unsafe { stub_log(c("again").as_ptr(), 2) };
+    unsafe { stub_log(c("after").as_ptr(), 2) };
+    assert_eq!(*logs.lock().unwrap(), vec!["again", "after"]);
+    wr::set_log_handler(|_, _| {});
+}
+
+#[test]
+fn connect_reports_qr_codes_and_errors() {
+    let _serial = serial();
+
+    let codes = Arc::new(Mutex::new(Vec::new()));
+    {
+        let codes = Arc::clone(&codes);
+        let result = wr::connect(move |code| codes.lock().unwrap().push(code));
+        assert_eq!(result, Ok(()));
+    }
+    assert_eq!(*codes.lock().unwrap(), vec!["qr-1", "qr-2"]);
+
+    fail_next(2, "not logged in");
+    assert_eq!(wr::connect(|_| {}), Err(wr::Error::NotLoggedIn));
+    assert_eq!(outstanding(), 0);
+}
@@ -0,0 +1,273 @@
+// Stand-in for the Go archive, linked with the `c-stub` feature. It follows the same
+// ownership rules as the bridge and counts the allocations it hands out, so the
+// tests can check that the Rust side frees everything it owns.
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "../lib/whatsrust.h"
+
+static long outstanding = 0;
+
+static uint8_t fail_code = 0;
+static char fail_message[256];
+
+static char last_text[256];
+static char last_jid[256];
+static char last_mentions[256];
+
+static EventCallback event_callback = NULL;
+static void *event_data = NULL;
+static MessageHandlerCallback message_callback = NULL;
+static void *message_data = NULL;
+static LogHandlerCallback log_callback = NULL;
+static void *log_data = NULL;
+
+static char *owned_string(const char *str) {
+	outstanding++;
+	return strdup(str);
+}
+
+static void record(char *dest, const char *src) {
+	strncpy(dest, src ? src : "", 255);
+	dest[255] = '\0';
+}
+
+static Error result(void) {
+	Error error = {0, NULL};
+	if (fail_code != 0) {
+		error.code = fail_code;
+		error.message = owned_string(fail_message);
+		fail_code = 0;
+	}
+	return error;
+}
+
+// Test controls.
+
+long stub_outstanding(void) { return outstanding; }
+
+void stub_fail_next(uint8_t code, const char *message) {
+	fail_code = code;
+	record(fail_message, message);
+}
+
+const char *stub_last_text(void) { return last_text; }
+const char *stub_last_jid(void) { return last_jid; }
+const char *stub_last_mentions(void) { return last_mentions; }
+
+void stub_emit_text(const char *id, JID chat, JID sender, const char *text, bool is_sync,
+		JID const *mentions, size_t mention_count) {
+	TextMessage content = {(char *)text};
+	Message message = {
+		.info = {(char *)id, chat, sender, 1000, false, NULL, 2, mentions, mention_count},
+		.messageType = 0,
+		.message = &content,
+	};
+	if (message_callback) message_callback(&message, is_sync, message_data);
+}
+
+void stub_emit_receipt(JID chat, JID sender, char *const *ids, size_t size) {
+	ReceiptEvent receipt = {1, chat, sender, ids, size, 1001};
+	Event event = {2, &receipt};
+	if (event_callback) event_callback(&event, event_data);
+}
+
+void stub_emit_group_change(uint8_t kind, JID chat, JID sender, JID const *participants,
+		size_t size, const char *text) {
+	GroupChangeEvent change = {kind, chat, sender, participants, size, (char *)text, 1002};
+	Event event = {6, &change};
+	if (event_callback) event_callback(&event, event_data);
+}
+
+void stub_emit_chat_read(JID chat, bool read) {
+	ChatReadEvent chat_read = {chat, read, 1003};
+	Event event = {7, &chat_read};
+	if (event_callback) event_callback(&event, event_data);
+}
+
+void stub_log(const char *msg, uint8_t level) {
+	if (log_callback) log_callback(msg, level, log_data);
+}
+
+// The bridge.
+
+void C_Free(void *ptr) {
+	if (ptr) {
+		outstanding--;
+		free(ptr);
+	}
+}
+
+void C_SetLogHandler(LogHandlerCallback callback, void *data) {
+	log_callback = callback;
+	log_data = data;
+}
+
+void C_SetEventHandler(EventCallback callback, void *data) {
+	event_callback = callback;
+	event_data = data;
+}
+
+void C_SetMessageHandler(MessageHandlerCallback callback, void *data) {
+	message_callback = callback;
+	message_data = data;
+}
+
+Error C_NewClient(const char *db_path) {
+	(void)db_path;
+	return result();
+}
+
+Error C_Connect(QrCallback callback, void *data) {
+	Error error = result();
+	if (error.code != 0) return error;
+	callback("qr-1", data);
+	callback("qr-2", data);
+	return error;
+}
+
+Error C_Disconnect(void) { return result(); }
+
+Error C_PairPhone(const char *phone, char **code) {
+	Error error = result();
+	if (error.code == 0) {
+		record(last_text, phone);
+		*code = owned_string("ABCD-EFGH");
+	}
+	return error;
+}
+
+Error C_SendMessage(JID jid, uint8_t type, const void *content, const char *quote_id,
+		JID quote_sender, JID const *mentions, size_t mention_count, char **id) {
+	(void)quote_id;
+	(void)quote_sender;
+	Error error = result();
+	if (error.code == 0) {
+		record(last_jid, jid);
+		if (type == 0) record(last_text, ((const TextMessage *)content)->text);
+		last_mentions[0] = '\0';
+		for (size_t i = 0; i < mention_count; i++) {
+			if (i > 0) strncat(last_mentions, " ", 255 - strlen(last_mentions));
+			strncat(last_mentions, mentions[i], 255 - strlen(last_mentions));
+		}
+		*id = owned_string("STUB-1");
+	}
+	return error;
+}
+
+Error C_SendReaction(JID chat, JID sender, const char *id, bool is_from_me,
+		const char *reaction) {
+	(void)sender;
+	(void)id;
+	(void)is_from_me;
+	record(last_jid, chat);
+	record(last_text, reaction);
+	return result();
+}
+
+Error C_EditMessage(JID chat, const char *id, const char *text) {
+	(void)id;
+	record(last_jid, chat);
+	record(last_text, text);
+	return result();
+}
+
+Error C_RevokeMessage(JID chat, JID sender, const char *id, bool is_from_me) {
+	(void)sender;
+	(void)id;
+	(void)is_from_me;
+	record(last_jid, chat);
+	return result();
+}
+
+Error C_MarkRead(JID chat, JID sender, const char *const *ids, size_t count) {
+	(void)sender;
+	record(last_jid, chat);
+	last_text[0] = '\0';
+	for (size_t i = 0; i < count; i++) {
+		if (i > 0) strncat(last_text, " ", 255 - strlen(last_text));
+		strncat(last_text, ids[i], 255 - strlen(last_text));
+	}
+	return result();
+}
+
+Error C_MuteChat(JID chat, bool mute, int64_t seconds) {
+	record(last_jid, chat);
+	if (mute) {
+		snprintf(last_text, sizeof last_text, "mute %lld", (long long)seconds);
+	} else {
+		record(last_text, "unmute");
+	}
+	return result();
+}
+
+Error C_DownloadFile(const char *file_id, const char *base_path) {
+	(void)file_id;
+	(void)base_path;
+	return result();
+}
+
+char *C_GetSelfJid(void) { return owned_string("0@s.whatsapp.net"); }
+
+Error C_GetContacts(GetContactsResult *contacts) {
+	Error error = result();
+	if (error.code != 0) return error;
+	contacts->size = 2;
+	contacts->entries = malloc(2 * sizeof(ContactEntry));
+	outstanding++;
+	contacts->entries[0] = (ContactEntry){owned_string("111@s.whatsapp.net"), owned_string("Alice")};
+	contacts->entries[1] = (ContactEntry){owned_string("222@s.whatsapp.net"), owned_string("Bob")};
+	return error;
+}
+
+void C_FreeContacts(GetContactsResult contacts) {
+	for (uint32_t i = 0; i < contacts.size; i++) {
+		C_Free((void *)contacts.entries[i].jid);
+		C_Free((void *)contacts.entries[i].name);
+	}
+	C_Free(contacts.entries);
+}
+
+Error C_GetChatSettings(JID jid, ChatSettings *settings) {
+	Error error = result();
+	if (error.code == 0) {
+		record(last_jid, jid);
+		*settings = (ChatSettings){true, 42, true, false};
+	}
+	return error;
+}
+
+Error C_GetGroupInfo(JID jid, GroupInfo *info) {
+	Error error = result();
+	if (error.code != 0) return error;
+	record(last_jid, jid);
+	info->jid = owned_string(jid);
+	info->name = owned_string("Stub group");
+	info->topic = owned_string("About the group");
+	info->created = 1000;
+	info->size = 2;
+	info->participants = malloc(2 * sizeof(GroupParticipant));
+	outstanding++;
+	info->participants[0] = (GroupParticipant){owned_string("111@s.whatsapp.net"), true, true};
+	info->participants[1] = (GroupParticipant){owned_string("222@s.whatsapp.net"), false, false};
+	return error;
+}
+
+Error C_UpdateGroupParticipants(JID group, JID const *participants, size_t count, uint8_t action) {
+	record(last_jid, group);
+	snprintf(last_text, sizeof last_text, "%u", (unsigned)action);
+	for (size_t i = 0; i < count; i++) {
+		strncat(last_text, " ", 255 - strlen(last_text));
+		strncat(last_text, participants[i], 255 - strlen(last_text));
+	}
+	return result();
+}
+
+void C_FreeGroupInfo(GroupInfo info) {
+	for (uint32_t i = 0; i < info.size; i++) C_Free((void *)info.participants[i].jid);
+	C_Free(info.participants);
+	C_Free((void *)info.jid);
+	C_Free(info.name);
+	C_Free(info.topic);
+}